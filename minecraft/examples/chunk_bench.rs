@@ -0,0 +1,113 @@
+// Times the terrain generation and the meshing of a square of chunks, and measures the memory the
+// generated blocks and the uploaded meshes take. Run it with
+// `cargo run --release --example chunk_bench`
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicIsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+
+use minecraft::chunk::ChunkData;
+use minecraft::world::{FREQUENCY, NOISE_SIZE, RNG_SEED};
+use minecraft::{noise, Chunk};
+
+const CHUNKS_PER_SIDE: i32 = 8;
+
+// Bytes currently allocated by the whole program
+struct CountingAllocator;
+static ALLOCATED: AtomicIsize = AtomicIsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size() as isize, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size() as isize, Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+fn main() {
+    let noise_data = Arc::new(noise::create_world_noise_data(
+        NOISE_SIZE, NOISE_SIZE, FREQUENCY, RNG_SEED,
+    ));
+    let coords: Vec<(i32, i32)> = (0..CHUNKS_PER_SIDE)
+        .flat_map(|x| (0..CHUNKS_PER_SIDE).map(move |y| (x, y)))
+        .collect();
+
+    let allocated_before = ALLOCATED.load(Ordering::Relaxed);
+    let start = Instant::now();
+    let blocks: Vec<_> = coords
+        .iter()
+        .map(|(x, y)| Chunk::create_blocks_data(*x, *y, noise_data.clone()))
+        .collect();
+    let generation_time = start.elapsed();
+    let allocated = ALLOCATED.load(Ordering::Relaxed) - allocated_before;
+    println!(
+        "generation: {} chunks in {:?}, {:.1} KiB of blocks per chunk",
+        coords.len(),
+        generation_time,
+        allocated as f64 / coords.len() as f64 / 1024.0
+    );
+
+    let instance = wgpu::Instance::default();
+    let Some(adapter) =
+        pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))
+    else {
+        println!("No adapter available, skipping the meshing");
+        return;
+    };
+    let (device, queue) =
+        pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default(), None))
+            .expect("Failed to create device");
+    let (device, queue) = (Arc::new(device), Arc::new(queue));
+    let layout = Arc::new(device.create_bind_group_layout(&Chunk::get_bind_group_layout()));
+    let chunks: HashMap<_, _> = coords
+        .iter()
+        .zip(blocks)
+        .map(|(&(x, y), blocks)| {
+            let data = ChunkData {
+                x,
+                y,
+                blocks,
+                outside_blocks: vec![],
+            };
+            let chunk = Chunk::from_data(
+                data,
+                noise_data.clone(),
+                device.clone(),
+                queue.clone(),
+                layout.clone(),
+                None,
+            );
+            ((x, y), Arc::new(RwLock::new(chunk)))
+        })
+        .collect();
+    let chunk_map = Arc::new(RwLock::new(chunks));
+
+    for (name, greedy) in [("meshing", false), ("greedy meshing", true)] {
+        let start = Instant::now();
+        let mut faces = 0;
+        let mut mesh_bytes = 0;
+        for chunk in chunk_map.read().unwrap().values() {
+            let mesh = chunk
+                .read()
+                .unwrap()
+                .mesh_data(chunk_map.clone(), greedy, 0);
+            faces += (mesh.indices.len() + mesh.water_indices.len()) / 6;
+            mesh_bytes += mesh.gpu_size();
+        }
+        println!(
+            "{}: {} chunks in {:?}, {} faces, {} KiB of vertex and index buffers per chunk",
+            name,
+            coords.len(),
+            start.elapsed(),
+            faces,
+            mesh_bytes / coords.len() / 1024
+        );
+    }
+}
@@ -0,0 +1,40 @@
+// Generates the world around the origin without a window and prints the highest block of a column,
+// using only the public api of the crate.
+use std::sync::{Arc, RwLock};
+
+use minecraft::world::{WORLD_MAX_Y, WORLD_MIN_Y};
+use minecraft::{Camera, Player, World};
+
+fn main() {
+    let instance = wgpu::Instance::default();
+    let adapter =
+        pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))
+            .expect("Failed to find an appropriate adapter");
+    let (device, queue) =
+        pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default(), None))
+            .expect("Failed to create device");
+    let device = Arc::new(device);
+    let queue = Arc::new(queue);
+
+    let camera = Camera::new(1.0, 1.0, device.clone(), queue.clone(), None);
+    let player = Arc::new(RwLock::new(Player::new(camera, None)));
+
+    let mut world = World::init_world(device, queue, None, false);
+    world.init_chunks(player);
+
+    let (x, z) = (3.0, 5.0);
+    let top = (WORLD_MIN_Y..=WORLD_MAX_Y)
+        .rev()
+        .find_map(|y| Some((y, world.get_block(glam::vec3(x, y as f32, z))?)));
+
+    match top {
+        Some((y, block_type)) => {
+            println!(
+                "Highest block at ({}, {}): {:?} at y = {}",
+                x, z, block_type, y
+            );
+        }
+        None => println!("No blocks at ({}, {})", x, z),
+    }
+    world.dispose();
+}
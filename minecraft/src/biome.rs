@@ -0,0 +1,175 @@
+use crate::blocks::block_type::{BlockType, SNOW_LINE};
+use crate::structures::{Bush, Cactus, DeadBush, Pine, Structure, Tree};
+use crate::utils::noise::perlin_noise;
+use crate::world::{NoiseData, MAX_TREES_PER_CHUNK, WATER_HEIGHT_LEVEL};
+
+// Biomes are a lot wider than the terrain features, ~256 blocks between noise cells
+pub const BIOME_FREQUENCY: f32 = 1. / 256.;
+const HOT_THRESHOLD: f32 = 0.15;
+const COLD_THRESHOLD: f32 = -0.15;
+const WET_THRESHOLD: f32 = 0.1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Biome {
+    Plains,
+    Desert,
+    Forest,
+    Snowy,
+}
+
+impl Biome {
+    // Temperature and humidity are two samples of the world noise, offset from each other
+    // so they aren't correlated. Only depends on the position and the seed.
+    pub fn at(world_x: i32, world_z: i32, noise_data: &NoiseData) -> Biome {
+        let x = world_x as f32 * BIOME_FREQUENCY;
+        let z = world_z as f32 * BIOME_FREQUENCY;
+        let temperature = perlin_noise(x + 0.5, z + 0.5, 256, &noise_data.perm);
+        let humidity = perlin_noise(z + 37.3, x + 11.7, 256, &noise_data.perm);
+
+        if temperature > HOT_THRESHOLD && humidity < WET_THRESHOLD {
+            Biome::Desert
+        } else if temperature < COLD_THRESHOLD {
+            Biome::Snowy
+        } else if humidity > WET_THRESHOLD {
+            Biome::Forest
+        } else {
+            Biome::Plains
+        }
+    }
+    // Top block of a column, `block_type` is what the column would have without biomes
+    pub fn surface_block(&self, y: u32, block_type: BlockType) -> BlockType {
+        match self {
+            Biome::Desert => BlockType::Sand,
+            // Beaches and the sea floor keep their sand
+            _ if y <= WATER_HEIGHT_LEVEL as u32 => block_type,
+            _ if self.has_snow(y) => BlockType::Snow,
+            _ => match block_type {
+                BlockType::Dirt => BlockType::Grass,
+                b => b,
+            },
+        }
+    }
+    // Whether the ground at this height is covered in snow, trees on it get snow on top too
+    pub fn has_snow(&self, y: u32) -> bool {
+        match self {
+            Biome::Snowy => y > WATER_HEIGHT_LEVEL as u32,
+            Biome::Desert => false,
+            Biome::Plains | Biome::Forest => y >= SNOW_LINE,
+        }
+    }
+    // Upper bound (exclusive) of the structures placed in a chunk of this biome
+    pub fn max_structures(&self) -> u32 {
+        match self {
+            Biome::Plains => MAX_TREES_PER_CHUNK,
+            Biome::Forest => MAX_TREES_PER_CHUNK * 3,
+            Biome::Snowy => 1,
+            Biome::Desert => MAX_TREES_PER_CHUNK * 2,
+        }
+    }
+    // Structures (trees and plants) placed on the ground and the weight of each one
+    pub fn structure_species(&self) -> &'static [(&'static dyn Structure, u32)] {
+        match self {
+            Biome::Plains => &[(&Tree, 3), (&Bush, 2)],
+            Biome::Forest => &[(&Tree, 5), (&Pine, 3), (&Bush, 2)],
+            Biome::Snowy => &[(&Pine, 1)],
+            Biome::Desert => &[(&Cactus, 2), (&DeadBush, 3)],
+        }
+    }
+    // Picks a species with a roll in [0, 1), None if the biome has no structures
+    pub fn pick_structure(&self, roll: f32) -> Option<&'static dyn Structure> {
+        let species = self.structure_species();
+        let total: u32 = species.iter().map(|(_, weight)| weight).sum();
+        let mut target = roll * total as f32;
+        for (structure, weight) in species {
+            if target < *weight as f32 {
+                return Some(*structure);
+            }
+            target -= *weight as f32;
+        }
+        species.last().map(|(structure, _)| *structure)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Biome;
+    use crate::blocks::block_type::{BlockType, SNOW_LINE};
+    use crate::utils::noise::create_world_noise_data;
+    use crate::world::{FREQUENCY, NOISE_SIZE, RNG_SEED, WATER_HEIGHT_LEVEL};
+
+    #[test]
+    fn should_pick_the_same_biome_for_the_same_position() {
+        let noise_data = create_world_noise_data(NOISE_SIZE, NOISE_SIZE, FREQUENCY, RNG_SEED);
+        let mut found = vec![];
+
+        for x in (-2048..2048).step_by(64) {
+            for z in (-2048..2048).step_by(64) {
+                let biome = Biome::at(x, z, &noise_data);
+                assert_eq!(biome, Biome::at(x, z, &noise_data));
+                if !found.contains(&biome) {
+                    found.push(biome);
+                }
+            }
+        }
+        assert_eq!(found.len(), 4);
+        assert_eq!(
+            Biome::Desert.surface_block(8, BlockType::Dirt),
+            BlockType::Sand
+        );
+    }
+
+    #[test]
+    fn should_cover_the_cold_biome_and_the_peaks_above_the_stone_with_snow() {
+        let (low, beach) = (WATER_HEIGHT_LEVEL as u32 + 1, WATER_HEIGHT_LEVEL as u32);
+        assert_eq!(
+            Biome::Snowy.surface_block(low, BlockType::Dirt),
+            BlockType::Snow
+        );
+        assert_eq!(
+            Biome::Snowy.surface_block(beach, BlockType::Sand),
+            BlockType::Sand
+        );
+        assert_eq!(
+            Biome::Plains.surface_block(low, BlockType::Dirt),
+            BlockType::Grass
+        );
+
+        // Right under the snow line the ground is already all stone
+        let below = BlockType::from_position(3, SNOW_LINE - 1, 5, RNG_SEED);
+        assert_eq!(below, BlockType::Stone);
+        assert_eq!(
+            Biome::Plains.surface_block(SNOW_LINE - 1, below),
+            BlockType::Stone
+        );
+        for biome in [Biome::Plains, Biome::Forest, Biome::Snowy] {
+            assert_eq!(
+                biome.surface_block(SNOW_LINE, BlockType::Stone),
+                BlockType::Snow
+            );
+            assert!(biome.has_snow(SNOW_LINE));
+        }
+        assert!(!Biome::Desert.has_snow(SNOW_LINE));
+        assert!(!Biome::Forest.has_snow(low));
+    }
+
+    #[test]
+    fn should_pick_structure_species_by_biome() {
+        // Desert weights are cactus 2, dead bush 3
+        assert_eq!(Biome::Desert.pick_structure(0.1).unwrap().kind(), "cactus");
+        assert_eq!(
+            Biome::Desert.pick_structure(0.9).unwrap().kind(),
+            "dead bush"
+        );
+        for roll in [0.0, 0.5, 0.999] {
+            assert_eq!(Biome::Snowy.pick_structure(roll).unwrap().kind(), "pine");
+        }
+        // Plains weights are tree 3, bush 2
+        assert_eq!(Biome::Plains.pick_structure(0.1).unwrap().kind(), "tree");
+        assert_eq!(Biome::Plains.pick_structure(0.9).unwrap().kind(), "bush");
+        let forest: Vec<_> = [0.1, 0.6, 0.95]
+            .iter()
+            .map(|roll| Biome::Forest.pick_structure(*roll).unwrap().kind())
+            .collect();
+        assert_eq!(forest, ["tree", "pine", "bush"]);
+    }
+}
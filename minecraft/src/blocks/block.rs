@@ -1,12 +1,10 @@
 use bytemuck::{Pod, Zeroable};
 
 use super::block_type::BlockType;
-use crate::chunk::BlockVec;
+use crate::chunk::ChunkBlocks;
 use crate::collision::CollisionBox;
 use crate::effects::ao::{convert_ao_u8_to_f32, from_vertex_position};
-use crate::world::CHUNK_SIZE;
 use glam::Vec3;
-use std::sync::{Arc, RwLock};
 
 #[derive(Debug)]
 pub struct Block {
@@ -14,6 +12,8 @@ pub struct Block {
     pub absolute_position: glam::Vec3,
     pub collision_box: CollisionBox,
     pub block_type: BlockType,
+    // Size of the chunk it belongs to, the world's `chunk_size`
+    pub chunk_size: u32,
 }
 
 #[rustfmt::skip]
@@ -28,11 +28,21 @@ pub const CUBE_VERTEX: [f32; 24] = [
     0.5, -0.5, 0.5,
 ];
 
+// Size of the post non full blocks are drawn as
+const POST_WIDTH: f32 = 2.0 / 16.0;
+const POST_HEIGHT: f32 = 10.0 / 16.0;
+// Fixed point steps per block of the packed positions, y has less precision to fit the world
+// height in 16 bits. Texture coordinates count steps of a tile
+const PACKED_XZ_STEPS: f32 = 256.0;
+const PACKED_Y_STEPS: f32 = 128.0;
+const PACKED_UV_STEPS: f32 = 128.0;
+
 pub trait TexturedBlock {
-    fn get_texcoords(&self, face_dir: FaceDirections) -> [[f32; 2]; 4];
+    // Texture layer and uv of each corner of the face, from 0 to 1
+    fn get_texcoords(&self, face_dir: FaceDirections) -> (u32, [[f32; 2]; 4]);
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
 pub enum FaceDirections {
     Front,
     Back,
@@ -45,8 +55,8 @@ pub enum FaceDirections {
 impl FaceDirections {
     pub fn create_face_data(
         &self,
-        block: Arc<RwLock<Block>>,
-        blocks: &Vec<((i32, i32), BlockVec)>,
+        block: &Block,
+        blocks: &[((i32, i32), &ChunkBlocks)],
     ) -> (Vec<BlockVertexData>, Vec<u32>) {
         let indices = self.get_indices();
 
@@ -72,49 +82,184 @@ impl FaceDirections {
             *indices_map = index_of as u32;
         }
 
-        let block_read = block.read().unwrap();
-        let face_texcoords = block_read.block_type.get_texcoords(*self);
+        let (layer, face_texcoords) = block.block_type.get_texcoords(*self);
         let normals = self.get_normal_vector();
+        let mut ao = [0u8; 4];
 
         unique_indices.iter().enumerate().for_each(|(i, index)| {
             let vertex_position = glam::vec3(
-                CUBE_VERTEX[*index as usize * 3_usize] + block_read.absolute_position.x,
-                CUBE_VERTEX[*index as usize * 3 + 1] + block_read.absolute_position.y,
-                CUBE_VERTEX[*index as usize * 3 + 2] + block_read.absolute_position.z,
+                CUBE_VERTEX[*index as usize * 3_usize] + block.absolute_position.x,
+                CUBE_VERTEX[*index as usize * 3 + 1] + block.absolute_position.y,
+                CUBE_VERTEX[*index as usize * 3 + 2] + block.absolute_position.z,
             );
 
+            ao[i] = from_vertex_position(&vertex_position, *self, *index as usize, blocks);
+
             vertex_data.push(BlockVertexData {
                 position: [
-                    CUBE_VERTEX[*index as usize * 3_usize] + block_read.position.x,
-                    CUBE_VERTEX[*index as usize * 3 + 1] + block_read.position.y,
-                    CUBE_VERTEX[*index as usize * 3 + 2] + block_read.position.z,
+                    CUBE_VERTEX[*index as usize * 3_usize] + block.position.x,
+                    CUBE_VERTEX[*index as usize * 3 + 1] + block.position.y,
+                    CUBE_VERTEX[*index as usize * 3 + 2] + block.position.z,
                 ],
-                ao: convert_ao_u8_to_f32(from_vertex_position(&vertex_position, blocks)),
+                ao: convert_ao_u8_to_f32(ao[i]),
                 normal: normals.into(),
                 tex_coords: face_texcoords[i],
+                light: 0.0,
+                layer,
+                tiling: 0.0,
+                sky_light: 0.0,
+                water: [0.0; 2],
             })
         });
+        // The quad is split along the 0-2 diagonal, if those are the brightest corners the
+        // occlusion is interpolated unevenly, so it's split along 1-3 instead
+        if ao[0] + ao[2] > ao[1] + ao[3] {
+            indices_map = vec![1, 2, 3, 1, 3, 0];
+        }
 
         (vertex_data, indices_map)
     }
+    // The face shrunk to the thin post non full blocks (torches) are drawn as, standing on the
+    // bottom of the block. The texture keeps its scale, so the post shows a strip of the tile
+    pub fn create_post_face_data(&self, block: &Block) -> (Vec<BlockVertexData>, Vec<u32>) {
+        let (mut vertex_data, indices) = self.create_face_data(block, &[]);
+        let normal = self.get_normal_vector();
+        let (u, v) = if normal.x != 0.0 {
+            (1, 2)
+        } else if normal.y != 0.0 {
+            (0, 2)
+        } else {
+            (0, 1)
+        };
+        let offsets: Vec<glam::Vec3> = vertex_data
+            .iter()
+            .map(|vertex| glam::Vec3::from(vertex.position) - block.position)
+            .collect();
+        // The texture coordinates are linear over the face, from its corner at -0.5 on both axes
+        let tex_at = |s: f32, t: f32| {
+            let corner = offsets
+                .iter()
+                .position(|offset| (offset[u] + 0.5, offset[v] + 0.5) == (s, t))
+                .unwrap();
+            glam::Vec2::from(vertex_data[corner].tex_coords)
+        };
+        let origin = tex_at(0.0, 0.0);
+        let (along_u, along_v) = (tex_at(1.0, 0.0) - origin, tex_at(0.0, 1.0) - origin);
+
+        for (vertex, offset) in vertex_data.iter_mut().zip(offsets) {
+            let post = glam::vec3(
+                offset.x * POST_WIDTH,
+                (offset.y + 0.5) * POST_HEIGHT - 0.5,
+                offset.z * POST_WIDTH,
+            );
+            vertex.position = (block.position + post).into();
+            vertex.tex_coords =
+                (origin + along_u * (post[u] + 0.5) + along_v * (post[v] + 0.5)).into();
+        }
+        (vertex_data, indices)
+    }
+    // A side face folded onto a diagonal of the cell, for plants. Front and back end up on one
+    // diagonal facing opposite ways, left and right on the other, so both are seen from either side
+    pub fn create_cross_face_data(&self, block: &Block) -> (Vec<BlockVertexData>, Vec<u32>) {
+        let (mut vertex_data, indices) = self.create_face_data(block, &[]);
+        let normal = self.get_normal_vector();
+        let cross_normal = self.get_cross_normal();
+        for vertex in vertex_data.iter_mut() {
+            let mut offset = glam::Vec3::from(vertex.position) - block.position;
+            if normal.z != 0.0 {
+                offset.z = offset.x;
+            } else {
+                offset.x = -offset.z;
+            }
+            vertex.position = (block.position + offset).into();
+            vertex.normal = cross_normal.into();
+        }
+        (vertex_data, indices)
+    }
 }
 
 #[repr(C)]
-#[derive(Pod, Copy, Clone, Zeroable)]
+#[derive(Pod, Copy, Clone, Zeroable, Debug)]
 pub struct BlockVertexData {
     pub position: [f32; 3],
     pub normal: [f32; 3],
+    // On the face's texture, from 0 to 1 except on merged faces
     pub tex_coords: [f32; 2],
     pub ao: f32,
+    // Block light (0-1), set by the chunk once the light is propagated
+    pub light: f32,
+    // Layer of the block textures, the tile of the atlas with the `atlas` feature
+    pub layer: u32,
+    // 1 on merged faces, their tex_coords count blocks and the texture repeats
+    pub tiling: f32,
+    // Sunlight (0-1), set by the chunk like `light`
+    pub sky_light: f32,
+    // x: 1 on water faces, their texture scrolls. y: 1 on the vertices of the water surface, they
+    // move with the waves
+    pub water: [f32; 2],
+}
+// What the chunk meshes upload, 16 bytes instead of the 60 of `BlockVertexData`. Unpacked by `unpack_vertex` in
+// block_vertex.wgsl:
+// - position_xz: x and z relative to the chunk, u16 each in 1/256 of a block from -0.5
+// - position_y_light: y (u16, 1/128 of a block from -0.5), then light and sky light (u8 each)
+// - tex_coords: u and v on the texture (u16 each, 1/128 of it), merged faces go past 1
+// - attributes: texture layer (6 bits), face (3, order of `FaceDirections::all`), diagonal normal of
+//   plants (1), ao (2), tiling (1), water (2)
+#[repr(C)]
+#[derive(Pod, Copy, Clone, Zeroable, Debug, PartialEq)]
+pub struct BlockVertexPacked {
+    pub position_xz: u32,
+    pub position_y_light: u32,
+    pub tex_coords: u32,
+    pub attributes: u32,
+}
+impl From<&BlockVertexData> for BlockVertexPacked {
+    fn from(vertex: &BlockVertexData) -> Self {
+        let fixed = |value: f32, steps: f32| (value * steps).round() as u32 & 0xffff;
+        let unorm8 = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u32;
+        let flag = |value: f32| (value > 0.5) as u32;
+
+        let normal = glam::Vec3::from(vertex.normal);
+        let (face, cross) = FaceDirections::all()
+            .into_iter()
+            .enumerate()
+            .find_map(|(i, face)| {
+                if face.get_normal_vector() == normal {
+                    Some((i as u32, 0))
+                } else if face.get_cross_normal() == normal {
+                    Some((i as u32, 1))
+                } else {
+                    None
+                }
+            })
+            .expect("Block faces face along an axis or a diagonal");
+        let [u, v] = vertex.tex_coords;
+
+        BlockVertexPacked {
+            position_xz: fixed(vertex.position[0] + 0.5, PACKED_XZ_STEPS)
+                | fixed(vertex.position[2] + 0.5, PACKED_XZ_STEPS) << 16,
+            position_y_light: fixed(vertex.position[1] + 0.5, PACKED_Y_STEPS)
+                | unorm8(vertex.light) << 16
+                | unorm8(vertex.sky_light) << 24,
+            tex_coords: fixed(u, PACKED_UV_STEPS) | fixed(v, PACKED_UV_STEPS) << 16,
+            attributes: vertex.layer & 0x3f
+                | face << 6
+                | cross << 9
+                | ((vertex.ao * 3.0).round() as u32 & 0x3) << 10
+                | flag(vertex.tiling) << 12
+                | flag(vertex.water[0]) << 13
+                | flag(vertex.water[1]) << 14,
+        }
+    }
 }
 
 impl Block {
     // Takes in relative position
-    pub fn new(position: Vec3, chunk: (i32, i32), block_type: BlockType) -> Block {
+    pub fn new(position: Vec3, chunk: (i32, i32), block_type: BlockType, chunk_size: u32) -> Block {
         let absolute_position = glam::vec3(
-            (chunk.0 * CHUNK_SIZE as i32 + position.x as i32) as f32,
+            (chunk.0 * chunk_size as i32 + position.x as i32) as f32,
             position.y,
-            (chunk.1 * CHUNK_SIZE as i32 + position.z as i32) as f32,
+            (chunk.1 * chunk_size as i32 + position.z as i32) as f32,
         );
         let collision_box = CollisionBox::from_block_position(
             absolute_position.x,
@@ -126,38 +271,69 @@ impl Block {
             position,
             block_type,
             absolute_position,
+            chunk_size,
         }
     }
+    // Chunks affected by this block other than its own. Border blocks touch the chunk next to them,
+    // light emitters reach every chunk within their light range (diagonals included)
     pub fn get_neighbour_chunks_coords(&self) -> Vec<(i32, i32)> {
         let chunk = self.get_chunk_coords();
+        let reach = self
+            .block_type
+            .get_config()
+            .light_emission
+            .saturating_sub(1) as f32;
+        let edge = self.edge();
+        let offsets = |p: f32| {
+            let mut offsets = vec![0];
+            if p + reach >= edge {
+                offsets.push(1);
+            }
+            if p - reach <= 0.0 {
+                offsets.push(-1);
+            }
+            offsets
+        };
         let mut neighbour_chunks = vec![];
 
-        if self.position.x == 15.0 {
-            neighbour_chunks.push((chunk.0 + 1, chunk.1));
-        }
-        if self.position.x == 0.0 {
-            neighbour_chunks.push((chunk.0 - 1, chunk.1));
-        }
-        if self.position.z == 15.0 {
-            neighbour_chunks.push((chunk.0, chunk.1 + 1));
-        }
-        if self.position.z == 0.0 {
-            neighbour_chunks.push((chunk.0, chunk.1 - 1));
+        for dx in offsets(self.position.x) {
+            for dz in offsets(self.position.z) {
+                if (dx, dz) != (0, 0) {
+                    neighbour_chunks.push((chunk.0 + dx, chunk.1 + dz));
+                }
+            }
         }
         neighbour_chunks
     }
     pub fn is_on_chunk_border(&self) -> bool {
+        let edge = self.edge();
         self.position.x == 0.0
-            || self.position.x == 15.0
+            || self.position.x == edge
             || self.position.z == 0.0
-            || self.position.z == 15.0
+            || self.position.z == edge
+    }
+    // Relative x and z of the last blocks of the chunk
+    fn edge(&self) -> f32 {
+        (self.chunk_size - 1) as f32
     }
     pub fn get_chunk_coords(&self) -> (i32, i32) {
         (
-            (f32::floor(self.absolute_position.x / CHUNK_SIZE as f32)) as i32,
-            (f32::floor(self.absolute_position.z / CHUNK_SIZE as f32)) as i32,
+            (f32::floor(self.absolute_position.x / self.chunk_size as f32)) as i32,
+            (f32::floor(self.absolute_position.z / self.chunk_size as f32)) as i32,
         )
     }
+    // The chunk meshes are uploaded packed, see `BlockVertexPacked`
+    pub fn get_packed_vertex_data_layout() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<BlockVertexPacked>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[wgpu::VertexAttribute {
+                format: wgpu::VertexFormat::Uint32x4,
+                offset: 0,
+                shader_location: 0,
+            }],
+        }
+    }
     pub fn get_vertex_data_layout() -> wgpu::VertexBufferLayout<'static> {
         wgpu::VertexBufferLayout {
             array_stride: std::mem::size_of::<BlockVertexData>() as wgpu::BufferAddress,
@@ -187,6 +363,36 @@ impl Block {
                     offset: std::mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
                     shader_location: 3,
                 },
+                // Light
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32,
+                    offset: std::mem::size_of::<[f32; 9]>() as wgpu::BufferAddress,
+                    shader_location: 4,
+                },
+                // Layer
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Uint32,
+                    offset: std::mem::size_of::<[f32; 10]>() as wgpu::BufferAddress,
+                    shader_location: 5,
+                },
+                // Tiling
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32,
+                    offset: std::mem::size_of::<[f32; 11]>() as wgpu::BufferAddress,
+                    shader_location: 6,
+                },
+                // Sky light
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32,
+                    offset: std::mem::size_of::<[f32; 12]>() as wgpu::BufferAddress,
+                    shader_location: 7,
+                },
+                // Water
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x2,
+                    offset: std::mem::size_of::<[f32; 13]>() as wgpu::BufferAddress,
+                    shader_location: 8,
+                },
             ],
         }
     }
@@ -223,6 +429,12 @@ impl FaceDirections {
             FaceDirections::Right => glam::vec3(1.0, 0.0, 0.0),
         }
     }
+    // Normal of the face folded onto a diagonal of the cell (plants), perpendicular to the diagonal
+    // towards the same side as the face
+    pub fn get_cross_normal(&self) -> glam::Vec3 {
+        let normal = self.get_normal_vector();
+        (normal + glam::vec3(-normal.z, 0.0, normal.x)).normalize()
+    }
     pub fn get_indices(&self) -> [u32; 6] {
         match self {
             FaceDirections::Back => [7, 6, 5, 7, 5, 4],
@@ -234,3 +446,193 @@ impl FaceDirections {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Block, BlockVertexData, BlockVertexPacked, FaceDirections};
+    use crate::blocks::block_type::BlockType;
+    use crate::chunk::ChunkBlocks;
+    use crate::world::DEFAULT_CHUNK_SIZE;
+
+    // Same as unpack_vertex in block_vertex.wgsl
+    fn unpack(packed: &BlockVertexPacked) -> BlockVertexData {
+        let low = |word: u32| (word & 0xffff) as f32;
+        let high = |word: u32| (word >> 16) as f32;
+        let bits = |shift: u32, count: u32| (packed.attributes >> shift) & ((1 << count) - 1);
+        let face = FaceDirections::all()[bits(6, 3) as usize];
+        let normal = match bits(9, 1) {
+            1 => face.get_cross_normal(),
+            _ => face.get_normal_vector(),
+        };
+        let tex_coords = [
+            low(packed.tex_coords) / 128.0,
+            high(packed.tex_coords) / 128.0,
+        ];
+        BlockVertexData {
+            position: [
+                low(packed.position_xz) / 256.0 - 0.5,
+                low(packed.position_y_light) / 128.0 - 0.5,
+                high(packed.position_xz) / 256.0 - 0.5,
+            ],
+            normal: normal.into(),
+            tex_coords,
+            ao: bits(10, 2) as f32 / 3.0,
+            light: ((packed.position_y_light >> 16) & 0xff) as f32 / 255.0,
+            layer: bits(0, 6),
+            tiling: bits(12, 1) as f32,
+            sky_light: (packed.position_y_light >> 24) as f32 / 255.0,
+            water: [bits(13, 1) as f32, bits(14, 1) as f32],
+        }
+    }
+
+    #[test]
+    fn should_find_the_chunk_border_from_the_chunk_size() {
+        let block =
+            |x: f32, z: f32| Block::new(glam::vec3(x, 3.0, z), (2, -1), BlockType::Stone, 32);
+        assert_eq!(
+            block(31.0, 5.0).absolute_position,
+            glam::vec3(95.0, 3.0, -27.0)
+        );
+        assert_eq!(block(31.0, 5.0).get_chunk_coords(), (2, -1));
+
+        // 15 is in the middle of a 32 wide chunk
+        assert!(!block(15.0, 15.0).is_on_chunk_border());
+        assert!(block(15.0, 15.0).get_neighbour_chunks_coords().is_empty());
+
+        assert!(block(31.0, 5.0).is_on_chunk_border());
+        assert_eq!(
+            block(31.0, 5.0).get_neighbour_chunks_coords(),
+            vec![(3, -1)]
+        );
+        assert_eq!(block(5.0, 0.0).get_neighbour_chunks_coords(), vec![(2, -2)]);
+        let mut corner = block(0.0, 31.0).get_neighbour_chunks_coords();
+        corner.sort();
+        assert_eq!(corner, vec![(1, -1), (1, 0), (2, 0)]);
+
+        // Light emitters reach further than the border
+        let lamp = Block::new(glam::vec3(25.0, 3.0, 16.0), (0, 0), BlockType::Torch, 32);
+        assert_eq!(lamp.get_neighbour_chunks_coords(), vec![(1, 0)]);
+    }
+
+    #[test]
+    fn should_split_faces_along_the_diagonal_of_the_occluded_corner() {
+        let center = glam::vec3(5.0, 5.0, 5.0);
+        let block = Block::new(center, (0, 0), BlockType::Stone, DEFAULT_CHUNK_SIZE);
+        for face in FaceDirections::all() {
+            let normal = face.get_normal_vector();
+            let tangents: Vec<_> = [glam::Vec3::X, glam::Vec3::Y, glam::Vec3::Z]
+                .into_iter()
+                .filter(|axis| axis.dot(normal) == 0.0)
+                .collect();
+            // A block in front of each corner of the face
+            for (sign1, sign2) in [(1.0, 1.0), (1.0, -1.0), (-1.0, 1.0), (-1.0, -1.0)] {
+                let mut blocks = ChunkBlocks::new((0, 0), DEFAULT_CHUNK_SIZE);
+                blocks.set_at(&center, Some(BlockType::Stone));
+                let corner = center + normal + tangents[0] * sign1 + tangents[1] * sign2;
+                blocks.set_at(&corner, Some(BlockType::Stone));
+
+                let (vertices, indices) = face.create_face_data(&block, &[((0, 0), &blocks)]);
+                let dark: Vec<_> = (0..4).filter(|i| vertices[*i].ao > 0.0).collect();
+                assert_eq!(dark.len(), 1, "{face:?} {corner}");
+                // The diagonal is the edge both triangles share
+                let shared = indices.iter().filter(|i| **i == dark[0] as u32).count();
+                assert_eq!(shared, 2, "{face:?} {corner}");
+            }
+        }
+    }
+
+    #[test]
+    fn should_pack_every_kind_of_face_into_16_bytes() {
+        assert_eq!(std::mem::size_of::<BlockVertexPacked>(), 16);
+        let close = |a: &[f32], b: &[f32], tolerance: f32| {
+            a.iter().zip(b).all(|(a, b)| (a - b).abs() <= tolerance)
+        };
+        // At the top of the world, on the last block of the chunk
+        let corner = glam::vec3(15.0, 255.0, 15.0);
+        let mut vertices = vec![];
+        for face in FaceDirections::all() {
+            let block = |block_type| Block::new(corner, (3, -2), block_type, DEFAULT_CHUNK_SIZE);
+            vertices.extend(face.create_face_data(&block(BlockType::Grass), &[]).0);
+            vertices.extend(face.create_post_face_data(&block(BlockType::Torch)).0);
+            vertices.extend(face.create_cross_face_data(&block(BlockType::DeadBush)).0);
+        }
+        // Light, a merged face and the surface of the water
+        vertices[0].light = 0.6;
+        vertices[1].sky_light = 0.8;
+        vertices[2].tiling = 1.0;
+        vertices[2].tex_coords = [3.0, 16.0];
+        vertices[3].water = [1.0, 1.0];
+        vertices[3].position[1] -= 1.0 / 8.0;
+        vertices[4].ao = 2.0 / 3.0;
+
+        for vertex in vertices.iter() {
+            let unpacked = unpack(&BlockVertexPacked::from(vertex));
+            assert!(
+                close(&unpacked.position, &vertex.position, 1.0 / 256.0),
+                "{vertex:?}"
+            );
+            assert!(close(&unpacked.normal, &vertex.normal, 1e-6), "{vertex:?}");
+            assert_eq!(unpacked.layer, vertex.layer, "{vertex:?}");
+            assert!(
+                close(&unpacked.tex_coords, &vertex.tex_coords, 1.0 / 128.0),
+                "{vertex:?}"
+            );
+            assert!(close(
+                &[unpacked.light, unpacked.sky_light],
+                &[vertex.light, vertex.sky_light],
+                1.0 / 255.0
+            ));
+            assert!(close(
+                &[unpacked.ao, unpacked.tiling],
+                &[vertex.ao, vertex.tiling],
+                1e-6
+            ));
+            assert_eq!(unpacked.water, vertex.water);
+        }
+    }
+
+    #[test]
+    fn should_draw_torches_as_a_post_with_a_strip_of_the_texture() {
+        let center = glam::vec3(5.0, 5.0, 5.0);
+        let torch = Block::new(center, (0, 0), BlockType::Torch, DEFAULT_CHUNK_SIZE);
+        for face in FaceDirections::all() {
+            let (vertices, _) = face.create_post_face_data(&torch);
+            let (cube, _) = face.create_face_data(&torch, &[]);
+            for (vertex, cube_vertex) in vertices.iter().zip(&cube) {
+                let offset = glam::Vec3::from(vertex.position) - center;
+                assert!(offset.x.abs() <= 1.0 / 16.0 && offset.z.abs() <= 1.0 / 16.0);
+                assert!(offset.y >= -0.5 && offset.y <= 0.5 - 6.0 / 16.0);
+                assert_eq!(vertex.ao, 0.0);
+                // Inside the block's texture
+                assert_eq!(vertex.layer, cube_vertex.layer);
+                assert!(vertex.tex_coords.iter().all(|uv| (0.0..=1.0).contains(uv)));
+            }
+        }
+    }
+
+    #[test]
+    fn should_fold_the_sides_of_a_dead_bush_onto_the_diagonals() {
+        let center = glam::vec3(5.0, 5.0, 5.0);
+        let bush = Block::new(center, (0, 0), BlockType::DeadBush, DEFAULT_CHUNK_SIZE);
+        for face in [
+            FaceDirections::Front,
+            FaceDirections::Back,
+            FaceDirections::Left,
+            FaceDirections::Right,
+        ] {
+            let (vertices, _) = face.create_cross_face_data(&bush);
+            let (cube, _) = face.create_face_data(&bush, &[]);
+            let normal = glam::Vec3::from(vertices[0].normal);
+            assert!((normal.length() - 1.0).abs() < 1e-5);
+            // Still facing the same way as the side it comes from
+            assert!(normal.dot(face.get_normal_vector()) > 0.0);
+            for (vertex, cube_vertex) in vertices.iter().zip(&cube) {
+                let offset = glam::Vec3::from(vertex.position) - center;
+                assert_eq!(offset.x.abs(), offset.z.abs(), "{face:?}");
+                assert!(normal.dot(offset).abs() < 1e-5, "{face:?}");
+                assert_eq!(offset.y, cube_vertex.position[1] - center.y);
+                assert_eq!(vertex.tex_coords, cube_vertex.tex_coords);
+            }
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use super::block::{FaceDirections, TexturedBlock};
-use crate::world::{RNG_SEED, WATER_HEIGHT_LEVEL};
+use crate::world::WATER_HEIGHT_LEVEL;
 use rand::{rngs::StdRng, Rng, SeedableRng};
 
 #[derive(Clone, Copy, Debug)]
@@ -11,6 +11,12 @@ pub struct BlockTypeConfigs {
     // Integers representing the nth texture to use.
     pub textures: [FaceTexture; 3], // 1: Lateral texture, 2: Top texture, 3: Bottom texture
     pub is_translucent: bool,
+    // Block light level it gives off, 0 for most blocks
+    pub light_emission: u8,
+    // Seconds of mining to break it, 0 breaks right away
+    pub hardness: f32,
+    // The player can't remove the blocks that aren't (water, bedrock)
+    pub breakable: bool,
 }
 
 impl BlockTypeConfigs {
@@ -20,38 +26,139 @@ impl BlockTypeConfigs {
                 id: 0,
                 textures: [FaceTexture(6), FaceTexture(7), FaceTexture(8)],
                 is_translucent: false,
+                light_emission: 0,
+                hardness: 0.6,
+                breakable: true,
             },
             BlockType::Dirt => BlockTypeConfigs {
                 id: 1,
                 textures: [FaceTexture(0), FaceTexture(0), FaceTexture(0)],
                 is_translucent: false,
+                light_emission: 0,
+                hardness: 0.5,
+                breakable: true,
             },
 
             BlockType::Water => BlockTypeConfigs {
                 id: 2,
                 textures: [FaceTexture(1), FaceTexture(1), FaceTexture(1)],
                 is_translucent: true,
+                light_emission: 0,
+                hardness: f32::INFINITY,
+                breakable: false,
             },
 
             BlockType::Wood => BlockTypeConfigs {
                 id: 3,
                 textures: [FaceTexture(4), FaceTexture(5), FaceTexture(5)],
                 is_translucent: false,
+                light_emission: 0,
+                hardness: 1.0,
+                breakable: true,
             },
             BlockType::Leaf => BlockTypeConfigs {
                 id: 4,
                 textures: [FaceTexture(2), FaceTexture(2), FaceTexture(2)],
                 is_translucent: false,
+                light_emission: 0,
+                hardness: 0.0,
+                breakable: true,
             },
             BlockType::Stone => BlockTypeConfigs {
                 id: 5,
                 textures: [FaceTexture(3), FaceTexture(3), FaceTexture(3)],
                 is_translucent: false,
+                light_emission: 0,
+                hardness: 1.5,
+                breakable: true,
             },
             BlockType::Sand => BlockTypeConfigs {
                 id: 6,
                 textures: [FaceTexture(9), FaceTexture(9), FaceTexture(9)],
                 is_translucent: false,
+                light_emission: 0,
+                hardness: 0.5,
+                breakable: true,
+            },
+            BlockType::Glass => BlockTypeConfigs {
+                id: 7,
+                textures: [FaceTexture(10), FaceTexture(10), FaceTexture(10)],
+                is_translucent: true,
+                light_emission: 0,
+                hardness: 0.3,
+                breakable: true,
+            },
+            BlockType::Snow => BlockTypeConfigs {
+                id: 8,
+                textures: [FaceTexture(12), FaceTexture(11), FaceTexture(8)],
+                is_translucent: false,
+                light_emission: 0,
+                hardness: 0.2,
+                breakable: true,
+            },
+            BlockType::CoalOre => BlockTypeConfigs {
+                id: 9,
+                textures: [FaceTexture(13), FaceTexture(13), FaceTexture(13)],
+                is_translucent: false,
+                light_emission: 0,
+                hardness: 2.0,
+                breakable: true,
+            },
+            BlockType::IronOre => BlockTypeConfigs {
+                id: 10,
+                textures: [FaceTexture(14), FaceTexture(14), FaceTexture(14)],
+                is_translucent: false,
+                light_emission: 0,
+                hardness: 2.0,
+                breakable: true,
+            },
+            BlockType::Torch => BlockTypeConfigs {
+                id: 11,
+                textures: [FaceTexture(15), FaceTexture(15), FaceTexture(15)],
+                is_translucent: false,
+                light_emission: 14,
+                hardness: 0.0,
+                breakable: true,
+            },
+            BlockType::Cactus => BlockTypeConfigs {
+                id: 12,
+                textures: [FaceTexture(16), FaceTexture(17), FaceTexture(17)],
+                is_translucent: false,
+                light_emission: 0,
+                hardness: 0.4,
+                breakable: true,
+            },
+            BlockType::DeadBush => BlockTypeConfigs {
+                id: 13,
+                textures: [FaceTexture(18), FaceTexture(18), FaceTexture(18)],
+                is_translucent: false,
+                light_emission: 0,
+                hardness: 0.0,
+                breakable: true,
+            },
+            BlockType::BirchWood => BlockTypeConfigs {
+                id: 14,
+                textures: [FaceTexture(19), FaceTexture(20), FaceTexture(20)],
+                is_translucent: false,
+                light_emission: 0,
+                hardness: 1.0,
+                breakable: true,
+            },
+            BlockType::BirchLeaf => BlockTypeConfigs {
+                id: 15,
+                textures: [FaceTexture(21), FaceTexture(21), FaceTexture(21)],
+                is_translucent: false,
+                light_emission: 0,
+                hardness: 0.0,
+                breakable: true,
+            },
+            BlockType::Bedrock => BlockTypeConfigs {
+                id: 16,
+                textures: [FaceTexture(32), FaceTexture(32), FaceTexture(32)],
+                is_translucent: false,
+                light_emission: 0,
+                hardness: f32::INFINITY,
+                breakable: false,
             },
         }
     }
@@ -67,9 +174,28 @@ pub enum BlockType {
     Leaf,
     Stone,
     Sand,
+    Glass,
+    Snow,
+    CoalOre,
+    IronOre,
+    Torch,
+    Cactus,
+    DeadBush,
+    BirchWood,
+    BirchLeaf,
+    Bedrock,
+}
+// How a block is drawn, only full cubes hide the faces next to them and collide
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlockShape {
+    Cube,
+    // A thin post standing in the middle of the cell
+    Post,
+    // Two quads crossing diagonally through the cell, the texture is cut out around the plant
+    Cross,
 }
 impl BlockType {
-    pub const MAX_ID: u32 = 6;
+    pub const MAX_ID: u32 = 16;
 
     pub fn get_config(&self) -> BlockTypeConfigs {
         BlockTypeConfigs::get(*self)
@@ -77,6 +203,32 @@ impl BlockType {
     pub fn to_id(&self) -> u32 {
         self.get_config().id
     }
+    // Whether a block of this type next to a `face_owner` block hides the face between them.
+    // Translucent blocks only hide faces of their own type (water to water, glass to glass).
+    // Cutout blocks don't hide any, not even each other's, the faces behind show through their holes
+    pub fn hides_face_of(&self, face_owner: BlockType) -> bool {
+        self.is_full_cube()
+            && !self.is_cutout()
+            && (!self.get_config().is_translucent || *self == face_owner)
+    }
+    // Opaque blocks with transparent texels in their texture, the main pass discards them
+    pub fn is_cutout(&self) -> bool {
+        matches!(self, BlockType::Leaf | BlockType::BirchLeaf)
+    }
+    pub fn shape(&self) -> BlockShape {
+        match self {
+            BlockType::Torch => BlockShape::Post,
+            BlockType::DeadBush => BlockShape::Cross,
+            _ => BlockShape::Cube,
+        }
+    }
+    pub fn is_full_cube(&self) -> bool {
+        self.shape() == BlockShape::Cube
+    }
+    // Whether the player collides with blocks of this type
+    pub fn has_collision(&self) -> bool {
+        *self != BlockType::Water && self.is_full_cube()
+    }
     pub fn from_id(id: u32) -> BlockType {
         match id {
             0 => Self::Grass,
@@ -86,6 +238,16 @@ impl BlockType {
             4 => Self::Leaf,
             5 => Self::Stone,
             6 => Self::Sand,
+            7 => Self::Glass,
+            8 => Self::Snow,
+            9 => Self::CoalOre,
+            10 => Self::IronOre,
+            11 => Self::Torch,
+            12 => Self::Cactus,
+            13 => Self::DeadBush,
+            14 => Self::BirchWood,
+            15 => Self::BirchLeaf,
+            16 => Self::Bedrock,
             _ => panic!("Invalid id"),
         }
     }
@@ -96,10 +258,13 @@ fn calc_scalar(y: u32, t: Threshold) -> f32 {
 // Threshold: ( lowerbound , upperbound )
 type Threshold = [u32; 2];
 const STONE_THRESHOLD: Threshold = [15, 24];
+// Columns topping out from here up get snow in (almost) every biome, it's past the stone band so
+// peaks are snow over stone
+pub const SNOW_LINE: u32 = STONE_THRESHOLD[1] + 1;
 const SAND_THRESHOLD: Threshold = [WATER_HEIGHT_LEVEL as u32, WATER_HEIGHT_LEVEL as u32 + 2];
 impl BlockType {
-    pub fn from_position(x: u32, y: u32, z: u32) -> BlockType {
-        let mut rng = StdRng::seed_from_u64(RNG_SEED + (y * x * z) as u64);
+    pub fn from_position(x: u32, y: u32, z: u32, seed: u64) -> BlockType {
+        let mut rng = StdRng::seed_from_u64(seed.wrapping_add((y * x * z) as u64));
 
         if y <= SAND_THRESHOLD[0] {
             BlockType::Sand
@@ -130,37 +295,126 @@ impl BlockType {
 const TEXTURE_SIZE: u32 = 256;
 const BLOCK_PER_ROW: u32 = 8;
 // 32px per block
-const BLOCK_OFFSET: u32 = TEXTURE_SIZE / BLOCK_PER_ROW;
-const BLOCK_OFFSET_NORMALIZED: f32 = BLOCK_OFFSET as f32 / TEXTURE_SIZE as f32;
-
-fn get_base_coords(config: &BlockTypeConfigs, face_dir: FaceDirections) -> glam::Vec2 {
-    let face_offset = match face_dir {
-        FaceDirections::Top => config.textures[1],
-        FaceDirections::Bottom => config.textures[2],
-        _ => config.textures[0],
-    };
-    let y_offset = (face_offset.0 / BLOCK_PER_ROW) as f32;
-    let x_offset = (face_offset.0 % BLOCK_PER_ROW) as f32;
-
-    let low_bound = y_offset * BLOCK_OFFSET_NORMALIZED + BLOCK_OFFSET_NORMALIZED;
-    let left_bound = x_offset * BLOCK_OFFSET_NORMALIZED;
-    glam::vec2(left_bound, low_bound)
+pub(crate) const BLOCK_OFFSET: u32 = TEXTURE_SIZE / BLOCK_PER_ROW;
+pub(crate) const BLOCK_OFFSET_NORMALIZED: f32 = BLOCK_OFFSET as f32 / TEXTURE_SIZE as f32;
+// Texels kept between the uvs and the edges of a tile, so linear filtering doesn't reach the
+// next tiles. The shader insets by half a texel of the mip level it samples instead
+const TILE_INSET_TEXELS: f32 = 0.5;
+pub(crate) const TILE_INSET: f32 =
+    TILE_INSET_TEXELS * BLOCK_OFFSET_NORMALIZED / BLOCK_OFFSET as f32;
+
+// Corners of a face in the order of its vertices, v goes down the texture
+const FACE_UVS: [[f32; 2]; 4] = [[0.0, 1.0], [0.0, 0.0], [1.0, 0.0], [1.0, 1.0]];
+
+// Top left corner of a tile of the atlas, the tiles are the layers of the block textures in order
+pub(crate) fn tile_origin(tile: u32) -> [f32; 2] {
+    [
+        (tile % BLOCK_PER_ROW) as f32 * BLOCK_OFFSET_NORMALIZED,
+        (tile / BLOCK_PER_ROW) as f32 * BLOCK_OFFSET_NORMALIZED,
+    ]
 }
-fn get_tex_coords(config: &BlockTypeConfigs, face_dir: FaceDirections) -> [[f32; 2]; 4] {
-    let bc = get_base_coords(config, face_dir);
+// Where a uv on a layer is in tex_atlas.png, 0 and 1 land `TILE_INSET` inside the tile's edges
+pub(crate) fn atlas_coords(layer: u32, uv: [f32; 2]) -> [f32; 2] {
+    let origin = tile_origin(layer);
+    let size = BLOCK_OFFSET_NORMALIZED - 2.0 * TILE_INSET;
     [
-        [bc.x, bc.y],
-        [bc.x, bc.y - BLOCK_OFFSET_NORMALIZED],
-        [
-            bc.x + BLOCK_OFFSET_NORMALIZED,
-            bc.y - BLOCK_OFFSET_NORMALIZED,
-        ],
-        [bc.x + BLOCK_OFFSET_NORMALIZED, bc.y],
+        origin[0] + TILE_INSET + uv[0] * size,
+        origin[1] + TILE_INSET + uv[1] * size,
     ]
 }
 
+impl BlockType {
+    // Layer of the block textures (tile of the atlas) shown on the face
+    pub fn get_texture_layer(&self, face_dir: FaceDirections) -> u32 {
+        let textures = self.get_config().textures;
+        let texture = match face_dir {
+            FaceDirections::Top => textures[1],
+            FaceDirections::Bottom => textures[2],
+            _ => textures[0],
+        };
+        texture.0
+    }
+}
+
 impl TexturedBlock for BlockType {
-    fn get_texcoords(&self, face_dir: FaceDirections) -> [[f32; 2]; 4] {
-        get_tex_coords(&self.get_config(), face_dir)
+    fn get_texcoords(&self, face_dir: FaceDirections) -> (u32, [[f32; 2]; 4]) {
+        (self.get_texture_layer(face_dir), FACE_UVS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{atlas_coords, tile_origin, BlockType, BLOCK_OFFSET_NORMALIZED, TILE_INSET};
+    use crate::blocks::block::{FaceDirections, TexturedBlock};
+
+    #[test]
+    fn should_only_cull_glass_faces_against_glass_or_opaque_blocks() {
+        // Glass next to glass, like water next to water
+        assert!(BlockType::Glass.hides_face_of(BlockType::Glass));
+        assert!(BlockType::Water.hides_face_of(BlockType::Water));
+        // Opaque faces behind glass are kept
+        assert!(!BlockType::Glass.hides_face_of(BlockType::Stone));
+        // Glass above water, both surfaces are kept
+        assert!(!BlockType::Water.hides_face_of(BlockType::Glass));
+        assert!(!BlockType::Glass.hides_face_of(BlockType::Water));
+        assert!(BlockType::Stone.hides_face_of(BlockType::Glass));
+    }
+
+    #[test]
+    fn should_keep_the_faces_between_leaves_and_cull_the_ones_between_grass() {
+        assert!(BlockType::Grass.hides_face_of(BlockType::Grass));
+        assert!(!BlockType::Leaf.hides_face_of(BlockType::Leaf));
+        assert!(!BlockType::BirchLeaf.hides_face_of(BlockType::Leaf));
+        // The trunk shows through the leaves, but not the other way around
+        assert!(!BlockType::Leaf.hides_face_of(BlockType::Wood));
+        assert!(BlockType::Wood.hides_face_of(BlockType::Leaf));
+        // Still solid and opaque, it's drawn in the main pass
+        assert!(BlockType::Leaf.is_full_cube() && BlockType::Leaf.has_collision());
+        assert!(!BlockType::Leaf.get_config().is_translucent);
+    }
+
+    #[test]
+    fn should_not_hide_faces_or_collide_with_torches() {
+        assert!(!BlockType::Torch.is_full_cube());
+        assert!(!BlockType::Torch.has_collision());
+        assert!(!BlockType::Water.has_collision());
+        assert!(BlockType::Stone.has_collision());
+        assert!(!BlockType::Torch.hides_face_of(BlockType::Stone));
+        assert!(!BlockType::Torch.hides_face_of(BlockType::Torch));
+        // Dead bushes neither, cacti are solid
+        assert!(!BlockType::DeadBush.has_collision());
+        assert!(!BlockType::DeadBush.hides_face_of(BlockType::Sand));
+        assert!(BlockType::Cactus.has_collision());
+    }
+
+    #[test]
+    fn should_round_trip_every_block_id() {
+        for id in 0..=BlockType::MAX_ID {
+            assert_eq!(BlockType::from_id(id).to_id(), id);
+        }
+        assert_eq!(BlockType::from_id(7), BlockType::Glass);
+        assert!(BlockType::Glass.get_config().is_translucent);
+    }
+
+    #[test]
+    fn should_keep_the_atlas_coords_inside_the_tiles() {
+        // Half a texel of the 256px atlas
+        assert_eq!(TILE_INSET, 0.5 / 256.0);
+        for id in 0..=BlockType::MAX_ID {
+            let block_type = BlockType::from_id(id);
+            for face in FaceDirections::all() {
+                let (layer, uvs) = block_type.get_texcoords(face);
+                let [left, top] = tile_origin(layer);
+                let (right, bottom) = (
+                    left + BLOCK_OFFSET_NORMALIZED,
+                    top + BLOCK_OFFSET_NORMALIZED,
+                );
+                for uv in uvs {
+                    let [u, v] = atlas_coords(layer, uv);
+                    assert!(u >= left + TILE_INSET - 1e-6 && u <= right - TILE_INSET + 1e-6);
+                    assert!(v >= top + TILE_INSET - 1e-6 && v <= bottom - TILE_INSET + 1e-6);
+                }
+            }
+        }
     }
 }
@@ -1,25 +1,309 @@
-use crate::persistence::{Loadable, Saveable};
+use crate::biome::Biome;
+use crate::collision::CollisionBox;
+use crate::lighting::{ChunkLight, GpuLighting, SkyLight, MAX_LIGHT};
+use crate::mesh_cache::{index_bytes, index_format, MeshBuffer, MeshCache};
+use crate::persistence::{
+    read_region_chunk, read_save_file, read_u16, read_u32, read_u8, save_dir, write_region_chunk,
+    Loadable, Saveable, WorldArchive,
+};
 use crate::player::Player;
-use crate::utils::math_utils::Plane;
-use crate::world::{ChunkMap, RNG_SEED, WATER_HEIGHT_LEVEL};
+use crate::structures::covered_with_snow;
+use crate::utils::noise::perlin_noise_3d;
+use crate::utils::threadpool::ThreadPool;
+use crate::world::{ChunkMap, CAVE_FREQUENCY, CAVE_THRESHOLD, WATER_HEIGHT_LEVEL};
 use crate::{
     blocks::{
-        block::{Block, BlockVertexData, FaceDirections},
-        block_type::BlockType,
+        block::{Block, BlockVertexData, BlockVertexPacked, FaceDirections},
+        block_type::{atlas_coords, BlockShape, BlockType},
     },
-    structures::Structure,
-    world::{NoiseData, CHUNK_SIZE, MAX_TREES_PER_CHUNK, NOISE_CHUNK_PER_ROW, NOISE_SIZE},
+    world::{NoiseData, NOISE_SIZE, WORLD_MAX_Y, WORLD_MIN_Y},
 };
 
 use glam::Vec3;
 use rand::rngs::StdRng;
 use rand::{Rng, SeedableRng};
 use std::any::Any;
+use std::collections::BTreeMap;
 use std::error::Error;
-use std::sync::{Arc, RwLock};
+use std::fmt::Write;
+use std::io::Read;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, RwLock};
 use wgpu::util::DeviceExt;
 
-pub type BlockVec = Arc<RwLock<Vec<Vec<Option<Arc<RwLock<Block>>>>>>>;
+pub type BlockVec = Arc<RwLock<ChunkBlocks>>;
+
+const BLOCKS_DATA_POOL_SIZE: usize = 4;
+const WATER_UNDER_GLASS_OFFSET: f32 = 0.02;
+// The water surface under the sky is lower than a full block, so shores read against the sand
+pub const WATER_SURFACE_DROP: f32 = 1.0 / 8.0;
+// Ground at the highest noise, the room left under WORLD_MAX_Y is for the trees on top
+const TERRAIN_MAX_Y: u32 = WORLD_MAX_Y as u32 - 32;
+// The bottom layer is bedrock. Chance of the stone turning into bedrock on the layers above it,
+// from y = 1 up
+const BEDROCK_CHANCE: [f32; 2] = [0.5, 0.25];
+// Blocks deeper than this under the surface are stone
+const STONE_DEPTH: u32 = 1;
+// Ore, vein attempts per chunk, max blocks in a vein and the depth under the surface from which
+// every attempt is kept. Shallower attempts are only kept with a depth / full_depth chance
+const ORES: [(BlockType, u32, usize, u32); 2] = [
+    (BlockType::CoalOre, 12, 6, 2),
+    (BlockType::IronOre, 8, 4, 5),
+];
+// Coarsest mesh detail, level n merges cells of 2^n blocks per side
+pub const MAX_LOD: u8 = 2;
+const NEIGHBOUR_OFFSETS: [(i32, i32, i32); 6] = [
+    (1, 0, 0),
+    (-1, 0, 0),
+    (0, 1, 0),
+    (0, -1, 0),
+    (0, 0, 1),
+    (0, 0, -1),
+];
+lazy_static! {
+    // Separate from the world pool, chunks are generated from its jobs and they wait for these
+    static ref BLOCKS_DATA_POOL: ThreadPool = ThreadPool::new(BLOCKS_DATA_POOL_SIZE);
+}
+
+// Indices into a palette packed in u64 words, `bits` per index without crossing into the next word.
+// With 0 bits every index is 0 and nothing is stored
+#[derive(Debug, Clone)]
+struct PackedIndices {
+    bits: u32,
+    len: usize,
+    words: Vec<u64>,
+}
+
+impl PackedIndices {
+    fn new(bits: u32, len: usize) -> Self {
+        let mut indices = Self {
+            bits,
+            len: 0,
+            words: vec![],
+        };
+        indices.resize(len);
+        indices
+    }
+    // Bits to tell apart the entries of a palette of `len` types
+    fn bits_for(len: usize) -> u32 {
+        if len <= 1 {
+            0
+        } else {
+            usize::BITS - (len - 1).leading_zeros()
+        }
+    }
+    fn per_word(&self) -> usize {
+        (u64::BITS / self.bits) as usize
+    }
+    fn get(&self, i: usize) -> usize {
+        if self.bits == 0 {
+            return 0;
+        }
+        let shift = (i % self.per_word()) as u32 * self.bits;
+        ((self.words[i / self.per_word()] >> shift) & ((1 << self.bits) - 1)) as usize
+    }
+    fn set(&mut self, i: usize, value: usize) {
+        let shift = (i % self.per_word()) as u32 * self.bits;
+        let mask = ((1u64 << self.bits) - 1) << shift;
+        let word_index = i / self.per_word();
+        let word = &mut self.words[word_index];
+        *word = (*word & !mask) | ((value as u64) << shift);
+    }
+    // The added indices are 0
+    fn resize(&mut self, len: usize) {
+        self.len = len;
+        if self.bits > 0 {
+            self.words.resize(len.div_ceil(self.per_word()), 0);
+        }
+    }
+    fn with_bits(&self, bits: u32) -> Self {
+        let mut indices = Self::new(bits, self.len);
+        for i in 0..self.len {
+            indices.set(i, self.get(i));
+        }
+        indices
+    }
+}
+
+// Block types of a chunk indexed by (x, y, z), as a palette of the types in the chunk and the
+// palette index of every block, packed with as few bits as the palette needs. The y layers are
+// stacked, so it only grows as tall as the highest block. The `Block` data (positions and collision
+// box) isn't stored, it's derived from the index when needed
+#[derive(Debug, Clone)]
+pub struct ChunkBlocks {
+    chunk: (i32, i32),
+    // Width and depth, the world's chunk size
+    chunk_size: u32,
+    height: u32,
+    // None is air. Entries that no block uses anymore are reused for the next new type
+    palette: Vec<Option<BlockType>>,
+    // Blocks of each palette entry
+    counts: Vec<usize>,
+    indices: PackedIndices,
+}
+
+impl ChunkBlocks {
+    pub fn new(chunk: (i32, i32), chunk_size: u32) -> Self {
+        Self {
+            chunk,
+            chunk_size,
+            height: 0,
+            palette: vec![],
+            counts: vec![],
+            indices: PackedIndices::new(0, 0),
+        }
+    }
+    pub fn chunk(&self) -> (i32, i32) {
+        self.chunk
+    }
+    pub fn chunk_size(&self) -> u32 {
+        self.chunk_size
+    }
+    // Blocks in a y layer
+    fn layer_size(&self) -> usize {
+        (self.chunk_size * self.chunk_size) as usize
+    }
+    fn index(&self, x: u32, y: u32, z: u32) -> usize {
+        y as usize * self.layer_size() + (x * self.chunk_size + z) as usize
+    }
+    // Number of y layers stored, every block is below it
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+    // Different block types (and air) in the chunk, a single one for a uniform chunk
+    pub fn palette_len(&self) -> usize {
+        self.counts.iter().filter(|count| **count > 0).count()
+    }
+    fn type_of(&self, i: usize) -> Option<BlockType> {
+        self.palette[self.indices.get(i)]
+    }
+    pub fn get(&self, x: u32, y: u32, z: u32) -> Option<BlockType> {
+        if x >= self.chunk_size || z >= self.chunk_size || y >= self.height {
+            return None;
+        }
+        self.type_of(self.index(x, y, z))
+    }
+    pub fn set(&mut self, x: u32, y: u32, z: u32, block_type: Option<BlockType>) {
+        assert!(
+            x < self.chunk_size && z < self.chunk_size,
+            "Cannot set oob block"
+        );
+        assert!(y <= WORLD_MAX_Y as u32, "Cannot set a block over the world");
+        if y >= self.height() {
+            if block_type.is_none() {
+                return;
+            }
+            self.grow(y + 1);
+        }
+        let i = self.index(x, y, z);
+        let old = self.indices.get(i);
+        if self.palette[old] == block_type {
+            return;
+        }
+        let new = self.palette_index(block_type);
+        self.indices.set(i, new);
+        self.counts[new] += 1;
+        self.counts[old] -= 1;
+        if self.counts[old] == 0 && self.palette_len() == 1 {
+            self.collapse();
+        }
+    }
+    // Stacks air layers up to `height`
+    fn grow(&mut self, height: u32) {
+        let len = self.indices.len;
+        let added = (height - self.height) as usize * self.layer_size();
+        let air = self.palette_index(None);
+        self.indices.resize(len + added);
+        if air != 0 {
+            for i in len..len + added {
+                self.indices.set(i, air);
+            }
+        }
+        self.counts[air] += added;
+        self.height = height;
+    }
+    // Adds the type to the palette if it's not there, repacking the indices when it needs more bits
+    fn palette_index(&mut self, block_type: Option<BlockType>) -> usize {
+        if let Some(i) =
+            (0..self.palette.len()).find(|i| self.palette[*i] == block_type && self.counts[*i] > 0)
+        {
+            return i;
+        }
+        if let Some(i) = self.counts.iter().position(|count| *count == 0) {
+            self.palette[i] = block_type;
+            return i;
+        }
+        self.palette.push(block_type);
+        self.counts.push(0);
+        let bits = PackedIndices::bits_for(self.palette.len());
+        if bits > self.indices.bits {
+            self.indices = self.indices.with_bits(bits);
+        }
+        self.palette.len() - 1
+    }
+    // Every block is the same type, only it is kept in the palette and no index is stored
+    fn collapse(&mut self) {
+        let used = self.counts.iter().position(|count| *count > 0).unwrap();
+        self.palette = vec![self.palette[used]];
+        self.counts = vec![self.counts[used]];
+        self.indices = PackedIndices::new(0, self.indices.len);
+    }
+    // Position relative to the chunk, None outside of it
+    pub fn type_at(&self, position: &Vec3) -> Option<BlockType> {
+        if position.min_element() < 0.0 {
+            return None;
+        }
+        self.get(position.x as u32, position.y as u32, position.z as u32)
+    }
+    pub fn set_at(&mut self, position: &Vec3, block_type: Option<BlockType>) {
+        self.set(
+            position.x as u32,
+            position.y as u32,
+            position.z as u32,
+            block_type,
+        );
+    }
+    // One over the highest block of the column, 0 if it's empty
+    pub fn column_height(&self, x: u32, z: u32) -> u32 {
+        (0..self.height())
+            .rev()
+            .find(|y| self.get(x, *y, z).is_some())
+            .map_or(0, |y| y + 1)
+    }
+    // Every block with its position relative to the chunk, from the bottom layer up
+    pub fn iter(&self) -> impl Iterator<Item = (Vec3, BlockType)> + '_ {
+        (0..self.indices.len).filter_map(|i| {
+            let block_type = self.type_of(i)?;
+            let column = (i % self.layer_size()) as u32;
+            let position = glam::vec3(
+                (column / self.chunk_size) as f32,
+                (i / self.layer_size()) as f32,
+                (column % self.chunk_size) as f32,
+            );
+            Some((position, block_type))
+        })
+    }
+    pub fn block_at(&self, position: &Vec3) -> Option<Block> {
+        let block_type = self.type_at(position)?;
+        Some(Block::new(
+            position.floor(),
+            self.chunk,
+            block_type,
+            self.chunk_size,
+        ))
+    }
+}
+
+// The same blocks, whatever the order of the palettes
+impl PartialEq for ChunkBlocks {
+    fn eq(&self, other: &Self) -> bool {
+        self.chunk == other.chunk
+            && self.chunk_size == other.chunk_size
+            && self.height == other.height
+            && (0..self.indices.len).all(|i| self.type_of(i) == other.type_of(i))
+    }
+}
 
 #[derive(Debug)]
 pub struct Chunk {
@@ -33,261 +317,633 @@ pub struct Chunk {
     pub noise_data: Arc<NoiseData>,
     pub chunk_bind_group: wgpu::BindGroup,
     pub chunk_position_buffer: wgpu::Buffer,
-    pub chunk_index_buffer: Option<wgpu::Buffer>,
-    pub chunk_vertex_buffer: Option<wgpu::Buffer>,
-    pub chunk_water_vertex_buffer: Option<wgpu::Buffer>,
-    pub chunk_water_index_buffer: Option<wgpu::Buffer>,
+    // Written in place by the next mesh while it fits, unless shared by the mesh cache
+    pub chunk_index_buffer: Option<MeshBuffer>,
+    pub chunk_vertex_buffer: Option<MeshBuffer>,
+    pub chunk_water_vertex_buffer: Option<MeshBuffer>,
+    pub chunk_water_index_buffer: Option<MeshBuffer>,
+    // 16 bit unless the mesh has too many vertices, see `mesh_cache::index_format`
+    pub index_format: wgpu::IndexFormat,
+    pub water_index_format: wgpu::IndexFormat,
+    // Kept on the cpu so the translucent faces can be sorted by distance
+    pub water_faces: Vec<TranslucentFace>,
     pub outside_blocks: Vec<Arc<RwLock<Block>>>,
     pub visible: bool,
-    pub modified: bool, // if true, it will be saved
+    // Highest y with a block, it doesn't shrink when blocks are removed
+    pub max_height: u32,
+    pub modified: AtomicBool, // if true, it will be saved
+    // Edited since the last mesh, the world queues a new one
+    pub dirty: bool,
+    // Last uploaded mesh, kept to replace the faces around single edits
+    pub mesh: Option<ChunkMesh>,
+    // Region files are rewritten on save, the chunks of the region that are only in the archive are kept
+    pub archive: Option<Arc<WorldArchive>>,
+    // Where its region file is written, `save_dir()` when the chunk is created
+    pub save_dir: String,
+    // Block light is spread with the compute shader when set, see `World::gpu_lighting`
+    pub gpu_lighting: Option<Arc<GpuLighting>>,
 }
 
 impl Chunk {
-    pub fn add_block(&mut self, block: Arc<RwLock<Block>>, modify_status: bool) {
-        let block_borrow = block.read().unwrap();
-        let block_position = block_borrow.position;
-        std::mem::drop(block_borrow);
-        let mut blocks_borrow = self.blocks.write().unwrap();
-
-        let y_blocks = blocks_borrow
-            .get_mut(((block_position.x * CHUNK_SIZE as f32) + block_position.z) as usize)
-            .expect("Cannot add oob block");
-
-        if block_position.y as usize >= y_blocks.len() {
-            y_blocks.resize(block_position.y as usize + 1, None);
-        }
-
-        y_blocks[block_position.y as usize] = Some(block);
+    // Compatibility with the blocks stored one by one, only the position and type of `block` are kept
+    pub fn add_block(
+        &mut self,
+        block: Arc<RwLock<Block>>,
+        modify_status: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        let block = block.read().unwrap();
+        self.set_block(&block.position, block.block_type, modify_status)
+    }
+    // Errors outside of the world's height, the chunk isn't changed
+    pub fn set_block(
+        &mut self,
+        position: &Vec3,
+        block_type: BlockType,
+        modify_status: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        Chunk::check_bounds(position)?;
+        self.max_height = self.max_height.max(position.y as u32);
+        self.blocks
+            .write()
+            .unwrap()
+            .set_at(position, Some(block_type));
         if modify_status {
-            self.modified = true;
+            self.modified.store(true, Ordering::Relaxed);
+            self.dirty = true;
         }
+        Ok(())
     }
-    pub fn remove_block(&mut self, block_r_position: &Vec3) {
-        let mut blocks_borrow = self.blocks.write().unwrap();
-        let y_blocks = blocks_borrow
-            .get_mut(((block_r_position.x * CHUNK_SIZE as f32) + block_r_position.z) as usize)
-            .expect("Cannot delete oob block");
-        y_blocks[block_r_position.y as usize] = None;
-        self.modified = true;
+    pub fn remove_block(&mut self, block_r_position: &Vec3) -> Result<(), Box<dyn Error>> {
+        Chunk::check_bounds(block_r_position)?;
+        self.blocks.write().unwrap().set_at(block_r_position, None);
+        self.modified.store(true, Ordering::Relaxed);
+        self.dirty = true;
+        Ok(())
+    }
+    // Width and depth of the chunk, the world's chunk size
+    pub fn chunk_size(&self) -> u32 {
+        self.noise_data.chunk_size
+    }
+    pub fn is_modified(&self) -> bool {
+        self.modified.load(Ordering::Relaxed)
     }
     pub fn block_type_at(&self, position: &glam::Vec3) -> Option<BlockType> {
-        let block = self.get_block_at_relative(position)?;
-        let block_type = block.read().unwrap().block_type;
-        Some(block_type)
+        self.blocks.read().unwrap().type_at(position)
     }
     pub fn exists_block_at(&self, position: &glam::Vec3) -> bool {
-        if let Some(y_blocks) = self
-            .blocks
-            .read()
-            .unwrap()
-            .get(((position.x as u32 * CHUNK_SIZE) + position.z as u32) as usize)
-        {
-            if let Some(block_opt) = y_blocks.get(position.y as usize) {
-                if block_opt.is_some() {
-                    return true;
+        self.block_type_at(position).is_some()
+    }
+    // Compatibility with the blocks stored one by one. The block is built from the chunk data, so
+    // writing to it doesn't change the chunk
+    pub fn get_block_at_relative(&self, position: &glam::Vec3) -> Option<Arc<RwLock<Block>>> {
+        let block = self.blocks.read().unwrap().block_at(position)?;
+        Some(Arc::new(RwLock::new(block)))
+    }
+    pub fn is_outside_chunk(position: &glam::Vec3, chunk_size: u32) -> bool {
+        position.x < 0.0
+            || position.x >= chunk_size as f32
+            || position.z < 0.0
+            || position.z >= chunk_size as f32
+    }
+    // Below the world or over its height
+    pub fn is_outside_bounds(position: &glam::Vec3) -> bool {
+        position.y < WORLD_MIN_Y as f32 || position.y > WORLD_MAX_Y as f32
+    }
+    fn check_bounds(position: &glam::Vec3) -> Result<(), Box<dyn Error>> {
+        if Chunk::is_outside_bounds(position) {
+            return Err(format!(
+                "Block at y {} is outside of the world ({WORLD_MIN_Y} to {WORLD_MAX_Y})",
+                position.y
+            )
+            .into());
+        }
+        Ok(())
+    }
+    // Meshes the chunk and uploads it, the water buffers hold every translucent block (water and
+    // glass)
+    pub fn build_mesh(&mut self, other_chunks: ChunkMap, mesh_cache: Option<&MeshCache>) {
+        let mesh = self.build_mesh_data(other_chunks);
+        self.set_mesh(mesh, mesh_cache);
+    }
+    // Uploads a mesh built with `build_mesh_data`, meant to be called from the render thread
+    pub fn set_mesh(&mut self, mesh: ChunkMesh, mesh_cache: Option<&MeshCache>) {
+        self.upload_mesh(&mesh, mesh_cache);
+        self.indices = mesh.indices.len() as u32;
+        self.water_indices = mesh.water_indices.len() as u32;
+        self.index_format = index_format(mesh.vertex.len());
+        self.water_index_format = index_format(mesh.water_vertex.len());
+        self.water_faces = mesh.water_faces();
+        self.mesh = Some(mesh);
+    }
+    // Only touches the cpu, so it can run on any thread
+    pub fn build_mesh_data(&self, other_chunks: ChunkMap) -> ChunkMesh {
+        let mut mesh = ChunkMesh::default();
+        self.with_adjacent_blocks(other_chunks, |adjacent_blocks| {
+            let chunk_light = ChunkLight::new(
+                (self.x, self.y),
+                adjacent_blocks,
+                self.gpu_lighting.as_deref(),
+            );
+            let sky_light = SkyLight::new((self.x, self.y), adjacent_blocks);
+            Chunk::for_each_visible_face(
+                (self.x, self.y),
+                adjacent_blocks,
+                chunk_light.as_ref(),
+                &sky_light,
+                None,
+                |face| mesh.push_face(face),
+            );
+        });
+        mesh
+    }
+    // Same as `build_mesh_data`, but neighbouring opaque faces on the same plane with the same
+    // block type, light and a flat ao are merged into bigger quads that repeat the texture.
+    // Faces with an ao gradient (and translucent ones, sorted per face) are kept as they are
+    pub fn build_greedy_mesh_data(&self, other_chunks: ChunkMap) -> ChunkMesh {
+        let mut mesh = ChunkMesh::default();
+        // Mergeable faces by direction and plane, then by (v, u) so rows are scanned in order
+        let mut planes: BTreeMap<(FaceDirections, i32), BTreeMap<(i32, i32), MeshFace>> =
+            BTreeMap::new();
+        self.with_adjacent_blocks(other_chunks, |adjacent_blocks| {
+            let chunk_light = ChunkLight::new(
+                (self.x, self.y),
+                adjacent_blocks,
+                self.gpu_lighting.as_deref(),
+            );
+            let sky_light = SkyLight::new((self.x, self.y), adjacent_blocks);
+            Chunk::for_each_visible_face(
+                (self.x, self.y),
+                adjacent_blocks,
+                chunk_light.as_ref(),
+                &sky_light,
+                None,
+                |face| {
+                    if face.is_mergeable() {
+                        let (plane, cell) = face.plane_cell();
+                        planes
+                            .entry((face.direction, plane))
+                            .or_default()
+                            .insert(cell, face);
+                    } else {
+                        mesh.push_face(face);
+                    }
+                },
+            );
+        });
+        for mut cells in planes.into_values() {
+            while let Some(((v, u), face)) = cells.pop_first() {
+                let key = face.merge_key();
+                let same = |cells: &BTreeMap<(i32, i32), MeshFace>, v: i32, u: i32| {
+                    cells.get(&(v, u)).is_some_and(|f| f.merge_key() == key)
+                };
+                let mut width = 1;
+                while same(&cells, v, u + width) {
+                    width += 1;
+                }
+                let mut height = 1;
+                while (u..u + width).all(|u| same(&cells, v + height, u)) {
+                    height += 1;
+                }
+                for dv in 0..height {
+                    for du in 0..width {
+                        cells.remove(&(v + dv, u + du));
+                    }
                 }
+                mesh.push_face(face.stretched(width, height));
             }
         }
-        false
+        mesh.merged = true;
+        mesh
     }
-    pub fn get_block_at_relative(&self, position: &glam::Vec3) -> Option<Arc<RwLock<Block>>> {
-        if let Some(y_blocks) = self
-            .blocks
-            .read()
-            .unwrap()
-            .get(((position.x * CHUNK_SIZE as f32) + position.z) as usize)
-        {
-            if let Some(block) = y_blocks.get(position.y as usize)? {
-                return Some(Arc::clone(block));
+    // Coarse mesh of a far chunk: cells of 2^lod blocks per side (see `LodCells`) are meshed like
+    // blocks and their faces stretched over the cell, the texture repeats once per block. There's
+    // no ao, the light is the one in front of the cell's first block
+    pub fn build_lod_mesh_data(&self, other_chunks: ChunkMap, lod: u8) -> ChunkMesh {
+        let mut scale = 1 << lod.min(MAX_LOD);
+        // The cells have to tile the chunk
+        while !self.chunk_size().is_multiple_of(scale) {
+            scale /= 2;
+        }
+        let mut mesh = ChunkMesh {
+            merged: true,
+            lod,
+            ..Default::default()
+        };
+        self.with_adjacent_blocks(other_chunks, |adjacent_blocks| {
+            let chunk_light = ChunkLight::new(
+                (self.x, self.y),
+                adjacent_blocks,
+                self.gpu_lighting.as_deref(),
+            );
+            let sky_light = SkyLight::new((self.x, self.y), adjacent_blocks);
+            // The chunk's own blocks come first
+            let cells: Vec<((i32, i32), LodCells)> = adjacent_blocks
+                .iter()
+                .map(|(coords, blocks)| (*coords, LodCells::new(blocks, scale)))
+                .collect();
+            let own = &cells[0].1;
+            let size = own.size as i32;
+            for (cell, block_type) in own.iter() {
+                let position = cell.as_vec3() * scale as f32;
+                let block = Block::new(position, (self.x, self.y), block_type, self.chunk_size());
+                for face in FaceDirections::all().iter() {
+                    let normal = face.get_normal_vector();
+                    let front = cell + normal.as_ivec3();
+                    // Nothing sees the bottom of the world, and a neighbour that isn't loaded
+                    // counts as solid like in the full mesh
+                    if front.y * (scale as i32) < WORLD_MIN_Y {
+                        continue;
+                    }
+                    let front_chunk = (
+                        self.x + front.x.div_euclid(size),
+                        self.y + front.z.div_euclid(size),
+                    );
+                    let Some((_, front_cells)) = cells.iter().find(|(c, _)| *c == front_chunk)
+                    else {
+                        continue;
+                    };
+                    let front = front_cells.get(glam::ivec3(
+                        front.x.rem_euclid(size),
+                        front.y,
+                        front.z.rem_euclid(size),
+                    ));
+                    if front.is_some_and(|front| front.hides_face_of(block_type))
+                        || (block_type == BlockType::Water
+                            && *face != FaceDirections::Top
+                            && front.is_some())
+                    {
+                        continue;
+                    }
+
+                    let (mut vertex_data, index_data) =
+                        face.create_face_data(&block, adjacent_blocks);
+                    let lit_from = block.absolute_position
+                        + normal
+                            * if normal.max_element() > 0.0 {
+                                scale as f32
+                            } else {
+                                1.0
+                            };
+                    let light = chunk_light.as_ref().map_or(0, |light| light.at(lit_from));
+                    let sky_level = sky_light.at(lit_from);
+                    for vertex in vertex_data.iter_mut() {
+                        vertex.ao = 0.0;
+                        vertex.light = light as f32 / MAX_LIGHT as f32;
+                        vertex.sky_light = sky_level as f32 / MAX_LIGHT as f32;
+                    }
+                    let mut face = MeshFace {
+                        position,
+                        direction: *face,
+                        block_type,
+                        vertex: vertex_data,
+                        indices: index_data,
+                    }
+                    .scaled(scale as i32);
+                    if block_type == BlockType::Water {
+                        let above = own.get(cell + glam::IVec3::Y);
+                        shape_water(&mut face.vertex, position, above.is_none());
+                    }
+                    mesh.push_face(face);
+                }
             }
+        });
+        mesh
+    }
+    // Picks the mesher the world is configured with, far chunks (`lod` over 0) get a coarse mesh
+    pub fn mesh_data(&self, other_chunks: ChunkMap, greedy: bool, lod: u8) -> ChunkMesh {
+        match (lod, greedy) {
+            (0, true) => self.build_greedy_mesh_data(other_chunks),
+            (0, false) => self.build_mesh_data(other_chunks),
+            _ => self.build_lod_mesh_data(other_chunks, lod),
         }
-        None
     }
-    pub fn is_outside_chunk(position: &glam::Vec3) -> bool {
-        position.x < 0.0
-            || position.x >= CHUNK_SIZE as f32
-            || position.z < 0.0 || position.z >= CHUNK_SIZE as f32
+    // Detail level of the uploaded mesh, None before the first one
+    pub fn lod(&self) -> Option<u8> {
+        self.mesh.as_ref().map(|mesh| mesh.lod)
     }
-    pub fn is_outside_bounds(position: &glam::Vec3) -> bool {
-        position.y < 0.0
-    }
-    /*
-    Return tuple:
-    0: vertex indices     , 1: water vertex indices
-    2: vertex buffer      , 3: index buffer
-    4: water vertex buffer, 5: water index buffer */
-    pub fn build_mesh(
+    // Replaces the faces of the blocks around an edit at `position` (relative) and uploads the
+    // mesh again, only their visibility and ao can change. Returns false when the whole chunk has
+    // to be meshed: there's no mesh yet, its quads were merged, there's block light around or the
+    // edit lets the sun further down (or stops it) than the blocks around it
+    pub fn remesh_around(
+        &mut self,
+        position: &Vec3,
+        other_chunks: ChunkMap,
+        mesh_cache: Option<&MeshCache>,
+    ) -> bool {
+        let Some(mut mesh) = self.mesh.take() else {
+            return false;
+        };
+        let lit = mesh
+            .vertex
+            .iter()
+            .chain(mesh.water_vertex.iter())
+            .any(|v| v.light > 0.0);
+        let replaced = !mesh.merged
+            && !lit
+            && self.with_adjacent_blocks(other_chunks, |adjacent_blocks| {
+                // Only whether some light reaches the chunk, the cpu is enough
+                if ChunkLight::new((self.x, self.y), adjacent_blocks, None).is_some() {
+                    return false;
+                }
+                // The chunk's own blocks come first
+                let below = adjacent_blocks[0].1.type_at(&(*position - Vec3::Y));
+                let shades_below = below.is_some_and(|block_type| {
+                    !block_type.get_config().is_translucent && block_type.is_full_cube()
+                });
+                if position.y >= 1.0 && !shades_below {
+                    return false;
+                }
+                let sky_light = SkyLight::new((self.x, self.y), adjacent_blocks);
+                mesh.retain_faces(|block| (*block - *position).abs().max_element() > 1.0);
+                Chunk::for_each_visible_face(
+                    (self.x, self.y),
+                    adjacent_blocks,
+                    None,
+                    &sky_light,
+                    Some(*position),
+                    |face| mesh.push_face(face),
+                );
+                true
+            });
+        if replaced {
+            self.set_mesh(mesh, mesh_cache);
+        } else {
+            self.mesh = Some(mesh);
+        }
+        replaced
+    }
+    // Writes the faces `build_mesh_data` would mesh as a Wavefront OBJ, the neighbours hide the
+    // faces on the borders like in the game
+    pub fn export_obj(
         &self,
         other_chunks: ChunkMap,
-    ) -> (
-        u32,
-        u32,
-        wgpu::Buffer,
-        wgpu::Buffer,
-        wgpu::Buffer,
-        wgpu::Buffer,
-    ) {
-        let mut water_vertex: Vec<BlockVertexData> = vec![];
-        let mut water_indices: Vec<u32> = vec![];
-        let mut vertex: Vec<BlockVertexData> = vec![];
-        let mut indices: Vec<u32> = vec![];
-        let mut adjacent_chunks: Vec<((i32, i32), BlockVec)> = vec![];
+        path: impl AsRef<Path>,
+    ) -> Result<(), Box<dyn Error>> {
+        let obj = self.with_adjacent_blocks(other_chunks, |adjacent_blocks| {
+            Chunk::obj((self.x, self.y), adjacent_blocks)
+        });
+        std::fs::write(path, obj)?;
+        Ok(())
+    }
+    // World positions, the atlas uvs and the normals of every visible face, grouped by block
+    // type. A chunk without faces is a file with only the header
+    fn obj(chunk: (i32, i32), adjacent_blocks: &[((i32, i32), &ChunkBlocks)]) -> String {
+        let mut groups: BTreeMap<u32, (BlockType, Vec<MeshFace>)> = BTreeMap::new();
+        let sky_light = SkyLight::new(chunk, adjacent_blocks);
+        Chunk::for_each_visible_face(chunk, adjacent_blocks, None, &sky_light, None, |face| {
+            let id = face.block_type.get_config().id;
+            groups
+                .entry(id)
+                .or_insert_with(|| (face.block_type, vec![]))
+                .1
+                .push(face)
+        });
+
+        // The chunk's own blocks come first
+        let chunk_size = adjacent_blocks[0].1.chunk_size() as i32;
+        let offset = glam::vec3(
+            (chunk.0 * chunk_size) as f32,
+            0.0,
+            (chunk.1 * chunk_size) as f32,
+        );
+        let mut obj = format!("# Chunk {}, {}\n", chunk.0, chunk.1);
+        // OBJ indices start at 1 and count every vertex before them in the file
+        let mut first_vertex = 1;
+        for (block_type, faces) in groups.into_values() {
+            writeln!(obj, "g {block_type:?}").unwrap();
+            for face in faces {
+                for vertex in face.vertex.iter() {
+                    let position = Vec3::from(vertex.position) + offset;
+                    let [u, v] = atlas_coords(vertex.layer, vertex.tex_coords);
+                    let [nx, ny, nz] = vertex.normal;
+                    writeln!(obj, "v {} {} {}", position.x, position.y, position.z).unwrap();
+                    // The atlas starts at the top, OBJ textures at the bottom
+                    writeln!(obj, "vt {u} {}", 1.0 - v).unwrap();
+                    writeln!(obj, "vn {nx} {ny} {nz}").unwrap();
+                }
+                for triangle in face.indices.chunks_exact(3) {
+                    let [a, b, c] = [0, 1, 2].map(|i| triangle[i] + first_vertex);
+                    writeln!(obj, "f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}").unwrap();
+                }
+                first_vertex += face.vertex.len() as u32;
+            }
+        }
+        obj
+    }
+    // Runs `f` with the blocks of this chunk and the loaded ones around it, each locked once
+    fn with_adjacent_blocks<R>(
+        &self,
+        other_chunks: ChunkMap,
+        f: impl FnOnce(&[((i32, i32), &ChunkBlocks)]) -> R,
+    ) -> R {
+        // The chunk itself may be locked by the caller, its blocks are taken from `self`
+        let mut adjacent_chunks: Vec<((i32, i32), BlockVec)> =
+            vec![((self.x, self.y), self.blocks.clone())];
 
         for x in self.x - 1..=self.x + 1 {
             for y in self.y - 1..=self.y + 1 {
+                if (x, y) == (self.x, self.y) {
+                    continue;
+                }
                 if let Some(chunk) = other_chunks.read().unwrap().get(&(x, y)) {
                     let chunk_read = chunk.read().unwrap();
                     adjacent_chunks.push(((x, y), chunk_read.blocks.clone()));
                 }
             }
         }
+        let adjacent_guards: Vec<_> = adjacent_chunks
+            .iter()
+            .map(|(coords, blocks)| (*coords, blocks.read().unwrap()))
+            .collect();
+        let adjacent_blocks: Vec<((i32, i32), &ChunkBlocks)> = adjacent_guards
+            .iter()
+            .map(|(coords, blocks)| (*coords, &**blocks))
+            .collect();
+        f(&adjacent_blocks)
+    }
+    // Visible faces of the whole `chunk`, or only of the blocks within one block of `around`. Only
+    // reads the blocks, the chunk doesn't need to be on the gpu
+    fn for_each_visible_face(
+        chunk: (i32, i32),
+        adjacent_blocks: &[((i32, i32), &ChunkBlocks)],
+        chunk_light: Option<&ChunkLight>,
+        sky_light: &SkyLight,
+        around: Option<Vec3>,
+        mut on_face: impl FnMut(MeshFace),
+    ) {
+        let blocks_at = |coords: (i32, i32)| {
+            adjacent_blocks
+                .iter()
+                .find_map(|(c, blocks)| (*c == coords).then_some(*blocks))
+        };
+        let blocks = blocks_at(chunk).unwrap();
+        let chunk_size = blocks.chunk_size();
+        let cells: Box<dyn Iterator<Item = (Vec3, BlockType)>> = match around {
+            Some(center) => Box::new(
+                (-1..=1)
+                    .flat_map(|x| (-1..=1).flat_map(move |y| (-1..=1).map(move |z| (x, y, z))))
+                    .filter_map(move |(x, y, z)| {
+                        let position = center + glam::vec3(x as f32, y as f32, z as f32);
+                        let in_chunk = !Chunk::is_outside_chunk(&position, chunk_size)
+                            && !Chunk::is_outside_bounds(&position);
+                        Some((position, blocks.type_at(&position).filter(|_| in_chunk)?))
+                    }),
+            ),
+            None => Box::new(blocks.iter()),
+        };
 
-        for region in self.blocks.read().unwrap().iter() {
-            for y in 0..region.len() {
-                if let Some(block_ptr) = &region[y] {
-                    let block = block_ptr.read().unwrap();
-                    let position = block.position;
-                    let faces = FaceDirections::all();
+        for (position, block_type) in cells {
+            let block = Block::new(position, chunk, block_type, chunk_size);
+            let faces = FaceDirections::all();
 
-                    for face in faces.iter() {
-                        // For water block types, we only care about the top face
-                        if block.block_type == BlockType::Water && *face != FaceDirections::Top {
-                            continue;
-                        }
-                        let mut is_visible = true;
-                        let face_position = face.get_normal_vector() + position;
-
-                        if Chunk::is_outside_bounds(&face_position) {
-                            is_visible = false;
-                        } else if Chunk::is_outside_chunk(&face_position) {
-                            let target_chunk_x =
-                                self.x + (f32::floor(face_position.x / CHUNK_SIZE as f32) as i32);
-                            let target_chunk_y =
-                                self.y + (f32::floor(face_position.z / CHUNK_SIZE as f32) as i32);
-
-                            let target_block = glam::vec3(
-                                (face_position.x + CHUNK_SIZE as f32) % CHUNK_SIZE as f32,
-                                face_position.y,
-                                (face_position.z + CHUNK_SIZE as f32) % CHUNK_SIZE as f32,
-                            );
-
-                            let other_chunks_brw = other_chunks.read().unwrap();
-                            let target_chunk =
-                                other_chunks_brw.get(&(target_chunk_x, target_chunk_y));
-                            // let target_chunk = other_chunks.iter().find(|c| {
-                            //     let c = c.read().unwrap();
-                            //     c.x == target_chunk_x && c.y == target_chunk_y
-                            // });
-                            // If there's a chunk loaded in memory then check that, else it means we're on a edge and we can
-                            // Calculate the block's height when the chunk gets generated
-                            // TODO: Check for saved file chunk
-                            match target_chunk {
-                                Some(chunk) => {
-                                    let chunk = chunk.read().unwrap();
-                                    if chunk.exists_block_at(&target_block) {
-                                        is_visible = false;
-
-                                        if chunk.block_type_at(&target_block)
-                                            == Some(BlockType::Water)
-                                            && block.block_type != BlockType::Water
-                                        {
-                                            is_visible = true;
-                                        }
-                                    }
-                                }
-                                None => {
-                                    let h = Chunk::get_height_value(
-                                        target_chunk_x,
-                                        target_chunk_y,
-                                        target_block.x as u32,
-                                        target_block.z as u32,
-                                        self.noise_data.clone(),
-                                    );
-
-                                    if face_position.y as u32 <= h {
-                                        is_visible = false
-                                    };
-                                }
-                            }
-                        } else if self.exists_block_at(&face_position) {
-                            is_visible = false;
-                            // This can be a oneline if, but it gets very hard to read
-                            if self.block_type_at(&face_position) == Some(BlockType::Water)
-                                && block.block_type != BlockType::Water
-                            {
-                                is_visible = true;
-                            }
-                        }
+            for face in faces.iter() {
+                let face_position = face.get_normal_vector() + position;
+                // Block in front of the face (None for air). Nothing sees the bottom of the world,
+                // the sky sees the top
+                let mut front = None;
+                let mut is_visible = true;
 
-                        if is_visible {
-                            let (mut vertex_data, index_data) =
-                                face.create_face_data(block_ptr.clone(), &adjacent_chunks);
-                            match block.block_type {
-                                BlockType::Water => {
-                                    water_vertex.append(&mut vertex_data);
-                                    let indices_offset = water_vertex.len() as u32 - 4;
-                                    water_indices.append(
-                                        &mut index_data
-                                            .iter()
-                                            .map(|i| i + indices_offset)
-                                            .collect(),
-                                    )
-                                }
-                                _ => {
-                                    vertex.append(&mut vertex_data);
-                                    let indices_offset = vertex.len() as u32 - 4;
-                                    indices.append(
-                                        &mut index_data
-                                            .iter()
-                                            .map(|i| i + indices_offset)
-                                            .collect(),
-                                    )
-                                }
-                            }
-                        }
+                if face_position.y < WORLD_MIN_Y as f32 {
+                    is_visible = false;
+                } else if Chunk::is_outside_chunk(&face_position, chunk_size) {
+                    let size = chunk_size as f32;
+                    let target_chunk_x = chunk.0 + (f32::floor(face_position.x / size) as i32);
+                    let target_chunk_y = chunk.1 + (f32::floor(face_position.z / size) as i32);
+
+                    let target_block = glam::vec3(
+                        (face_position.x + size) % size,
+                        face_position.y,
+                        (face_position.z + size) % size,
+                    );
+
+                    // A neighbour that isn't loaded counts as solid, the world meshes this chunk
+                    // again when it loads (see `World::chunks_loaded`)
+                    match blocks_at((target_chunk_x, target_chunk_y)) {
+                        Some(target_blocks) => front = target_blocks.type_at(&target_block),
+                        None => is_visible = false,
+                    }
+                } else {
+                    front = blocks.type_at(&face_position);
+                }
+                if let Some(front) = front {
+                    is_visible &= !front.hides_face_of(block_type);
+                }
+                // The cross is inside its cell, nothing next to it hides it
+                if block_type.shape() == BlockShape::Cross {
+                    is_visible = !matches!(face, FaceDirections::Top | FaceDirections::Bottom);
+                }
+                // Under water only the surface shows, the sides and bottom only towards air
+                // (waterfalls over a cliff) or a block that doesn't fill its cell, like a torch
+                if block_type == BlockType::Water && *face != FaceDirections::Top {
+                    is_visible &= !front.is_some_and(|front| front.is_full_cube());
+                }
+
+                if is_visible {
+                    let (mut vertex_data, index_data) = match block_type.shape() {
+                        BlockShape::Cube => face.create_face_data(&block, adjacent_blocks),
+                        BlockShape::Post => face.create_post_face_data(&block),
+                        BlockShape::Cross => face.create_cross_face_data(&block),
+                    };
+                    // A face is lit by the cell in front of it, a cross by its own. Emitters are
+                    // always fully lit
+                    let lit_from = match block_type.shape() {
+                        BlockShape::Cross => block.absolute_position,
+                        _ => block.absolute_position + face.get_normal_vector(),
+                    };
+                    if let Some(chunk_light) = chunk_light {
+                        let level = chunk_light
+                            .at(lit_from)
+                            .max(block_type.get_config().light_emission);
+                        vertex_data
+                            .iter_mut()
+                            .for_each(|v| v.light = level as f32 / MAX_LIGHT as f32);
                     }
+                    let sky_level = sky_light.at(lit_from);
+                    vertex_data
+                        .iter_mut()
+                        .for_each(|v| v.sky_light = sky_level as f32 / MAX_LIGHT as f32);
+                    if block_type == BlockType::Water {
+                        let above = blocks.type_at(&(position + Vec3::Y));
+                        shape_water(&mut vertex_data, position, above.is_none());
+                    }
+                    on_face(MeshFace {
+                        position,
+                        direction: *face,
+                        block_type,
+                        vertex: vertex_data,
+                        indices: index_data,
+                    });
                 }
             }
         }
-
-        let chunk_vertex_buffer =
-            self.device
-                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    contents: bytemuck::cast_slice(&vertex),
-                    label: Some(&format!("chunk-vertex-{}-{}", self.x, self.y)),
-                    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-                });
-        let chunk_index_buffer =
-            self.device
-                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    contents: bytemuck::cast_slice(&indices),
-                    label: Some(&format!("chunk-vertex-{}-{}", self.x, self.y)),
-                    usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
-                });
-
-        let chunk_water_vertex_buffer =
-            self.device
-                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    contents: bytemuck::cast_slice(&water_vertex),
-                    label: Some(&format!("water-chunk-vertex-{}-{}", self.x, self.y)),
-                    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-                });
-        let chunk_water_index_buffer =
-            self.device
-                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    contents: bytemuck::cast_slice(&water_indices),
-                    label: Some(&format!("water-chunk-vertex-{}-{}", self.x, self.y)),
-                    usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
-                });
-
-        (
-            indices.len() as u32,
-            water_indices.len() as u32,
-            chunk_vertex_buffer,
-            chunk_index_buffer,
-            chunk_water_vertex_buffer,
-            chunk_water_index_buffer,
-        )
+    }
+    // With a mesh cache the buffers are shared with the chunks that have the same geometry, else
+    // the chunk's own buffers are written in place when the new mesh fits
+    fn upload_mesh(&mut self, mesh: &ChunkMesh, mesh_cache: Option<&MeshCache>) {
+        let previous = [
+            self.chunk_vertex_buffer.take(),
+            self.chunk_index_buffer.take(),
+            self.chunk_water_vertex_buffer.take(),
+            self.chunk_water_index_buffer.take(),
+        ];
+        let upload = |vertex: &[BlockVertexData],
+                      indices: &[u32],
+                      label: String,
+                      previous_vertex: Option<MeshBuffer>,
+                      previous_index: Option<MeshBuffer>| {
+            let vertex: Vec<BlockVertexPacked> =
+                vertex.iter().map(BlockVertexPacked::from).collect();
+            let vertex_bytes: &[u8] = bytemuck::cast_slice(&vertex);
+            let index_bytes = index_bytes(indices, vertex.len());
+            match mesh_cache {
+                Some(mesh_cache) => {
+                    let (vertex_buffer, index_buffer) =
+                        mesh_cache.get_or_create(&self.device, &vertex, indices, &label);
+                    (
+                        MeshBuffer::shared(vertex_buffer, vertex_bytes.len()),
+                        MeshBuffer::shared(index_buffer, index_bytes.len()),
+                    )
+                }
+                None => (
+                    MeshBuffer::write(
+                        previous_vertex,
+                        &self.device,
+                        &self.queue,
+                        vertex_bytes,
+                        wgpu::BufferUsages::VERTEX,
+                        &format!("{label}-vertex"),
+                    ),
+                    MeshBuffer::write(
+                        previous_index,
+                        &self.device,
+                        &self.queue,
+                        &index_bytes,
+                        wgpu::BufferUsages::INDEX,
+                        &format!("{label}-index"),
+                    ),
+                ),
+            }
+        };
+        let [vertex, index, water_vertex, water_index] = previous;
+        let (vertex_buffer, index_buffer) = upload(
+            &mesh.vertex,
+            &mesh.indices,
+            format!("chunk-{}-{}", self.x, self.y),
+            vertex,
+            index,
+        );
+        let (water_vertex_buffer, water_index_buffer) = upload(
+            &mesh.water_vertex,
+            &mesh.water_indices,
+            format!("water-chunk-{}-{}", self.x, self.y),
+            water_vertex,
+            water_index,
+        );
+        self.chunk_vertex_buffer = Some(vertex_buffer);
+        self.chunk_index_buffer = Some(index_buffer);
+        self.chunk_water_vertex_buffer = Some(water_vertex_buffer);
+        self.chunk_water_index_buffer = Some(water_index_buffer);
     }
     pub fn get_bind_group_layout() -> wgpu::BindGroupLayoutDescriptor<'static> {
         wgpu::BindGroupLayoutDescriptor {
@@ -312,199 +968,305 @@ impl Chunk {
         z: u32,
         noise_data: Arc<NoiseData>,
     ) -> u32 {
-        let mut x = (chunk_x * CHUNK_SIZE as i32) + x as i32 % NOISE_SIZE as i32;
-        let mut z = (chunk_y * CHUNK_SIZE as i32) + z as i32 % NOISE_SIZE as i32;
-
-        if x < 0 {
-            x = NOISE_SIZE as i32 + (x % (NOISE_CHUNK_PER_ROW * CHUNK_SIZE) as i32);
-        }
-        if z < 0 {
-            z = NOISE_SIZE as i32 + (z % (NOISE_CHUNK_PER_ROW * CHUNK_SIZE) as i32);
-        }
-        if let Some(v) = noise_data.get((z * (NOISE_SIZE as i32) + x) as usize) {
-            let y_top = (v + 1.0) * 0.5;
-            (f32::powf(8.0, y_top) - 1.0).min(10.0) as u32
-
-            
+        // The height map repeats, NOISE_SIZE is a whole number of chunks
+        let chunk_size = noise_data.chunk_size as i32;
+        let x = (chunk_x * chunk_size + x as i32).rem_euclid(NOISE_SIZE as i32);
+        let z = (chunk_y * chunk_size + z as i32).rem_euclid(NOISE_SIZE as i32);
+        if let Some(v) = noise_data
+            .heights
+            .get((z * (NOISE_SIZE as i32) + x) as usize)
+        {
+            // Cubed, most of the terrain stays flat around the water level and only the highest
+            // noise climbs up to TERRAIN_MAX_Y
+            let y_top = ((v + 1.0) * 0.5).clamp(0.0, 1.0).powi(3);
+            (f32::powf((TERRAIN_MAX_Y + 1) as f32, y_top) - 1.0) as u32
         } else {
             0
         }
     }
 
+    // Generates the columns in parallel, each job fills a contiguous range of x rows.
     pub fn create_blocks_data(chunk_x: i32, chunk_y: i32, noise_data: Arc<NoiseData>) -> BlockVec {
-        let size = (CHUNK_SIZE * CHUNK_SIZE) as usize;
-        let blocks: BlockVec = Arc::new(RwLock::new(vec![
-            Vec::with_capacity(
-                WATER_HEIGHT_LEVEL as usize
-            );
-            size
-        ]));
+        let (sender, receiver) = mpsc::channel();
+        let chunk_size = noise_data.chunk_size;
+        let rows_per_job = chunk_size.div_ceil(BLOCKS_DATA_POOL_SIZE as u32);
 
-        for x in 0..CHUNK_SIZE {
-            for z in 0..CHUNK_SIZE {
-                let y_top = Chunk::get_height_value(chunk_x, chunk_y, x, z, noise_data.clone());
-
-                let curr = &mut blocks.write().unwrap()[((x * CHUNK_SIZE) + z) as usize];
-
-                for y in 0..=y_top {
-                    let block_type = match BlockType::from_position(x, y, z) {
-                        BlockType::Dirt if y == y_top => BlockType::Grass,
-                        b => b,
-                    };
+        let mut jobs = 0;
+        for x_start in (0..chunk_size).step_by(rows_per_job as usize) {
+            let sender = sender.clone();
+            let noise_data = noise_data.clone();
+            jobs += 1;
 
-                    let block = Arc::new(RwLock::new(Block::new(
-                        glam::vec3(x as f32, y as f32, z as f32),
-                        (chunk_x, chunk_y),
-                        block_type,
-                    )));
-
-                    curr.push(Some(block.clone()));
-                }
-                // Fill with water empty blocks
-                for y in curr.len()..=(WATER_HEIGHT_LEVEL as usize) {
-                    if curr.get(y).is_none() {
-                        let block = Arc::new(RwLock::new(Block::new(
-                            glam::vec3(x as f32, y as f32, z as f32),
-                            (chunk_x, chunk_y),
-                            BlockType::Water,
-                        )));
-                        curr.push(Some(block));
+            BLOCKS_DATA_POOL.execute(move || {
+                let x_end = (x_start + rows_per_job).min(chunk_size);
+                let mut columns = Vec::with_capacity(((x_end - x_start) * chunk_size) as usize);
+                for x in x_start..x_end {
+                    for z in 0..chunk_size {
+                        columns.push(Chunk::create_column(
+                            chunk_x,
+                            chunk_y,
+                            x,
+                            z,
+                            noise_data.clone(),
+                        ));
                     }
                 }
-            }
+                sender.send((x_start, columns)).unwrap();
+            });
         }
 
-        blocks
+        let mut blocks = ChunkBlocks::new((chunk_x, chunk_y), chunk_size);
+        for _ in 0..jobs {
+            let (x_start, columns) = receiver.recv().expect("Some columns are missing");
+            for (i, column) in columns.into_iter().enumerate() {
+                let (x, z) = (x_start + i as u32 / chunk_size, i as u32 % chunk_size);
+                Chunk::set_column(&mut blocks, x, z, column);
+            }
+        }
+        // Veins cross columns, so this runs once the whole chunk is there
+        Chunk::place_ores(chunk_x, chunk_y, &mut blocks, noise_data.seed);
+        Chunk::place_bedrock(chunk_x, chunk_y, &mut blocks, noise_data.seed);
+        Arc::new(RwLock::new(blocks))
     }
-    // TODO: Use white noise + check that the tree is not being placed on water.
-    pub fn place_trees(&mut self) {
-        let mut rng = StdRng::seed_from_u64((self.x * 10 * self.y) as u64 + RNG_SEED);
-        let number_of_trees = rng.gen::<f32>();
-        let mut number_of_trees = f32::floor(number_of_trees * MAX_TREES_PER_CHUNK as f32) as u32;
-
-        // Do a max 100 retries
-        for _ in 0..100 {
-            if number_of_trees == 0 {
-                break;
+    pub fn create_blocks_data_sequential(
+        chunk_x: i32,
+        chunk_y: i32,
+        noise_data: Arc<NoiseData>,
+    ) -> BlockVec {
+        let chunk_size = noise_data.chunk_size;
+        let mut blocks = ChunkBlocks::new((chunk_x, chunk_y), chunk_size);
+        for x in 0..chunk_size {
+            for z in 0..chunk_size {
+                let column = Chunk::create_column(chunk_x, chunk_y, x, z, noise_data.clone());
+                Chunk::set_column(&mut blocks, x, z, column);
             }
-            let mut tree_blocks = vec![];
-            {
-                let x = f32::floor(rng.gen::<f32>() * CHUNK_SIZE as f32) as usize;
-                let z = f32::floor(rng.gen::<f32>() * CHUNK_SIZE as f32) as usize;
+        }
+        Chunk::place_ores(chunk_x, chunk_y, &mut blocks, noise_data.seed);
+        Chunk::place_bedrock(chunk_x, chunk_y, &mut blocks, noise_data.seed);
+        Arc::new(RwLock::new(blocks))
+    }
+    fn set_column(blocks: &mut ChunkBlocks, x: u32, z: u32, column: Vec<Option<BlockType>>) {
+        for (y, block_type) in column.into_iter().enumerate() {
+            blocks.set(x, y as u32, z, block_type);
+        }
+    }
+    fn create_column(
+        chunk_x: i32,
+        chunk_y: i32,
+        x: u32,
+        z: u32,
+        noise_data: Arc<NoiseData>,
+    ) -> Vec<Option<BlockType>> {
+        let seed = noise_data.seed;
+        let chunk_size = noise_data.chunk_size as i32;
+        let biome = Biome::at(
+            chunk_x * chunk_size + x as i32,
+            chunk_y * chunk_size + z as i32,
+            &noise_data,
+        );
+        let y_top = Chunk::get_height_value(chunk_x, chunk_y, x, z, noise_data.clone());
+        let carved = Chunk::carve_column(chunk_x, chunk_y, x, z, y_top, &noise_data);
+        let mut column = Vec::with_capacity(WATER_HEIGHT_LEVEL as usize);
 
-                let blocks_read = self.blocks.read().unwrap();
-                let block_column = blocks_read
-                    .get((x * CHUNK_SIZE as usize) + z)
-                    .expect("TODO: fix this case");
-                let highest_block = block_column
-                    .last()
-                    .expect("TODO: Fix this case -h")
-                    .as_ref()
-                    .unwrap()
-                    .read()
-                    .unwrap();
-                if highest_block.block_type == BlockType::Water
-                    || highest_block.block_type == BlockType::Leaf
-                {
-                    continue;
-                }
-                let highest_block_position = highest_block.absolute_position;
+        for y in 0..=y_top {
+            // Caves stop at the bottom layer
+            if y == 0 {
+                column.push(Some(BlockType::Bedrock));
+                continue;
+            }
+            if carved[y as usize] {
+                column.push(None);
+                continue;
+            }
+            let block_type = match BlockType::from_position(x, y, z, seed) {
+                b if y == y_top => biome.surface_block(y, b),
+                _ if y + STONE_DEPTH < y_top => BlockType::Stone,
+                b => b,
+            };
 
-                tree_blocks.append(&mut crate::structures::Tree::get_blocks(
-                    highest_block_position,
-                ));
-                number_of_trees -= 1;
+            column.push(Some(block_type));
+        }
+        // Fill with water empty blocks
+        for y in column.len()..=(WATER_HEIGHT_LEVEL as usize) {
+            if column.get(y).is_none() {
+                column.push(Some(BlockType::Water));
             }
-            for block in tree_blocks.iter() {
-                let block_brw = block.read().unwrap();
-                let block_chunk = block_brw.get_chunk_coords();
-                if block_chunk == (self.x, self.y) {
-                    self.add_block(block.clone(), false);
-                } else {
-                    self.outside_blocks.push(block.clone())
+        }
+        column
+    }
+    // Some of the stone right over the bottom layer, after the ores so it doesn't take their place.
+    // Hashed from the position, it doesn't depend on the order of the chunks
+    fn place_bedrock(chunk_x: i32, chunk_y: i32, blocks: &mut ChunkBlocks, seed: u64) {
+        let chunk_size = blocks.chunk_size();
+        for i in 0..chunk_size * chunk_size {
+            let (x, z) = (i / chunk_size, i % chunk_size);
+            for (y, chance) in (1..).zip(BEDROCK_CHANCE) {
+                let hash = chunk_seed(
+                    chunk_x * chunk_size as i32 + x as i32,
+                    chunk_y * chunk_size as i32 + z as i32,
+                    seed.wrapping_add(y as u64),
+                );
+                let roll = (hash >> 40) as f32 / (1u64 << 24) as f32;
+                if roll < chance && blocks.get(x, y, z) == Some(BlockType::Stone) {
+                    blocks.set(x, y, z, Some(BlockType::Bedrock));
                 }
             }
         }
     }
-    // https://www.lighthouse3d.com/tutorials/view-frustum-culling/
-    // Note: we don't compute the top and bottom planes, only far,near,right,left
-    pub fn is_visible(&self, player: Arc<RwLock<Player>>) -> bool {
-        let player = player.read().unwrap();
-        let forward = player.camera.get_forward_dir();
-        let right = player.camera.get_right_dir();
-        let halfvside = player.camera.zfar / f32::tan(player.camera.fovy / 2.0);
-        let halfhside = halfvside * player.camera.aspect_ratio;
-        let front_mult_far = player.camera.zfar * forward;
-
-        let chunk_points = [
-            (
-                (self.x as f32) * CHUNK_SIZE as f32,
-                (self.y as f32) * CHUNK_SIZE as f32,
-            ),
-            (
-                (self.x as f32 + 1.0) * CHUNK_SIZE as f32,
-                (self.y as f32) * CHUNK_SIZE as f32,
-            ),
-            (
-                (self.x as f32) * CHUNK_SIZE as f32,
-                (self.y as f32 + 1.0) * CHUNK_SIZE as f32,
-            ),
-            (
-                (self.x as f32 + 1.0) * CHUNK_SIZE as f32,
-                (self.y as f32 + 1.0) * CHUNK_SIZE as f32,
-            ),
-        ];
-
-        let near_plane = Plane {
-            point: player.camera.eye + player.camera.znear * forward,
-            normal: forward,
+    // Turns some of the stone into small veins of ore, they always have more than one block
+    fn place_ores(chunk_x: i32, chunk_y: i32, blocks: &mut ChunkBlocks, seed: u64) {
+        let chunk_hash = (chunk_x as i64 * 73_856_093) ^ (chunk_y as i64 * 19_349_663);
+        let mut rng = StdRng::seed_from_u64(seed.wrapping_add(chunk_hash as u64));
+        let chunk_size = blocks.chunk_size() as i32;
+        let is_stone = |blocks: &ChunkBlocks, (x, y, z): (i32, i32, i32)| {
+            x >= 0
+                && y >= 0
+                && z >= 0
+                && blocks.get(x as u32, y as u32, z as u32) == Some(BlockType::Stone)
         };
-        let far_plane = Plane {
-            point: player.camera.eye + front_mult_far,
-            normal: -forward,
+        let set_ore = |blocks: &mut ChunkBlocks, (x, y, z): (i32, i32, i32), ore: BlockType| {
+            blocks.set(x as u32, y as u32, z as u32, Some(ore));
         };
-        let right_plane = Plane {
-            point: player.camera.eye,
-            normal: glam::vec3(0.0, 1.0, 0.0)
-                .cross(player.camera.eye - (front_mult_far + right * halfhside))
-                .normalize(),
-        };
-        let left_plane = Plane {
-            point: player.camera.eye,
-            normal: (player.camera.eye - (front_mult_far - right * halfhside))
-                .cross(glam::vec3(0.0, 1.0, 0.0))
-                .normalize(),
+        let neighbour = |(x, y, z): (i32, i32, i32), i: usize| {
+            let (dx, dy, dz) = NEIGHBOUR_OFFSETS[i];
+            (x + dx, y + dy, z + dz)
         };
 
-        // returns true if at least one border of a chunk is visible is inside the frustum
-        [far_plane, near_plane, left_plane, right_plane]
-            .iter()
-            .all(|p| {
-                chunk_points.iter().any(|chunk_point| {
-                    p.signed_plane_dist(glam::vec3(chunk_point.0, 0.0, chunk_point.1)) >= 0.0
-                })
-            })
-    }
+        for (ore, attempts, max_size, full_depth) in ORES {
+            for _ in 0..attempts {
+                let x = rng.gen_range(0..chunk_size);
+                let z = rng.gen_range(0..chunk_size);
+                let column_height = blocks.column_height(x as u32, z as u32) as i32;
+                let y = rng.gen_range(0..column_height.max(1));
+                if !is_stone(blocks, (x, y, z)) {
+                    continue;
+                }
+                let depth = (column_height - 1 - y) as f32;
+                if rng.gen::<f32>() * full_depth as f32 > depth {
+                    continue;
+                }
+                let next: Vec<_> = (0..NEIGHBOUR_OFFSETS.len())
+                    .map(|i| neighbour((x, y, z), i))
+                    .filter(|p| is_stone(blocks, *p))
+                    .collect();
+                // A single block isn't a vein
+                if next.is_empty() {
+                    continue;
+                }
+                let next = next[rng.gen_range(0..next.len())];
+                set_ore(blocks, (x, y, z), ore);
+                set_ore(blocks, next, ore);
 
-    pub fn new(
+                let mut vein = vec![(x, y, z), next];
+                let size = rng.gen_range(2..=max_size);
+                for _ in 0..size * 4 {
+                    if vein.len() >= size {
+                        break;
+                    }
+                    let from = vein[rng.gen_range(0..vein.len())];
+                    let position = neighbour(from, rng.gen_range(0..NEIGHBOUR_OFFSETS.len()));
+                    if is_stone(blocks, position) {
+                        set_ore(blocks, position, ore);
+                        vein.push(position);
+                    }
+                }
+            }
+        }
+    }
+    // Blocks of the column (indexed by y) that are inside a cave
+    fn carve_column(
+        chunk_x: i32,
+        chunk_y: i32,
+        x: u32,
+        z: u32,
+        y_top: u32,
+        noise_data: &NoiseData,
+    ) -> Vec<bool> {
+        let water_level = WATER_HEIGHT_LEVEL as u32;
+        let mut carved = vec![false; y_top as usize + 1];
+        // Underwater columns stay solid, a cave there would only get flooded
+        if y_top <= water_level {
+            return carved;
+        }
+
+        let chunk_size = noise_data.chunk_size as i32;
+        let world_x = (chunk_x * chunk_size + x as i32) as f32;
+        let world_z = (chunk_y * chunk_size + z as i32) as f32;
+        let is_cave = |y: u32| {
+            perlin_noise_3d(
+                world_x * CAVE_FREQUENCY,
+                y as f32 * CAVE_FREQUENCY,
+                world_z * CAVE_FREQUENCY,
+                256,
+                &noise_data.perm,
+            ) > CAVE_THRESHOLD
+        };
+
+        for y in water_level + 1..y_top {
+            carved[y as usize] = is_cave(y);
+        }
+        // The surface only opens where the cave goes on below it, not for a single block dent
+        carved[y_top as usize] = is_cave(y_top) && is_cave(y_top - 1);
+        // Under the water level, only the blocks connected to the air above. y = 0 is never carved
+        for y in (1..=water_level).rev() {
+            carved[y as usize] = carved[y as usize + 1] && is_cave(y);
+        }
+        carved
+    }
+    pub fn is_visible(&self, player: Arc<RwLock<Player>>) -> bool {
+        let player = player.read().unwrap();
+        player
+            .camera
+            .frustum()
+            .intersects_aabb(&self.bounding_box())
+    }
+    // From the bottom of the world to the highest block, a chunk is never taller than that
+    pub fn bounding_box(&self) -> CollisionBox {
+        let chunk_size = self.chunk_size();
+        let min_x = (self.x * chunk_size as i32) as f32;
+        let min_z = (self.y * chunk_size as i32) as f32;
+        CollisionBox {
+            min_x,
+            max_x: min_x + chunk_size as f32,
+            min_y: 0.0,
+            max_y: self.max_height as f32 + 1.0,
+            min_z,
+            max_z: min_z + chunk_size as f32,
+        }
+    }
+
+    pub fn new(
         x: i32,
         y: i32,
         noise_data: Arc<NoiseData>,
         device: Arc<wgpu::Device>,
         queue: Arc<wgpu::Queue>,
         chunk_data_layout: Arc<wgpu::BindGroupLayout>,
+        archive: Option<Arc<WorldArchive>>,
     ) -> Chunk {
-        let mut was_loaded = false;
-
-        let blocks = if let Ok(blocks) = Self::load(Box::new((x, y))) {
-            was_loaded = true;
-            blocks
-        } else {
-            Self::create_blocks_data(x, y, noise_data.clone())
-        };
+        let data = ChunkData::generate(x, y, noise_data.clone(), &save_dir(), archive.clone());
+        Self::from_data(data, noise_data, device, queue, chunk_data_layout, archive)
+    }
+    // Creates the gpu resources for already generated blocks, the mesh is built later.
+    pub fn from_data(
+        data: ChunkData,
+        noise_data: Arc<NoiseData>,
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        chunk_data_layout: Arc<wgpu::BindGroupLayout>,
+        archive: Option<Arc<WorldArchive>>,
+    ) -> Chunk {
+        let ChunkData {
+            x,
+            y,
+            blocks,
+            outside_blocks,
+        } = data;
+        let max_height = max_block_height(&blocks);
 
         let chunk_position_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            contents: bytemuck::cast_slice(&[x, y]),
+            // x, y and the chunk size, padded to 16 bytes
+            contents: bytemuck::cast_slice(&[x, y, noise_data.chunk_size as i32, 0]),
             label: Some(&format!("chunk-position-{x}-{y}")),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
@@ -518,10 +1280,16 @@ impl Chunk {
             }],
         });
 
-        let mut chunk = Chunk {
-            modified: false,
+        Chunk {
+            modified: AtomicBool::new(false),
+            dirty: false,
+            mesh: None,
+            archive,
             chunk_water_index_buffer: None,
+            index_format: wgpu::IndexFormat::Uint16,
+            water_index_format: wgpu::IndexFormat::Uint16,
             chunk_water_vertex_buffer: None,
+            water_faces: vec![],
             blocks,
             x,
             y,
@@ -534,41 +1302,650 @@ impl Chunk {
             chunk_position_buffer,
             indices: 0,
             water_indices: 0,
-            outside_blocks: vec![],
+            max_height,
+            outside_blocks,
             visible: true,
+            save_dir: save_dir(),
+            gpu_lighting: None,
+        }
+    }
+}
+
+// Geometry of a chunk before it gets uploaded to the gpu
+#[derive(Default, Debug)]
+pub struct ChunkMesh {
+    pub vertex: Vec<BlockVertexData>,
+    pub indices: Vec<u32>,
+    // Every translucent block (water and glass)
+    pub water_vertex: Vec<BlockVertexData>,
+    pub water_indices: Vec<u32>,
+    // Block (relative position) of each quad, to replace the faces around an edit
+    pub face_blocks: Vec<Vec3>,
+    pub water_face_blocks: Vec<Vec3>,
+    // Quads span several blocks after greedy meshing, they can't be replaced block by block
+    pub merged: bool,
+    // Detail level it was built for, 0 has every block (see `Chunk::build_lod_mesh_data`)
+    pub lod: u8,
+}
+impl ChunkMesh {
+    // Bytes of the vertex and index buffers it's uploaded as
+    pub fn gpu_size(&self) -> usize {
+        [
+            (&self.vertex, &self.indices),
+            (&self.water_vertex, &self.water_indices),
+        ]
+        .iter()
+        .map(|(vertex, indices)| {
+            vertex.len() * std::mem::size_of::<BlockVertexPacked>()
+                + index_bytes(indices, vertex.len()).len()
+        })
+        .sum()
+    }
+    fn push_face(&mut self, mut face: MeshFace) {
+        let (vertex, indices, blocks) = match face.block_type.get_config().is_translucent {
+            true => (
+                &mut self.water_vertex,
+                &mut self.water_indices,
+                &mut self.water_face_blocks,
+            ),
+            false => (&mut self.vertex, &mut self.indices, &mut self.face_blocks),
         };
+        let indices_offset = vertex.len() as u32;
+        vertex.append(&mut face.vertex);
+        indices.extend(face.indices.iter().map(|i| i + indices_offset));
+        blocks.push(face.position);
+    }
+    // Drops the quads of the blocks that don't pass `keep`, every quad has 4 vertices and 6 indices
+    fn retain_faces(&mut self, keep: impl Fn(&Vec3) -> bool) {
+        for (vertex, indices, blocks) in [
+            (&mut self.vertex, &mut self.indices, &mut self.face_blocks),
+            (
+                &mut self.water_vertex,
+                &mut self.water_indices,
+                &mut self.water_face_blocks,
+            ),
+        ] {
+            let mut kept = 0;
+            for face in 0..blocks.len() {
+                if !keep(&blocks[face]) {
+                    continue;
+                }
+                vertex.copy_within(face * 4..face * 4 + 4, kept * 4);
+                for i in 0..6 {
+                    indices[kept * 6 + i] = indices[face * 6 + i] - (face * 4 - kept * 4) as u32;
+                }
+                blocks[kept] = blocks[face];
+                kept += 1;
+            }
+            vertex.truncate(kept * 4);
+            indices.truncate(kept * 6);
+            blocks.truncate(kept);
+        }
+    }
+    pub fn water_faces(&self) -> Vec<TranslucentFace> {
+        self.water_indices
+            .chunks_exact(6)
+            .map(|indices| {
+                // The two corners on the diagonal are repeated, the average is still the center
+                let center = indices
+                    .iter()
+                    .map(|i| Vec3::from(self.water_vertex[*i as usize].position))
+                    .sum::<Vec3>()
+                    / 6.0;
+                TranslucentFace {
+                    center,
+                    indices: indices.try_into().unwrap(),
+                }
+            })
+            .collect()
+    }
+}
 
-        if !was_loaded {
-            chunk.place_trees();
+// Under the sky the top of a water block (its top face and the upper edge of its sides) is lowered
+// and moves with the waves. Under glass the surface only sinks enough to not z-fight with it, and
+// water under water keeps its full height so the sides of a waterfall join
+fn shape_water(vertex_data: &mut [BlockVertexData], position: Vec3, open_sky: bool) {
+    for vertex in vertex_data.iter_mut() {
+        vertex.water[0] = 1.0;
+        if vertex.position[1] <= position.y {
+            continue;
+        }
+        match open_sky {
+            true => {
+                vertex.position[1] -= WATER_SURFACE_DROP;
+                vertex.water[1] = 1.0;
+            }
+            false if vertex.normal[1] > 0.0 => vertex.position[1] -= WATER_UNDER_GLASS_OFFSET,
+            false => {}
         }
-        chunk
     }
 }
 
-impl Saveable<Chunk> for Chunk {
-    fn save(&self) -> Result<(), Box<dyn Error>> {
-        if std::fs::create_dir("data").is_ok() {
-            println!("Created dir");
-        }
-        let mut data = String::new();
-
-        for col in self.blocks.read().unwrap().iter() {
-            for block in col.iter() {
-                if let Some(block_ptr) = block {
-                    let blockbrw = block_ptr.read().unwrap();
-                    data += &format!(
-                        "{},{},{},{}\n",
-                        blockbrw.position.x,
-                        blockbrw.position.y,
-                        blockbrw.position.z,
-                        blockbrw.block_type.to_id()
-                    );
+// A visible block face (4 vertices), before it's added to a mesh
+struct MeshFace {
+    // Relative to the chunk
+    position: Vec3,
+    direction: FaceDirections,
+    block_type: BlockType,
+    vertex: Vec<BlockVertexData>,
+    indices: Vec<u32>,
+}
+impl MeshFace {
+    // Axis of the normal, then the two axes along the face
+    fn axes(&self) -> (usize, usize, usize) {
+        let normal = self.direction.get_normal_vector();
+        if normal.x != 0.0 {
+            (0, 1, 2)
+        } else if normal.y != 0.0 {
+            (1, 0, 2)
+        } else {
+            (2, 0, 1)
+        }
+    }
+    fn is_mergeable(&self) -> bool {
+        !self.block_type.get_config().is_translucent
+            && self.block_type.is_full_cube()
+            && self.vertex.iter().all(|v| v.ao == self.vertex[0].ao)
+    }
+    // Position of the face's plane along the normal and its (v, u) cell on it
+    fn plane_cell(&self) -> (i32, (i32, i32)) {
+        let (normal, u, v) = self.axes();
+        (
+            self.position[normal] as i32,
+            (self.position[v] as i32, self.position[u] as i32),
+        )
+    }
+    fn merge_key(&self) -> (BlockType, u32, u32, u32) {
+        (
+            self.block_type,
+            self.vertex[0].ao.to_bits(),
+            self.vertex[0].light.to_bits(),
+            self.vertex[0].sky_light.to_bits(),
+        )
+    }
+    // Grows a block's face into the face of a cell of `scale` blocks per side, the far faces
+    // move to the far side of the cell
+    fn scaled(self, scale: i32) -> MeshFace {
+        let mut face = self.stretched(scale, scale);
+        let (normal, _, _) = face.axes();
+        for vertex in face.vertex.iter_mut() {
+            if vertex.position[normal] > face.position[normal] {
+                vertex.position[normal] += (scale - 1) as f32;
+            }
+        }
+        face
+    }
+    // Grows the face over `width` cells along u and `height` along v, the texture repeats on
+    // each cell
+    fn stretched(mut self, width: i32, height: i32) -> MeshFace {
+        if (width, height) == (1, 1) {
+            return self;
+        }
+        let (_, u, v) = self.axes();
+        // Whether each vertex is on the far side of the face along u and v
+        let sides: Vec<(f32, f32)> = self
+            .vertex
+            .iter()
+            .map(|vertex| {
+                (
+                    (vertex.position[u] > self.position[u]) as u8 as f32,
+                    (vertex.position[v] > self.position[v]) as u8 as f32,
+                )
+            })
+            .collect();
+        let tex_coords_at = |side: (f32, f32)| {
+            let i = sides.iter().position(|s| *s == side).unwrap();
+            glam::Vec2::from(self.vertex[i].tex_coords)
+        };
+        // Tex coords at the first cell, and how much they change per cell
+        let origin = tex_coords_at((0.0, 0.0));
+        let along_u = tex_coords_at((1.0, 0.0)) - origin;
+        let along_v = tex_coords_at((0.0, 1.0)) - origin;
+
+        for (vertex, (side_u, side_v)) in self.vertex.iter_mut().zip(sides) {
+            vertex.position[u] += side_u * (width - 1) as f32;
+            vertex.position[v] += side_v * (height - 1) as f32;
+            vertex.tex_coords =
+                (origin + along_u * side_u * width as f32 + along_v * side_v * height as f32)
+                    .into();
+            vertex.tiling = 1.0;
+        }
+        self
+    }
+}
+
+// A chunk's blocks in cells of `scale` blocks per side, the coarse meshes of far chunks are built
+// from them
+struct LodCells {
+    scale: u32,
+    // Cells per side horizontally
+    size: u32,
+    height: u32,
+    types: Vec<Option<BlockType>>,
+}
+impl LodCells {
+    fn new(blocks: &ChunkBlocks, scale: u32) -> Self {
+        let size = blocks.chunk_size() / scale;
+        let height = blocks.height().div_ceil(scale);
+        let mut cells = Self {
+            scale,
+            size,
+            height,
+            types: Vec::with_capacity((size * size * height) as usize),
+        };
+        for y in 0..height {
+            for x in 0..size {
+                for z in 0..size {
+                    let block_type = cells.cell_type(blocks, glam::uvec3(x, y, z));
+                    cells.types.push(block_type);
+                }
+            }
+        }
+        cells
+    }
+    // A cell at least half full of blocks (full cubes) takes the type most of its columns show on
+    // top, the first one found on a tie. The rest are air
+    fn cell_type(&self, blocks: &ChunkBlocks, cell: glam::UVec3) -> Option<BlockType> {
+        let first = cell * self.scale;
+        let mut filled = 0;
+        let mut surface: Vec<(BlockType, u32)> = vec![];
+        for x in first.x..first.x + self.scale {
+            for z in first.z..first.z + self.scale {
+                let mut top = None;
+                for y in first.y..first.y + self.scale {
+                    if let Some(block_type) = blocks.get(x, y, z).filter(|b| b.is_full_cube()) {
+                        filled += 1;
+                        top = Some(block_type);
+                    }
+                }
+                let Some(top) = top else {
+                    continue;
+                };
+                match surface
+                    .iter_mut()
+                    .find(|(block_type, _)| *block_type == top)
+                {
+                    Some((_, count)) => *count += 1,
+                    None => surface.push((top, 1)),
+                }
+            }
+        }
+        if filled * 2 < self.scale.pow(3) {
+            return None;
+        }
+        let mut dominant = surface[0];
+        for candidate in surface {
+            if candidate.1 > dominant.1 {
+                dominant = candidate;
+            }
+        }
+        Some(dominant.0)
+    }
+    // None for air and outside of the chunk
+    fn get(&self, cell: glam::IVec3) -> Option<BlockType> {
+        let size = self.size as i32;
+        if cell.min_element() < 0
+            || cell.x >= size
+            || cell.z >= size
+            || cell.y >= self.height as i32
+        {
+            return None;
+        }
+        self.types[((cell.y * size + cell.x) * size + cell.z) as usize]
+    }
+    fn iter(&self) -> impl Iterator<Item = (glam::IVec3, BlockType)> + '_ {
+        let size = self.size as i32;
+        self.types
+            .iter()
+            .enumerate()
+            .filter_map(move |(i, block_type)| {
+                let i = i as i32;
+                let cell = glam::ivec3(i / size % size, i / (size * size), i % size);
+                Some((cell, (*block_type)?))
+            })
+    }
+}
+
+// A translucent quad of a chunk mesh, the center is relative to the chunk
+#[derive(Debug, Clone)]
+pub struct TranslucentFace {
+    pub center: Vec3,
+    pub indices: [u32; 6],
+}
+
+// Blocks of a chunk before it gets uploaded to the gpu, safe to build on any thread.
+#[derive(Debug)]
+pub struct ChunkData {
+    pub x: i32,
+    pub y: i32,
+    pub blocks: BlockVec,
+    // Blocks generated by this chunk (like tree leaves) that belong to a neighbour chunk
+    pub outside_blocks: Vec<Arc<RwLock<Block>>>,
+}
+
+impl ChunkData {
+    // `Chunk::export_obj` without a gpu, the chunks around aren't known so the faces on the
+    // borders are hidden
+    pub fn export_obj(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        let blocks = self.blocks.read().unwrap();
+        std::fs::write(
+            path,
+            Chunk::obj((self.x, self.y), &[((self.x, self.y), &blocks)]),
+        )?;
+        Ok(())
+    }
+    // Loads the chunk from the save in `save_dir` if it exists, else generates the terrain and its
+    // structures.
+    pub fn generate(
+        x: i32,
+        y: i32,
+        noise_data: Arc<NoiseData>,
+        save_dir: &str,
+        archive: Option<Arc<WorldArchive>>,
+    ) -> ChunkData {
+        let load_args = (x, y, noise_data.chunk_size, save_dir.to_string(), archive);
+        if let Ok(blocks) = <Chunk as Loadable<BlockVec>>::load(Box::new(load_args)) {
+            return ChunkData {
+                x,
+                y,
+                blocks,
+                outside_blocks: vec![],
+            };
+        }
+        let mut data = ChunkData {
+            x,
+            y,
+            blocks: Chunk::create_blocks_data(x, y, noise_data.clone()),
+            outside_blocks: vec![],
+        };
+        data.place_structures(&noise_data);
+        data
+    }
+    // TODO: Use white noise + check that the tree is not being placed on water.
+    fn place_structures(&mut self, noise_data: &Arc<NoiseData>) {
+        let mut rng = StdRng::seed_from_u64(chunk_seed(self.x, self.y, noise_data.seed));
+        let chunk_size = noise_data.chunk_size;
+        // The density comes from the biome at the center of the chunk
+        let chunk_biome = Biome::at(
+            self.x * chunk_size as i32 + chunk_size as i32 / 2,
+            self.y * chunk_size as i32 + chunk_size as i32 / 2,
+            noise_data,
+        );
+        let number_of_structures = rng.gen::<f32>();
+        let mut number_of_structures =
+            f32::floor(number_of_structures * chunk_biome.max_structures() as f32) as u32;
+
+        // Do a max 100 retries
+        for _ in 0..100 {
+            if number_of_structures == 0 {
+                break;
+            }
+            let mut structure_blocks = vec![];
+            {
+                let x = f32::floor(rng.gen::<f32>() * chunk_size as f32) as u32;
+                let z = f32::floor(rng.gen::<f32>() * chunk_size as f32) as u32;
+
+                let blocks_read = self.blocks.read().unwrap();
+                let y_top = Chunk::get_height_value(self.x, self.y, x, z, noise_data.clone());
+                let column_height = blocks_read.column_height(x, z);
+                // Columns opened by a cave have no block on top
+                if column_height <= y_top {
+                    continue;
+                }
+                let highest_position = glam::vec3(x as f32, (column_height - 1) as f32, z as f32);
+                let Some(highest_block) = blocks_read.block_at(&highest_position) else {
+                    continue;
+                };
+                // Only on the ground, not on another structure
+                if matches!(
+                    highest_block.block_type,
+                    BlockType::Water
+                        | BlockType::Leaf
+                        | BlockType::BirchLeaf
+                        | BlockType::Cactus
+                        | BlockType::DeadBush
+                ) {
+                    continue;
+                }
+                // The edge of a desert that crosses the chunk
+                let highest_block_position = highest_block.absolute_position;
+                let column_biome = Biome::at(
+                    highest_block_position.x as i32,
+                    highest_block_position.z as i32,
+                    noise_data,
+                );
+                let Some(species) = column_biome.pick_structure(rng.gen::<f32>()) else {
+                    continue;
+                };
+                if !species.can_grow(highest_position, &blocks_read) {
+                    continue;
+                }
+
+                let mut blocks =
+                    species.get_blocks_seeded(highest_block_position, chunk_size, &mut rng);
+                if column_biome.has_snow(highest_block_position.y as u32) {
+                    blocks = covered_with_snow(blocks);
+                }
+                structure_blocks.append(&mut blocks);
+                number_of_structures -= 1;
+            }
+            for block in structure_blocks.iter() {
+                let block_brw = block.read().unwrap();
+                // Canopies of the tallest trees on the highest peaks
+                if Chunk::is_outside_bounds(&block_brw.absolute_position) {
+                    continue;
+                }
+                let block_chunk = block_brw.get_chunk_coords();
+                if block_chunk == (self.x, self.y) {
+                    insert_block(&self.blocks, block.clone());
+                } else {
+                    self.outside_blocks.push(block.clone())
                 }
             }
         }
+    }
+}
+
+// Independent seeds for every chunk of a world, a splitmix64 step over the packed coordinates
+fn chunk_seed(x: i32, y: i32, seed: u64) -> u64 {
+    let coords = ((x as u32 as u64) << 32) | y as u32 as u64;
+    let mut z = seed.wrapping_add(coords.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+// Block position is relative to the chunk
+fn max_block_height(blocks: &BlockVec) -> u32 {
+    let blocks = blocks.read().unwrap();
+    let chunk_size = blocks.chunk_size();
+    (0..chunk_size * chunk_size)
+        .map(|i| blocks.column_height(i / chunk_size, i % chunk_size))
+        .max()
+        .unwrap_or(0)
+        .saturating_sub(1)
+}
+// Compatibility with the blocks stored one by one, used by the structures
+pub(crate) fn insert_block(blocks: &BlockVec, block: Arc<RwLock<Block>>) {
+    let block = block.read().unwrap();
+    blocks
+        .write()
+        .unwrap()
+        .set_at(&block.position, Some(block.block_type));
+}
+
+/*
+Chunk layout, stored in the region files (integers little endian):
+  u8 format version
+  i32 chunk x, i32 chunk y
+  u8 palette length, per entry u8 block id (AIR_ID for no block)
+  per column (x * chunk size + z): u16 run count, then per run u8 palette index and u16 length
+  runs go from y = 0 upwards and the air above the highest block isn't stored
+Version 1 has no palette, the runs have the block id. Those were saved as <x>_<y>.chunk files */
+pub const CHUNK_FORMAT_VERSION: u8 = 2;
+const AIR_ID: u8 = u8::MAX;
 
-        let chunk_file_name = format!("data/chunk{}_{}", self.x, self.y);
-        std::fs::write(chunk_file_name.clone(), data.as_bytes())?;
+pub fn chunk_file_name(x: i32, y: i32) -> String {
+    format!("{}_{}.chunk", x, y)
+}
+
+pub fn encode_chunk(x: i32, y: i32, blocks: &BlockVec) -> Vec<u8> {
+    let mut palette: Vec<u8> = vec![];
+    let mut columns: Vec<Vec<(u8, u16)>> = vec![];
+
+    let blocks = blocks.read().unwrap();
+    let chunk_size = blocks.chunk_size();
+    for i in 0..chunk_size * chunk_size {
+        let (bx, bz) = (i / chunk_size, i % chunk_size);
+        let mut runs: Vec<(u8, u16)> = vec![];
+
+        for by in 0..blocks.column_height(bx, bz) {
+            let id = match blocks.get(bx, by, bz) {
+                Some(block_type) => block_type.to_id() as u8,
+                None => AIR_ID,
+            };
+            let index = match palette.iter().position(|entry| *entry == id) {
+                Some(index) => index,
+                None => {
+                    palette.push(id);
+                    palette.len() - 1
+                }
+            } as u8;
+            match runs.last_mut() {
+                Some((run_index, len)) if *run_index == index => *len += 1,
+                _ => runs.push((index, 1)),
+            }
+        }
+        columns.push(runs);
+    }
+
+    let mut data = vec![CHUNK_FORMAT_VERSION];
+    data.extend_from_slice(&x.to_le_bytes());
+    data.extend_from_slice(&y.to_le_bytes());
+    data.push(palette.len() as u8);
+    data.extend_from_slice(&palette);
+    for runs in columns {
+        data.extend_from_slice(&(runs.len() as u16).to_le_bytes());
+        for (index, len) in runs {
+            data.push(index);
+            data.extend_from_slice(&len.to_le_bytes());
+        }
+    }
+    data
+}
+
+// The chunk size isn't stored, it's the one of the world the chunk was saved in
+pub fn decode_chunk(
+    x: i32,
+    y: i32,
+    chunk_size: u32,
+    data: &[u8],
+) -> Result<BlockVec, Box<dyn Error>> {
+    let mut reader = data;
+    let version = read_u8(&mut reader)?;
+    if version != CHUNK_FORMAT_VERSION && version != 1 {
+        return Err(format!("Unsupported chunk format version {}", version).into());
+    }
+    let (file_x, file_y) = (read_u32(&mut reader)? as i32, read_u32(&mut reader)? as i32);
+    if (file_x, file_y) != (x, y) {
+        return Err(format!("Expected chunk {}_{}, found {}_{}", x, y, file_x, file_y).into());
+    }
+    let palette = if version == 1 {
+        (0..=u8::MAX).collect()
+    } else {
+        let mut palette = vec![0u8; read_u8(&mut reader)? as usize];
+        reader.read_exact(&mut palette)?;
+        palette
+    };
+
+    let mut blocks = ChunkBlocks::new((x, y), chunk_size);
+    for i in 0..chunk_size * chunk_size {
+        let (bx, bz) = (i / chunk_size, i % chunk_size);
+        let mut by = 0;
+
+        for _ in 0..read_u16(&mut reader)? {
+            let index = read_u8(&mut reader)?;
+            let len = read_u16(&mut reader)?;
+            let id = *palette
+                .get(index as usize)
+                .ok_or_else(|| format!("Invalid palette index {}", index))?;
+            if id != AIR_ID && id as u32 > BlockType::MAX_ID {
+                return Err(format!("Invalid block id {}", id).into());
+            }
+            if by + len as u32 > WORLD_MAX_Y as u32 + 1 {
+                return Err("Column is taller than the world".into());
+            }
+            let block_type = (id != AIR_ID).then(|| BlockType::from_id(id as u32));
+            for _ in 0..len {
+                blocks.set(bx, by, bz, block_type);
+                by += 1;
+            }
+        }
+    }
+    Ok(Arc::new(RwLock::new(blocks)))
+}
+
+// A copy of the blocks of a modified chunk, written away from the render thread while the chunk
+// keeps changing
+pub struct ChunkSnapshot {
+    pub x: i32,
+    pub y: i32,
+    blocks: BlockVec,
+    save_dir: String,
+    archive: Option<Arc<WorldArchive>>,
+}
+
+impl ChunkSnapshot {
+    pub fn write(&self) -> Result<(), Box<dyn Error>> {
+        let data = encode_chunk(self.x, self.y, &self.blocks);
+        write_region_chunk(
+            &self.save_dir,
+            self.x,
+            self.y,
+            data,
+            self.archive.as_deref(),
+        )
+    }
+}
+
+impl Chunk {
+    // None if the chunk wasn't modified since the last save. The chunk counts as saved from now on,
+    // call `mark_modified` if writing the snapshot fails
+    pub fn take_snapshot(&self) -> Option<ChunkSnapshot> {
+        if !self.modified.swap(false, Ordering::Relaxed) {
+            return None;
+        }
+        Some(ChunkSnapshot {
+            x: self.x,
+            y: self.y,
+            blocks: Arc::new(RwLock::new(self.blocks.read().unwrap().clone())),
+            save_dir: self.save_dir.clone(),
+            archive: self.archive.clone(),
+        })
+    }
+    pub fn mark_modified(&self) {
+        self.modified.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Saveable<Chunk> for Chunk {
+    // Only modified chunks are written, the others can be generated again. The flag is cleared
+    // before encoding so an edit made while writing is saved the next time
+    fn save(&self) -> Result<(), Box<dyn Error>> {
+        if !self.modified.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+        let data = encode_chunk(self.x, self.y, &self.blocks);
+        if let Err(e) = write_region_chunk(
+            &self.save_dir,
+            self.x,
+            self.y,
+            data,
+            self.archive.as_deref(),
+        ) {
+            self.mark_modified();
+            return Err(e);
+        }
 
         Ok(())
     }
@@ -576,52 +1953,1135 @@ impl Saveable<Chunk> for Chunk {
 
 impl Loadable<BlockVec> for Chunk {
     fn load(args: Box<dyn Any>) -> Result<BlockVec, Box<dyn Error>> {
-        if let Ok(chunk_position) = args.downcast::<(i32, i32)>() {
-            for entry in std::fs::read_dir("data")? {
-                let file = entry?;
-                let filename_chunk = file.file_name();
-                let mut coords = filename_chunk
-                    .to_str()
+        if let Ok(args) = args.downcast::<(i32, i32, u32, String, Option<Arc<WorldArchive>>)>() {
+            let (x, y, chunk_size, dir, archive) = *args;
+            if let Ok(data) = read_region_chunk(&dir, x, y, archive.as_deref()) {
+                return decode_chunk(x, y, chunk_size, &data);
+            }
+            // Saves from before the region files
+            if let Ok(data) = read_save_file(&dir, &chunk_file_name(x, y), archive.as_deref()) {
+                return decode_chunk(x, y, chunk_size, &data);
+            }
+            // Saves from before the binary format
+            let file_contents = String::from_utf8(read_save_file(
+                &dir,
+                &format!("chunk{}_{}", x, y),
+                archive.as_deref(),
+            )?)?;
+
+            let mut blocks = ChunkBlocks::new((x, y), chunk_size);
+            for line in file_contents.lines() {
+                let mut i = line.split(',');
+                let bx = i.next().unwrap().parse::<u32>()?;
+                let by = i.next().unwrap().parse::<u32>()?;
+                let bz = i.next().unwrap().parse::<u32>()?;
+                let block_type = i.next().unwrap().parse::<u32>()?;
+                if bx >= chunk_size || bz >= chunk_size || by > WORLD_MAX_Y as u32 {
+                    return Err(format!("Block out of the chunk {},{},{}", bx, by, bz).into());
+                }
+                blocks.set(bx, by, bz, Some(BlockType::from_id(block_type)));
+            }
+            return Ok(Arc::new(RwLock::new(blocks)));
+        }
+        Err("Not valid args".into())
+    }
+}
+
+// Arguments of `Loadable<Chunk>`, the ones of `Chunk::new` and the directory it's saved in
+pub struct ChunkLoadArgs {
+    pub x: i32,
+    pub y: i32,
+    pub device: Arc<wgpu::Device>,
+    pub queue: Arc<wgpu::Queue>,
+    pub noise_data: Arc<NoiseData>,
+    pub chunk_data_layout: Arc<wgpu::BindGroupLayout>,
+    pub save_dir: String,
+    pub archive: Option<Arc<WorldArchive>>,
+}
+
+impl Loadable<Chunk> for Chunk {
+    // Uses the saved blocks when there's a chunk file, else falls back to generating them.
+    // The gpu resources are created the same way in both cases.
+    fn load(args: Box<dyn Any>) -> Result<Chunk, Box<dyn Error>> {
+        let args = args
+            .downcast::<ChunkLoadArgs>()
+            .map_err(|_| "Not valid args")?;
+        let ChunkLoadArgs {
+            x,
+            y,
+            device,
+            queue,
+            noise_data,
+            chunk_data_layout,
+            save_dir,
+            archive,
+        } = *args;
+
+        let data = ChunkData::generate(x, y, noise_data.clone(), &save_dir, archive.clone());
+        let mut chunk =
+            Chunk::from_data(data, noise_data, device, queue, chunk_data_layout, archive);
+        chunk.save_dir = save_dir;
+        Ok(chunk)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        chunk_seed, decode_chunk, encode_chunk, insert_block, BlockVec, Chunk, ChunkBlocks,
+        ChunkData, ChunkLoadArgs, ChunkMesh, AIR_ID, CHUNK_FORMAT_VERSION, TERRAIN_MAX_Y,
+        WATER_SURFACE_DROP,
+    };
+    use crate::biome::Biome;
+    use crate::blocks::{
+        block::{Block, FaceDirections},
+        block_type::BlockType,
+    };
+    use crate::persistence::{region_file_name, save_dir, Loadable, Region, Saveable};
+    use crate::utils::noise::create_world_noise_data;
+    use crate::utils::threadpool::ThreadPool;
+    use crate::utils::{temp_dir, test_device};
+    use crate::world::{
+        DEFAULT_CHUNK_SIZE, FREQUENCY, NOISE_SIZE, RNG_SEED, WATER_HEIGHT_LEVEL, WORLD_MAX_Y,
+        WORLD_MIN_Y,
+    };
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::collections::HashMap;
+    use std::sync::{Arc, RwLock};
+    use std::time::Instant;
+
+    fn snapshot(blocks: &BlockVec) -> ChunkBlocks {
+        blocks.read().unwrap().clone()
+    }
+
+    #[test]
+    fn should_grow_the_flat_storage_only_up_to_the_highest_block() {
+        let mut blocks = ChunkBlocks::new((2, -1), DEFAULT_CHUNK_SIZE);
+        assert_eq!(blocks.height(), 0);
+        // Air above the stored layers doesn't grow it
+        blocks.set(1, 10, 1, None);
+        assert_eq!(blocks.height(), 0);
+
+        blocks.set(3, 4, 5, Some(BlockType::Stone));
+        blocks.set_at(&glam::vec3(3.0, 1.0, 5.0), Some(BlockType::Dirt));
+        assert_eq!(blocks.height(), 5);
+        assert_eq!(blocks.column_height(3, 5), 5);
+        assert_eq!(blocks.column_height(5, 3), 0);
+        assert_eq!(blocks.get(3, 4, 5), Some(BlockType::Stone));
+        assert_eq!(
+            blocks.type_at(&glam::vec3(3.5, 1.2, 5.9)),
+            Some(BlockType::Dirt)
+        );
+        // Outside of the chunk
+        assert_eq!(blocks.type_at(&glam::vec3(-1.0, 4.0, 5.0)), None);
+        assert_eq!(blocks.get(DEFAULT_CHUNK_SIZE, 4, 5), None);
+        assert_eq!(blocks.get(3, 40, 5), None);
+
+        let block = blocks.block_at(&glam::vec3(3.0, 4.0, 5.0)).unwrap();
+        assert_eq!(
+            block.absolute_position,
+            glam::vec3(
+                (2 * DEFAULT_CHUNK_SIZE + 3) as f32,
+                4.0,
+                5.0 - DEFAULT_CHUNK_SIZE as f32
+            )
+        );
+        assert_eq!(block.collision_box.min_y, 4.0);
+        assert_eq!(
+            blocks.iter().collect::<Vec<_>>(),
+            vec![
+                (glam::vec3(3.0, 1.0, 5.0), BlockType::Dirt),
+                (glam::vec3(3.0, 4.0, 5.0), BlockType::Stone),
+            ]
+        );
+
+        blocks.set(3, 4, 5, None);
+        assert_eq!(blocks.column_height(3, 5), 2);
+    }
+
+    #[test]
+    fn should_round_trip_every_block_type_through_the_palette() {
+        let mut blocks = ChunkBlocks::new((0, 0), DEFAULT_CHUNK_SIZE);
+        let types: Vec<BlockType> = (0..=BlockType::MAX_ID).map(BlockType::from_id).collect();
+        // Spread over two layers, with air between them
+        for (i, block_type) in types.iter().enumerate() {
+            blocks.set(
+                i as u32 % 4,
+                i as u32 / 4 % 2,
+                i as u32 / 8,
+                Some(*block_type),
+            );
+        }
+        // Every type and the air, 17 entries need 5 bits
+        assert_eq!(blocks.palette_len(), types.len() + 1);
+        assert_eq!(blocks.indices.bits, 5);
+        for (i, block_type) in types.iter().enumerate() {
+            let (x, y, z) = (i as u32 % 4, i as u32 / 4 % 2, i as u32 / 8);
+            assert_eq!(blocks.get(x, y, z), Some(*block_type), "{x} {y} {z}");
+        }
+        assert_eq!(blocks.get(5, 0, 5), None);
+        assert_eq!(blocks.iter().count(), types.len());
+
+        // A type that's gone frees its entry for the next new one
+        blocks.set(0, 0, 0, None);
+        assert_eq!(blocks.palette_len(), types.len());
+        blocks.set(0, 0, 0, Some(types[0]));
+        assert_eq!(blocks.palette.len(), types.len() + 1);
+        assert_eq!(blocks.get(0, 0, 0), Some(types[0]));
+    }
+
+    #[test]
+    fn should_keep_a_single_palette_entry_for_a_uniform_chunk() {
+        let mut blocks = ChunkBlocks::new((0, 0), DEFAULT_CHUNK_SIZE);
+        let height = 3;
+        for y in 0..height {
+            for x in 0..DEFAULT_CHUNK_SIZE {
+                for z in 0..DEFAULT_CHUNK_SIZE {
+                    blocks.set(x, y, z, Some(BlockType::Stone));
+                }
+            }
+        }
+        assert_eq!(blocks.height(), height);
+        assert_eq!(blocks.palette, vec![Some(BlockType::Stone)]);
+        assert_eq!(blocks.indices.bits, 0);
+        assert!(blocks.indices.words.is_empty());
+        assert_eq!(
+            blocks.iter().count(),
+            (DEFAULT_CHUNK_SIZE * DEFAULT_CHUNK_SIZE * height) as usize
+        );
+
+        // Back to two entries, packed in a bit per block
+        blocks.set(1, 1, 1, Some(BlockType::Dirt));
+        assert_eq!(blocks.palette_len(), 2);
+        assert_eq!(blocks.indices.bits, 1);
+        assert_eq!(blocks.get(1, 1, 1), Some(BlockType::Dirt));
+        assert_eq!(blocks.get(1, 2, 1), Some(BlockType::Stone));
+    }
+
+    #[test]
+    fn should_generate_the_same_blocks_in_parallel() {
+        let noise_data = Arc::new(create_world_noise_data(
+            NOISE_SIZE, NOISE_SIZE, FREQUENCY, RNG_SEED,
+        ));
+
+        for (x, y) in [(0, 0), (3, -2), (-7, 5)] {
+            let start = Instant::now();
+            let sequential = Chunk::create_blocks_data_sequential(x, y, noise_data.clone());
+            let sequential_time = start.elapsed();
+
+            let start = Instant::now();
+            let parallel = Chunk::create_blocks_data(x, y, noise_data.clone());
+            let parallel_time = start.elapsed();
+
+            println!(
+                "chunk ({}, {}): sequential {:?}, parallel {:?}",
+                x, y, sequential_time, parallel_time
+            );
+            assert_eq!(snapshot(&sequential), snapshot(&parallel));
+        }
+    }
+
+    #[test]
+    fn should_put_bedrock_under_every_column() {
+        let noise_data = Arc::new(create_world_noise_data(
+            NOISE_SIZE, NOISE_SIZE, FREQUENCY, RNG_SEED,
+        ));
+        let blocks = snapshot(&Chunk::create_blocks_data(2, 1, noise_data));
+        let bedrock_at = |y| {
+            (0..DEFAULT_CHUNK_SIZE * DEFAULT_CHUNK_SIZE)
+                .filter(|i| {
+                    blocks.get(i / DEFAULT_CHUNK_SIZE, y, i % DEFAULT_CHUNK_SIZE)
+                        == Some(BlockType::Bedrock)
+                })
+                .count()
+        };
+        assert_eq!(
+            bedrock_at(0),
+            (DEFAULT_CHUNK_SIZE * DEFAULT_CHUNK_SIZE) as usize
+        );
+        // Some of the layers above, never higher
+        assert!(bedrock_at(1) > 0);
+        assert!(bedrock_at(1) < bedrock_at(0));
+        assert!((3..blocks.height()).all(|y| bedrock_at(y) == 0));
+    }
+
+    #[test]
+    fn should_round_trip_the_binary_chunk_format() {
+        // Column 0: stone, stone, dirt, air, glass. The other columns are empty
+        let mut columns = ChunkBlocks::new((-3, 7), DEFAULT_CHUNK_SIZE);
+        for (y, block_type) in [
+            Some(BlockType::Stone),
+            Some(BlockType::Stone),
+            Some(BlockType::Dirt),
+            None,
+            Some(BlockType::Glass),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            columns.set(0, y as u32, 0, block_type);
+        }
+        let blocks: BlockVec = Arc::new(RwLock::new(columns));
+        let data = encode_chunk(-3, 7, &blocks);
+
+        assert_eq!(data[0], CHUNK_FORMAT_VERSION);
+        assert_eq!(data[1..5], (-3i32).to_le_bytes());
+        assert_eq!(data[5..9], 7i32.to_le_bytes());
+        let stone = BlockType::Stone.to_id() as u8;
+        let dirt = BlockType::Dirt.to_id() as u8;
+        let glass = BlockType::Glass.to_id() as u8;
+        // The palette is in order of appearance, the runs point into it
+        assert_eq!(data[9..14], [4, stone, dirt, AIR_ID, glass]);
+        assert_eq!(data[14..28], [4, 0, 0, 2, 0, 1, 1, 0, 2, 1, 0, 3, 1, 0]);
+        // Every other column is an empty run list
+        assert!(data[28..].iter().all(|b| *b == 0));
+        assert_eq!(
+            data.len(),
+            28 + 2 * (DEFAULT_CHUNK_SIZE * DEFAULT_CHUNK_SIZE - 1) as usize
+        );
+
+        let decoded = decode_chunk(-3, 7, DEFAULT_CHUNK_SIZE, &data).unwrap();
+        assert_eq!(snapshot(&decoded), snapshot(&blocks));
+        assert!(decode_chunk(0, 0, DEFAULT_CHUNK_SIZE, &data).is_err());
+    }
+
+    #[test]
+    fn should_seed_the_trees_of_every_chunk_differently() {
+        // The columns the trees are tried on come from this sequence
+        let sequences: Vec<Vec<u32>> = [(0, 0), (1, 0), (0, 1), (2, 0), (0, 2), (-1, 0), (1, 1)]
+            .iter()
+            .map(|(x, y)| {
+                let mut rng = StdRng::seed_from_u64(chunk_seed(*x, *y, RNG_SEED));
+                (0..8).map(|_| rng.gen()).collect()
+            })
+            .collect();
+        for (i, a) in sequences.iter().enumerate() {
+            for b in &sequences[i + 1..] {
+                assert_ne!(a, b);
+            }
+        }
+        assert_ne!(chunk_seed(3, 5, 0), chunk_seed(3, 5, 1));
+        assert_eq!(chunk_seed(3, 5, 7), chunk_seed(3, 5, 7));
+    }
+
+    #[test]
+    fn should_round_trip_a_generated_chunk_with_trees_through_a_region() {
+        let noise_data = Arc::new(create_world_noise_data(
+            NOISE_SIZE, NOISE_SIZE, FREQUENCY, RNG_SEED,
+        ));
+        let generate = |x: i32, y: i32| {
+            let mut data = ChunkData {
+                x,
+                y,
+                blocks: Chunk::create_blocks_data(x, y, noise_data.clone()),
+                outside_blocks: vec![],
+            };
+            data.place_structures(&noise_data);
+            data
+        };
+        let has_trees = |data: &ChunkData| {
+            data.blocks
+                .read()
+                .unwrap()
+                .iter()
+                .any(|(_, block_type)| block_type == BlockType::Wood)
+        };
+        let data = (0..100)
+            .map(|i| generate(i % 10 + 1, i / 10 + 1))
+            .find(has_trees)
+            .expect("No chunk with trees");
+
+        let mut region = Region::default();
+        region.set(data.x, data.y, encode_chunk(data.x, data.y, &data.blocks));
+        region.set(data.x + 1, data.y, vec![1, 2, 3]);
+        let file = region.encode();
+
+        let decoded = Region::decode(&file).unwrap();
+        assert_eq!(decoded.get(data.x + 1, data.y), Some(&[1, 2, 3][..]));
+        assert!(decoded.get(data.x + 2, data.y).is_none());
+        let chunk = decoded.get(data.x, data.y).unwrap();
+        let blocks = decode_chunk(data.x, data.y, DEFAULT_CHUNK_SIZE, chunk).unwrap();
+        assert_eq!(snapshot(&blocks), snapshot(&data.blocks));
+
+        // Truncated or corrupted data is an error, not a panic
+        assert!(Region::decode(&file[..file.len() / 2]).is_err());
+        assert!(Region::decode(&file[..100]).is_err());
+        assert!(decode_chunk(
+            data.x,
+            data.y,
+            DEFAULT_CHUNK_SIZE,
+            &chunk[..chunk.len() / 2]
+        )
+        .is_err());
+        let mut bad_offset = file.clone();
+        bad_offset[1..9].copy_from_slice(&[0xff; 8]);
+        assert!(Region::decode(&bad_offset).is_err());
+        let mut bad_version = file.clone();
+        bad_version[0] = 0;
+        assert!(Region::decode(&bad_version).is_err());
+    }
+
+    #[test]
+    fn should_generate_the_chunk_again_when_its_region_is_corrupted() {
+        let noise_data = Arc::new(create_world_noise_data(
+            NOISE_SIZE, NOISE_SIZE, FREQUENCY, RNG_SEED,
+        ));
+        let dir = temp_dir("corrupted-region");
+        let (x, y) = (3, 5);
+        let mut region = Region::default();
+        region.set(x, y, vec![CHUNK_FORMAT_VERSION; 64]);
+        let file = region.encode();
+        std::fs::write(dir.join(region_file_name(x, y)), &file[..file.len() - 8]).unwrap();
+
+        let loaded = ChunkData::generate(x, y, noise_data.clone(), dir.to_str().unwrap(), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let generated = Chunk::create_blocks_data(x, y, noise_data);
+        let column_heights = |blocks: &BlockVec| -> Vec<u32> {
+            let blocks = blocks.read().unwrap();
+            (0..DEFAULT_CHUNK_SIZE * DEFAULT_CHUNK_SIZE)
+                .map(|i| blocks.column_height(i / DEFAULT_CHUNK_SIZE, i % DEFAULT_CHUNK_SIZE))
+                .collect()
+        };
+        assert!(column_heights(&loaded.blocks)
+            .iter()
+            .zip(column_heights(&generated))
+            .all(|(loaded, generated)| *loaded >= generated));
+    }
+
+    #[test]
+    fn should_load_a_saved_chunk_instead_of_generating_it() {
+        let Some((device, queue)) = test_device() else {
+            println!("No adapter available, skipping chunk load test");
+            return;
+        };
+        let layout = Arc::new(device.create_bind_group_layout(&Chunk::get_bind_group_layout()));
+        let noise_data = Arc::new(create_world_noise_data(
+            NOISE_SIZE, NOISE_SIZE, FREQUENCY, RNG_SEED,
+        ));
+        let dir = temp_dir("chunk-load");
+        let (x, y) = (4, -3);
+        let load_args = || ChunkLoadArgs {
+            x,
+            y,
+            device: device.clone(),
+            queue: queue.clone(),
+            noise_data: noise_data.clone(),
+            chunk_data_layout: layout.clone(),
+            save_dir: dir.to_str().unwrap().to_string(),
+            archive: None,
+        };
+
+        let mut chunk = Chunk::new(
+            x,
+            y,
+            noise_data.clone(),
+            device.clone(),
+            queue.clone(),
+            layout.clone(),
+            None,
+        );
+        chunk.save_dir = dir.to_str().unwrap().to_string();
+        let removed = glam::vec3(2.0, 0.0, 5.0);
+        let placed = glam::vec3(9.0, 20.0, 9.0);
+        chunk.remove_block(&removed).unwrap();
+        chunk
+            .add_block(
+                Arc::new(RwLock::new(Block::new(
+                    placed,
+                    (x, y),
+                    BlockType::Glass,
+                    DEFAULT_CHUNK_SIZE,
+                ))),
+                true,
+            )
+            .unwrap();
+        // Over the world's height, rejected without growing the blocks
+        let height = chunk.blocks.read().unwrap().height();
+        let over = glam::vec3(9.0, WORLD_MAX_Y as f32 + 1.0, 9.0);
+        assert!(chunk.set_block(&over, BlockType::Glass, true).is_err());
+        assert_eq!(chunk.blocks.read().unwrap().height(), height);
+        // Same for removing, under the world it used to clamp to the bedrock floor
+        let under = glam::vec3(4.0, WORLD_MIN_Y as f32 - 1.0, 4.0);
+        assert!(chunk.remove_block(&over).is_err());
+        assert!(chunk.remove_block(&under).is_err());
+        assert_eq!(
+            chunk.block_type_at(&glam::vec3(4.0, 0.0, 4.0)),
+            Some(BlockType::Bedrock)
+        );
+        chunk.save().unwrap();
+        assert!(!chunk.is_modified());
+
+        let loaded = <Chunk as Loadable<Chunk>>::load(Box::new(load_args())).unwrap();
+        std::fs::remove_file(dir.join(region_file_name(x, y))).unwrap();
+
+        assert_eq!((loaded.x, loaded.y), (x, y));
+        assert_eq!(loaded.save_dir, chunk.save_dir);
+        assert_eq!(loaded.block_type_at(&removed), None);
+        assert_eq!(loaded.block_type_at(&placed), Some(BlockType::Glass));
+        let untouched = glam::vec3(7.0, 0.0, 7.0);
+        assert_eq!(
+            loaded.block_type_at(&untouched),
+            chunk.block_type_at(&untouched)
+        );
+
+        // Without a file it's generated again, so the edits are gone
+        let generated = <Chunk as Loadable<Chunk>>::load(Box::new(load_args())).unwrap();
+        assert!(generated.block_type_at(&removed).is_some());
+
+        // A failed write keeps the chunk modified so the next save tries again
+        let path = dir.join(region_file_name(x, y));
+        std::fs::write(&path, b"not a region").unwrap();
+        chunk.remove_block(&placed).unwrap();
+        assert!(chunk.save().is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(chunk.is_modified());
+    }
+
+    #[test]
+    fn should_map_the_highest_noise_just_under_the_world_height() {
+        let noise_data = create_world_noise_data(NOISE_SIZE, NOISE_SIZE, FREQUENCY, RNG_SEED);
+        let height_with = |noise: f32| {
+            let mut flat = noise_data.clone();
+            flat.heights.iter_mut().for_each(|v| *v = noise);
+            Chunk::get_height_value(3, -2, 5, 7, Arc::new(flat))
+        };
+        assert_eq!(height_with(1.0), TERRAIN_MAX_Y);
+        assert!(TERRAIN_MAX_Y < WORLD_MAX_Y as u32);
+        // Past the noise range too
+        assert_eq!(height_with(1.5), TERRAIN_MAX_Y);
+        assert_eq!(height_with(-1.0), 0);
+        // The middle of the noise is around the water level
+        assert!(height_with(0.0) <= WATER_HEIGHT_LEVEL as u32);
+        // Mountains well over the old cap of 10 near the top of the noise
+        assert!(height_with(0.8) > 60);
+    }
+
+    #[test]
+    fn should_carve_caves_without_breaking_the_surface() {
+        let noise_data = Arc::new(create_world_noise_data(
+            NOISE_SIZE, NOISE_SIZE, FREQUENCY, RNG_SEED,
+        ));
+        let mut carved_columns = 0;
+
+        // Most of the terrain is at the water level, caves are only in the few hills
+        let chunks = (-8..8).flat_map(|x| (-8..8).map(move |y| (x, y)));
+        for (chunk_x, chunk_y) in chunks {
+            for i in 0..DEFAULT_CHUNK_SIZE * DEFAULT_CHUNK_SIZE {
+                let (x, z) = (i / DEFAULT_CHUNK_SIZE, i % DEFAULT_CHUNK_SIZE);
+                let y_top = Chunk::get_height_value(chunk_x, chunk_y, x, z, noise_data.clone());
+                let column = Chunk::create_column(chunk_x, chunk_y, x, z, noise_data.clone());
+                let is_air = |y: u32| column[y as usize].is_none();
+
+                if (1..y_top).any(is_air) {
+                    carved_columns += 1;
+                }
+                assert!(!is_air(0));
+                // The surface is only open above a cave
+                if is_air(y_top) {
+                    assert!(is_air(y_top - 1));
+                }
+                // Nothing under the water level unless it's connected to the air above
+                for y in 1..=WATER_HEIGHT_LEVEL as u32 {
+                    if is_air(y) {
+                        assert!(is_air(y + 1));
+                    }
+                }
+            }
+        }
+        assert!(carved_columns > 0);
+    }
+
+    #[test]
+    fn should_batch_glass_faces_with_the_translucent_mesh() {
+        let Some((device, queue)) = test_device() else {
+            println!("No adapter available, skipping glass mesh test");
+            return;
+        };
+        let layout = Arc::new(device.create_bind_group_layout(&Chunk::get_bind_group_layout()));
+        let noise_data = Arc::new(create_world_noise_data(
+            NOISE_SIZE, NOISE_SIZE, FREQUENCY, RNG_SEED,
+        ));
+
+        // Two glass blocks side by side, one of them on top of a stone block
+        let blocks: BlockVec = Arc::new(RwLock::new(ChunkBlocks::new((0, 0), DEFAULT_CHUNK_SIZE)));
+        for (position, block_type) in [
+            (glam::vec3(5.0, 6.0, 5.0), BlockType::Glass),
+            (glam::vec3(6.0, 6.0, 5.0), BlockType::Glass),
+            (glam::vec3(5.0, 5.0, 5.0), BlockType::Stone),
+        ] {
+            let block = Block::new(position, (0, 0), block_type, DEFAULT_CHUNK_SIZE);
+            insert_block(&blocks, Arc::new(RwLock::new(block)));
+        }
+        let data = ChunkData {
+            x: 0,
+            y: 0,
+            blocks,
+            outside_blocks: vec![],
+        };
+        let chunk = Chunk::from_data(data, noise_data, device, queue, layout, None);
+        let mesh = chunk.build_mesh_data(Arc::new(RwLock::new(HashMap::new())));
+
+        // 12 glass faces, minus the two between the glass blocks and the one over the stone
+        assert_eq!(mesh.water_vertex.len(), 9 * 4);
+        assert_eq!(mesh.water_indices.len(), 9 * 6);
+        assert_eq!(mesh.water_faces().len(), 9);
+        // The stone keeps its top face under the glass
+        assert_eq!(mesh.vertex.len(), 6 * 4);
+        assert_eq!(mesh.indices.len(), 6 * 6);
+        let faces_towards = |normal: [f32; 3]| {
+            mesh.water_vertex
+                .chunks(4)
+                .filter(|face| face[0].normal == normal)
+                .count()
+        };
+        // Only the outer side of the pair along x, and a single bottom face next to the stone
+        assert_eq!(faces_towards([1.0, 0.0, 0.0]), 1);
+        assert_eq!(faces_towards([-1.0, 0.0, 0.0]), 1);
+        assert_eq!(faces_towards([0.0, -1.0, 0.0]), 1);
+        assert_eq!(faces_towards([0.0, 1.0, 0.0]), 2);
+    }
+
+    #[test]
+    fn should_export_the_visible_faces_of_a_chunk_as_obj() {
+        let (x, y) = (1, -2);
+        let chunk_data = |region: &[(u32, u32, u32)]| {
+            let mut blocks = ChunkBlocks::new((x, y), DEFAULT_CHUNK_SIZE);
+            for &(bx, by, bz) in region {
+                blocks.set(bx, by, bz, Some(BlockType::Stone));
+            }
+            ChunkData {
+                x,
+                y,
+                blocks: Arc::new(RwLock::new(blocks)),
+                outside_blocks: vec![],
+            }
+        };
+        let export = |data: &ChunkData, name: &str| {
+            let path =
+                std::env::temp_dir().join(format!("diorama-{}-{}.obj", name, std::process::id()));
+            data.export_obj(&path).unwrap();
+            let obj = std::fs::read_to_string(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            obj
+        };
+        let count = |obj: &str, prefix: &str| {
+            obj.lines()
+                .filter(|line| line.split_whitespace().next() == Some(prefix))
+                .count()
+        };
+
+        // 2x2 blocks, 1 high, off the ground: 4 on top, 4 below and 2 on each side
+        let region = [(4, 1, 4), (5, 1, 4), (4, 1, 5), (5, 1, 5)];
+        let obj = export(&chunk_data(&region), "region");
+        let quads = 16;
+        assert_eq!(count(&obj, "v"), quads * 4);
+        assert_eq!(count(&obj, "vt"), quads * 4);
+        assert_eq!(count(&obj, "vn"), quads * 4);
+        assert_eq!(count(&obj, "f"), quads * 2);
+        assert_eq!(count(&obj, "g"), 1);
+        assert!(obj.contains("g Stone"));
+        // Every face points at vertices that exist, in the chunk's place in the world
+        let vertices: Vec<Vec<f32>> = obj
+            .lines()
+            .filter(|line| line.starts_with("v "))
+            .map(|line| line[2..].split(' ').map(|v| v.parse().unwrap()).collect())
+            .collect();
+        let min_x = (x * DEFAULT_CHUNK_SIZE as i32) as f32;
+        assert!(vertices
+            .iter()
+            .all(|v| v[0] >= min_x && v[0] <= min_x + DEFAULT_CHUNK_SIZE as f32));
+        assert!(obj
+            .lines()
+            .filter(|line| line.starts_with("f "))
+            .flat_map(|line| line[2..].split(' '))
+            .all(|corner| corner.split('/').all(|i| {
+                let i: usize = i.parse().unwrap();
+                i >= 1 && i <= vertices.len()
+            })));
+
+        let empty = export(&chunk_data(&[]), "empty");
+        assert_eq!(empty.lines().count(), 1);
+        assert!(empty.starts_with('#'));
+    }
+
+    #[test]
+    fn should_cull_the_faces_between_solid_neighbour_chunks() {
+        let Some((device, queue)) = test_device() else {
+            println!("No adapter available, skipping chunk border test");
+            return;
+        };
+        let layout = Arc::new(device.create_bind_group_layout(&Chunk::get_bind_group_layout()));
+        let noise_data = Arc::new(create_world_noise_data(
+            NOISE_SIZE, NOISE_SIZE, FREQUENCY, RNG_SEED,
+        ));
+        const HEIGHT: u32 = 3;
+
+        // A slab of stone in (0, 0) and (1, 0), an empty chunk at (-1, 0)
+        let chunk = |x: i32, height: u32| {
+            let mut blocks = ChunkBlocks::new((x, 0), DEFAULT_CHUNK_SIZE);
+            for i in 0..DEFAULT_CHUNK_SIZE * DEFAULT_CHUNK_SIZE {
+                for y in 0..height {
+                    blocks.set(
+                        i / DEFAULT_CHUNK_SIZE,
+                        y,
+                        i % DEFAULT_CHUNK_SIZE,
+                        Some(BlockType::Stone),
+                    );
+                }
+            }
+            let data = ChunkData {
+                x,
+                y: 0,
+                blocks: Arc::new(RwLock::new(blocks)),
+                outside_blocks: vec![],
+            };
+            Chunk::from_data(
+                data,
+                noise_data.clone(),
+                device.clone(),
+                queue.clone(),
+                layout.clone(),
+                None,
+            )
+        };
+        let (first, second) = (chunk(0, HEIGHT), chunk(1, HEIGHT));
+        let chunk_map = Arc::new(RwLock::new(HashMap::from([
+            ((-1, 0), Arc::new(RwLock::new(chunk(-1, 0)))),
+            ((0, 0), Arc::new(RwLock::new(chunk(0, HEIGHT)))),
+            ((1, 0), Arc::new(RwLock::new(chunk(1, HEIGHT)))),
+        ])));
+        // Faces of the blocks at `x` pointing along x towards `normal`
+        let faces_towards = |mesh: &ChunkMesh, x: f32, normal: f32| {
+            mesh.face_blocks
+                .iter()
+                .enumerate()
+                .filter(|(i, block)| block.x == x && mesh.vertex[i * 4].normal[0] == normal)
+                .count()
+        };
+        let edge = (DEFAULT_CHUNK_SIZE - 1) as f32;
+
+        let first_mesh = first.build_mesh_data(chunk_map.clone());
+        let second_mesh = second.build_mesh_data(chunk_map);
+        assert_eq!(faces_towards(&first_mesh, edge, 1.0), 0);
+        assert_eq!(faces_towards(&second_mesh, 0.0, -1.0), 0);
+        // The side towards the empty chunk is drawn
+        assert_eq!(
+            faces_towards(&first_mesh, 0.0, -1.0),
+            (DEFAULT_CHUNK_SIZE * HEIGHT) as usize
+        );
+    }
+
+    #[test]
+    fn should_lower_the_water_surface_and_keep_the_sides_towards_air() {
+        let Some((device, queue)) = test_device() else {
+            println!("No adapter available, skipping water mesh test");
+            return;
+        };
+        let layout = Arc::new(device.create_bind_group_layout(&Chunk::get_bind_group_layout()));
+        let noise_data = Arc::new(create_world_noise_data(
+            NOISE_SIZE, NOISE_SIZE, FREQUENCY, RNG_SEED,
+        ));
+
+        // A column of two water blocks over a stone, with another stone next to the lower one
+        let blocks: BlockVec = Arc::new(RwLock::new(ChunkBlocks::new((0, 0), DEFAULT_CHUNK_SIZE)));
+        for (position, block_type) in [
+            (glam::vec3(5.0, 6.0, 5.0), BlockType::Water),
+            (glam::vec3(5.0, 5.0, 5.0), BlockType::Water),
+            (glam::vec3(5.0, 4.0, 5.0), BlockType::Stone),
+            (glam::vec3(6.0, 5.0, 5.0), BlockType::Stone),
+        ] {
+            let block = Block::new(position, (0, 0), block_type, DEFAULT_CHUNK_SIZE);
+            insert_block(&blocks, Arc::new(RwLock::new(block)));
+        }
+        let data = ChunkData {
+            x: 0,
+            y: 0,
+            blocks,
+            outside_blocks: vec![],
+        };
+        let chunk = Chunk::from_data(data, noise_data, device, queue, layout, None);
+        let mesh = chunk.build_mesh_data(Arc::new(RwLock::new(HashMap::new())));
+
+        // The surface and the sides towards air, none between the water blocks or against stone
+        assert_eq!(mesh.water_faces().len(), 1 + 4 + 3);
+        assert!(mesh.water_vertex.iter().all(|v| v.water[0] == 1.0));
+        let surface_height = 6.5 - WATER_SURFACE_DROP;
+        for vertex in mesh.water_vertex.iter() {
+            let y = vertex.position[1];
+            if vertex.water[1] == 1.0 {
+                assert_eq!(y, surface_height);
+            } else {
+                // The sides of the lower block reach the upper one, so the column is closed
+                assert!(y == 4.5 || y == 5.5, "{y}");
+            }
+        }
+        let surface_vertices = mesh.water_vertex.iter().filter(|v| v.water[1] == 1.0);
+        // The 4 of the top face and the upper 2 of each side of the upper block
+        assert_eq!(surface_vertices.count(), 4 + 4 * 2);
+    }
+
+    #[test]
+    fn should_only_mesh_the_surface_and_the_exposed_sides_of_a_water_column() {
+        let Some((device, queue)) = test_device() else {
+            println!("No adapter available, skipping water column test");
+            return;
+        };
+        let layout = Arc::new(device.create_bind_group_layout(&Chunk::get_bind_group_layout()));
+        let noise_data = Arc::new(create_world_noise_data(
+            NOISE_SIZE, NOISE_SIZE, FREQUENCY, RNG_SEED,
+        ));
+
+        // Four blocks of water in a well of stone, open to the air on one side of the top block
+        // and with a torch on another
+        let blocks: BlockVec = Arc::new(RwLock::new(ChunkBlocks::new((0, 0), DEFAULT_CHUNK_SIZE)));
+        let mut placed = vec![
+            (glam::vec3(5.0, 1.0, 5.0), BlockType::Stone),
+            (glam::vec3(4.0, 5.0, 5.0), BlockType::Torch),
+        ];
+        for y in 2..=5 {
+            placed.push((glam::vec3(5.0, y as f32, 5.0), BlockType::Water));
+            for (x, z) in [(4, 5), (6, 5), (5, 4), (5, 6)] {
+                let open = y == 5 && (x, z) != (5, 4) && (x, z) != (5, 6);
+                if !open {
+                    placed.push((glam::vec3(x as f32, y as f32, z as f32), BlockType::Stone));
+                }
+            }
+        }
+        for (position, block_type) in placed {
+            let block = Block::new(position, (0, 0), block_type, DEFAULT_CHUNK_SIZE);
+            insert_block(&blocks, Arc::new(RwLock::new(block)));
+        }
+        let data = ChunkData {
+            x: 0,
+            y: 0,
+            blocks,
+            outside_blocks: vec![],
+        };
+        let chunk = Chunk::from_data(data, noise_data, device, queue, layout, None);
+        let mesh = chunk.build_mesh_data(Arc::new(RwLock::new(HashMap::new())));
+
+        // A single surface quad, the side towards the air and the one towards the torch
+        let faces = mesh.water_faces();
+        assert_eq!(faces.len(), 3);
+        assert_eq!(mesh.water_indices.len(), 3 * 6);
+        let normals: Vec<_> = mesh
+            .water_vertex
+            .chunks(4)
+            .map(|face| face[0].normal)
+            .collect();
+        assert_eq!(normals.iter().filter(|n| **n == [0.0, 1.0, 0.0]).count(), 1);
+        assert!(normals.contains(&[1.0, 0.0, 0.0]));
+        assert!(normals.contains(&[-1.0, 0.0, 0.0]));
+        // All of them on the top block
+        assert!(mesh.water_vertex.iter().all(|v| v.position[1] >= 4.5));
+    }
+
+    #[test]
+    fn should_merge_a_flat_plane_into_far_fewer_vertices() {
+        let Some((device, queue)) = test_device() else {
+            println!("No adapter available, skipping greedy mesh test");
+            return;
+        };
+        let layout = Arc::new(device.create_bind_group_layout(&Chunk::get_bind_group_layout()));
+        let noise_data = Arc::new(create_world_noise_data(
+            NOISE_SIZE, NOISE_SIZE, FREQUENCY, RNG_SEED,
+        ));
+
+        let mut blocks = ChunkBlocks::new((0, 0), DEFAULT_CHUNK_SIZE);
+        for x in 0..DEFAULT_CHUNK_SIZE {
+            for z in 0..DEFAULT_CHUNK_SIZE {
+                blocks.set(x, 0, z, Some(BlockType::Grass));
+            }
+        }
+        let data = ChunkData {
+            x: 0,
+            y: 0,
+            blocks: Arc::new(RwLock::new(blocks)),
+            outside_blocks: vec![],
+        };
+        let chunk = Chunk::from_data(data, noise_data, device, queue, layout, None);
+        let chunk_map = Arc::new(RwLock::new(HashMap::new()));
+        let mesh = chunk.build_mesh_data(chunk_map.clone());
+        let greedy = chunk.build_greedy_mesh_data(chunk_map);
+
+        let area = |mesh: &ChunkMesh| {
+            mesh.indices
+                .chunks(3)
+                .map(|triangle| {
+                    let [a, b, c] = [0, 1, 2]
+                        .map(|i| glam::Vec3::from(mesh.vertex[triangle[i] as usize].position));
+                    (b - a).cross(c - a).length() / 2.0
+                })
+                .sum::<f32>()
+        };
+        assert!(mesh.vertex.len() >= 256 * 4);
+        assert!(greedy.vertex.len() * 10 < mesh.vertex.len());
+        assert_eq!(area(&greedy), area(&mesh));
+
+        // The whole top is a single quad repeating the grass texture once per block
+        let top: Vec<_> = greedy
+            .vertex
+            .iter()
+            .filter(|v| v.normal == [0.0, 1.0, 0.0])
+            .collect();
+        assert_eq!(top.len(), 4);
+        assert!(top.iter().all(|v| v.tiling == 1.0));
+        for axis in 0..2 {
+            let coords = top.iter().map(|v| v.tex_coords[axis]);
+            let span = coords.clone().fold(f32::MIN, f32::max) - coords.fold(f32::MAX, f32::min);
+            assert_eq!(span, 16.0);
+        }
+    }
+
+    #[test]
+    fn should_build_the_same_mesh_data_on_the_worker_threads() {
+        let Some((device, queue)) = test_device() else {
+            println!("No adapter available, skipping threaded mesh test");
+            return;
+        };
+        let layout = Arc::new(device.create_bind_group_layout(&Chunk::get_bind_group_layout()));
+        let noise_data = Arc::new(create_world_noise_data(
+            NOISE_SIZE, NOISE_SIZE, FREQUENCY, RNG_SEED,
+        ));
+        // Generated terrain with a neighbour on two sides, so the borders get culled too
+        let keys = [(0, 0), (1, 0), (0, 1)];
+        let chunk_map = Arc::new(RwLock::new(HashMap::from(keys.map(|(x, y)| {
+            let data = ChunkData::generate(x, y, noise_data.clone(), &save_dir(), None);
+            let chunk = Chunk::from_data(
+                data,
+                noise_data.clone(),
+                device.clone(),
+                queue.clone(),
+                layout.clone(),
+                None,
+            );
+            ((x, y), Arc::new(RwLock::new(chunk)))
+        }))));
+        let bytes = |mesh: &ChunkMesh| {
+            [
+                bytemuck::cast_slice::<_, u8>(&mesh.vertex),
+                bytemuck::cast_slice(&mesh.indices),
+                bytemuck::cast_slice(&mesh.water_vertex),
+                bytemuck::cast_slice(&mesh.water_indices),
+            ]
+            .concat()
+        };
+        let chunk = chunk_map.read().unwrap()[&(0, 0)].clone();
+        let expected = [false, true].map(|greedy| {
+            bytes(
+                &chunk
+                    .read()
                     .unwrap()
-                    .split('k')
-                    .last()
-                    .expect("Invalid filename")
-                    .split('_');
-                let x = coords.next().unwrap().parse::<i32>()?;
-                let y = coords.next().unwrap().parse::<i32>()?;
-
-                let size = (CHUNK_SIZE * CHUNK_SIZE) as usize;
-                let blocks: BlockVec = Arc::new(RwLock::new(vec![vec![]; size]));
-                if *chunk_position == (x, y) {
-                    let file_contents = std::fs::read_to_string(format!("data/chunk{}_{}", x, y))?;
-                    for line in file_contents.lines() {
-                        let mut i = line.split(',');
-                        let bx = i.next().unwrap().parse::<u32>()?;
-                        let by = i.next().unwrap().parse::<u32>()?;
-                        let bz = i.next().unwrap().parse::<u32>()?;
-                        let block_type = i.next().unwrap().parse::<u32>()?;
-                        let block_type = BlockType::from_id(block_type);
-
-                        let block = Block::new(
-                            glam::vec3(bx as f32, by as f32, bz as f32),
-                            (x, y),
-                            block_type,
-                        );
-                        let y_blocks =
-                            &mut blocks.write().unwrap()[((bx * CHUNK_SIZE) + bz) as usize];
-                        let start_len = y_blocks.len();
-
-                        for i in start_len..=by as usize {
-                            if i >= y_blocks.len() {
-                                y_blocks.push(None);
+                    .mesh_data(chunk_map.clone(), greedy, 0),
+            )
+        });
+
+        // The workers only read the chunks, several of them mesh the same one at once
+        let pool = ThreadPool::new(4);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        for job in 0..8 {
+            let (sender, chunk, chunk_map) = (sender.clone(), chunk.clone(), chunk_map.clone());
+            pool.execute(move || {
+                let greedy = job % 2 == 1;
+                let mesh = chunk.read().unwrap().mesh_data(chunk_map, greedy, 0);
+                sender.send((greedy, bytes(&mesh))).unwrap();
+            });
+        }
+        for _ in 0..8 {
+            let (greedy, mesh) = receiver.recv().unwrap();
+            assert!(mesh == expected[greedy as usize]);
+        }
+    }
+
+    #[test]
+    fn should_mesh_a_far_plane_with_a_quarter_of_the_faces() {
+        let Some((device, queue)) = test_device() else {
+            println!("No adapter available, skipping lod mesh test");
+            return;
+        };
+        let layout = Arc::new(device.create_bind_group_layout(&Chunk::get_bind_group_layout()));
+        let noise_data = Arc::new(create_world_noise_data(
+            NOISE_SIZE, NOISE_SIZE, FREQUENCY, RNG_SEED,
+        ));
+
+        // Stone with a grass top, and a stripe of sand on a quarter of it
+        let mut blocks = ChunkBlocks::new((0, 0), DEFAULT_CHUNK_SIZE);
+        for x in 0..DEFAULT_CHUNK_SIZE {
+            for z in 0..DEFAULT_CHUNK_SIZE {
+                blocks.set(x, 0, z, Some(BlockType::Stone));
+                let top = if x < DEFAULT_CHUNK_SIZE / 4 {
+                    BlockType::Sand
+                } else {
+                    BlockType::Grass
+                };
+                blocks.set(x, 1, z, Some(top));
+            }
+        }
+        let data = ChunkData {
+            x: 0,
+            y: 0,
+            blocks: Arc::new(RwLock::new(blocks)),
+            outside_blocks: vec![],
+        };
+        let chunk = Chunk::from_data(data, noise_data, device, queue, layout, None);
+        let chunk_map = Arc::new(RwLock::new(HashMap::new()));
+        let full = chunk.mesh_data(chunk_map.clone(), false, 0);
+        let far = chunk.mesh_data(chunk_map, false, 1);
+        assert_eq!((full.lod, far.lod), (0, 1));
+        assert!(far.merged);
+
+        // Only the top is visible, the neighbours aren't loaded
+        assert_eq!(
+            full.face_blocks.len(),
+            (DEFAULT_CHUNK_SIZE * DEFAULT_CHUNK_SIZE) as usize
+        );
+        assert_eq!(far.face_blocks.len() * 4, full.face_blocks.len());
+        // Each face covers a 2x2 cell at the same height and keeps the block type on top. The
+        // vertices are around the block centers
+        assert!(full.vertex.iter().all(|v| v.position[1] == 1.5));
+        for face in far.vertex.chunks_exact(4) {
+            assert!(face.iter().all(|v| v.position[1] == 1.5));
+            let xs = face.iter().map(|v| v.position[0]);
+            assert_eq!(
+                xs.clone().fold(f32::MIN, f32::max) - xs.fold(f32::MAX, f32::min),
+                2.0
+            );
+            let x = face[0].position[0].min(face[2].position[0]) + 0.5;
+            let top = if x < (DEFAULT_CHUNK_SIZE / 4) as f32 {
+                BlockType::Sand
+            } else {
+                BlockType::Grass
+            };
+            assert_eq!(face[0].layer, top.get_texture_layer(FaceDirections::Top));
+            assert_eq!(face[0].tiling, 1.0);
+        }
+    }
+
+    #[test]
+    fn should_place_ores_in_veins_with_iron_deeper() {
+        let noise_data = Arc::new(create_world_noise_data(
+            NOISE_SIZE, NOISE_SIZE, FREQUENCY, RNG_SEED,
+        ));
+        // Ore: (blocks, sum of depths)
+        let mut found = [(BlockType::CoalOre, 0, 0), (BlockType::IronOre, 0, 0)];
+
+        let chunks = (-8..8).flat_map(|x| (-8..8).map(move |y| (x, y)));
+        for (chunk_x, chunk_y) in chunks {
+            let blocks = Chunk::create_blocks_data(chunk_x, chunk_y, noise_data.clone());
+            let blocks = blocks.read().unwrap();
+            let type_at = |x: i32, y: i32, z: i32| {
+                if x < 0 || y < 0 || z < 0 {
+                    return None;
+                }
+                blocks.get(x as u32, y as u32, z as u32)
+            };
+
+            for i in 0..DEFAULT_CHUNK_SIZE * DEFAULT_CHUNK_SIZE {
+                let (x, z) = (
+                    (i / DEFAULT_CHUNK_SIZE) as i32,
+                    (i % DEFAULT_CHUNK_SIZE) as i32,
+                );
+                let column_height = blocks.column_height(x as u32, z as u32) as i32;
+                for y in 0..column_height {
+                    let Some(block_type) = type_at(x, y, z) else {
+                        continue;
+                    };
+                    let Some(ore) = found.iter_mut().find(|(ore, ..)| *ore == block_type) else {
+                        continue;
+                    };
+                    ore.1 += 1;
+                    ore.2 += column_height - 1 - y;
+                    // Part of a cluster, no lonely ore blocks
+                    let neighbours = [
+                        (x + 1, y, z),
+                        (x - 1, y, z),
+                        (x, y + 1, z),
+                        (x, y - 1, z),
+                        (x, y, z + 1),
+                        (x, y, z - 1),
+                    ];
+                    assert!(neighbours
+                        .iter()
+                        .any(|&(x, y, z)| type_at(x, y, z) == Some(block_type)));
+                }
+            }
+        }
+
+        let [(_, coal, coal_depth), (_, iron, iron_depth)] = found;
+        assert!(coal > 0 && iron > 0);
+        assert!(iron < coal);
+        assert!(iron_depth as f32 / iron as f32 > coal_depth as f32 / coal as f32);
+    }
+
+    #[test]
+    fn should_cover_deserts_with_sand_and_lone_cacti() {
+        let noise_data = Arc::new(create_world_noise_data(
+            NOISE_SIZE, NOISE_SIZE, FREQUENCY, RNG_SEED,
+        ));
+        let is_desert = |x: i32, z: i32| Biome::at(x, z, &noise_data) == Biome::Desert;
+        // Chunks where every column is desert
+        let deserts = (-64..64)
+            .flat_map(|x| (-64..64).map(move |y| (x, y)))
+            .filter(|(x, y)| {
+                let (x, z) = (x * DEFAULT_CHUNK_SIZE as i32, y * DEFAULT_CHUNK_SIZE as i32);
+                let edge = DEFAULT_CHUNK_SIZE as i32 - 1;
+                is_desert(x, z)
+                    && is_desert(x + edge, z)
+                    && is_desert(x, z + edge)
+                    && is_desert(x + edge, z + edge)
+            });
+
+        let mut found = [0; 2];
+        for (chunk_x, chunk_y) in deserts.take(32) {
+            let mut data = ChunkData {
+                x: chunk_x,
+                y: chunk_y,
+                blocks: Chunk::create_blocks_data(chunk_x, chunk_y, noise_data.clone()),
+                outside_blocks: vec![],
+            };
+            data.place_structures(&noise_data);
+
+            assert!(data.outside_blocks.is_empty());
+            let blocks = data.blocks.read().unwrap();
+            for i in 0..DEFAULT_CHUNK_SIZE * DEFAULT_CHUNK_SIZE {
+                let (x, z) = (i / DEFAULT_CHUNK_SIZE, i % DEFAULT_CHUNK_SIZE);
+                let y_top = Chunk::get_height_value(chunk_x, chunk_y, x, z, noise_data.clone());
+                let column_height = blocks.column_height(x, z);
+                let full_height = y_top.max(WATER_HEIGHT_LEVEL as u32) + 1;
+                // Only a cave can open the surface
+                match blocks.get(x, y_top, z) {
+                    Some(surface) => {
+                        // The bottom layer is always bedrock
+                        let expected = match y_top {
+                            0 => BlockType::Bedrock,
+                            _ => BlockType::Sand,
+                        };
+                        assert_eq!(surface, expected);
+                        assert!(column_height >= full_height);
+                    }
+                    None => assert!(column_height < full_height),
+                }
+                // A dead bush, or a cactus on sand with nothing next to it
+                for y in full_height.min(column_height)..column_height {
+                    match blocks.get(x, y, z) {
+                        Some(BlockType::DeadBush) => found[1] += 1,
+                        Some(BlockType::Cactus) => {
+                            found[0] += 1;
+                            assert!(column_height - full_height <= 3);
+                            assert_eq!(blocks.get(x, full_height - 1, z), Some(BlockType::Sand));
+                            let sides = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+                            for (dx, dz) in sides {
+                                let (x, z) = (x as i32 + dx, z as i32 + dz);
+                                assert!(x >= 0 && z >= 0);
+                                let side = blocks.get(x as u32, y, z as u32);
+                                assert!(side.is_none(), "{side:?} next to a cactus");
                             }
                         }
-                        y_blocks[by as usize] = Some(Arc::new(RwLock::new(block)));
+                        other => panic!("{other:?} in a desert"),
                     }
-                    return Ok(blocks);
                 }
             }
         }
-        Err("Not valid args".into())
+        assert!(found.iter().all(|count| *count > 0), "{found:?}");
     }
 }
@@ -1,3 +1,11 @@
+use glam::{IVec3, Vec3};
+
+use crate::blocks::block::FaceDirections;
+
+// Boxes closer than this are touching, not overlapping. Keeps float errors from letting a box sink
+// into the one it was just clamped against
+const EPSILON: f32 = 1e-4;
+
 #[derive(Debug, Clone)]
 pub struct CollisionBox {
     pub min_x: f32,
@@ -20,6 +28,80 @@ pub struct Ray {
 }
 
 impl Ray {
+    // Amanatides-Woo voxel traversal: every cell the ray goes through up to `max_distance`, in order,
+    // with the face of the cell it enters through. The cell of the origin isn't returned
+    pub fn voxel_traverse(
+        &self,
+        max_distance: f32,
+    ) -> impl Iterator<Item = (IVec3, FaceDirections)> {
+        let direction = self.direction.normalize_or_zero().to_array();
+        let origin = self.origin.to_array();
+        let mut cell = self.origin.floor().as_ivec3();
+        let mut step = [0; 3];
+        // Distance along the ray to the next boundary of each axis, and between two boundaries
+        let mut t_max = [f32::INFINITY; 3];
+        let mut t_delta = [f32::INFINITY; 3];
+
+        for i in 0..3 {
+            // Zero components never cross a boundary, they keep the infinite distances
+            if direction[i] > 0.0 {
+                step[i] = 1;
+                t_max[i] = ((cell[i] + 1) as f32 - origin[i]) / direction[i];
+            } else if direction[i] < 0.0 {
+                step[i] = -1;
+                t_max[i] = (cell[i] as f32 - origin[i]) / direction[i];
+            } else {
+                continue;
+            }
+            t_delta[i] = 1.0 / direction[i].abs();
+        }
+
+        std::iter::from_fn(move || {
+            let axis = (0..3).min_by(|a, b| t_max[*a].total_cmp(&t_max[*b]))?;
+            if t_max[axis] > max_distance {
+                return None;
+            }
+            cell[axis] += step[axis];
+            t_max[axis] += t_delta[axis];
+            Some((cell, entering_face(axis, step[axis] > 0)))
+        })
+    }
+    // Entry point of the ray into the box and the face it goes through, which is the face of
+    // the slab the ray gets into last
+    pub fn entered_face(&self, collision_box: &CollisionBox) -> Option<(Vec3, FaceDirections)> {
+        let entry = self.intersects_box(collision_box)?[0];
+        let near_slab = |min: f32, max: f32, origin: f32, direction: f32| {
+            if direction > 0.0 {
+                (min - origin) / direction
+            } else if direction < 0.0 {
+                (max - origin) / direction
+            } else {
+                f32::NEG_INFINITY
+            }
+        };
+        let slabs = [
+            near_slab(
+                collision_box.min_x,
+                collision_box.max_x,
+                self.origin.x,
+                self.direction.x,
+            ),
+            near_slab(
+                collision_box.min_y,
+                collision_box.max_y,
+                self.origin.y,
+                self.direction.y,
+            ),
+            near_slab(
+                collision_box.min_z,
+                collision_box.max_z,
+                self.origin.z,
+                self.direction.z,
+            ),
+        ];
+        let axis = (0..3).max_by(|a, b| slabs[*a].total_cmp(&slabs[*b]))?;
+        Some((entry, entering_face(axis, self.direction[axis] > 0.0)))
+    }
     pub fn intersects_box(&self, collision_box: &CollisionBox) -> Option<Vec<glam::Vec3>> {
         let mut tmin;
         let mut tmax;
@@ -66,7 +148,7 @@ impl Ray {
             tzmax = (collision_box.min_z - self.origin.z) * invdirz;
         }
 
-        if tmin > tzmax || tzmin > tmax || tmin < 0.0 || tmax < 0.0 {
+        if tmin > tzmax || tzmin > tmax {
             return None;
         }
 
@@ -76,6 +158,10 @@ impl Ray {
         if tzmax < tmax {
             tmax = tzmax;
         }
+        // Only checked once every slab is in, the ray can start between the planes of one axis
+        if tmin < 0.0 || tmax < 0.0 {
+            return None;
+        }
 
         Some(vec![
             self.origin + self.direction * tmin,
@@ -84,6 +170,18 @@ impl Ray {
     }
 }
 
+// Face of a block that is entered moving along `axis`
+fn entering_face(axis: usize, positive: bool) -> FaceDirections {
+    match (axis, positive) {
+        (0, true) => FaceDirections::Left,
+        (0, false) => FaceDirections::Right,
+        (1, true) => FaceDirections::Bottom,
+        (1, false) => FaceDirections::Top,
+        (_, true) => FaceDirections::Front,
+        (_, false) => FaceDirections::Back,
+    }
+}
+
 #[derive(Debug)]
 pub struct RayResult {
     pub points: Vec<glam::Vec3>,
@@ -143,8 +241,68 @@ impl CollisionBox {
             && self.min_z <= other.max_z
             && self.max_z >= other.min_z
     }
-    pub fn intersects_direction() {
-        todo!()
+    // Swept AABB: the fraction of `velocity` (0-1) this box can move before touching `other` and the
+    // normal of the face it hits. None if it doesn't reach `other` or already overlaps it
+    pub fn intersects_direction(
+        &self,
+        velocity: Vec3,
+        other: &CollisionBox,
+    ) -> Option<(f32, Vec3)> {
+        let axes = [
+            (velocity.x, self.min_x, self.max_x, other.min_x, other.max_x),
+            (velocity.y, self.min_y, self.max_y, other.min_y, other.max_y),
+            (velocity.z, self.min_z, self.max_z, other.min_z, other.max_z),
+        ];
+        let mut entry = [f32::NEG_INFINITY; 3];
+        let mut exit = [f32::INFINITY; 3];
+
+        for (i, (v, min, max, other_min, other_max)) in axes.into_iter().enumerate() {
+            if v == 0.0 {
+                // Not moving on this axis, it has to overlap the whole time
+                if max <= other_min + EPSILON || min >= other_max - EPSILON {
+                    return None;
+                }
+                continue;
+            }
+            let (entry_distance, exit_distance) = if v > 0.0 {
+                (other_min - max, other_max - min)
+            } else {
+                (other_max - min, other_min - max)
+            };
+            entry[i] = if entry_distance.abs() < EPSILON {
+                0.0
+            } else {
+                entry_distance / v
+            };
+            exit[i] = exit_distance / v;
+        }
+
+        let entry_time = entry.into_iter().fold(f32::NEG_INFINITY, f32::max);
+        let exit_time = exit.into_iter().fold(f32::INFINITY, f32::min);
+        if entry_time > exit_time || !(0.0..=1.0).contains(&entry_time) {
+            return None;
+        }
+
+        // The last axis to start overlapping is the hit one. On a tie (hitting an edge) the axis that
+        // would go deeper into `other` wins
+        let v = velocity.to_array();
+        let axis = (0..3)
+            .filter(|i| (entry[*i] - entry_time).abs() < f32::EPSILON)
+            .max_by(|a, b| v[*a].abs().total_cmp(&v[*b].abs()))
+            .unwrap();
+        let mut normal = Vec3::ZERO;
+        normal[axis] = -v[axis].signum();
+        Some((entry_time, normal))
+    }
+    // Earliest hit of `intersects_direction` against several boxes
+    pub fn sweep_all<'a>(
+        &self,
+        velocity: Vec3,
+        others: impl Iterator<Item = &'a CollisionBox>,
+    ) -> Option<(f32, Vec3)> {
+        others
+            .filter_map(|other| self.intersects_direction(velocity, other))
+            .min_by(|a, b| a.0.total_cmp(&b.0))
     }
 }
 
@@ -162,3 +320,171 @@ impl std::ops::Add<glam::Vec3> for CollisionBox {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{CollisionBox, Ray};
+    use crate::blocks::block::FaceDirections;
+    use glam::{ivec3, vec3};
+
+    fn player_box(x: f32) -> CollisionBox {
+        CollisionBox::new(x, 1.0, 0.1, 0.8, 2.0, 0.8)
+    }
+
+    #[test]
+    fn should_not_tunnel_through_a_wall_at_high_speed() {
+        let wall = CollisionBox::from_block_position(2.0, 1.0, 0.0);
+        let player = player_box(0.1);
+        let velocity = vec3(3.0, 0.0, 0.0);
+
+        // Testing only the destination misses the wall
+        assert!(!(player.clone() + velocity).intersects(&wall));
+
+        let (toi, normal) = player.intersects_direction(velocity, &wall).unwrap();
+        assert!((toi - 1.1 / 3.0).abs() < 1e-5);
+        assert_eq!(normal, vec3(-1.0, 0.0, 0.0));
+        let stopped = player + velocity * toi;
+        assert!((stopped.max_x - wall.min_x).abs() < 1e-5);
+
+        // Once against it, it can't go through even a whole frame later
+        assert_eq!(
+            stopped
+                .intersects_direction(velocity, &wall)
+                .map(|(t, _)| t),
+            Some(0.0)
+        );
+        // But it can still slide along the wall
+        assert!(stopped
+            .intersects_direction(vec3(0.0, 0.0, 3.0), &wall)
+            .is_none());
+        // Or move away from it
+        assert!(stopped
+            .intersects_direction(vec3(-3.0, 0.0, 0.0), &wall)
+            .is_none());
+    }
+
+    #[test]
+    fn should_handle_zero_velocity_axes_and_edge_hits() {
+        let block = CollisionBox::from_block_position(2.0, 0.0, 2.0);
+
+        // No movement, no hit and no NaN
+        assert!(player_box(0.0)
+            .intersects_direction(vec3(0.0, 0.0, 0.0), &block)
+            .is_none());
+        // Moving along x only, out of the block's z range
+        assert!(player_box(0.0)
+            .intersects_direction(vec3(3.0, 0.0, 0.0), &block)
+            .is_none());
+
+        // Reaches the x and z faces at the same time (t = 0.5), z is moving faster so it goes deeper
+        let (toi, normal) = CollisionBox::new(0.5, 0.0, 0.0, 1.0, 1.0, 1.0)
+            .intersects_direction(vec3(1.0, 0.0, 2.0), &block)
+            .unwrap();
+        assert_eq!(toi, 0.5);
+        assert_eq!(normal, vec3(0.0, 0.0, -1.0));
+
+        // Sweeping against many boxes returns the closest one
+        let walls = [
+            CollisionBox::from_block_position(5.0, 1.0, 0.0),
+            CollisionBox::from_block_position(2.0, 1.0, 0.0),
+        ];
+        let (toi, _) = player_box(0.0)
+            .sweep_all(vec3(6.0, 0.0, 0.0), walls.iter())
+            .unwrap();
+        assert!((toi - 0.2).abs() < 1e-5);
+    }
+
+    #[test]
+    fn should_hit_every_face_when_approaching_along_an_axis() {
+        let block = CollisionBox::from_block_position(0.0, 0.0, 0.0);
+        let unit = |x: f32, y: f32, z: f32| CollisionBox::new(x, y, z, 1.0, 1.0, 1.0);
+
+        // Starting one block away from each face and moving two blocks towards it
+        for (start, velocity) in [
+            (vec3(-2.0, 0.0, 0.0), vec3(2.0, 0.0, 0.0)),
+            (vec3(2.0, 0.0, 0.0), vec3(-2.0, 0.0, 0.0)),
+            (vec3(0.0, -2.0, 0.0), vec3(0.0, 2.0, 0.0)),
+            (vec3(0.0, 2.0, 0.0), vec3(0.0, -2.0, 0.0)),
+            (vec3(0.0, 0.0, -2.0), vec3(0.0, 0.0, 2.0)),
+            (vec3(0.0, 0.0, 2.0), vec3(0.0, 0.0, -2.0)),
+        ] {
+            let (toi, normal) = unit(start.x, start.y, start.z)
+                .intersects_direction(velocity, &block)
+                .unwrap();
+            assert!((toi - 0.5).abs() < 1e-5);
+            assert_eq!(normal, -velocity.normalize());
+        }
+
+        // Stops short of the block
+        assert!(unit(-3.0, 0.0, 0.0)
+            .intersects_direction(vec3(1.5, 0.0, 0.0), &block)
+            .is_none());
+    }
+
+    #[test]
+    fn should_not_hit_when_grazing_a_face() {
+        let block = CollisionBox::from_block_position(0.0, 0.0, 0.0);
+
+        // Sliding along the top face, only touching it
+        let on_top = CollisionBox::new(-2.0, 1.0, 0.0, 1.0, 1.0, 1.0);
+        assert!(on_top
+            .intersects_direction(vec3(4.0, 0.0, 0.0), &block)
+            .is_none());
+        // Passing the edge of the block diagonally without going in
+        let beside = CollisionBox::new(-2.0, 0.0, 1.0, 1.0, 1.0, 1.0);
+        assert!(beside
+            .intersects_direction(vec3(4.0, 0.0, 0.0), &block)
+            .is_none());
+    }
+
+    #[test]
+    fn should_traverse_every_cell_the_ray_crosses() {
+        let ray = Ray {
+            origin: vec3(0.5, 0.5, 0.5),
+            direction: vec3(1.0, 0.0, 0.0),
+        };
+        let cells: Vec<_> = ray.voxel_traverse(3.0).collect();
+        assert_eq!(
+            cells,
+            vec![
+                (ivec3(1, 0, 0), FaceDirections::Left),
+                (ivec3(2, 0, 0), FaceDirections::Left),
+                (ivec3(3, 0, 0), FaceDirections::Left),
+            ]
+        );
+
+        // Straight down, only one non zero component
+        let ray = Ray {
+            origin: vec3(-0.5, 2.5, 3.2),
+            direction: vec3(0.0, -1.0, 0.0),
+        };
+        let (cell, face) = ray.voxel_traverse(1.0).next().unwrap();
+        assert_eq!((cell, face), (ivec3(-1, 1, 3), FaceDirections::Top));
+        // No direction, no cells
+        let ray = Ray {
+            origin: vec3(0.5, 0.5, 0.5),
+            direction: vec3(0.0, 0.0, 0.0),
+        };
+        assert_eq!(ray.voxel_traverse(10.0).count(), 0);
+    }
+
+    #[test]
+    fn should_not_skip_cells_on_a_diagonal_ray() {
+        let ray = Ray {
+            origin: vec3(0.5, 0.2, 0.9),
+            direction: vec3(1.0, 1.0, -0.7),
+        };
+        let mut previous = ivec3(0, 0, 0);
+        let mut count = 0;
+        for (cell, face) in ray.voxel_traverse(10.0) {
+            // Neighbour of the previous cell, entered through the face shared with it
+            let offset = (previous - cell).as_vec3();
+            assert_eq!(offset.abs().dot(glam::Vec3::ONE), 1.0);
+            assert_eq!(face.get_normal_vector(), offset);
+            previous = cell;
+            count += 1;
+        }
+        // 10 units along that direction cross about 6 + 6 + 4 cell boundaries
+        assert!((14..=17).contains(&count));
+    }
+}
@@ -0,0 +1,159 @@
+use bytemuck::{Pod, Zeroable};
+use glam::{vec3, Vec3};
+
+// Sky colors at noon (the old fixed clear color) and at midnight
+pub const DAY_SKY: Vec3 = vec3(0.03, 0.64, 0.97);
+pub const NIGHT_SKY: Vec3 = vec3(0.01, 0.015, 0.05);
+const SUNSET_COLOR: Vec3 = vec3(1.0, 0.55, 0.3);
+// Sky around sunrise and sunset
+const TWILIGHT_SKY: Vec3 = vec3(0.95, 0.45, 0.3);
+// Straight up the sky is deeper than at the horizon, which has the fog color
+const DAY_ZENITH: Vec3 = vec3(0.01, 0.3, 0.8);
+const NIGHT_ZENITH: Vec3 = vec3(0.0, 0.0, 0.01);
+const MOON_COLOR: Vec3 = vec3(0.15, 0.18, 0.3);
+// Tilts the sun orbit towards -z, so faces pointing north and south are lit differently
+const SUN_TILT: f32 = -0.5;
+
+// Light uniform shared by the main and translucent shaders, vec4s to avoid any padding issue.
+// The sky color goes to the fog uniform of the main pipeline
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+pub struct SunUniform {
+    // xyz: direction towards the light (sun or moon), w: how much of the day it is (0-1)
+    pub direction: [f32; 4],
+    pub color: [f32; 4],
+}
+
+// time_of_day goes from 0 to 1: 0 is midnight, 0.25 sunrise, 0.5 noon and 0.75 sunset
+#[derive(Debug, Clone)]
+pub struct DayCycle {
+    pub time_of_day: f32,
+    // Real seconds for a whole day
+    pub day_length: f32,
+}
+
+impl DayCycle {
+    pub fn new(day_length: f32) -> Self {
+        Self {
+            time_of_day: 0.5,
+            day_length: day_length.max(1.0),
+        }
+    }
+    pub fn advance(&mut self, delta_time: f32) {
+        self.time_of_day = (self.time_of_day + delta_time / self.day_length).rem_euclid(1.0);
+    }
+    pub fn sun_direction(&self) -> Vec3 {
+        let angle = (self.time_of_day - 0.25) * std::f32::consts::TAU;
+        vec3(f32::cos(angle), f32::sin(angle), SUN_TILT).normalize()
+    }
+    // 0 at night, 1 during the day, blends around the horizon
+    pub fn daylight(&self) -> f32 {
+        let elevation = self.sun_direction().y;
+        ((elevation + 0.1) / 0.3).clamp(0.0, 1.0)
+    }
+    // The moon lights the world from the opposite side at night
+    pub fn light_direction(&self) -> Vec3 {
+        let sun = self.sun_direction();
+        if sun.y >= 0.0 {
+            sun
+        } else {
+            -sun
+        }
+    }
+    pub fn light_color(&self) -> Vec3 {
+        let elevation = self.sun_direction().y.max(0.0);
+        let sun = SUNSET_COLOR.lerp(Vec3::ONE, (elevation * 3.0).min(1.0));
+        MOON_COLOR.lerp(sun, self.daylight())
+    }
+    // Night -> dawn -> noon -> dusk -> night, the twilight tint peaks with the sun on the horizon
+    pub fn sky_color(&self) -> Vec3 {
+        let twilight = (1.0 - self.sun_direction().y.abs() / 0.3).max(0.0);
+        NIGHT_SKY
+            .lerp(DAY_SKY, self.daylight())
+            .lerp(TWILIGHT_SKY, twilight * 0.6)
+    }
+    // The twilight only tints the sky around the horizon
+    pub fn zenith_color(&self) -> Vec3 {
+        NIGHT_ZENITH.lerp(DAY_ZENITH, self.daylight())
+    }
+    // Stars fade in as the daylight goes
+    pub fn star_visibility(&self) -> f32 {
+        1.0 - self.daylight()
+    }
+    pub fn uniform(&self) -> SunUniform {
+        let direction = self.light_direction();
+        SunUniform {
+            direction: [direction.x, direction.y, direction.z, self.daylight()],
+            color: self.light_color().extend(1.0).to_array(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DayCycle, DAY_SKY, NIGHT_SKY};
+    use glam::Vec3;
+
+    #[test]
+    fn should_go_from_a_lit_noon_to_a_dark_midnight() {
+        let mut cycle = DayCycle::new(600.0);
+        assert_eq!(cycle.daylight(), 1.0);
+        assert!(cycle.sun_direction().y > 0.8);
+        assert!((cycle.sky_color() - DAY_SKY).length() < 1e-5);
+        assert!((cycle.light_color() - glam::Vec3::ONE).length() < 1e-5);
+
+        // Half a day later
+        cycle.advance(300.0);
+        assert!(cycle.time_of_day.abs() < 1e-5 || (cycle.time_of_day - 1.0).abs() < 1e-5);
+        assert_eq!(cycle.daylight(), 0.0);
+        assert!((cycle.sky_color() - NIGHT_SKY).length() < 1e-5);
+        // Lit from above by the moon
+        assert!(cycle.light_direction().y > 0.8);
+
+        // Wraps around
+        cycle.advance(900.0);
+        assert!((cycle.time_of_day - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn should_deepen_the_zenith_and_only_show_stars_at_night() {
+        let mut cycle = DayCycle::new(600.0);
+        assert_eq!(cycle.star_visibility(), 0.0);
+        assert!(cycle.zenith_color().length() < cycle.sky_color().length());
+        assert!(cycle.zenith_color().z > cycle.zenith_color().x);
+
+        cycle.time_of_day = 0.0;
+        assert_eq!(cycle.star_visibility(), 1.0);
+        assert!(cycle.zenith_color().length() < 0.05);
+        // Fading in around the sunset
+        cycle.time_of_day = 0.75;
+        assert!(cycle.star_visibility() > 0.0 && cycle.star_visibility() < 1.0);
+    }
+
+    #[test]
+    fn should_color_the_sky_from_a_black_midnight_to_a_blue_noon() {
+        let mut cycle = DayCycle::new(600.0);
+        let sky_at = |cycle: &mut DayCycle, time_of_day: f32| {
+            cycle.time_of_day = time_of_day;
+            cycle.sky_color()
+        };
+
+        let midnight = sky_at(&mut cycle, 0.0);
+        assert!(midnight.length() < 0.1);
+        let noon = sky_at(&mut cycle, 0.5);
+        assert!(noon.z > 0.9 && noon.z > noon.x && noon.z > noon.y);
+        // Dawn and dusk are reddish, brighter than the night but not blue
+        for twilight in [0.25, 0.75] {
+            let sky = sky_at(&mut cycle, twilight);
+            assert!(sky.x > sky.z && sky.length() > midnight.length());
+        }
+        // Nothing jumps between two frames
+        let mut previous = sky_at(&mut cycle, 0.0);
+        for i in 1..=1000 {
+            let sky = sky_at(&mut cycle, i as f32 / 1000.0);
+            assert!(sky.distance(previous) < 0.05);
+            assert!(sky.cmpge(Vec3::ZERO).all() && sky.cmple(Vec3::ONE).all());
+            previous = sky;
+        }
+    }
+}
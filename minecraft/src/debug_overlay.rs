@@ -0,0 +1,191 @@
+use std::fmt::Write;
+
+use crate::blocks::block::FaceDirections;
+use crate::blocks::block_type::BlockType;
+
+// The fps and frame time are averaged over this many seconds, so they can be read
+const FPS_INTERVAL: f32 = 0.5;
+const LINE_COUNT: usize = 7;
+
+// What the overlay shows, gathered by the state every frame while it's visible
+#[derive(Debug, Clone, Default)]
+pub struct DebugStats {
+    pub position: glam::Vec3,
+    pub chunk: (i32, i32),
+    pub facing: Option<(BlockType, FaceDirections)>,
+    pub loaded_chunks: usize,
+    // Of the chunks in view
+    pub vertices: usize,
+    pub present_mode: wgpu::PresentMode,
+    // Cap of the frame limiter
+    pub max_fps: Option<u32>,
+}
+
+// A line and the values it was formatted from, it's only formatted again when they change
+#[derive(Debug, Default)]
+struct DebugLine {
+    key: Option<[i64; 3]>,
+    text: String,
+}
+
+// Text in the top left corner with the frame rate, where the player is and what it looks at.
+// Toggled with F3
+#[derive(Debug, Default)]
+pub struct DebugOverlay {
+    pub visible: bool,
+    lines: [DebugLine; LINE_COUNT],
+    // Bumped whenever a line changes, the text is only laid out again then
+    revision: u64,
+    frames: u32,
+    frames_time: f32,
+    fps: u32,
+    frame_time: f32,
+}
+
+impl DebugOverlay {
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+    pub fn lines(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().map(|line| line.text.as_str())
+    }
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+    // `delta_time` is the raw frame time, before any smoothing
+    pub fn update(&mut self, delta_time: f32, stats: &DebugStats) {
+        self.frames += 1;
+        self.frames_time += delta_time;
+        if self.frames_time >= FPS_INTERVAL {
+            self.fps = (self.frames as f32 / self.frames_time).round() as u32;
+            self.frame_time = self.frames_time / self.frames as f32;
+            (self.frames, self.frames_time) = (0, 0.0);
+        }
+
+        let (fps, frame_time) = (self.fps, self.frame_time * 1000.0);
+        self.set_line(
+            0,
+            [fps as i64, (frame_time * 10.0).round() as i64, 0],
+            |text| write!(text, "FPS: {fps} ({frame_time:.1} ms)"),
+        );
+        let (present_mode, max_fps) = (stats.present_mode, stats.max_fps);
+        self.set_line(
+            1,
+            [present_mode as i64, max_fps.map_or(-1, i64::from), 0],
+            |text| match max_fps {
+                Some(max_fps) => write!(text, "Present: {present_mode:?} (max {max_fps} fps)"),
+                None => write!(text, "Present: {present_mode:?}"),
+            },
+        );
+        let position = stats.position;
+        let hundredths = (position * 100.0).round();
+        self.set_line(
+            2,
+            [hundredths.x, hundredths.y, hundredths.z].map(|v| v as i64),
+            |text| {
+                write!(
+                    text,
+                    "XYZ: {:.2} / {:.2} / {:.2}",
+                    position.x, position.y, position.z
+                )
+            },
+        );
+        let (x, z) = stats.chunk;
+        self.set_line(3, [x as i64, z as i64, 0], |text| {
+            write!(text, "Chunk: {x}, {z}")
+        });
+        let facing = stats.facing;
+        let key = facing.map_or([-1, -1, 0], |(block_type, face)| {
+            let face = FaceDirections::all().iter().position(|f| *f == face);
+            [block_type.get_config().id as i64, face.unwrap() as i64, 0]
+        });
+        self.set_line(4, key, |text| match facing {
+            Some((block_type, face)) => write!(text, "Facing: {block_type:?} ({face:?})"),
+            None => write!(text, "Facing: -"),
+        });
+        let loaded_chunks = stats.loaded_chunks;
+        self.set_line(5, [loaded_chunks as i64, 0, 0], |text| {
+            write!(text, "Chunks: {loaded_chunks}")
+        });
+        let vertices = stats.vertices;
+        self.set_line(6, [vertices as i64, 0, 0], |text| {
+            write!(text, "Vertices: {vertices}")
+        });
+    }
+    // Formats the line into its old text, keeping the allocation, only if `key` changed
+    fn set_line(
+        &mut self,
+        index: usize,
+        key: [i64; 3],
+        format: impl FnOnce(&mut String) -> std::fmt::Result,
+    ) {
+        let line = &mut self.lines[index];
+        if line.key == Some(key) {
+            return;
+        }
+        line.key = Some(key);
+        line.text.clear();
+        format(&mut line.text).unwrap();
+        self.revision += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DebugOverlay, DebugStats, FPS_INTERVAL};
+    use crate::blocks::block::FaceDirections;
+    use crate::blocks::block_type::BlockType;
+
+    #[test]
+    fn should_only_format_the_lines_whose_values_changed() {
+        let mut overlay = DebugOverlay::default();
+        let mut stats = DebugStats {
+            position: glam::vec3(12.3, 64.0, -3.5),
+            chunk: (0, -1),
+            facing: Some((BlockType::Stone, FaceDirections::Top)),
+            loaded_chunks: 121,
+            vertices: 40_000,
+            present_mode: wgpu::PresentMode::Immediate,
+            max_fps: Some(144),
+        };
+        // A bit over the averaging interval at 60 fps
+        let frame = 1.0 / 60.0;
+        for _ in 0..=(FPS_INTERVAL * 60.0) as usize {
+            overlay.update(frame, &stats);
+        }
+        let lines: Vec<_> = overlay.lines().map(str::to_string).collect();
+        assert_eq!(
+            lines,
+            [
+                "FPS: 60 (16.7 ms)",
+                "Present: Immediate (max 144 fps)",
+                "XYZ: 12.30 / 64.00 / -3.50",
+                "Chunk: 0, -1",
+                "Facing: Stone (Top)",
+                "Chunks: 121",
+                "Vertices: 40000",
+            ]
+        );
+
+        // Same values, nothing is formatted nor allocated again
+        let revision = overlay.revision();
+        let buffers: Vec<_> = overlay.lines.iter().map(|l| l.text.as_ptr()).collect();
+        overlay.update(frame, &stats);
+        assert_eq!(overlay.revision(), revision);
+        assert!(overlay
+            .lines
+            .iter()
+            .zip(&buffers)
+            .all(|(l, b)| l.text.as_ptr() == *b));
+
+        // Moving less than what's shown doesn't change the text either
+        stats.position.x += 0.001;
+        overlay.update(frame, &stats);
+        assert_eq!(overlay.revision(), revision);
+
+        stats.facing = None;
+        overlay.update(frame, &stats);
+        assert_eq!(overlay.revision(), revision + 1);
+        assert_eq!(overlay.lines().nth(4), Some("Facing: -"));
+    }
+}
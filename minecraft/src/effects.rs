@@ -1,8 +1,9 @@
 pub mod ao {
+    use crate::blocks::block::{FaceDirections, CUBE_VERTEX};
     use crate::blocks::block_type::BlockType;
-    use crate::chunk::BlockVec;
+    use crate::chunk::ChunkBlocks;
     use crate::utils::{ChunkFromPosition, RelativeFromAbsolute};
-    use crate::world::CHUNK_SIZE;
+    use crate::world::WORLD_MIN_Y;
 
     pub(crate) fn calc_vertex_ao(side1: bool, side2: bool, up: bool) -> u8 {
         if side1 && side2 {
@@ -10,47 +11,55 @@ pub mod ao {
         }
         3 - (side1 as u8 + side2 as u8 + up as u8)
     }
+    // Samples the three blocks in front of the face that touch the vertex: the two next to it along
+    // the face's tangents and the one in the corner between them. `corner` is the index of the vertex
+    // in `CUBE_VERTEX`, its sign on each axis tells which way the neighbours are
     pub(crate) fn from_vertex_position(
         vertex_position: &glam::Vec3,
-        blocks_positions: &Vec<((i32, i32), BlockVec)>,
+        face: FaceDirections,
+        corner: usize,
+        blocks_positions: &[((i32, i32), &ChunkBlocks)],
     ) -> u8 {
-        let side1_position = *vertex_position + glam::vec3(1.0, 1.0, 0.0);
-        let side2_position = *vertex_position + glam::vec3(0.0, 1.0, 1.0);
-        let corner_position = *vertex_position + glam::vec3(1.0, 1.0, 1.0);
+        let corner_offset = glam::Vec3::from_slice(&CUBE_VERTEX[corner * 3..corner * 3 + 3]);
+        let normal = face.get_normal_vector();
+        // Center of the cell in front of the face
+        let front = *vertex_position - corner_offset + normal;
+        let (tangent1, tangent2) = if normal.x != 0.0 {
+            (glam::Vec3::Y, glam::Vec3::Z)
+        } else if normal.y != 0.0 {
+            (glam::Vec3::X, glam::Vec3::Z)
+        } else {
+            (glam::Vec3::X, glam::Vec3::Y)
+        };
+        let side1_offset = tangent1 * (corner_offset * 2.0);
+        let side2_offset = tangent2 * (corner_offset * 2.0);
 
-        let side1_chunk = side1_position.get_chunk_from_position_absolute();
-        let side1_position = side1_position.relative_from_absolute();
-
-        let side2_chunk = side2_position.get_chunk_from_position_absolute();
-        let side2_position = side2_position.relative_from_absolute();
-
-        let corner_chunk = corner_position.get_chunk_from_position_absolute();
-        let corner_position = corner_position.relative_from_absolute();
+        let side1_position = front + side1_offset;
+        let side2_position = front + side2_offset;
+        let corner_position = front + side1_offset + side2_offset;
 
+        let Some((_, first)) = blocks_positions.first() else {
+            return calc_vertex_ao(false, false, false);
+        };
+        let chunk_size = first.chunk_size();
         let mut has_side1 = false;
         let mut has_side2 = false;
         let mut has_corner = false;
 
-        for (position, chunk, val) in [
-            (side1_position, side1_chunk, &mut has_side1),
-            (side2_position, side2_chunk, &mut has_side2),
-            (corner_position, corner_chunk, &mut has_corner),
+        for (position, val) in [
+            (side1_position, &mut has_side1),
+            (side2_position, &mut has_side2),
+            (corner_position, &mut has_corner),
         ] {
-            if let Some(blocks) = blocks_positions.iter().find_map(|c| {
-                if c.0 == chunk {
-                    Some(c.1.clone())
-                } else {
-                    None
-                }
-            }) {
-                let blocks = blocks.read().unwrap();
-                let ycol = &blocks[((position.x * CHUNK_SIZE as f32) + position.z) as usize];
-                if let Some(place) = ycol.get(position.y as usize) {
-                    if let Some(block) = place {
-                        if block.read().unwrap().block_type != BlockType::Water {
-                            *val = true
-                        }
-                    }
+            // Below the world, nothing to occlude
+            if position.y < WORLD_MIN_Y as f32 {
+                continue;
+            }
+            let chunk = position.get_chunk_from_position_absolute(chunk_size);
+            let position = position.relative_from_absolute(chunk_size);
+            if let Some((_, blocks)) = blocks_positions.iter().find(|c| c.0 == chunk) {
+                if let Some(block_type) = blocks.type_at(&position) {
+                    *val = block_type != BlockType::Water && block_type.is_full_cube();
                 }
             }
         }
@@ -61,4 +70,96 @@ pub mod ao {
     pub(crate) fn convert_ao_u8_to_f32(ao: u8) -> f32 {
         1.0 - (ao as f32 / 3.0)
     }
+
+    #[cfg(test)]
+    mod tests {
+        use super::from_vertex_position;
+        use crate::blocks::block::{FaceDirections, CUBE_VERTEX};
+        use crate::blocks::block_type::BlockType;
+        use crate::chunk::ChunkBlocks;
+        use crate::world::DEFAULT_CHUNK_SIZE;
+
+        const FACES: [FaceDirections; 6] = [
+            FaceDirections::Front,
+            FaceDirections::Back,
+            FaceDirections::Left,
+            FaceDirections::Right,
+            FaceDirections::Top,
+            FaceDirections::Bottom,
+        ];
+
+        fn chunk_with(positions: &[glam::Vec3]) -> ChunkBlocks {
+            let mut blocks = ChunkBlocks::new((0, 0), DEFAULT_CHUNK_SIZE);
+            for position in positions {
+                blocks.set_at(position, Some(BlockType::Stone));
+            }
+            blocks
+        }
+        // AO of every vertex of a face of the block at `center`, with its offset from the center
+        fn face_ao(
+            center: glam::Vec3,
+            face: FaceDirections,
+            blocks: &ChunkBlocks,
+        ) -> Vec<(glam::Vec3, u8)> {
+            let mut corners = face.get_indices().to_vec();
+            corners.sort();
+            corners.dedup();
+            corners
+                .into_iter()
+                .map(|corner| {
+                    let corner = corner as usize;
+                    let offset = glam::Vec3::from_slice(&CUBE_VERTEX[corner * 3..corner * 3 + 3]);
+                    let ao =
+                        from_vertex_position(&(center + offset), face, corner, &[((0, 0), blocks)]);
+                    (offset, ao)
+                })
+                .collect()
+        }
+
+        #[test]
+        fn should_only_darken_the_vertex_next_to_a_corner_block_on_every_face() {
+            let center = glam::vec3(5.0, 5.0, 5.0);
+            for face in FACES {
+                let normal = face.get_normal_vector();
+                // Diagonal to the face, in front of it, touching only one of its vertices
+                let diagonal = glam::Vec3::ONE - normal.abs();
+                let blocks = chunk_with(&[center, center + normal + diagonal]);
+
+                let vertices = face_ao(center, face, &blocks);
+                assert_eq!(vertices.len(), 4);
+                for (offset, ao) in vertices {
+                    let touches = (offset * 2.0) * diagonal == diagonal;
+                    assert_eq!(ao, if touches { 2 } else { 3 }, "{face:?} {offset}");
+                }
+            }
+        }
+
+        #[test]
+        fn should_fully_occlude_a_vertex_between_two_side_blocks() {
+            let center = glam::vec3(5.0, 5.0, 5.0);
+            // A step on top, along +x and +z
+            let blocks = chunk_with(&[
+                center,
+                center + glam::vec3(1.0, 1.0, 0.0),
+                center + glam::vec3(0.0, 1.0, 1.0),
+            ]);
+            let vertices = face_ao(center, FaceDirections::Top, &blocks);
+            let ao_at = |x: f32, z: f32| {
+                vertices
+                    .iter()
+                    .find(|(offset, _)| offset.x == x && offset.z == z)
+                    .unwrap()
+                    .1
+            };
+            assert_eq!(ao_at(0.5, 0.5), 0);
+            assert_eq!(ao_at(-0.5, -0.5), 3);
+            assert_eq!(ao_at(0.5, -0.5), 2);
+            assert_eq!(ao_at(-0.5, 0.5), 2);
+
+            // Nothing below the world occludes the bottom of the lowest blocks
+            let blocks = chunk_with(&[glam::vec3(5.0, 0.0, 5.0), glam::vec3(5.0, 0.0, 6.0)]);
+            let vertices = face_ao(glam::vec3(5.0, 0.0, 5.0), FaceDirections::Bottom, &blocks);
+            assert!(vertices.iter().all(|(_, ao)| *ao == 3));
+        }
+    }
 }
@@ -0,0 +1,130 @@
+use std::time::{Duration, Instant};
+
+// The last part of the wait is spun instead of slept, sleeps overshoot by about a millisecond
+const SPIN_MARGIN: Duration = Duration::from_millis(1);
+
+// Smooths the frame time with a clamped exponential moving average so a single slow frame
+// doesn't make the camera jump. The raw frame time is kept for profiling.
+#[derive(Debug, Clone)]
+pub struct FramePacing {
+    // Weight of the newest frame time, 1 uses the raw (clamped) delta
+    pub smoothing: f32,
+    // Frame times above this (a breakpoint, a stall while loading) are clamped to it
+    pub max_delta: f32,
+    pub raw_delta: f32,
+    pub smoothed_delta: f32,
+    first_frame: bool,
+}
+
+impl FramePacing {
+    pub fn new(smoothing: f32, max_delta: f32) -> Self {
+        Self {
+            smoothing: smoothing.clamp(0.0, 1.0),
+            max_delta,
+            raw_delta: 0.0,
+            smoothed_delta: 0.0,
+            first_frame: true,
+        }
+    }
+    // Returns the delta time to simulate with
+    pub fn update(&mut self, raw_delta: f32) -> f32 {
+        self.raw_delta = raw_delta;
+        let delta = raw_delta.clamp(0.0, self.max_delta);
+
+        if self.first_frame {
+            self.smoothed_delta = delta;
+            self.first_frame = false;
+        } else {
+            self.smoothed_delta += (delta - self.smoothed_delta) * self.smoothing;
+        }
+        self.smoothed_delta
+    }
+}
+
+// Caps the frame rate by waiting on the cpu after a frame is presented, for the present modes
+// that don't wait for the display. The wait is part of the next frame time, so the simulation
+// speed doesn't change
+#[derive(Debug, Clone)]
+pub struct FrameLimiter {
+    interval: Duration,
+    // When the next frame may start
+    next_frame: Option<Instant>,
+}
+
+impl FrameLimiter {
+    pub fn new(max_fps: u32) -> Self {
+        Self {
+            interval: Duration::from_secs(1) / max_fps.max(1),
+            next_frame: None,
+        }
+    }
+    pub fn max_fps(&self) -> u32 {
+        (1.0 / self.interval.as_secs_f64()).round() as u32
+    }
+    // How long to wait from `now`. The frames are scheduled one interval apart, a late frame moves
+    // the schedule instead of letting the next ones catch up
+    pub fn delay(&mut self, now: Instant) -> Duration {
+        let start = self.next_frame.map_or(now, |next| next.max(now));
+        self.next_frame = Some(start + self.interval);
+        start - now
+    }
+    pub fn wait(&mut self) {
+        let now = Instant::now();
+        let deadline = now + self.delay(now);
+        if let Some(sleep) = (deadline - now).checked_sub(SPIN_MARGIN) {
+            std::thread::sleep(sleep);
+        }
+        while Instant::now() < deadline {
+            std::hint::spin_loop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FrameLimiter, FramePacing};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn should_clamp_frame_time_spikes() {
+        let mut pacing = FramePacing::new(1.0, 0.1);
+        assert_eq!(pacing.update(0.016), 0.016);
+        assert_eq!(pacing.update(5.0), 0.1);
+        assert_eq!(pacing.raw_delta, 5.0);
+
+        // With smoothing the spike is spread over the next frames but never above the max
+        let mut pacing = FramePacing::new(0.2, 0.1);
+        pacing.update(0.016);
+        let after_spike = pacing.update(5.0);
+        assert!(after_spike > 0.016 && after_spike < 0.1);
+        for _ in 0..100 {
+            assert!(pacing.update(5.0) <= 0.1);
+        }
+        assert!((pacing.smoothed_delta - 0.1).abs() < 1e-4);
+    }
+
+    #[test]
+    fn should_space_the_frames_without_catching_up_after_a_late_one() {
+        let mut limiter = FrameLimiter::new(50);
+        assert_eq!(limiter.max_fps(), 50);
+        let interval = Duration::from_millis(20);
+        let start = Instant::now();
+        // The first frame doesn't wait
+        assert_eq!(limiter.delay(start), Duration::ZERO);
+        // A frame that took 5 ms waits the rest of the interval
+        assert_eq!(
+            limiter.delay(start + Duration::from_millis(5)),
+            interval - Duration::from_millis(5)
+        );
+        // One that took 50 ms starts right away and the next one waits a whole interval again
+        let late = start + interval + Duration::from_millis(50);
+        assert_eq!(limiter.delay(late), Duration::ZERO);
+        assert_eq!(limiter.delay(late), interval);
+
+        let before = Instant::now();
+        let mut limiter = FrameLimiter::new(100);
+        limiter.wait();
+        limiter.wait();
+        assert!(before.elapsed() >= Duration::from_millis(10));
+    }
+}
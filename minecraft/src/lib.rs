@@ -0,0 +1,71 @@
+//! Voxel engine behind the diorama.
+//!
+//! The binary only owns the window and the event loop, everything else lives here so it
+//! can be embedded by another application. The entry points are:
+//!
+//! - [`World::init_world`] + [`World::init_chunks`] to generate (or load) the chunks around a [`Player`].
+//! - [`World::get_block`] and [`World::set_block`] to read and edit the block at a world position.
+//! - [`State`] if you want the whole renderer driving a `winit` window.
+//!
+//! Terrain generation can also be used without a gpu:
+//!
+//! ```
+//! use minecraft::{noise, BlockType, Chunk};
+//! use std::sync::Arc;
+//!
+//! let noise_data = Arc::new(noise::create_world_noise_data(
+//!     minecraft::world::NOISE_SIZE,
+//!     minecraft::world::NOISE_SIZE,
+//!     minecraft::world::FREQUENCY,
+//!     minecraft::world::RNG_SEED,
+//! ));
+//! let blocks = Chunk::create_blocks_data(0, 0, noise_data);
+//! let blocks = blocks.read().unwrap();
+//!
+//! assert!(blocks.column_height(0, blocks.chunk_size() - 1) > 0);
+//! assert!(blocks.get(0, 0, 0) != Some(BlockType::Water));
+//! ```
+//!
+//! See `examples/embed.rs` for a headless world queried through this api.
+
+#[macro_use]
+extern crate lazy_static;
+
+pub mod biome;
+pub mod blocks;
+pub mod chunk;
+pub mod collision;
+pub mod day_cycle;
+pub mod debug_overlay;
+pub(crate) mod effects;
+pub mod frame_pacing;
+pub(crate) mod lighting;
+pub(crate) mod macros;
+pub mod material;
+pub(crate) mod mesh_cache;
+pub mod persistence;
+pub mod pipeline;
+pub(crate) mod pipelines;
+pub mod player;
+pub mod screenshot;
+pub mod state;
+pub mod structures;
+pub mod utils;
+pub mod world;
+pub mod worldgen;
+
+/// Block storage and the block kinds that can be generated or placed.
+pub use blocks::{block::Block, block_type::BlockType};
+/// A column of blocks, `WorldConfig::chunk_size` wide, with its gpu buffers.
+pub use chunk::Chunk;
+/// Axis aligned boxes and rays used for collisions and block picking.
+pub use collision::{CollisionBox, Ray};
+/// Implement it to add a render pass to the pipeline manager.
+pub use pipelines::Pipeline;
+pub use player::{Camera, Player};
+pub use state::{Config, State};
+/// Structures are groups of blocks placed during generation, like trees.
+pub use structures::Structure;
+/// Perlin noise used by the terrain generation.
+pub use utils::noise;
+pub use world::{World, WorldConfig, DEFAULT_CHUNK_SIZE};
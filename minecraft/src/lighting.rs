@@ -0,0 +1,559 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use glam::{IVec3, Vec3};
+use wgpu::util::DeviceExt;
+
+use crate::blocks::block::Block;
+use crate::blocks::block_type::BlockType;
+use crate::chunk::ChunkBlocks;
+use crate::world::WORLD_MIN_Y;
+
+pub const MAX_LIGHT: u8 = 15;
+// A light level can travel at most MAX_LIGHT - 1 cells, so that many relaxation steps always converge
+const GPU_ITERATIONS: u32 = MAX_LIGHT as u32;
+const OPAQUE_BIT: u32 = 1 << 8;
+// Sunlight lost on every water cell it goes through
+const WATER_SKY_FALLOFF: u8 = 2;
+
+// Dense grid of cells to relight, usually a chunk plus a border of its neighbours.
+// Cells are indexed by x + size_x * (y + size_y * z)
+#[derive(Debug, Clone)]
+pub struct LightGrid {
+    pub size: [u32; 3],
+    pub opaque: Vec<bool>,
+    pub emission: Vec<u8>,
+}
+
+impl LightGrid {
+    pub fn new(size: [u32; 3]) -> Self {
+        let len = (size[0] * size[1] * size[2]) as usize;
+        Self {
+            size,
+            opaque: vec![false; len],
+            emission: vec![0; len],
+        }
+    }
+    pub fn index(&self, x: u32, y: u32, z: u32) -> usize {
+        (x + self.size[0] * (y + self.size[1] * z)) as usize
+    }
+    pub fn set_opaque(&mut self, x: u32, y: u32, z: u32) {
+        let i = self.index(x, y, z);
+        self.opaque[i] = true;
+    }
+    pub fn set_emission(&mut self, x: u32, y: u32, z: u32, level: u8) {
+        let i = self.index(x, y, z);
+        self.emission[i] = level.min(MAX_LIGHT);
+    }
+    fn neighbours(&self, i: usize) -> impl Iterator<Item = usize> + '_ {
+        let [sx, sy, sz] = self.size;
+        let i = i as u32;
+        let (x, y, z) = (i % sx, (i / sx) % sy, i / (sx * sy));
+        [
+            (x > 0).then(|| i - 1),
+            (x + 1 < sx).then(|| i + 1),
+            (y > 0).then(|| i - sx),
+            (y + 1 < sy).then(|| i + sx),
+            (z > 0).then(|| i - sx * sy),
+            (z + 1 < sz).then(|| i + sx * sy),
+        ]
+        .into_iter()
+        .flatten()
+        .map(|n| n as usize)
+    }
+    // Packs the grid in the layout expected by the compute shader
+    fn to_gpu_cells(&self) -> Vec<u32> {
+        self.emission
+            .iter()
+            .zip(self.opaque.iter())
+            .map(|(e, o)| *e as u32 | if *o { OPAQUE_BIT } else { 0 })
+            .collect()
+    }
+}
+
+// Breadth first flood fill from every emitter, light decreases by one per cell and stops at opaque cells.
+pub fn propagate_cpu(grid: &LightGrid) -> Vec<u8> {
+    let mut light = grid.emission.clone();
+    let mut queue: VecDeque<usize> = (0..light.len()).filter(|i| light[*i] > 0).collect();
+
+    while let Some(i) = queue.pop_front() {
+        let level = light[i];
+        if level <= 1 {
+            continue;
+        }
+        for n in grid.neighbours(i) {
+            if !grid.opaque[n] && light[n] < level - 1 {
+                light[n] = level - 1;
+                queue.push_back(n);
+            }
+        }
+    }
+    light
+}
+
+// Block light of a chunk, propagated over the chunk and a border with the light from its neighbours
+pub struct ChunkLight {
+    // Absolute position of the grid cell 0
+    origin: IVec3,
+    grid: LightGrid,
+    light: Vec<u8>,
+}
+
+impl ChunkLight {
+    // `chunks` has the chunk itself and the ones around it. None if no emitter reaches the chunk.
+    // The light spreads with the compute shader when `gpu` is set
+    pub fn new(
+        chunk: (i32, i32),
+        chunks: &[((i32, i32), &ChunkBlocks)],
+        gpu: Option<&GpuLighting>,
+    ) -> Option<Self> {
+        let chunk_size = chunks[0].1.chunk_size();
+        let border = MAX_LIGHT as i32;
+        let origin = IVec3::new(
+            chunk.0 * chunk_size as i32 - border,
+            0,
+            chunk.1 * chunk_size as i32 - border,
+        );
+        let size_xz = chunk_size + 2 * border as u32;
+        let mut height = 0;
+        let mut has_emitters = false;
+
+        for (coords, blocks) in chunks.iter() {
+            height = height.max(blocks.height() + 1);
+            for (position, block_type) in blocks.iter() {
+                has_emitters |= block_type.get_config().light_emission > 0
+                    && (*coords == chunk
+                        || Block::new(position, *coords, block_type, chunk_size)
+                            .get_neighbour_chunks_coords()
+                            .contains(&chunk));
+            }
+        }
+        if !has_emitters {
+            return None;
+        }
+
+        let mut grid = LightGrid::new([size_xz, height, size_xz]);
+        for (coords, blocks) in chunks.iter() {
+            let chunk_origin = IVec3::new(
+                coords.0 * chunk_size as i32,
+                0,
+                coords.1 * chunk_size as i32,
+            );
+            for (position, block_type) in blocks.iter() {
+                let p = chunk_origin + position.as_ivec3() - origin;
+                if p.min_element() < 0 || p.x as u32 >= size_xz || p.z as u32 >= size_xz {
+                    continue;
+                }
+                let (x, y, z) = (p.x as u32, p.y as u32, p.z as u32);
+                let config = block_type.get_config();
+                if config.light_emission > 0 {
+                    grid.set_emission(x, y, z, config.light_emission);
+                } else if !config.is_translucent {
+                    grid.set_opaque(x, y, z);
+                }
+            }
+        }
+        let light = propagate(&grid, gpu);
+        Some(Self {
+            origin,
+            grid,
+            light,
+        })
+    }
+    // Light level (0 - MAX_LIGHT) of the cell at an absolute position
+    pub fn at(&self, absolute_position: Vec3) -> u8 {
+        let p = absolute_position.floor().as_ivec3() - self.origin;
+        let [sx, sy, sz] = self.grid.size;
+        if p.min_element() < 0 || p.x as u32 >= sx || p.y as u32 >= sy || p.z as u32 >= sz {
+            return 0;
+        }
+        self.light[self.grid.index(p.x as u32, p.y as u32, p.z as u32)]
+    }
+}
+
+// Sunlight of a chunk and a one cell border (the cells in front of its faces). It goes straight down
+// each column from the sky until an opaque block, water dims it on the way
+pub struct SkyLight {
+    // Absolute position of the cell 0, indexed like `LightGrid`
+    origin: IVec3,
+    size: [u32; 3],
+    light: Vec<u8>,
+}
+
+impl SkyLight {
+    // `chunks` has the chunk itself and the ones around it, missing ones count as open sky
+    pub fn new(chunk: (i32, i32), chunks: &[((i32, i32), &ChunkBlocks)]) -> Self {
+        let size = chunks[0].1.chunk_size() as i32;
+        let origin = IVec3::new(chunk.0 * size - 1, 0, chunk.1 * size - 1);
+        let size_xz = size as u32 + 2;
+        let height = chunks
+            .iter()
+            .map(|(_, blocks)| blocks.height())
+            .max()
+            .unwrap_or(0);
+        let mut light = vec![0; (size_xz * size_xz * height) as usize];
+
+        for z in 0..size_xz {
+            for x in 0..size_xz {
+                let absolute = origin + IVec3::new(x as i32, 0, z as i32);
+                let coords = (absolute.x.div_euclid(size), absolute.z.div_euclid(size));
+                let (block_x, block_z) = (
+                    absolute.x.rem_euclid(size) as u32,
+                    absolute.z.rem_euclid(size) as u32,
+                );
+                let blocks = chunks.iter().find(|(c, _)| *c == coords).map(|(_, b)| b);
+                let mut level = MAX_LIGHT;
+                for y in (0..height).rev() {
+                    match blocks.and_then(|blocks| blocks.get(block_x, y, block_z)) {
+                        Some(BlockType::Water) => level = level.saturating_sub(WATER_SKY_FALLOFF),
+                        Some(block_type)
+                            if !block_type.get_config().is_translucent
+                                && block_type.is_full_cube() =>
+                        {
+                            break
+                        }
+                        _ => {}
+                    }
+                    light[(x + size_xz * (y + height * z)) as usize] = level;
+                }
+            }
+        }
+        Self {
+            origin,
+            size: [size_xz, height, size_xz],
+            light,
+        }
+    }
+    // Light level (0 - MAX_LIGHT) of the cell at an absolute position, the sky is above every block
+    pub fn at(&self, absolute_position: Vec3) -> u8 {
+        if absolute_position.y < WORLD_MIN_Y as f32 {
+            return 0;
+        }
+        let p = absolute_position.floor().as_ivec3() - self.origin;
+        let [sx, sy, sz] = self.size;
+        if p.x < 0 || p.z < 0 || p.x as u32 >= sx || p.y as u32 >= sy || p.z as u32 >= sz {
+            return MAX_LIGHT;
+        }
+        self.light[(p.x as u32 + sx * (p.y as u32 + sy * p.z as u32)) as usize]
+    }
+}
+
+#[derive(Debug)]
+pub struct GpuLighting {
+    pub pipeline: wgpu::ComputePipeline,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    // Kept to run the propagation from the meshing threads
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
+}
+
+impl GpuLighting {
+    // Returns None if the adapter can't run compute shaders, callers should use `propagate_cpu` instead
+    pub fn new(
+        adapter: &wgpu::Adapter,
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+    ) -> Option<Self> {
+        if !adapter
+            .get_downlevel_capabilities()
+            .flags
+            .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS)
+        {
+            return None;
+        }
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("light_propagation"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("./shaders/light_compute.wgsl").into()),
+        });
+
+        let storage_entry = |binding: u32, read_only: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("light_propagation_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage_entry(1, true),
+                storage_entry(2, true),
+                storage_entry(3, false),
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("light_propagation"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("light_propagation"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "cs_main",
+        });
+
+        Some(Self {
+            pipeline,
+            bind_group_layout,
+            device,
+            queue,
+        })
+    }
+
+    // Runs the propagation with two ping-pong light buffers and blocks until the result is read back.
+    pub fn propagate(&self, grid: &LightGrid) -> Vec<u8> {
+        let (device, queue) = (&self.device, &self.queue);
+        let cells = grid.to_gpu_cells();
+        let initial_light: Vec<u32> = grid.emission.iter().map(|e| *e as u32).collect();
+        let buffer_size = (cells.len() * std::mem::size_of::<u32>()) as wgpu::BufferAddress;
+
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("light_params"),
+            contents: bytemuck::cast_slice(&[grid.size[0], grid.size[1], grid.size[2], 0]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let cells_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("light_cells"),
+            contents: bytemuck::cast_slice(&cells),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let light_buffers = [0, 1].map(|i| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("light_{}", i)),
+                contents: bytemuck::cast_slice(&initial_light),
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            })
+        });
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("light_readback"),
+            size: buffer_size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        // bind_groups[0] reads from buffer 0 and writes to 1, bind_groups[1] the other way around
+        let bind_groups = [(0, 1), (1, 0)].map(|(src, dst): (usize, usize)| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("light_propagation"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: params_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: cells_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: light_buffers[src].as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: light_buffers[dst].as_entire_binding(),
+                    },
+                ],
+            })
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("light_propagation"),
+        });
+        {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: None,
+                timestamp_writes: None,
+            });
+            cpass.set_pipeline(&self.pipeline);
+            for i in 0..GPU_ITERATIONS {
+                cpass.set_bind_group(0, &bind_groups[(i % 2) as usize], &[]);
+                cpass.dispatch_workgroups(
+                    grid.size[0].div_ceil(4),
+                    grid.size[1].div_ceil(4),
+                    grid.size[2].div_ceil(4),
+                );
+            }
+        }
+        let result_buffer = &light_buffers[(GPU_ITERATIONS % 2) as usize];
+        encoder.copy_buffer_to_buffer(result_buffer, 0, &readback_buffer, 0, buffer_size);
+        queue.submit(Some(encoder.finish()));
+
+        let slice = readback_buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |_| {});
+        device.poll(wgpu::Maintain::Wait);
+
+        let light = bytemuck::cast_slice::<u8, u32>(&slice.get_mapped_range())
+            .iter()
+            .map(|l| *l as u8)
+            .collect();
+        readback_buffer.unmap();
+        light
+    }
+}
+
+// Uses the compute shader when available, else the cpu flood fill
+pub fn propagate(grid: &LightGrid, gpu: Option<&GpuLighting>) -> Vec<u8> {
+    match gpu {
+        Some(gpu) => gpu.propagate(grid),
+        None => propagate_cpu(grid),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{propagate_cpu, ChunkLight, GpuLighting, LightGrid, SkyLight, MAX_LIGHT};
+    use crate::blocks::block_type::BlockType;
+    use crate::chunk::ChunkBlocks;
+    use crate::utils::{test_adapter, test_device};
+    use crate::world::DEFAULT_CHUNK_SIZE;
+
+    // A chunk with a stone floor and the given blocks over it, positions are relative
+    fn chunk_with(chunk: (i32, i32), blocks: &[(glam::Vec3, BlockType)]) -> ChunkBlocks {
+        let mut chunk_blocks = ChunkBlocks::new(chunk, DEFAULT_CHUNK_SIZE);
+        for i in 0..DEFAULT_CHUNK_SIZE * DEFAULT_CHUNK_SIZE {
+            chunk_blocks.set(
+                i / DEFAULT_CHUNK_SIZE,
+                0,
+                i % DEFAULT_CHUNK_SIZE,
+                Some(BlockType::Stone),
+            );
+        }
+        for (position, block_type) in blocks {
+            chunk_blocks.set_at(position, Some(*block_type));
+        }
+        chunk_blocks
+    }
+
+    // A torch in a 9x9x9 box with a wall splitting it in half (with a one cell gap at the bottom)
+    fn known_grid() -> LightGrid {
+        let mut grid = LightGrid::new([9, 9, 9]);
+        for y in 1..9 {
+            for z in 0..9 {
+                grid.set_opaque(5, y, z);
+            }
+        }
+        grid.set_emission(2, 4, 4, 15);
+        grid
+    }
+
+    #[test]
+    fn should_propagate_light_around_opaque_blocks() {
+        let grid = known_grid();
+        let light = propagate_cpu(&grid);
+
+        assert_eq!(light[grid.index(2, 4, 4)], 15);
+        assert_eq!(light[grid.index(3, 4, 4)], 14);
+        assert_eq!(light[grid.index(2, 4, 0)], 11);
+        // Walls stay dark
+        assert_eq!(light[grid.index(5, 4, 4)], 0);
+        // Goes under the wall: (2,4,4) -> (5,0,4) is 7 steps, then up to (6,4,4) is 5 more
+        assert_eq!(light[grid.index(5, 0, 4)], 8);
+        assert_eq!(light[grid.index(6, 4, 4)], 3);
+    }
+
+    #[test]
+    fn should_spread_torch_light_into_the_neighbour_chunk() {
+        // A torch two blocks from the +x border, with a stone wall on its -x side
+        let torch_chunk = chunk_with(
+            (0, 0),
+            &[
+                (glam::vec3(13.0, 1.0, 8.0), BlockType::Torch),
+                (glam::vec3(12.0, 1.0, 8.0), BlockType::Stone),
+            ],
+        );
+        let empty_chunk = chunk_with((1, 0), &[]);
+        let chunks = [((0, 0), &torch_chunk), ((1, 0), &empty_chunk)];
+
+        let light = ChunkLight::new((0, 0), &chunks, None).unwrap();
+        assert_eq!(light.at(glam::vec3(13.0, 1.0, 8.0)), 14);
+        // Decreasing along +x, past the chunk border
+        let levels: Vec<u8> = (13..20)
+            .map(|x| light.at(glam::vec3(x as f32, 1.0, 8.0)))
+            .collect();
+        assert_eq!(levels, vec![14, 13, 12, 11, 10, 9, 8]);
+        // Opaque blocks stay dark, the light goes around them
+        assert_eq!(light.at(glam::vec3(12.0, 1.0, 8.0)), 0);
+        assert_eq!(light.at(glam::vec3(11.0, 1.0, 8.0)), 10);
+        assert_eq!(light.at(glam::vec3(13.0, 0.0, 8.0)), 0);
+
+        // The neighbour chunk gets the same light without owning the torch
+        let neighbour_light = ChunkLight::new((1, 0), &chunks, None).unwrap();
+        assert_eq!(neighbour_light.at(glam::vec3(16.0, 1.0, 8.0)), 11);
+        assert_eq!(neighbour_light.at(glam::vec3(16.0, 1.0, 10.0)), 9);
+        // Too far from the torch to be lit
+        assert!(ChunkLight::new((2, 0), &[((2, 0), &chunk_with((2, 0), &[]))], None).is_none());
+    }
+
+    #[test]
+    fn should_light_columns_from_the_sky_down_to_the_first_opaque_block() {
+        let chunk = chunk_with(
+            (0, 0),
+            &[
+                // A roof over (3, 1, 3)
+                (glam::vec3(3.0, 4.0, 3.0), BlockType::Stone),
+                // Glass and torches let the sun through, water dims it
+                (glam::vec3(5.0, 4.0, 5.0), BlockType::Glass),
+                (glam::vec3(6.0, 4.0, 6.0), BlockType::Torch),
+                (glam::vec3(7.0, 3.0, 7.0), BlockType::Water),
+                (glam::vec3(7.0, 2.0, 7.0), BlockType::Water),
+                // Right on the -x border of the neighbour chunk
+                (glam::vec3(15.0, 4.0, 8.0), BlockType::Stone),
+            ],
+        );
+        let light = SkyLight::new((0, 0), &[((0, 0), &chunk)]);
+        assert_eq!(light.at(glam::vec3(3.0, 1.0, 3.0)), 0);
+        assert_eq!(light.at(glam::vec3(3.0, 5.0, 3.0)), MAX_LIGHT);
+        assert_eq!(light.at(glam::vec3(5.0, 1.0, 5.0)), MAX_LIGHT);
+        assert_eq!(light.at(glam::vec3(6.0, 1.0, 6.0)), MAX_LIGHT);
+        assert_eq!(light.at(glam::vec3(7.0, 1.0, 7.0)), MAX_LIGHT - 4);
+        // The floor and below the world are dark, above the highest block is open sky
+        assert_eq!(light.at(glam::vec3(2.0, 0.0, 2.0)), 0);
+        assert_eq!(light.at(glam::vec3(2.0, -1.0, 2.0)), 0);
+        assert_eq!(light.at(glam::vec3(2.0, 40.0, 2.0)), MAX_LIGHT);
+
+        // The neighbour samples the column past its border
+        let neighbour = chunk_with((1, 0), &[]);
+        let chunks = [((0, 0), &chunk), ((1, 0), &neighbour)];
+        let light = SkyLight::new((1, 0), &chunks);
+        assert_eq!(light.at(glam::vec3(15.0, 2.0, 8.0)), 0);
+        assert_eq!(light.at(glam::vec3(16.0, 2.0, 8.0)), MAX_LIGHT);
+    }
+
+    #[test]
+    fn should_match_cpu_and_gpu_light() {
+        let (Some(adapter), Some((device, queue))) = (test_adapter(), test_device()) else {
+            println!("No adapter available, skipping gpu lighting test");
+            return;
+        };
+        let Some(gpu) = GpuLighting::new(&adapter, device, queue) else {
+            println!("Compute shaders not supported, skipping gpu lighting test");
+            return;
+        };
+
+        let grid = known_grid();
+        assert_eq!(gpu.propagate(&grid), propagate_cpu(&grid));
+
+        // A chunk lit on the gpu gets the same light
+        let torch_chunk = chunk_with((0, 0), &[(glam::vec3(8.0, 1.0, 8.0), BlockType::Torch)]);
+        let chunks = [((0, 0), &torch_chunk)];
+        let cpu_light = ChunkLight::new((0, 0), &chunks, None).unwrap();
+        let gpu_light = ChunkLight::new((0, 0), &chunks, Some(&gpu)).unwrap();
+        assert_eq!(gpu_light.light, cpu_light.light);
+        assert_eq!(gpu_light.at(glam::vec3(10.0, 1.0, 8.0)), 12);
+    }
+}
@@ -2,7 +2,7 @@
     all(target_os = "windows", not(debug_assertions)),
     windows_subsystem = "windows"
 )]
-use state::State;
+use minecraft::state::{Config, State};
 use std::io::Cursor;
 use std::sync::{Arc, Mutex};
 use std::time::Instant;
@@ -18,25 +18,7 @@ use winit::{
 const DEFAULT_WINDOW_WIDTH: u32 = 1200;
 const DEFAULT_WINDOW_HEIGHT: u32 = 800;
 
-#[macro_use]
-extern crate lazy_static;
-
-pub mod blocks;
-pub mod chunk;
-pub mod collision;
-pub mod effects;
-pub mod macros;
-pub mod material;
-pub mod persistence;
-pub mod pipeline;
-pub mod pipelines;
-pub mod player;
-pub mod state;
-pub mod structures;
-pub mod utils;
-pub mod world;
-
-async fn run(event_loop: EventLoop<()>, window: Window) {
+async fn run(event_loop: EventLoop<()>, window: Window, config: Config) {
     let start = Instant::now();
     let mut total_time = start.elapsed();
     let mut delta_time = start.elapsed();
@@ -54,15 +36,28 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
         .unwrap();
     window.set_cursor_visible(false);
     let window = Arc::new(Mutex::new(window));
-    let mut state = State::new(window.clone()).await;
+    let mut state = State::new(window.clone(), config).await;
 
     let mut prev_mouse_pos = glam::vec2(0.0, 0.0);
     let mut cursor_in = false;
     let mut first_render = true;
 
+    // Ctrl+C in the terminal saves like closing the window, the handler only wakes the event loop
+    let proxy = event_loop.create_proxy();
+    if let Err(err) = ctrlc::set_handler(move || {
+        let _ = proxy.send_event(());
+    }) {
+        eprintln!("Failed to set the Ctrl+C handler: {err}");
+    }
+
     event_loop
         .run(move |event, target| {
-            if let Event::WindowEvent {
+            if let Event::UserEvent(()) = event {
+                println!("Interrupted, saving the world");
+                state.save_state();
+                state.dispose();
+                target.exit();
+            } else if let Event::WindowEvent {
                 window_id: _,
                 event,
             } = event
@@ -94,6 +89,11 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
                     } => {
                         state.on_click(button);
                     }
+                    WindowEvent::MouseInput {
+                        state: ElementState::Released,
+                        button,
+                        ..
+                    } => state.on_release(button),
 
                     WindowEvent::CursorMoved { position, .. } => {
                         if !cursor_in {
@@ -138,9 +138,7 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
                 match event {
                     DeviceEvent::MouseWheel {
                         delta: winit::event::MouseScrollDelta::LineDelta(_, deltay),
-                    } => {
-                        
-                    }
+                    } => state.handle_scroll(deltay),
                     DeviceEvent::MouseMotion { delta } => {
                         state.handle_mouse(&glam::vec2(delta.0 as f32, delta.1 as f32))
                     }
@@ -152,6 +150,11 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
 }
 
 fn main() {
+    let config = Config::from_args(std::env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{err}");
+        eprintln!("Usage: minecraft [--seed <u64>] [--world <name>]");
+        std::process::exit(2);
+    });
     let event_loop = EventLoop::new().unwrap();
     let builder = winit::window::WindowBuilder::new();
 
@@ -165,5 +168,5 @@ fn main() {
         .unwrap();
 
     env_logger::init();
-    pollster::block_on(run(event_loop, window))
+    pollster::block_on(run(event_loop, window, config))
 }
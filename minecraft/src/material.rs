@@ -1,27 +1,42 @@
-use crate::{state::State, utils::noise::perlin_noise};
-use image::GenericImageView;
+use crate::{
+    pipelines::lut::LutData,
+    state::State,
+    utils::noise::{perlin_noise, PermutationTable},
+};
 
 impl Texture {
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+    // Same size and sample count as the frame
     pub fn create_depth_texture(state: &State) -> Self {
+        Self::create_sized_depth_texture(
+            &state.device,
+            (state.surface_config.width, state.surface_config.height),
+            state.sample_count,
+        )
+    }
+    pub fn create_sized_depth_texture(
+        device: &wgpu::Device,
+        (width, height): (u32, u32),
+        sample_count: u32,
+    ) -> Self {
         let size = wgpu::Extent3d {
-            width: state.surface_config.width,
-            height: state.surface_config.height,
+            width,
+            height,
             depth_or_array_layers: 1,
         };
         let desc = wgpu::TextureDescriptor {
             label: Some("depth_texture"),
             size,
             mip_level_count: 1,
-            sample_count: 1,
+            sample_count,
             dimension: wgpu::TextureDimension::D2,
             format: Self::DEPTH_FORMAT,
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
             view_formats: &[],
         };
-        let texture = state.device.create_texture(&desc);
+        let texture = device.create_texture(&desc);
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = state.device.create_sampler(&wgpu::SamplerDescriptor {
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             address_mode_u: wgpu::AddressMode::ClampToEdge,
             address_mode_v: wgpu::AddressMode::ClampToEdge,
             address_mode_w: wgpu::AddressMode::ClampToEdge,
@@ -41,6 +56,116 @@ impl Texture {
             name: String::from("depth_texture"),
         }
     }
+    // Offscreen color target with the surface size, used when a post-process pass reads the frame
+    pub fn create_scene_texture(state: &State, format: wgpu::TextureFormat) -> Self {
+        let texture = state.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("scene_texture"),
+            size: wgpu::Extent3d {
+                width: state.surface_config.width,
+                height: state.surface_config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = state.device.create_sampler(&wgpu::SamplerDescriptor {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        Self {
+            data: None,
+            texture,
+            view,
+            sampler,
+            name: String::from("scene_texture"),
+        }
+    }
+    // Multisampled color target the scene is drawn into before it's resolved into the frame
+    pub fn create_msaa_texture(state: &State) -> Self {
+        let texture = state.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("msaa_texture"),
+            size: wgpu::Extent3d {
+                width: state.surface_config.width,
+                height: state.surface_config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: state.sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: state.surface_config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = state
+            .device
+            .create_sampler(&wgpu::SamplerDescriptor::default());
+        Self {
+            data: None,
+            texture,
+            view,
+            sampler,
+            name: String::from("msaa_texture"),
+        }
+    }
+    pub fn from_lut(lut: &LutData, device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let size = wgpu::Extent3d {
+            width: lut.size,
+            height: lut.size,
+            depth_or_array_layers: lut.size,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("color_lut"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            view_formats: &[],
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+        });
+        let data: Vec<u8> = lut.data.iter().flatten().copied().collect();
+
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                aspect: wgpu::TextureAspect::All,
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            data.as_slice(),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * lut.size),
+                rows_per_image: Some(lut.size),
+            },
+            size,
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            view,
+            sampler,
+            texture,
+            name: "color_lut".to_string(),
+            data: Some(data),
+        }
+    }
     //
     pub fn create_perlin_noise_texture(
         width: u32,
@@ -64,6 +189,7 @@ impl Texture {
             usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
             format: wgpu::TextureFormat::Rgba8Unorm,
         });
+        let perm = PermutationTable::new(state.world.seed);
         let mut perlin_noise_data: Vec<f32> = Vec::with_capacity((width * height) as usize);
         for y in 0..height {
             for x in 0..width {
@@ -71,6 +197,7 @@ impl Texture {
                     x as f32 * frequency,
                     y as f32 * frequency,
                     (width as f32 * frequency) as u32,
+                    &perm,
                 ))
             }
         }
@@ -113,119 +240,185 @@ impl Texture {
         }
     }
 
+    // Colors (the atlas) are stored in sRGB and sampled as linear values, data like normals or
+    // masks is read as is
+    pub fn color_format(srgb: bool) -> wgpu::TextureFormat {
+        if srgb {
+            wgpu::TextureFormat::Rgba8UnormSrgb
+        } else {
+            wgpu::TextureFormat::Rgba8Unorm
+        }
+    }
+    // Full mip chain levels of a square texture of this size, down to a single texel
+    pub fn mip_level_count(size: u32) -> u32 {
+        u32::BITS - size.leading_zeros()
+    }
+    pub fn sampler(device: &wgpu::Device, config: SamplerConfig) -> wgpu::Sampler {
+        device.create_sampler(&config.descriptor())
+    }
+    // Uploads `mip_level_count` levels at most. Each level averages 2x2 texels of the previous one,
+    // so tiles of a power of two size aligned to it never mix as long as the count stops at their
+    // own chain (`mip_level_count(tile_size)`)
     pub fn from_bytes(
         bytes: &[u8],
         name: String,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
+        srgb: bool,
+        mip_level_count: u32,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_bytes_with_sampler(
+            bytes,
+            name,
+            device,
+            queue,
+            srgb,
+            mip_level_count,
+            SamplerConfig::default(),
+        )
+    }
+    pub fn from_bytes_with_sampler(
+        bytes: &[u8],
+        name: String,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        srgb: bool,
+        mip_level_count: u32,
+        sampler: SamplerConfig,
     ) -> Result<Self, Box<dyn std::error::Error>> {
         let image = image::load_from_memory(bytes)?;
-        let dimensions = image.dimensions();
-        let rgba = image.as_rgba8().unwrap();
-
-        let size = wgpu::Extent3d {
-            width: dimensions.0,
-            height: dimensions.1,
-            depth_or_array_layers: 1,
-        };
-
-        let texture = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some(&name.clone()),
-            size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            view_formats: &[],
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
-        });
-
-        queue.write_texture(
-            wgpu::ImageCopyTexture {
-                aspect: wgpu::TextureAspect::All,
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-            },
-            rgba,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * dimensions.0),
-                rows_per_image: Some(dimensions.1),
-            },
-            size,
+        let mut texture = Self::from_layers(
+            vec![image.to_rgba8()],
+            name,
+            device,
+            queue,
+            srgb,
+            mip_level_count,
+            wgpu::TextureViewDimension::D2,
         );
-
-        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            ..Default::default()
-        });
-
-        Ok(Self {
-            view,
-            sampler,
-            texture,
+        texture.sampler = Self::sampler(device, sampler);
+        Ok(texture)
+    }
+    // Splits the atlas into its square tiles, row by row, and makes each one a layer of a
+    // texture array. The tiles get their full mip chain without bleeding into each other
+    pub fn from_atlas_bytes_as_array(
+        bytes: &[u8],
+        tile_size: u32,
+        name: String,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        srgb: bool,
+        sampler: SamplerConfig,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let image = image::load_from_memory(bytes)?.to_rgba8();
+        let tiles = split_tiles(&image, tile_size)?;
+        let mut texture = Self::from_layers(
+            tiles,
             name,
-            data: None,
-        })
+            device,
+            queue,
+            srgb,
+            Self::mip_level_count(tile_size),
+            wgpu::TextureViewDimension::D2Array,
+        );
+        texture.sampler = Self::sampler(device, sampler);
+        Ok(texture)
     }
     pub fn from_path(
         path: &str,
         name: String,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
+        srgb: bool,
+        mip_level_count: u32,
     ) -> Result<Self, Box<dyn std::error::Error>> {
         let f = std::fs::File::open(path)?;
         let reader = std::io::BufReader::new(f);
         let image = image::load(reader, image::ImageFormat::Png)?;
-        let dimensions = image.dimensions();
-        let rgba = image.as_rgba8().unwrap();
-
-        let size = wgpu::Extent3d {
-            width: dimensions.0,
-            height: dimensions.1,
-            depth_or_array_layers: 1,
-        };
+        Ok(Self::from_layers(
+            vec![image.to_rgba8()],
+            name,
+            device,
+            queue,
+            srgb,
+            mip_level_count,
+            wgpu::TextureViewDimension::D2,
+        ))
+    }
+    // The layers have to be the same size
+    fn from_layers(
+        layers: Vec<image::RgbaImage>,
+        name: String,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        srgb: bool,
+        mip_level_count: u32,
+        view_dimension: wgpu::TextureViewDimension,
+    ) -> Self {
+        let (width, height) = layers[0].dimensions();
+        let mip_level_count = mip_level_count
+            .min(Self::mip_level_count(width.min(height)))
+            .max(1);
 
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label: Some(&name.clone()),
-            size,
-            mip_level_count: 1,
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: layers.len() as u32,
+            },
+            mip_level_count,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             view_formats: &[],
             usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            format: Self::color_format(srgb),
         });
 
-        queue.write_texture(
-            wgpu::ImageCopyTexture {
-                aspect: wgpu::TextureAspect::All,
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-            },
-            rgba,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * dimensions.0),
-                rows_per_image: Some(dimensions.1),
-            },
-            size,
-        );
+        for (layer, mut level) in layers.into_iter().enumerate() {
+            for mip_level in 0..mip_level_count {
+                if mip_level > 0 {
+                    level = next_mip_level(&level, srgb);
+                }
+                queue.write_texture(
+                    wgpu::ImageCopyTexture {
+                        aspect: wgpu::TextureAspect::All,
+                        texture: &texture,
+                        mip_level,
+                        origin: wgpu::Origin3d {
+                            x: 0,
+                            y: 0,
+                            z: layer as u32,
+                        },
+                    },
+                    &level,
+                    wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(4 * level.width()),
+                        rows_per_image: Some(level.height()),
+                    },
+                    wgpu::Extent3d {
+                        width: level.width(),
+                        height: level.height(),
+                        depth_or_array_layers: 1,
+                    },
+                );
+            }
+        }
 
-        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(view_dimension),
             ..Default::default()
         });
+        let sampler = Self::sampler(device, SamplerConfig::default());
 
-        Ok(Self {
+        Self {
             view,
             sampler,
             texture,
             name,
             data: None,
-        })
+        }
     }
 }
 
@@ -242,3 +435,313 @@ pub struct Texture {
 pub struct Material {
     pub diffuse: Texture,
 }
+
+// How the atlas looks up close, far away the mip levels are blended either way
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TextureFiltering {
+    // Blurry blocks, like any other 3d texture
+    Smooth,
+    // Crisp texels
+    #[default]
+    Pixelated,
+}
+
+// Filters of a texture's sampler
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SamplerConfig {
+    // Up close, Nearest keeps the texels crisp
+    pub mag_filter: wgpu::FilterMode,
+    pub min_filter: wgpu::FilterMode,
+    // Between the mip levels, Nearest shows where the level changes
+    pub mipmap_filter: wgpu::FilterMode,
+    // Samples along the slope of surfaces seen at glancing angles, 1 is off. wgpu only allows it
+    // with every filter Linear, it's ignored otherwise
+    pub max_anisotropy: u16,
+}
+
+impl SamplerConfig {
+    // The block textures, far away the mip levels are blended either way
+    pub fn new(filtering: TextureFiltering, max_anisotropy: u16) -> Self {
+        let mag_filter = match filtering {
+            TextureFiltering::Smooth => wgpu::FilterMode::Linear,
+            TextureFiltering::Pixelated => wgpu::FilterMode::Nearest,
+        };
+        Self {
+            mag_filter,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            max_anisotropy,
+        }
+    }
+    pub fn descriptor(&self) -> wgpu::SamplerDescriptor<'static> {
+        let linear = [self.mag_filter, self.min_filter, self.mipmap_filter]
+            .iter()
+            .all(|filter| *filter == wgpu::FilterMode::Linear);
+        wgpu::SamplerDescriptor {
+            mag_filter: self.mag_filter,
+            min_filter: self.min_filter,
+            mipmap_filter: self.mipmap_filter,
+            anisotropy_clamp: if linear {
+                self.max_anisotropy.clamp(1, 16)
+            } else {
+                1
+            },
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for SamplerConfig {
+    fn default() -> Self {
+        Self::new(TextureFiltering::default(), 1)
+    }
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+    let value = value as f32 / 255.0;
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> u8 {
+    let value = if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    (value * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
+// The square tiles of an atlas from left to right and top to bottom, the index of a tile is its
+// layer once uploaded
+fn split_tiles(
+    atlas: &image::RgbaImage,
+    tile_size: u32,
+) -> Result<Vec<image::RgbaImage>, Box<dyn std::error::Error>> {
+    let (width, height) = atlas.dimensions();
+    if tile_size == 0 || width % tile_size != 0 || height % tile_size != 0 {
+        return Err(format!(
+            "A {width}x{height} atlas can't be split into {tile_size} pixel tiles"
+        )
+        .into());
+    }
+    let mut tiles = Vec::new();
+    for y in (0..height).step_by(tile_size as usize) {
+        for x in (0..width).step_by(tile_size as usize) {
+            tiles.push(image::imageops::crop_imm(atlas, x, y, tile_size, tile_size).to_image());
+        }
+    }
+    Ok(tiles)
+}
+
+// Half the size of `image`, each texel averages a 2x2 block. Colors are weighted by their alpha so
+// the invisible texels around leaves don't darken them, and averaged in linear space when `srgb`
+fn next_mip_level(image: &image::RgbaImage, srgb: bool) -> image::RgbaImage {
+    let decode = |value: u8| {
+        if srgb {
+            srgb_to_linear(value)
+        } else {
+            value as f32 / 255.0
+        }
+    };
+    let encode = |value: f32| {
+        if srgb {
+            linear_to_srgb(value)
+        } else {
+            (value * 255.0).round().clamp(0.0, 255.0) as u8
+        }
+    };
+    let (width, height) = ((image.width() / 2).max(1), (image.height() / 2).max(1));
+    image::RgbaImage::from_fn(width, height, |x, y| {
+        let mut color = [0.0; 3];
+        let mut alpha = 0.0;
+        for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let texel = image.get_pixel(
+                (x * 2 + dx).min(image.width() - 1),
+                (y * 2 + dy).min(image.height() - 1),
+            );
+            let weight = texel[3] as f32 / 255.0;
+            for (sum, value) in color.iter_mut().zip(texel.0) {
+                *sum += decode(value) * weight;
+            }
+            alpha += weight;
+        }
+        let [r, g, b] = color.map(|sum| if alpha > 0.0 { encode(sum / alpha) } else { 0 });
+        image::Rgba([r, g, b, (alpha / 4.0 * 255.0).round() as u8])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{next_mip_level, split_tiles, SamplerConfig, Texture, TextureFiltering};
+    use crate::blocks::block_type::BLOCK_OFFSET;
+    use crate::utils::test_device;
+
+    #[test]
+    fn should_recreate_the_depth_texture_with_the_new_size() {
+        let Some((device, _queue)) = test_device() else {
+            println!("No adapter available, skipping depth texture test");
+            return;
+        };
+
+        for (width, height) in [(1200, 800), (640, 960)] {
+            let depth = Texture::create_sized_depth_texture(&device, (width, height), 1);
+            let size = depth.texture.size();
+            assert_eq!((size.width, size.height), (width, height));
+            assert_eq!(depth.texture.format(), Texture::DEPTH_FORMAT);
+            assert!(depth
+                .texture
+                .usage()
+                .contains(wgpu::TextureUsages::RENDER_ATTACHMENT));
+        }
+    }
+
+    #[test]
+    fn should_average_the_mip_levels_inside_each_tile() {
+        // Two 2x2 tiles side by side, red and blue
+        let tiles = image::RgbaImage::from_fn(4, 2, |x, _| {
+            if x < 2 {
+                image::Rgba([255, 0, 0, 255])
+            } else {
+                image::Rgba([0, 0, 255, 255])
+            }
+        });
+        let level = next_mip_level(&tiles, true);
+        assert_eq!(level.dimensions(), (2, 1));
+        assert_eq!(level.get_pixel(0, 0).0, [255, 0, 0, 255]);
+        assert_eq!(level.get_pixel(1, 0).0, [0, 0, 255, 255]);
+        assert_eq!(Texture::mip_level_count(32), 6);
+
+        // Black and white average to half the light, brighter than 128 in sRGB
+        let checker = image::RgbaImage::from_fn(2, 2, |x, y| {
+            image::Rgba([((x + y) % 2 * 255) as u8, 0, 0, 255])
+        });
+        assert_eq!(next_mip_level(&checker, true).get_pixel(0, 0).0[0], 188);
+        assert_eq!(next_mip_level(&checker, false).get_pixel(0, 0).0[0], 128);
+
+        // A single green leaf texel keeps its color, only the alpha fades
+        let leaf = image::RgbaImage::from_fn(2, 2, |x, y| {
+            if (x, y) == (0, 0) {
+                image::Rgba([0, 200, 0, 255])
+            } else {
+                image::Rgba([0, 0, 0, 0])
+            }
+        });
+        assert_eq!(
+            next_mip_level(&leaf, true).get_pixel(0, 0).0,
+            [0, 200, 0, 64]
+        );
+    }
+
+    #[test]
+    fn should_keep_the_smallest_mips_of_the_shipped_atlas_inside_their_tiles() {
+        let atlas = image::load_from_memory(include_bytes!("../assets/tex_atlas.png"))
+            .unwrap()
+            .to_rgba8();
+        let tiles = split_tiles(&atlas, BLOCK_OFFSET).unwrap();
+        let per_row = atlas.width() / BLOCK_OFFSET;
+
+        // Each level of the whole atlas has to match the same level built from every tile alone
+        let mut level = atlas;
+        let mut tile_levels = tiles;
+        for _ in 1..Texture::mip_level_count(BLOCK_OFFSET) {
+            level = next_mip_level(&level, true);
+            tile_levels = tile_levels
+                .iter()
+                .map(|tile| next_mip_level(tile, true))
+                .collect();
+            let size = tile_levels[0].width();
+            for (i, tile) in tile_levels.iter().enumerate() {
+                let (x, y) = (i as u32 % per_row * size, i as u32 / per_row * size);
+                let from_atlas = image::imageops::crop_imm(&level, x, y, size, size).to_image();
+                assert_eq!(&from_atlas, tile, "tile {i} bleeds at {size}x{size}");
+            }
+        }
+        // Down to a texel per tile
+        assert_eq!(tile_levels[0].dimensions(), (1, 1));
+    }
+
+    #[test]
+    fn should_build_the_sampler_descriptor_from_the_config() {
+        let smooth = SamplerConfig::new(TextureFiltering::Smooth, 8).descriptor();
+        assert_eq!(smooth.mag_filter, wgpu::FilterMode::Linear);
+        assert_eq!(smooth.min_filter, wgpu::FilterMode::Linear);
+        assert_eq!(smooth.mipmap_filter, wgpu::FilterMode::Linear);
+        assert_eq!(smooth.anisotropy_clamp, 8);
+
+        // Not allowed with a Nearest filter
+        let pixelated = SamplerConfig::new(TextureFiltering::Pixelated, 8).descriptor();
+        assert_eq!(pixelated.mag_filter, wgpu::FilterMode::Nearest);
+        assert_eq!(pixelated.min_filter, wgpu::FilterMode::Linear);
+        assert_eq!(pixelated.anisotropy_clamp, 1);
+
+        let nearest_mips = SamplerConfig {
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..SamplerConfig::new(TextureFiltering::Smooth, 4)
+        };
+        assert_eq!(nearest_mips.descriptor().anisotropy_clamp, 1);
+        // Past what wgpu accepts
+        let too_many = SamplerConfig::new(TextureFiltering::Smooth, 64).descriptor();
+        assert_eq!(too_many.anisotropy_clamp, 16);
+
+        let Some((device, _queue)) = test_device() else {
+            println!("No adapter available, skipping sampler test");
+            return;
+        };
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        for descriptor in [smooth, pixelated, too_many] {
+            device.create_sampler(&descriptor);
+        }
+        let error = pollster::block_on(device.pop_error_scope());
+        assert!(error.is_none(), "{error:?}");
+    }
+
+    #[test]
+    fn should_split_the_atlas_into_layers_row_by_row() {
+        // 2x2 tiles of 2 pixels, each filled with its index
+        let atlas = image::RgbaImage::from_fn(4, 4, |x, y| {
+            image::Rgba([(y / 2 * 2 + x / 2) as u8, 0, 0, 255])
+        });
+        let tiles = split_tiles(&atlas, 2).unwrap();
+        assert_eq!(tiles.len(), 4);
+        for (layer, tile) in tiles.iter().enumerate() {
+            assert_eq!(tile.dimensions(), (2, 2));
+            assert!(tile.pixels().all(|p| p.0[0] == layer as u8));
+        }
+        assert!(split_tiles(&atlas, 3).is_err());
+    }
+
+    #[test]
+    fn should_load_the_shipped_atlas_as_a_layer_per_block() {
+        let bytes = include_bytes!("../assets/tex_atlas.png");
+        // The tiles need to line up with the pixels or their mips mix the neighbours
+        let atlas = image::load_from_memory(bytes).unwrap().to_rgba8();
+        assert_eq!(atlas.dimensions(), (256, 256));
+        assert_eq!(split_tiles(&atlas, BLOCK_OFFSET).unwrap().len(), 64);
+
+        let Some((device, queue)) = test_device() else {
+            println!("No adapter available, skipping atlas array test");
+            return;
+        };
+        let texture = Texture::from_atlas_bytes_as_array(
+            bytes,
+            BLOCK_OFFSET,
+            "tex_atlas".to_string(),
+            &device,
+            &queue,
+            true,
+            SamplerConfig::default(),
+        )
+        .unwrap();
+        let size = texture.texture.size();
+        assert_eq!((size.width, size.height), (BLOCK_OFFSET, BLOCK_OFFSET));
+        assert_eq!(size.depth_or_array_layers, 64);
+        assert_eq!(
+            texture.texture.mip_level_count(),
+            Texture::mip_level_count(BLOCK_OFFSET)
+        );
+    }
+}
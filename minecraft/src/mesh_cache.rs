@@ -0,0 +1,322 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, Weak};
+
+use wgpu::util::DeviceExt;
+
+use crate::blocks::block::BlockVertexPacked;
+use crate::persistence::content_hash;
+
+// Geometry hash plus the vertex and index counts, to make a collision even less likely
+type MeshKey = (u64, usize, usize);
+
+// Shared mesh buffers are only ever created, never written in place, so a chunk that gets edited
+// builds a new mesh and stops sharing (copy on write) while the others keep the old one.
+pub type MeshBuffers = (Arc<wgpu::Buffer>, Arc<wgpu::Buffer>);
+type WeakMeshBuffers = (Weak<wgpu::Buffer>, Weak<wgpu::Buffer>);
+
+// Shares one vertex/index buffer pair between chunks with identical geometry. Vertex positions are
+// relative to the chunk, which is placed by its position bind group, so the same buffers can be drawn anywhere.
+// Entries are weak, a mesh is freed as soon as no chunk uses it anymore.
+#[derive(Debug, Default)]
+pub struct MeshCache {
+    meshes: Mutex<HashMap<MeshKey, WeakMeshBuffers>>,
+}
+
+impl MeshCache {
+    pub fn geometry_hash(vertex: &[BlockVertexPacked], indices: &[u32]) -> u64 {
+        let mut data: Vec<u8> = bytemuck::cast_slice(vertex).to_vec();
+        data.extend_from_slice(bytemuck::cast_slice(indices));
+        content_hash(&data)
+    }
+    pub fn get_or_create(
+        &self,
+        device: &wgpu::Device,
+        vertex: &[BlockVertexPacked],
+        indices: &[u32],
+        label: &str,
+    ) -> MeshBuffers {
+        let key = (
+            Self::geometry_hash(vertex, indices),
+            vertex.len(),
+            indices.len(),
+        );
+        let mut meshes = self.meshes.lock().unwrap();
+
+        if let Some((vertex_buffer, index_buffer)) = meshes.get(&key) {
+            if let (Some(vertex_buffer), Some(index_buffer)) =
+                (vertex_buffer.upgrade(), index_buffer.upgrade())
+            {
+                return (vertex_buffer, index_buffer);
+            }
+        }
+
+        let buffers = create_mesh_buffers(device, vertex, indices, label);
+        meshes.retain(|_, (v, _)| v.strong_count() > 0);
+        meshes.insert(
+            key,
+            (Arc::downgrade(&buffers.0), Arc::downgrade(&buffers.1)),
+        );
+        buffers
+    }
+    // Meshes currently alive, shared ones are counted once
+    pub fn len(&self) -> usize {
+        self.meshes
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|(v, _)| v.strong_count() > 0)
+            .count()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// Headroom of a buffer that grows, so a chunk being edited doesn't reallocate on every block
+const GROWTH_FACTOR: f64 = 1.5;
+
+// A vertex or index buffer of a chunk, `len` of its `capacity` bytes hold the current mesh
+#[derive(Debug, Clone)]
+pub struct MeshBuffer {
+    pub buffer: Arc<wgpu::Buffer>,
+    pub capacity: u64,
+    pub len: u64,
+}
+
+impl MeshBuffer {
+    // From the mesh cache, other chunks may draw it so it's never written
+    pub fn shared(buffer: Arc<wgpu::Buffer>, len: usize) -> Self {
+        Self {
+            capacity: buffer.size(),
+            buffer,
+            len: len as u64,
+        }
+    }
+    // Writes `data` over the previous buffer of the chunk when it fits, else allocates a bigger one
+    pub fn write(
+        previous: Option<Self>,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        data: &[u8],
+        usage: wgpu::BufferUsages,
+        label: &str,
+    ) -> Self {
+        let len = data.len() as u64;
+        let mut mesh_buffer = match previous {
+            Some(previous) if len <= previous.capacity => previous,
+            _ => {
+                // Buffer sizes are a multiple of the copy alignment and never 0
+                let alignment = wgpu::COPY_BUFFER_ALIGNMENT;
+                let capacity = ((len as f64 * GROWTH_FACTOR) as u64)
+                    .div_ceil(alignment)
+                    .max(1)
+                    * alignment;
+                let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some(label),
+                    size: capacity,
+                    usage: usage | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                });
+                Self {
+                    buffer: Arc::new(buffer),
+                    capacity,
+                    len: 0,
+                }
+            }
+        };
+        if !data.is_empty() {
+            queue.write_buffer(&mesh_buffer.buffer, 0, data);
+        }
+        mesh_buffer.len = len;
+        mesh_buffer
+    }
+}
+
+// 16 bit indices halve the index buffer, only the meshes with more vertices than they can address
+// keep 32 bit ones
+pub fn index_format(vertex_count: usize) -> wgpu::IndexFormat {
+    match vertex_count <= u16::MAX as usize + 1 {
+        true => wgpu::IndexFormat::Uint16,
+        false => wgpu::IndexFormat::Uint32,
+    }
+}
+// Indices as they're uploaded, in the format of `index_format`
+pub fn index_bytes(indices: &[u32], vertex_count: usize) -> Vec<u8> {
+    match index_format(vertex_count) {
+        wgpu::IndexFormat::Uint16 => {
+            let indices: Vec<u16> = indices.iter().map(|i| *i as u16).collect();
+            bytemuck::cast_slice(&indices).to_vec()
+        }
+        wgpu::IndexFormat::Uint32 => bytemuck::cast_slice(indices).to_vec(),
+    }
+}
+
+pub fn create_mesh_buffers(
+    device: &wgpu::Device,
+    vertex: &[BlockVertexPacked],
+    indices: &[u32],
+    label: &str,
+) -> MeshBuffers {
+    let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        contents: bytemuck::cast_slice(vertex),
+        label: Some(&format!("{}-vertex", label)),
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+    });
+    let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        contents: &index_bytes(indices, vertex.len()),
+        label: Some(&format!("{}-index", label)),
+        usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
+    });
+    (Arc::new(vertex_buffer), Arc::new(index_buffer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{index_bytes, index_format, MeshCache};
+    use crate::blocks::block_type::BlockType;
+    use crate::chunk::{BlockVec, Chunk, ChunkBlocks, ChunkData};
+    use crate::utils::noise::create_world_noise_data;
+    use crate::utils::test_device;
+    use crate::world::{ChunkMap, DEFAULT_CHUNK_SIZE, FREQUENCY, NOISE_SIZE, RNG_SEED};
+    use std::collections::HashMap;
+    use std::sync::{Arc, RwLock};
+
+    fn flat_blocks(x: i32, y: i32) -> BlockVec {
+        let mut blocks = ChunkBlocks::new((x, y), DEFAULT_CHUNK_SIZE);
+        for i in 0..DEFAULT_CHUNK_SIZE * DEFAULT_CHUNK_SIZE {
+            blocks.set(
+                i / DEFAULT_CHUNK_SIZE,
+                0,
+                i % DEFAULT_CHUNK_SIZE,
+                Some(BlockType::Stone),
+            );
+        }
+        Arc::new(RwLock::new(blocks))
+    }
+
+    fn vertex_buffer(chunk: &Chunk) -> Arc<wgpu::Buffer> {
+        chunk.chunk_vertex_buffer.as_ref().unwrap().buffer.clone()
+    }
+    fn index_buffer(chunk: &Chunk) -> Arc<wgpu::Buffer> {
+        chunk.chunk_index_buffer.as_ref().unwrap().buffer.clone()
+    }
+
+    #[test]
+    fn should_share_identical_meshes_until_edited() {
+        let Some((device, queue)) = test_device() else {
+            println!("No adapter available, skipping mesh cache test");
+            return;
+        };
+        let layout = Arc::new(device.create_bind_group_layout(&Chunk::get_bind_group_layout()));
+        let noise_data = Arc::new(create_world_noise_data(
+            NOISE_SIZE, NOISE_SIZE, FREQUENCY, RNG_SEED,
+        ));
+
+        let chunks = [(0, 0), (1, 0)].map(|(x, y)| {
+            let data = ChunkData {
+                x,
+                y,
+                blocks: flat_blocks(x, y),
+                outside_blocks: vec![],
+            };
+            Chunk::from_data(
+                data,
+                noise_data.clone(),
+                device.clone(),
+                queue.clone(),
+                layout.clone(),
+                None,
+            )
+        });
+        let chunk_map: ChunkMap = Arc::new(RwLock::new(HashMap::new()));
+        let cache = MeshCache::default();
+
+        let [mut first, mut edited] = chunks;
+        first.build_mesh(chunk_map.clone(), Some(&cache));
+        edited.build_mesh(chunk_map.clone(), Some(&cache));
+        assert!(first.indices > 0);
+        assert!(Arc::ptr_eq(&vertex_buffer(&first), &vertex_buffer(&edited)));
+        assert!(Arc::ptr_eq(&index_buffer(&first), &index_buffer(&edited)));
+
+        edited.remove_block(&glam::vec3(3.0, 0.0, 3.0)).unwrap();
+        edited.build_mesh(chunk_map, Some(&cache));
+        assert!(!Arc::ptr_eq(
+            &vertex_buffer(&first),
+            &vertex_buffer(&edited)
+        ));
+        assert!(!Arc::ptr_eq(&index_buffer(&first), &index_buffer(&edited)));
+        assert_eq!(cache.len(), 3);
+    }
+
+    #[test]
+    fn should_write_a_remesh_in_place_while_it_fits() {
+        let Some((device, queue)) = test_device() else {
+            println!("No adapter available, skipping mesh buffer test");
+            return;
+        };
+        let layout = Arc::new(device.create_bind_group_layout(&Chunk::get_bind_group_layout()));
+        let noise_data = Arc::new(create_world_noise_data(
+            NOISE_SIZE, NOISE_SIZE, FREQUENCY, RNG_SEED,
+        ));
+        let data = ChunkData {
+            x: 0,
+            y: 0,
+            blocks: flat_blocks(0, 0),
+            outside_blocks: vec![],
+        };
+        let mut chunk = Chunk::from_data(data, noise_data, device, queue, layout, None);
+        let chunk_map: ChunkMap = Arc::new(RwLock::new(HashMap::new()));
+
+        chunk
+            .set_block(&glam::vec3(3.0, 1.0, 3.0), BlockType::Stone, true)
+            .unwrap();
+        chunk.build_mesh(chunk_map.clone(), None);
+        let built = chunk.chunk_vertex_buffer.clone().unwrap();
+        assert!(built.capacity > built.len);
+
+        // Less faces without the block on top, same buffer
+        chunk.remove_block(&glam::vec3(3.0, 1.0, 3.0)).unwrap();
+        chunk.build_mesh(chunk_map.clone(), None);
+        let smaller = chunk.chunk_vertex_buffer.clone().unwrap();
+        assert!(Arc::ptr_eq(&built.buffer, &smaller.buffer));
+        assert_eq!(smaller.capacity, built.capacity);
+        assert!(smaller.len < built.len);
+
+        // A second layer doubles the faces, past the headroom
+        for i in 0..DEFAULT_CHUNK_SIZE * DEFAULT_CHUNK_SIZE {
+            let position = glam::vec3(
+                (i / DEFAULT_CHUNK_SIZE) as f32,
+                2.0,
+                (i % DEFAULT_CHUNK_SIZE) as f32,
+            );
+            chunk.set_block(&position, BlockType::Stone, true).unwrap();
+        }
+        chunk.build_mesh(chunk_map, None);
+        let larger = chunk.chunk_vertex_buffer.clone().unwrap();
+        assert!(!Arc::ptr_eq(&built.buffer, &larger.buffer));
+        assert!(larger.capacity > built.capacity);
+        assert!(larger.len <= larger.capacity);
+        assert_eq!(
+            chunk.chunk_index_buffer.as_ref().unwrap().len,
+            chunk.indices as u64 * 2
+        );
+    }
+
+    #[test]
+    fn should_fall_back_to_32_bit_indices_past_65536_vertices() {
+        let limit = u16::MAX as usize + 1;
+        assert_eq!(index_format(4), wgpu::IndexFormat::Uint16);
+        assert_eq!(index_format(limit), wgpu::IndexFormat::Uint16);
+        assert_eq!(index_format(limit + 1), wgpu::IndexFormat::Uint32);
+
+        let indices = [0, 1, 2, 0, 2, u16::MAX as u32];
+        let small = index_bytes(&indices, limit);
+        assert_eq!(small.len(), indices.len() * 2);
+        assert_eq!(bytemuck::cast_slice::<u8, u16>(&small)[5], u16::MAX);
+        let large = index_bytes(&[0, 1, limit as u32], limit + 1);
+        assert_eq!(
+            bytemuck::cast_slice::<u8, u32>(&large),
+            [0, 1, limit as u32]
+        );
+    }
+}
@@ -1,5 +1,26 @@
 use std::any::Any;
+use std::collections::HashMap;
 use std::error::Error;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::{Mutex, RwLock};
+
+pub const SAVE_DIR: &str = "data";
+// Named worlds are saved in a folder of their own in here
+pub const WORLDS_DIR: &str = "worlds";
+static WORLD_NAME: RwLock<Option<String>> = RwLock::new(None);
+
+// Set once at startup, every save of the process goes to the same world
+pub fn set_world_name(name: Option<String>) {
+    *WORLD_NAME.write().unwrap() = name;
+}
+// SAVE_DIR, or the folder of the world picked with `set_world_name`
+pub fn save_dir() -> String {
+    match WORLD_NAME.read().unwrap().as_ref() {
+        Some(name) => format!("{WORLDS_DIR}/{name}"),
+        None => SAVE_DIR.to_string(),
+    }
+}
 
 pub trait Saveable<T> {
     fn save(&self) -> Result<(), Box<dyn Error>>;
@@ -8,3 +29,502 @@ pub trait Saveable<T> {
 pub trait Loadable<T> {
     fn load(args: Box<dyn Any>) -> Result<T, Box<dyn Error>>;
 }
+
+// Reads a save file from `dir`, usually `save_dir()`, falling back to the archive (if any) when
+// the loose file doesn't exist.
+pub(crate) fn read_save_file(
+    dir: &str,
+    name: &str,
+    archive: Option<&WorldArchive>,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    match std::fs::read(Path::new(dir).join(name)) {
+        Ok(data) => Ok(data),
+        Err(err) => match archive {
+            Some(archive) => archive.read(name),
+            None => Err(err.into()),
+        },
+    }
+}
+
+/*
+Archive layout (all integers little endian):
+  magic "DWA1"
+  u32 entry count
+  per entry: u16 name length, name bytes, u64 offset, u64 length, u64 hash
+  entries data, offsets are relative to the start of the data section */
+const ARCHIVE_MAGIC: &[u8; 4] = b"DWA1";
+
+#[derive(Debug, Clone)]
+pub(crate) struct ArchiveEntry {
+    pub(crate) offset: u64,
+    pub(crate) len: u64,
+    pub(crate) hash: u64,
+}
+
+#[derive(Debug)]
+pub struct WorldArchive {
+    pub(crate) path: String,
+    pub(crate) entries: HashMap<String, ArchiveEntry>,
+    data_start: u64,
+}
+
+// FNV-1a, stable across runs and platforms unlike the std hasher
+pub(crate) fn content_hash(data: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in data {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+pub(crate) fn read_u8(reader: &mut impl Read) -> Result<u8, Box<dyn Error>> {
+    let mut buf = [0u8; 1];
+    reader.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+pub(crate) fn read_u16(reader: &mut impl Read) -> Result<u16, Box<dyn Error>> {
+    let mut buf = [0u8; 2];
+    reader.read_exact(&mut buf)?;
+    Ok(u16::from_le_bytes(buf))
+}
+pub(crate) fn read_u32(reader: &mut impl Read) -> Result<u32, Box<dyn Error>> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+pub(crate) fn read_f32(reader: &mut impl Read) -> Result<f32, Box<dyn Error>> {
+    Ok(f32::from_bits(read_u32(reader)?))
+}
+pub(crate) fn read_u64(reader: &mut impl Read) -> Result<u64, Box<dyn Error>> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+/*
+Region file layout (r.<x>.<z>.bin in the save directory, integers little endian):
+  u8 format version
+  per chunk (local x * REGION_SIZE + local z): u32 offset from the start of the file, u32 length
+  chunks data, a length of 0 means the chunk isn't saved */
+pub(crate) const REGION_FORMAT_VERSION: u8 = 1;
+// Chunks per side of a region
+pub(crate) const REGION_SIZE: i32 = 32;
+const REGION_CHUNKS: usize = (REGION_SIZE * REGION_SIZE) as usize;
+const REGION_HEADER_LEN: usize = 1 + REGION_CHUNKS * 8;
+
+lazy_static! {
+    // Chunks of the same region can be saved from different threads, each save rewrites the file
+    static ref REGION_WRITE_LOCK: Mutex<()> = Mutex::new(());
+}
+
+pub(crate) fn region_file_name(chunk_x: i32, chunk_y: i32) -> String {
+    format!(
+        "r.{}.{}.bin",
+        chunk_x.div_euclid(REGION_SIZE),
+        chunk_y.div_euclid(REGION_SIZE)
+    )
+}
+
+// Every chunk of a region file, kept encoded
+#[derive(Debug, Clone)]
+pub(crate) struct Region {
+    chunks: Vec<Option<Vec<u8>>>,
+}
+
+impl Default for Region {
+    fn default() -> Self {
+        Self {
+            chunks: vec![None; REGION_CHUNKS],
+        }
+    }
+}
+
+impl Region {
+    fn index(chunk_x: i32, chunk_y: i32) -> usize {
+        (chunk_x.rem_euclid(REGION_SIZE) * REGION_SIZE + chunk_y.rem_euclid(REGION_SIZE)) as usize
+    }
+    pub(crate) fn get(&self, chunk_x: i32, chunk_y: i32) -> Option<&[u8]> {
+        self.chunks[Self::index(chunk_x, chunk_y)].as_deref()
+    }
+    pub(crate) fn set(&mut self, chunk_x: i32, chunk_y: i32, data: Vec<u8>) {
+        self.chunks[Self::index(chunk_x, chunk_y)] = Some(data);
+    }
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut header = vec![REGION_FORMAT_VERSION];
+        let mut body = vec![];
+        for chunk in self.chunks.iter() {
+            let (offset, len) = match chunk {
+                Some(data) => (REGION_HEADER_LEN + body.len(), data.len()),
+                None => (0, 0),
+            };
+            header.extend_from_slice(&(offset as u32).to_le_bytes());
+            header.extend_from_slice(&(len as u32).to_le_bytes());
+            if let Some(data) = chunk {
+                body.extend_from_slice(data);
+            }
+        }
+        header.extend_from_slice(&body);
+        header
+    }
+    // Truncated files and entries pointing outside of the file are errors
+    pub(crate) fn decode(data: &[u8]) -> Result<Region, Box<dyn Error>> {
+        let mut reader = data;
+        let version = read_u8(&mut reader)?;
+        if version != REGION_FORMAT_VERSION {
+            return Err(format!("Unsupported region format version {}", version).into());
+        }
+        let mut region = Region::default();
+        for chunk in region.chunks.iter_mut() {
+            let offset = read_u32(&mut reader)? as usize;
+            let len = read_u32(&mut reader)? as usize;
+            if len == 0 {
+                continue;
+            }
+            let end = offset
+                .checked_add(len)
+                .filter(|end| offset >= REGION_HEADER_LEN && *end <= data.len())
+                .ok_or("Region chunk is out of bounds")?;
+            *chunk = Some(data[offset..end].to_vec());
+        }
+        Ok(region)
+    }
+}
+
+// Encoded chunk from its region file, falling back to the archive like `read_save_file`
+pub(crate) fn read_region_chunk(
+    dir: &str,
+    chunk_x: i32,
+    chunk_y: i32,
+    archive: Option<&WorldArchive>,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let region = Region::decode(&read_save_file(
+        dir,
+        &region_file_name(chunk_x, chunk_y),
+        archive,
+    )?)?;
+    region
+        .get(chunk_x, chunk_y)
+        .map(|data| data.to_vec())
+        .ok_or_else(|| format!("Chunk {}_{} is not saved", chunk_x, chunk_y).into())
+}
+
+// Replaces a chunk in its region file in `dir`, the other chunks of the region are kept
+pub(crate) fn write_region_chunk(
+    dir: &str,
+    chunk_x: i32,
+    chunk_y: i32,
+    data: Vec<u8>,
+    archive: Option<&WorldArchive>,
+) -> Result<(), Box<dyn Error>> {
+    let _lock = REGION_WRITE_LOCK.lock().unwrap();
+    let name = region_file_name(chunk_x, chunk_y);
+    let path = Path::new(dir).join(&name);
+    let saved = path.exists() || archive.is_some_and(|archive| archive.contains(&name));
+    // A region that can't be read is left alone, rewriting it would lose its other chunks
+    let mut region = if saved {
+        Region::decode(&read_save_file(dir, &name, archive)?)?
+    } else {
+        Region::default()
+    };
+    region.set(chunk_x, chunk_y, data);
+
+    // Written next to it and renamed, so a crash mid write keeps the previous region
+    std::fs::create_dir_all(dir)?;
+    let temp_path = path.with_extension("bin.tmp");
+    std::fs::write(&temp_path, region.encode())?;
+    std::fs::rename(&temp_path, &path)?;
+    Ok(())
+}
+
+// Packs every file in `dir` (non recursive) into a single archive file.
+pub fn pack(dir: &str, archive: &str) -> Result<(), Box<dyn Error>> {
+    let mut files = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        let name = entry
+            .file_name()
+            .into_string()
+            .map_err(|_| "Invalid file name")?;
+        files.push((name, std::fs::read(entry.path())?));
+    }
+    // Keep the output deterministic
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut header: Vec<u8> = vec![];
+    header.extend_from_slice(ARCHIVE_MAGIC);
+    header.extend_from_slice(&(files.len() as u32).to_le_bytes());
+
+    let mut offset = 0u64;
+    for (name, data) in files.iter() {
+        header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        header.extend_from_slice(name.as_bytes());
+        header.extend_from_slice(&offset.to_le_bytes());
+        header.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        header.extend_from_slice(&content_hash(data).to_le_bytes());
+        offset += data.len() as u64;
+    }
+
+    let mut file = std::io::BufWriter::new(std::fs::File::create(archive)?);
+    file.write_all(&header)?;
+    for (_, data) in files.iter() {
+        file.write_all(data)?;
+    }
+    file.flush()?;
+
+    Ok(())
+}
+
+// Entries are plain files of the save directory, anything else could be written outside of it
+fn is_valid_entry_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains(['/', '\\', ':'])
+}
+
+// Extracts every entry of the archive into `dir`, creating it if needed.
+pub fn unpack(archive: &str, dir: &str) -> Result<(), Box<dyn Error>> {
+    WorldArchive::open(archive)?.unpack_to(dir, true)
+}
+
+impl WorldArchive {
+    // Only reads the index, entries are read lazily with `read`
+    pub fn open(path: &str) -> Result<WorldArchive, Box<dyn Error>> {
+        let mut file = std::io::BufReader::new(std::fs::File::open(path)?);
+        let file_len = file.get_ref().metadata()?.len();
+
+        let mut magic = [0u8; 4];
+        file.read_exact(&mut magic)?;
+        if &magic != ARCHIVE_MAGIC {
+            return Err("Not a world archive".into());
+        }
+
+        let count = read_u32(&mut file)?;
+        let mut entries = HashMap::with_capacity(count as usize);
+        let mut data_start = 8u64;
+
+        for _ in 0..count {
+            let name_len = read_u16(&mut file)?;
+            let mut name = vec![0u8; name_len as usize];
+            file.read_exact(&mut name)?;
+            let offset = read_u64(&mut file)?;
+            let len = read_u64(&mut file)?;
+            let hash = read_u64(&mut file)?;
+
+            data_start += 2 + name_len as u64 + 24;
+            let name = String::from_utf8(name)?;
+            if !is_valid_entry_name(&name) {
+                return Err(format!("Invalid archive entry name {name:?}").into());
+            }
+            entries.insert(name, ArchiveEntry { offset, len, hash });
+        }
+        // Every entry has to be inside the file, so `read` never allocates more than its size
+        for (name, entry) in entries.iter() {
+            let end = entry
+                .offset
+                .checked_add(entry.len)
+                .and_then(|end| end.checked_add(data_start));
+            if end.is_none_or(|end| end > file_len) {
+                return Err(format!("{name} is past the end of the archive").into());
+            }
+        }
+
+        Ok(WorldArchive {
+            path: path.to_string(),
+            entries,
+            data_start,
+        })
+    }
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries.contains_key(name)
+    }
+    pub fn read(&self, name: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        let entry = self
+            .entries
+            .get(name)
+            .ok_or_else(|| format!("{} is not in the archive", name))?;
+
+        let mut file = std::fs::File::open(&self.path)?;
+        file.seek(SeekFrom::Start(self.data_start + entry.offset))?;
+        let mut data = vec![0u8; entry.len as usize];
+        file.read_exact(&mut data)?;
+
+        if content_hash(&data) != entry.hash {
+            return Err(format!("{} is corrupted (hash mismatch)", name).into());
+        }
+        Ok(data)
+    }
+    // overwrite: false keeps the files that already exist in `dir` (they are newer than the archive)
+    pub fn unpack_to(&self, dir: &str, overwrite: bool) -> Result<(), Box<dyn Error>> {
+        std::fs::create_dir_all(dir)?;
+        for name in self.entries.keys() {
+            if !is_valid_entry_name(name) {
+                return Err(format!("Invalid archive entry name {name:?}").into());
+            }
+            let path = Path::new(dir).join(name);
+            if !overwrite && path.exists() {
+                continue;
+            }
+            std::fs::write(path, self.read(name)?)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        content_hash, pack, read_region_chunk, region_file_name, unpack, write_region_chunk,
+        Region, WorldArchive, ARCHIVE_MAGIC,
+    };
+    use crate::blocks::block_type::BlockType;
+    use crate::chunk::{encode_chunk, ChunkBlocks, ChunkData};
+    use crate::utils::noise::create_world_noise_data;
+    use crate::utils::temp_dir;
+    use crate::world::{DEFAULT_CHUNK_SIZE, FREQUENCY, NOISE_SIZE, RNG_SEED};
+    use std::sync::{Arc, RwLock};
+
+    #[test]
+    fn should_round_trip_a_packed_world() {
+        let dir = temp_dir("pack");
+        let files = [
+            ("player", "4.5,5.0,8.25,0.4,-0.1".as_bytes()),
+            ("chunk0_0", "0,0,0,5\n0,1,0,1\n0,2,0,0\n".as_bytes()),
+            ("chunk-1_2", "15,3,15,2\n".as_bytes()),
+        ];
+        for (name, data) in files.iter() {
+            std::fs::write(dir.join(name), data).unwrap();
+        }
+
+        let archive_path = dir.with_extension("archive");
+        pack(dir.to_str().unwrap(), archive_path.to_str().unwrap()).unwrap();
+
+        // Read without unpacking
+        let archive = WorldArchive::open(archive_path.to_str().unwrap()).unwrap();
+        assert_eq!(archive.entries.len(), files.len());
+        for (name, data) in files.iter() {
+            assert_eq!(archive.read(name).unwrap(), *data);
+            assert_eq!(archive.entries[*name].hash, content_hash(data));
+        }
+
+        let out = temp_dir("unpack");
+        unpack(archive_path.to_str().unwrap(), out.to_str().unwrap()).unwrap();
+        for (name, data) in files.iter() {
+            assert_eq!(std::fs::read(out.join(name)).unwrap(), *data);
+        }
+    }
+
+    #[test]
+    fn should_reject_a_corrupted_entry() {
+        let dir = temp_dir("corrupt");
+        std::fs::write(dir.join("chunk0_0"), "0,0,0,5\n").unwrap();
+        let archive_path = dir.with_extension("archive");
+        pack(dir.to_str().unwrap(), archive_path.to_str().unwrap()).unwrap();
+
+        let mut bytes = std::fs::read(&archive_path).unwrap();
+        let last = bytes.len() - 2;
+        bytes[last] = b'9';
+        std::fs::write(&archive_path, bytes).unwrap();
+
+        let archive = WorldArchive::open(archive_path.to_str().unwrap()).unwrap();
+        assert!(archive.read("chunk0_0").is_err());
+    }
+
+    // Archive with a single entry, its name and length are written as given
+    fn archive_with_entry(name: &str, len: u64, data: &[u8]) -> Vec<u8> {
+        let mut bytes = ARCHIVE_MAGIC.to_vec();
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(name.as_bytes());
+        bytes.extend_from_slice(&0u64.to_le_bytes());
+        bytes.extend_from_slice(&len.to_le_bytes());
+        bytes.extend_from_slice(&content_hash(data).to_le_bytes());
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    #[test]
+    fn should_reject_entries_outside_the_save_dir_or_the_file() {
+        let dir = temp_dir("malicious");
+        let open = |bytes: Vec<u8>| {
+            let path = dir.join("world.archive");
+            std::fs::write(&path, bytes).unwrap();
+            WorldArchive::open(path.to_str().unwrap())
+        };
+        assert!(open(archive_with_entry("player", 3, b"abc")).is_ok());
+        for name in [
+            "../player",
+            "saves/player",
+            "..\\player",
+            "/etc/player",
+            "..",
+            "",
+        ] {
+            assert!(open(archive_with_entry(name, 3, b"abc")).is_err(), "{name}");
+        }
+        // Longer than the file, or so long the end overflows
+        assert!(open(archive_with_entry("player", 4, b"abc")).is_err());
+        assert!(open(archive_with_entry("player", u64::MAX, b"abc")).is_err());
+    }
+
+    #[test]
+    fn should_load_the_chunks_of_a_packed_world() {
+        let dir = temp_dir("packed-world");
+        let (x, y) = (-3, 5);
+        let mut blocks = ChunkBlocks::new((x, y), DEFAULT_CHUNK_SIZE);
+        for i in 0..DEFAULT_CHUNK_SIZE {
+            blocks.set(i, 0, i, Some(BlockType::Stone));
+        }
+        blocks.set(3, 40, 7, Some(BlockType::Glass));
+        let blocks = Arc::new(RwLock::new(blocks));
+        let mut region = Region::default();
+        region.set(x, y, encode_chunk(x, y, &blocks));
+        std::fs::write(dir.join(region_file_name(x, y)), region.encode()).unwrap();
+
+        let archive_path = dir.with_extension("archive");
+        pack(dir.to_str().unwrap(), archive_path.to_str().unwrap()).unwrap();
+        let archive = Arc::new(WorldArchive::open(archive_path.to_str().unwrap()).unwrap());
+
+        let noise_data = Arc::new(create_world_noise_data(
+            NOISE_SIZE, NOISE_SIZE, FREQUENCY, RNG_SEED,
+        ));
+        // Nothing loose in the save dir, the chunk has to come from the archive
+        let save_dir = temp_dir("packed-world-save");
+        let data = ChunkData::generate(x, y, noise_data, save_dir.to_str().unwrap(), Some(archive));
+        let loaded = data.blocks.read().unwrap();
+        assert_eq!(*loaded, *blocks.read().unwrap());
+        assert_eq!(loaded.get(3, 40, 7), Some(BlockType::Glass));
+        assert_eq!(loaded.get(5, 0, 5), Some(BlockType::Stone));
+        assert_eq!(loaded.get(5, 0, 6), None);
+        let _ = std::fs::remove_dir_all(&dir);
+        let _ = std::fs::remove_dir_all(&save_dir);
+    }
+
+    #[test]
+    fn should_keep_a_region_it_cant_read() {
+        let dir = temp_dir("unreadable-region");
+        let save_dir = dir.to_str().unwrap();
+        let (x, y) = (-2, 3);
+        let path = dir.join(region_file_name(x, y));
+        std::fs::write(&path, b"not a region").unwrap();
+
+        assert!(write_region_chunk(save_dir, x, y, vec![1, 2, 3], None).is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), b"not a region");
+
+        // The other chunks of a readable region are kept, and no temporary file is left behind
+        std::fs::remove_file(&path).unwrap();
+        write_region_chunk(save_dir, x, y, vec![1, 2, 3], None).unwrap();
+        write_region_chunk(save_dir, x + 1, y, vec![4, 5], None).unwrap();
+        assert_eq!(
+            read_region_chunk(save_dir, x, y, None).unwrap(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(
+            read_region_chunk(save_dir, x + 1, y, None).unwrap(),
+            vec![4, 5]
+        );
+        assert!(!path.with_extension("bin.tmp").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,339 @@
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::material::Texture;
+use crate::player::Player;
+use crate::state::State;
+use crate::utils::noise::{fbm, PermutationTable};
+
+use super::pipeline_manager::PipelineManager;
+use super::shaders::shader_source;
+use super::Pipeline;
+
+// Height of the cloud layer
+pub const CLOUD_ALTITUDE: f32 = 96.0;
+// Blocks covered by the cloud texture before it repeats
+const CLOUD_TILE_SIZE: f32 = 512.0;
+const CLOUD_TEXTURE_SIZE: u32 = 256;
+// Noise periods across the texture, whole so it tiles
+const CLOUD_NOISE_PERIOD: u32 = 8;
+const CLOUD_NOISE_OCTAVES: u32 = 4;
+// Noise values below this are clear sky, the clouds fade in over `CLOUD_EDGE` above it
+const CLOUD_COVERAGE: f32 = 0.1;
+const CLOUD_EDGE: f32 = 0.15;
+// Blocks per second the clouds drift along x
+const CLOUD_SPEED: f32 = 1.5;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Pod, Zeroable)]
+pub struct CloudsUniform {
+    // For the fog, the plane is placed from `center`
+    pub eye: [f32; 3],
+    pub altitude: f32,
+    // The player's position snapped to the texture, so the plane moves by whole repeats
+    pub center: [f32; 2],
+    // Blocks the clouds drifted since the start
+    pub scroll: [f32; 2],
+}
+
+impl CloudsUniform {
+    pub fn new(eye: glam::Vec3, seconds: f32) -> Self {
+        let center = (glam::vec2(eye.x, eye.z) / CLOUD_TILE_SIZE).round() * CLOUD_TILE_SIZE;
+        Self {
+            eye: eye.to_array(),
+            altitude: CLOUD_ALTITUDE,
+            center: center.to_array(),
+            // Wrapped, a float that keeps growing loses the precision to move smoothly
+            scroll: [(seconds * CLOUD_SPEED) % CLOUD_TILE_SIZE, 0.0],
+        }
+    }
+}
+
+// Coverage of the cloud texture, from fbm noise that wraps around its edges
+fn cloud_pixels(seed: u64) -> Vec<u8> {
+    let perm = PermutationTable::new(seed);
+    let scale = CLOUD_NOISE_PERIOD as f32 / CLOUD_TEXTURE_SIZE as f32;
+    (0..CLOUD_TEXTURE_SIZE * CLOUD_TEXTURE_SIZE)
+        .map(|i| {
+            let (x, y) = (i % CLOUD_TEXTURE_SIZE, i / CLOUD_TEXTURE_SIZE);
+            let noise = fbm(
+                x as f32 * scale,
+                y as f32 * scale,
+                CLOUD_NOISE_PERIOD,
+                CLOUD_NOISE_OCTAVES,
+                &perm,
+            );
+            let coverage = ((noise - CLOUD_COVERAGE) / CLOUD_EDGE).clamp(0.0, 1.0);
+            (coverage * 255.0).round() as u8
+        })
+        .collect()
+}
+
+// A textured plane at `CLOUD_ALTITUDE` that follows the player, drawn with the translucent faces.
+// Left out when `Config::clouds` is off
+pub struct CloudsPipeline {
+    pub pipeline: wgpu::RenderPipeline,
+    pub clouds_buffer: wgpu::Buffer,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl Pipeline for CloudsPipeline {
+    fn shader_files() -> &'static [&'static str] {
+        &["clouds.wgsl"]
+    }
+    fn render(
+        &self,
+        state: &State,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        _player: &std::sync::RwLockReadGuard<'_, Player>,
+        _chunks: &Vec<std::sync::RwLockReadGuard<'_, crate::chunk::Chunk>>,
+    ) {
+        let main_pipeline_ref = state
+            .pipeline_manager
+            .main_pipeline
+            .as_ref()
+            .unwrap()
+            .borrow();
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(
+                main_pipeline_ref.color_attachment(view, wgpu::LoadOp::Load),
+            )],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &main_pipeline_ref.depth_texture.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        rpass.set_pipeline(&self.pipeline);
+        // The camera, fog and sun of the chunks
+        rpass.set_bind_group(0, &main_pipeline_ref.bind_group_0, &[]);
+        rpass.set_bind_group(1, &self.bind_group, &[]);
+        // Two triangles, built in the vertex shader
+        rpass.draw(0..6, 0..1);
+    }
+    fn init(
+        state: &State,
+        pipeline_manager: &PipelineManager,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let swapchain_format = state.surface_format;
+        let shader = state
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: None,
+                source: wgpu::ShaderSource::Wgsl(shader_source("clouds.wgsl")),
+            });
+
+        let clouds_texture = state.device.create_texture_with_data(
+            &state.queue,
+            &wgpu::TextureDescriptor {
+                label: Some("clouds"),
+                size: wgpu::Extent3d {
+                    width: CLOUD_TEXTURE_SIZE,
+                    height: CLOUD_TEXTURE_SIZE,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::R8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            &cloud_pixels(state.config.seed),
+        );
+        let clouds_view = clouds_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        // Repeats over the plane, smoothed between the texels
+        let clouds_sampler = state.device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let clouds_buffer = state
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("clouds"),
+                contents: bytemuck::cast_slice(&[CloudsUniform::new(
+                    state.player.read().unwrap().camera.eye,
+                    state.elapsed_time,
+                )]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+        let bind_group_layout =
+            state
+                .device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("clouds-layout"),
+                    entries: &[
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Texture {
+                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                                multisampled: false,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 2,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                            count: None,
+                        },
+                    ],
+                });
+        let bind_group = state.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("clouds"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: clouds_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&clouds_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&clouds_sampler),
+                },
+            ],
+        });
+
+        let pipeline_layout =
+            state
+                .device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: None,
+                    bind_group_layouts: &[
+                        &pipeline_manager
+                            .main_pipeline
+                            .as_ref()
+                            .unwrap()
+                            .borrow()
+                            .bind_group_0_layout,
+                        &bind_group_layout,
+                    ],
+                    push_constant_ranges: &[],
+                });
+        let pipeline = state
+            .device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: None,
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: swapchain_format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                // Seen from below and from above
+                primitive: wgpu::PrimitiveState {
+                    cull_mode: None,
+                    ..Default::default()
+                },
+                // Hidden behind the terrain, but like the water it doesn't hide what's drawn after
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: Texture::DEPTH_FORMAT,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::Less,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: state.sample_count,
+                    ..Default::default()
+                },
+                multiview: None,
+            });
+
+        Ok(Self {
+            pipeline,
+            clouds_buffer,
+            bind_group,
+        })
+    }
+    fn update(
+        &mut self,
+        _pipeline_manager: &PipelineManager,
+        state: &State,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let eye = state.player.read().unwrap().camera.eye;
+        state.queue.write_buffer(
+            &self.clouds_buffer,
+            0,
+            bytemuck::cast_slice(&[CloudsUniform::new(eye, state.elapsed_time)]),
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cloud_pixels, CloudsUniform, CLOUD_TEXTURE_SIZE, CLOUD_TILE_SIZE};
+
+    #[test]
+    fn should_snap_the_plane_to_the_texture_and_drift_with_time() {
+        let still = CloudsUniform::new(glam::vec3(100.0, 70.0, -300.0), 0.0);
+        assert_eq!(still.center, [0.0, -CLOUD_TILE_SIZE]);
+        // Moving a bit doesn't move the plane, the texture stays in place
+        let moved = CloudsUniform::new(glam::vec3(110.0, 70.0, -290.0), 0.0);
+        assert_eq!(moved.center, still.center);
+        assert_eq!(moved.scroll, still.scroll);
+
+        let later = CloudsUniform::new(glam::vec3(100.0, 70.0, -300.0), 10.0);
+        assert!(later.scroll[0] > still.scroll[0]);
+        let much_later = CloudsUniform::new(glam::vec3(100.0, 70.0, -300.0), 1e6);
+        assert!(much_later.scroll[0] < CLOUD_TILE_SIZE);
+    }
+
+    #[test]
+    fn should_bake_a_cloud_texture_with_clear_sky_that_tiles() {
+        let pixels = cloud_pixels(42);
+        let size = CLOUD_TEXTURE_SIZE as usize;
+        assert_eq!(pixels.len(), size * size);
+        // Some clouds and some clear sky
+        let cloudy = pixels.iter().filter(|&&p| p > 0).count();
+        assert!(cloudy > pixels.len() / 10 && cloudy < pixels.len() * 9 / 10);
+        assert_eq!(pixels, cloud_pixels(42));
+        // Neighbouring texels across the edges are as close as the ones inside
+        let step = |a: u8, b: u8| (a as i32 - b as i32).abs();
+        let max_inner = (0..size)
+            .map(|y| step(pixels[y * size], pixels[y * size + 1]))
+            .max()
+            .unwrap();
+        let max_wrap = (0..size)
+            .map(|y| step(pixels[y * size + size - 1], pixels[y * size]))
+            .max()
+            .unwrap();
+        assert!(max_wrap <= max_inner * 2 + 8, "{max_wrap} {max_inner}");
+    }
+}
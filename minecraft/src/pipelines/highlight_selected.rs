@@ -1,14 +1,40 @@
-use crate::{blocks::block::FaceDirections, material::Texture, player::Player, state::State};
+use crate::blocks::block::{Block, FaceDirections, CUBE_VERTEX};
+use crate::player::crack_stage;
+use crate::{material::Texture, player::Player, state::State};
 
-use super::{pipeline_manager::PipelineManager, Pipeline};
+use super::{
+    main::BLOCK_TEXTURE_SHADER, pipeline_manager::PipelineManager, shaders::concat_shader_sources,
+    Pipeline,
+};
+
+// Texture layer of the first crack stage, the others follow it
+const FIRST_CRACK_TILE: u32 = 22;
+// Two vertices for each of the 12 edges of the outline
+const EDGE_VERTEX_COUNT: usize = 2 * 12;
+// A bit larger than the block, so its own faces don't hide the edges
+const OUTLINE_SIZE: f32 = 1.004;
+// The six faces of the block being broken
+const CRACK_VERTEX_COUNT: usize = 4 * 6;
+const CRACK_INDEX_COUNT: u32 = 6 * 6;
+
+// Position, uv and texture layer, the outline ignores the last two
+pub(super) type HighlightVertex = [f32; 6];
 
 pub struct HighlightSelectedPipeline {
+    // Black lines along the edges of the selected block
     pub pipeline: wgpu::RenderPipeline,
-    pub selected_block_vertex_buffer: wgpu::Buffer,
-    pub selected_block_index_buffer: wgpu::Buffer,
-    pub indices: u32,
+    // The faces of the block being broken, textured with its crack stage
+    pub crack_pipeline: wgpu::RenderPipeline,
+    pub edge_vertex_buffer: wgpu::Buffer,
+    pub crack_vertex_buffer: wgpu::Buffer,
+    pub crack_index_buffer: wgpu::Buffer,
+    pub edge_vertices: u32,
+    pub crack_indices: u32,
 }
 impl Pipeline for HighlightSelectedPipeline {
+    fn shader_files() -> &'static [&'static str] {
+        &[BLOCK_TEXTURE_SHADER, "highlight.wgsl"]
+    }
     fn render(
         &self,
         state: &State,
@@ -25,18 +51,14 @@ impl Pipeline for HighlightSelectedPipeline {
             .borrow();
         let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: None,
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Load,
-                    store: wgpu::StoreOp::Store,
-                },
-            })],
+            color_attachments: &[Some(
+                main_pipeline_ref.color_attachment(view, wgpu::LoadOp::Load),
+            )],
             depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
                 view: &main_pipeline_ref.depth_texture.view,
+                // The scene's depth hides the edges behind the block and other blocks
                 depth_ops: Some(wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(1.0),
+                    load: wgpu::LoadOp::Load,
                     store: wgpu::StoreOp::Discard,
                 }),
                 stencil_ops: None,
@@ -44,14 +66,18 @@ impl Pipeline for HighlightSelectedPipeline {
             timestamp_writes: None,
             occlusion_query_set: None,
         });
-        rpass.set_pipeline(&self.pipeline);
         rpass.set_bind_group(0, &main_pipeline_ref.bind_group_0, &[]);
-        rpass.set_vertex_buffer(0, self.selected_block_vertex_buffer.slice(..));
-        rpass.set_index_buffer(
-            self.selected_block_index_buffer.slice(..),
-            wgpu::IndexFormat::Uint32,
-        );
-        rpass.draw_indexed(0..self.indices, 0, 0..1);
+        if self.crack_indices > 0 {
+            rpass.set_pipeline(&self.crack_pipeline);
+            rpass.set_vertex_buffer(0, self.crack_vertex_buffer.slice(..));
+            rpass.set_index_buffer(self.crack_index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            rpass.draw_indexed(0..self.crack_indices, 0, 0..1);
+        }
+        if self.edge_vertices > 0 {
+            rpass.set_pipeline(&self.pipeline);
+            rpass.set_vertex_buffer(0, self.edge_vertex_buffer.slice(..));
+            rpass.draw(0..self.edge_vertices, 0..1);
+        }
     }
     fn update(
         &mut self,
@@ -60,46 +86,37 @@ impl Pipeline for HighlightSelectedPipeline {
     ) -> Result<(), Box<dyn std::error::Error>> {
         let player = state.player.read().unwrap();
         if let Some(block_ptr) = player.facing_block.as_ref() {
-            let mut face_data = FaceDirections::all()
-                .iter()
-                .find(|f| **f == player.facing_face.unwrap())
-                .unwrap()
-                .create_face_data(block_ptr.clone(), &vec![]);
-
-            let block = block_ptr.read().unwrap();
-            let block_positions = face_data
-                .0
-                .iter_mut()
-                .map(|v| {
-                    [
-                        v.position[0] + block.absolute_position.x - block.position.x,
-                        v.position[1] + block.absolute_position.y - block.position.y,
-                        v.position[2] + block.absolute_position.z - block.position.z,
-                    ]
-                })
-                .collect::<Vec<_>>();
+            let edges = cube_edges(block_ptr.read().unwrap().absolute_position, OUTLINE_SIZE);
+            state
+                .queue
+                .write_buffer(&self.edge_vertex_buffer, 0, bytemuck::cast_slice(&edges));
+            self.edge_vertices = EDGE_VERTEX_COUNT as u32;
+        } else {
+            // Unselect block.
+            self.edge_vertices = 0;
+        }
 
+        self.crack_indices = 0;
+        if let Some((block_ptr, progress)) = player.breaking.as_ref() {
+            let (vertices, indices) = crack_face_data(&block_ptr.read().unwrap(), *progress);
             state.queue.write_buffer(
-                &self.selected_block_vertex_buffer,
+                &self.crack_vertex_buffer,
                 0,
-                bytemuck::cast_slice(&block_positions),
+                bytemuck::cast_slice(&vertices),
             );
-            state.queue.write_buffer(
-                &self.selected_block_index_buffer,
-                0,
-                bytemuck::cast_slice(&face_data.1),
-            );
-            self.indices = 6;
-        } else {
-            // Unselect block.
-            self.indices = 0;
+            state
+                .queue
+                .write_buffer(&self.crack_index_buffer, 0, bytemuck::cast_slice(&indices));
+            self.crack_indices = CRACK_INDEX_COUNT;
         }
         Ok(())
     }
-    fn init(state: &State, pipeline_manager: &PipelineManager) -> Self {
-        let swapchain_capabilities = state.surface.get_capabilities(&state.adapter);
-        let swapchain_format = swapchain_capabilities.formats[0];
-        let shader_source = include_str!("../shaders/highlight.wgsl");
+    fn init(
+        state: &State,
+        pipeline_manager: &PipelineManager,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let swapchain_format = state.surface_format;
+        let shader_source = concat_shader_sources(Self::shader_files());
 
         let shader = state
             .device
@@ -108,15 +125,21 @@ impl Pipeline for HighlightSelectedPipeline {
                 source: wgpu::ShaderSource::Wgsl(shader_source.into()),
             });
 
-        let selected_block_vertex_buffer = state.device.create_buffer(&wgpu::BufferDescriptor {
+        let edge_vertex_buffer = state.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (std::mem::size_of::<HighlightVertex>() * EDGE_VERTEX_COUNT) as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let crack_vertex_buffer = state.device.create_buffer(&wgpu::BufferDescriptor {
             label: None,
-            size: std::mem::size_of::<[[f32; 3]; 4]>() as u64 * 4,
+            size: (std::mem::size_of::<HighlightVertex>() * CRACK_VERTEX_COUNT) as u64,
             usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
-        let selected_block_index_buffer = state.device.create_buffer(&wgpu::BufferDescriptor {
+        let crack_index_buffer = state.device.create_buffer(&wgpu::BufferDescriptor {
             label: None,
-            size: std::mem::size_of::<u32>() as u64 * 6,
+            size: std::mem::size_of::<u32>() as u64 * CRACK_INDEX_COUNT as u64,
             usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
@@ -135,60 +158,207 @@ impl Pipeline for HighlightSelectedPipeline {
                         .bind_group_0_layout],
                     push_constant_ranges: &[],
                 });
-        let render_pipeline =
-            state
-                .device
-                .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                    label: None,
-                    layout: Some(&pipeline_layout),
-                    vertex: wgpu::VertexState {
-                        module: &shader,
-                        entry_point: "vs_main",
-                        buffers: &[Self::get_vertex_data_layout()],
-                    },
-                    fragment: Some(wgpu::FragmentState {
-                        module: &shader,
-                        entry_point: "fs_main",
-                        targets: &[Some(wgpu::ColorTargetState {
-                            format: swapchain_format,
-                            blend: Some(wgpu::BlendState::ALPHA_BLENDING),
-                            write_mask: wgpu::ColorWrites::ALL,
-                        })],
-                    }),
-                    primitive: wgpu::PrimitiveState {
-                        cull_mode: Some(wgpu::Face::Front),
-                        ..Default::default()
-                    },
-                    depth_stencil: Some(wgpu::DepthStencilState {
-                        format: Texture::DEPTH_FORMAT,
-                        depth_write_enabled: false,
-                        depth_compare: wgpu::CompareFunction::Always,
-                        stencil: wgpu::StencilState::default(),
-                        bias: wgpu::DepthBiasState::default(),
-                    }),
-                    multisample: wgpu::MultisampleState::default(),
-                    multiview: None,
-                });
+        let render_pipeline = Self::create_render_pipeline(
+            state,
+            &pipeline_layout,
+            &shader,
+            swapchain_format,
+            "fs_main",
+            wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                ..Default::default()
+            },
+            wgpu::CompareFunction::LessEqual,
+        );
+        let crack_pipeline = Self::create_render_pipeline(
+            state,
+            &pipeline_layout,
+            &shader,
+            swapchain_format,
+            "fs_crack",
+            wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Front),
+                ..Default::default()
+            },
+            wgpu::CompareFunction::Always,
+        );
 
-        Self {
-            indices: 6,
+        Ok(Self {
+            edge_vertices: 0,
+            crack_indices: 0,
             pipeline: render_pipeline,
-            selected_block_index_buffer,
-            selected_block_vertex_buffer,
-        }
+            crack_pipeline,
+            edge_vertex_buffer,
+            crack_vertex_buffer,
+            crack_index_buffer,
+        })
     }
 }
 
 impl HighlightSelectedPipeline {
     pub fn get_vertex_data_layout() -> wgpu::VertexBufferLayout<'static> {
         wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+            array_stride: std::mem::size_of::<HighlightVertex>() as wgpu::BufferAddress,
             step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &[wgpu::VertexAttribute {
-                format: wgpu::VertexFormat::Float32x3,
-                offset: 0,
-                shader_location: 0,
-            }],
+            attributes: &[
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x3,
+                    offset: 0,
+                    shader_location: 0,
+                },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x3,
+                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                },
+            ],
+        }
+    }
+    // The outline is a line list tested against the scene's depth, the cracks are drawn over it
+    // with the culling keeping only the faces towards the camera
+    fn create_render_pipeline(
+        state: &State,
+        layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+        format: wgpu::TextureFormat,
+        fragment_entry_point: &str,
+        primitive: wgpu::PrimitiveState,
+        depth_compare: wgpu::CompareFunction,
+    ) -> wgpu::RenderPipeline {
+        state
+            .device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: None,
+                layout: Some(layout),
+                vertex: wgpu::VertexState {
+                    module: shader,
+                    entry_point: "vs_main",
+                    buffers: &[Self::get_vertex_data_layout()],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: shader,
+                    entry_point: fragment_entry_point,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive,
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: Texture::DEPTH_FORMAT,
+                    depth_write_enabled: false,
+                    depth_compare,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: state.sample_count,
+                    ..Default::default()
+                },
+                multiview: None,
+            })
+    }
+}
+// Line list of the 12 edges of a cube of `size` around `center`, every pair of corners of
+// `CUBE_VERTEX` that only differ on one axis
+pub(super) fn cube_edges(center: glam::Vec3, size: f32) -> Vec<HighlightVertex> {
+    let corner = |i: usize| glam::Vec3::from_slice(&CUBE_VERTEX[i * 3..i * 3 + 3]);
+    let mut vertices = Vec::with_capacity(EDGE_VERTEX_COUNT);
+    for a in 0..8 {
+        for b in a + 1..8 {
+            let different_axes = corner(a).cmpne(corner(b)).bitmask().count_ones();
+            if different_axes != 1 {
+                continue;
+            }
+            for i in [a, b] {
+                let position = center + corner(i) * size;
+                vertices.push([position.x, position.y, position.z, 0.0, 0.0, 0.0]);
+            }
+        }
+    }
+    vertices
+}
+// The six faces of the block with the tile of its crack stage instead of its own texture
+fn crack_face_data(block: &Block, progress: f32) -> (Vec<HighlightVertex>, Vec<u32>) {
+    let crack_layer = (FIRST_CRACK_TILE + crack_stage(progress)) as f32;
+    let mut vertices = Vec::with_capacity(CRACK_VERTEX_COUNT);
+    let mut indices = Vec::with_capacity(CRACK_INDEX_COUNT as usize);
+    for face in FaceDirections::all() {
+        let (face_vertices, face_indices) = face.create_face_data(block, &[]);
+        let first_vertex = vertices.len() as u32;
+        indices.extend(face_indices.iter().map(|i| first_vertex + i));
+        vertices.extend(face_vertices.iter().map(|v| {
+            [
+                v.position[0] + block.absolute_position.x - block.position.x,
+                v.position[1] + block.absolute_position.y - block.position.y,
+                v.position[2] + block.absolute_position.z - block.position.z,
+                v.tex_coords[0],
+                v.tex_coords[1],
+                crack_layer,
+            ]
+        }));
+    }
+    (vertices, indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        crack_face_data, cube_edges, CRACK_INDEX_COUNT, CRACK_VERTEX_COUNT, EDGE_VERTEX_COUNT,
+        FIRST_CRACK_TILE,
+    };
+    use crate::blocks::block::Block;
+    use crate::blocks::block_type::BlockType;
+    use crate::player::CRACK_STAGES;
+    use crate::world::DEFAULT_CHUNK_SIZE;
+
+    #[test]
+    fn should_cover_every_face_of_the_block_with_its_crack_stage() {
+        let block = Block::new(
+            glam::vec3(2.0, 5.0, 3.0),
+            (1, 0),
+            BlockType::Stone,
+            DEFAULT_CHUNK_SIZE,
+        );
+        let (vertices, indices) = crack_face_data(&block, 0.55);
+        assert_eq!(vertices.len(), CRACK_VERTEX_COUNT);
+        assert_eq!(indices.len(), CRACK_INDEX_COUNT as usize);
+
+        // Around the block in the neighbour chunk, with the texture of the sixth stage
+        for v in vertices.iter() {
+            for axis in 0..3 {
+                let offset = v[axis] - block.absolute_position[axis];
+                assert!(offset.abs() == 0.5, "{v:?}");
+            }
+            assert!(v[3..5].iter().all(|uv| (0.0..=1.0).contains(uv)), "{v:?}");
+            assert_eq!(v[5], (FIRST_CRACK_TILE + 5) as f32);
+        }
+        // The last stage is still a crack texture
+        let (last, _) = crack_face_data(&block, 1.0);
+        assert!(last
+            .iter()
+            .all(|v| v[5] == (FIRST_CRACK_TILE + CRACK_STAGES - 1) as f32));
+    }
+
+    #[test]
+    fn should_emit_the_12_edges_of_the_cube() {
+        let center = glam::vec3(4.0, 7.0, -2.0);
+        let vertices = cube_edges(center, 1.0);
+        assert_eq!(vertices.len(), EDGE_VERTEX_COUNT);
+
+        let mut edges = vec![];
+        for edge in vertices.chunks(2) {
+            let [a, b] = [edge[0], edge[1]].map(|v| glam::vec3(v[0], v[1], v[2]) - center);
+            // From a corner of the unit cube to another one along an axis
+            assert!(a.abs() == glam::Vec3::splat(0.5) && b.abs() == glam::Vec3::splat(0.5));
+            assert_eq!((b - a).length(), 1.0);
+            let mut edge = [a, b].map(|p| p.to_array().map(f32::to_bits));
+            edge.sort();
+            edges.push(edge);
         }
+        edges.sort();
+        edges.dedup();
+        assert_eq!(edges.len(), 12);
     }
 }
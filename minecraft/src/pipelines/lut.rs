@@ -0,0 +1,516 @@
+use std::error::Error;
+use std::sync::RwLockReadGuard;
+
+use image::GenericImageView;
+use wgpu::util::DeviceExt;
+
+use super::pipeline_manager::PipelineManager;
+use super::shaders::shader_source;
+use super::Pipeline;
+use crate::chunk::Chunk;
+use crate::material::Texture;
+use crate::player::Player;
+use crate::state::State;
+
+// Color lookup table, `size` entries per channel with red varying the fastest
+#[derive(Debug, Clone)]
+pub struct LutData {
+    pub size: u32,
+    pub data: Vec<[u8; 4]>,
+}
+
+impl LutData {
+    pub fn identity(size: u32) -> Self {
+        let mut data = Vec::with_capacity((size * size * size) as usize);
+        let to_u8 = |v: u32| f32::round(v as f32 / (size - 1) as f32 * 255.0) as u8;
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    data.push([to_u8(r), to_u8(g), to_u8(b), 255]);
+                }
+            }
+        }
+        Self { size, data }
+    }
+    pub fn from_path(path: &str) -> Result<Self, Box<dyn Error>> {
+        Self::from_image(&image::open(path)?)
+    }
+    /*
+    Accepts the two common layouts:
+    strip: size * size wide, size tall, one size x size slice per blue value from left to right
+    hald: level^3 square image, with size = level^2 entries per channel read row by row */
+    pub fn from_image(image: &image::DynamicImage) -> Result<Self, Box<dyn Error>> {
+        let (width, height) = image.dimensions();
+        let rgba = image.to_rgba8();
+        let pixel = |x: u32, y: u32| {
+            let p = rgba.get_pixel(x, y).0;
+            [p[0], p[1], p[2], 255]
+        };
+
+        if height > 1 && width == height * height {
+            let size = height;
+            let mut data = Vec::with_capacity((size * size * size) as usize);
+            for b in 0..size {
+                for g in 0..size {
+                    for r in 0..size {
+                        data.push(pixel(b * size + r, g));
+                    }
+                }
+            }
+            return Ok(Self { size, data });
+        }
+
+        let level = f32::round(f32::cbrt(width as f32)) as u32;
+        if width == height && level > 1 && level * level * level == width {
+            let size = level * level;
+            let data = (0..width * height)
+                .map(|i| pixel(i % width, i / width))
+                .collect();
+            return Ok(Self { size, data });
+        }
+
+        Err(format!("{}x{} is not a strip or hald lut", width, height).into())
+    }
+    // Trilinear lookup, same as the gpu sampling in lut.wgsl
+    pub fn sample(&self, color: [f32; 3]) -> [f32; 3] {
+        let max = (self.size - 1) as f32;
+        let coords = color.map(|c| c.clamp(0.0, 1.0) * max);
+        let low = coords.map(|c| f32::floor(c) as u32);
+        let high = low.map(|c| (c + 1).min(self.size - 1));
+        let t = [0, 1, 2].map(|i| coords[i] - low[i] as f32);
+
+        let entry = |r: u32, g: u32, b: u32| {
+            self.data[(r + self.size * (g + self.size * b)) as usize].map(|c| c as f32 / 255.0)
+        };
+        let lerp =
+            |a: [f32; 4], b: [f32; 4], t: f32| [0, 1, 2, 3].map(|i| a[i] + (b[i] - a[i]) * t);
+
+        let c00 = lerp(
+            entry(low[0], low[1], low[2]),
+            entry(high[0], low[1], low[2]),
+            t[0],
+        );
+        let c10 = lerp(
+            entry(low[0], high[1], low[2]),
+            entry(high[0], high[1], low[2]),
+            t[0],
+        );
+        let c01 = lerp(
+            entry(low[0], low[1], high[2]),
+            entry(high[0], low[1], high[2]),
+            t[0],
+        );
+        let c11 = lerp(
+            entry(low[0], high[1], high[2]),
+            entry(high[0], high[1], high[2]),
+            t[0],
+        );
+        let c = lerp(lerp(c00, c10, t[1]), lerp(c01, c11, t[1]), t[2]);
+        [c[0], c[1], c[2]]
+    }
+}
+
+// Grades the whole frame with a 3d lut. When enabled the other pipelines render into `scene_texture`
+// and this pass writes the graded result to the surface.
+pub struct LutPipeline {
+    pub pipeline: wgpu::RenderPipeline,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    // None while disabled
+    pub bind_group: Option<wgpu::BindGroup>,
+    pub lut_texture: Option<Texture>,
+    pub scene_texture: Texture,
+    pub params_buffer: wgpu::Buffer,
+    pub lut_size: u32,
+}
+
+impl Pipeline for LutPipeline {
+    fn shader_files() -> &'static [&'static str] {
+        &["lut.wgsl"]
+    }
+    fn init(state: &State, _pipeline_manager: &PipelineManager) -> Result<Self, Box<dyn Error>> {
+        let swapchain_format = state.surface_format;
+        let bind_group_layout = Self::create_bind_group_layout(&state.device);
+
+        let params_buffer = state
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("lut_params"),
+                contents: bytemuck::cast_slice(&[0.0f32; 4]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+
+        let render_pipeline =
+            Self::create_render_pipeline(&state.device, swapchain_format, &bind_group_layout);
+
+        let mut lut_pipeline = Self {
+            pipeline: render_pipeline,
+            bind_group_layout,
+            bind_group: None,
+            lut_texture: None,
+            scene_texture: Texture::create_scene_texture(state, swapchain_format),
+            params_buffer,
+            lut_size: 0,
+        };
+        lut_pipeline.set_lut(state, state.config.lut_path.as_deref());
+        Ok(lut_pipeline)
+    }
+    fn update(
+        &mut self,
+        _pipeline_manager: &PipelineManager,
+        state: &State,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.is_enabled() {
+            let srgb = if self.scene_texture.texture.format().is_srgb() {
+                1.0
+            } else {
+                0.0
+            };
+            state.queue.write_buffer(
+                &self.params_buffer,
+                0,
+                bytemuck::cast_slice(&[
+                    state.config.lut_intensity.clamp(0.0, 1.0),
+                    self.lut_size as f32,
+                    srgb,
+                    0.0,
+                ]),
+            );
+        }
+        Ok(())
+    }
+    fn render(
+        &self,
+        _state: &State,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        _player: &RwLockReadGuard<'_, Player>,
+        _chunks: &Vec<RwLockReadGuard<'_, Chunk>>,
+    ) {
+        let Some(bind_group) = self.bind_group.as_ref() else {
+            return;
+        };
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("lut"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+    // The scene texture has to follow the surface size
+    fn resize(&mut self, state: &State, _new_size: (u32, u32)) {
+        let format = self.scene_texture.texture.format();
+        self.scene_texture = Texture::create_scene_texture(state, format);
+        if self.lut_texture.is_some() {
+            self.create_bind_group(state);
+        }
+    }
+}
+
+impl LutPipeline {
+    pub fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let texture_entry =
+            |binding: u32, view_dimension: wgpu::TextureViewDimension| wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension,
+                    multisampled: false,
+                },
+                count: None,
+            };
+        let sampler_entry = |binding: u32| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        };
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("lut_bind_group"),
+            entries: &[
+                texture_entry(0, wgpu::TextureViewDimension::D2),
+                sampler_entry(1),
+                texture_entry(2, wgpu::TextureViewDimension::D3),
+                sampler_entry(3),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        })
+    }
+    pub fn create_render_pipeline(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> wgpu::RenderPipeline {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(shader_source("lut.wgsl")),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("lut"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(format.into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    }
+    pub fn is_enabled(&self) -> bool {
+        self.bind_group.is_some()
+    }
+    // None disables the pass. An invalid lut is skipped with a warning and leaves the pass disabled.
+    pub fn set_lut(&mut self, state: &State, path: Option<&str>) {
+        self.lut_texture = None;
+        self.bind_group = None;
+
+        let Some(path) = path else {
+            return;
+        };
+        let lut = match LutData::from_path(path) {
+            Ok(lut) => lut,
+            Err(err) => {
+                eprintln!("Warning: skipping color lut {}: {}", path, err);
+                return;
+            }
+        };
+        self.lut_size = lut.size;
+        self.lut_texture = Some(Texture::from_lut(&lut, &state.device, &state.queue));
+        self.create_bind_group(state);
+    }
+    fn create_bind_group(&mut self, state: &State) {
+        let lut_texture = self.lut_texture.as_ref().expect("Lut texture not loaded");
+        self.bind_group = Some(state.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("lut_bind_group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&self.scene_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.scene_texture.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&lut_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&lut_texture.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: self.params_buffer.as_entire_binding(),
+                },
+            ],
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LutData, LutPipeline};
+    use crate::material::Texture;
+    use crate::screenshot::read_texture;
+    use crate::utils::test_device;
+    use wgpu::util::DeviceExt;
+
+    fn identity_strip(size: u32) -> image::DynamicImage {
+        let to_u8 = |v: u32| f32::round(v as f32 / (size - 1) as f32 * 255.0) as u8;
+        image::DynamicImage::ImageRgba8(image::RgbaImage::from_fn(size * size, size, |x, y| {
+            image::Rgba([to_u8(x % size), to_u8(y), to_u8(x / size), 255])
+        }))
+    }
+
+    #[test]
+    fn should_leave_colors_unchanged_with_an_identity_lut() {
+        let lut = LutData::from_image(&identity_strip(16)).unwrap();
+        assert_eq!(lut.size, 16);
+        assert_eq!(lut.data, LutData::identity(16).data);
+
+        for color in [
+            [0.0, 0.0, 0.0],
+            [1.0, 1.0, 1.0],
+            [0.03, 0.64, 0.97],
+            [0.5, 0.25, 0.75],
+            [0.91, 0.12, 0.33],
+        ] {
+            let graded = lut.sample(color);
+            for i in 0..3 {
+                assert!(
+                    (graded[i] - color[i]).abs() < 0.01,
+                    "{:?} -> {:?}",
+                    color,
+                    graded
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn should_reject_images_that_are_not_luts() {
+        let image = image::DynamicImage::ImageRgba8(image::RgbaImage::new(30, 20));
+        assert!(LutData::from_image(&image).is_err());
+    }
+
+    // LutPipeline::init needs a window, so the pass is rebuilt here from the same layout and
+    // pipeline, over a scene texture of a single color
+    #[test]
+    fn should_grade_a_rendered_frame_like_the_cpu_lookup() {
+        let Some((device, queue)) = test_device() else {
+            println!("No adapter available, skipping lut render test");
+            return;
+        };
+        let format = wgpu::TextureFormat::Rgba8Unorm;
+        // 64 pixels wide so the rows are already aligned for the readback
+        let size = wgpu::Extent3d {
+            width: 64,
+            height: 4,
+            depth_or_array_layers: 1,
+        };
+        let color = [64u8, 128, 192, 255];
+        let scene = device.create_texture_with_data(
+            &queue,
+            &wgpu::TextureDescriptor {
+                label: Some("scene_texture"),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+            &color.repeat((size.width * size.height) as usize),
+        );
+        let target = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("target"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+
+        // Inverts the colors, far enough from the identity that skipping the pass would show
+        let mut lut = LutData::identity(16);
+        lut.data
+            .iter_mut()
+            .for_each(|entry| *entry = [255 - entry[0], 255 - entry[1], 255 - entry[2], 255]);
+        let lut_texture = Texture::from_lut(&lut, &device, &queue);
+        let params = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("lut_params"),
+            contents: bytemuck::cast_slice(&[1.0f32, lut.size as f32, 0.0, 0.0]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let bind_group_layout = LutPipeline::create_bind_group_layout(&device);
+        let pipeline = LutPipeline::create_render_pipeline(&device, format, &bind_group_layout);
+        let scene_view = scene.create_view(&wgpu::TextureViewDescriptor::default());
+        let scene_sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("lut_bind_group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&scene_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&scene_sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&lut_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&lut_texture.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: params.as_entire_binding(),
+                },
+            ],
+        });
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("lut") });
+        {
+            let view = target.create_view(&wgpu::TextureViewDescriptor::default());
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("lut"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            rpass.set_pipeline(&pipeline);
+            rpass.set_bind_group(0, &bind_group, &[]);
+            rpass.draw(0..3, 0..1);
+        }
+        queue.submit(Some(encoder.finish()));
+        let error = pollster::block_on(device.pop_error_scope());
+        assert!(error.is_none(), "{error:?}");
+
+        let expected = lut.sample([0, 1, 2].map(|i| color[i] as f32 / 255.0));
+        let image = read_texture(&device, &queue, &target).unwrap();
+        for pixel in image.pixels() {
+            for i in 0..3 {
+                let graded = pixel.0[i] as f32 / 255.0;
+                assert!(
+                    (graded - expected[i]).abs() < 0.02,
+                    "{:?} -> {:?}, expected {:?}",
+                    color,
+                    pixel,
+                    expected
+                );
+            }
+        }
+    }
+}
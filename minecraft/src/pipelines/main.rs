@@ -1,22 +1,128 @@
 use wgpu::Face;
 
+use bytemuck::{Pod, Zeroable};
+
 use crate::{
-    blocks::block::Block, material::Texture, pipeline::Uniforms, player::Player, state::State,
+    blocks::{block::Block, block_type::BLOCK_OFFSET},
+    day_cycle::DayCycle,
+    material::{SamplerConfig, Texture},
+    pipeline::Uniforms,
+    player::Player,
+    state::State,
 };
 
-use super::{pipeline_manager::PipelineManager, Pipeline};
+use super::{
+    pipeline_manager::PipelineManager,
+    shaders::{catch_validation_error, concat_shader_sources},
+    Pipeline,
+};
 use wgpu::util::DeviceExt;
 
+// Fraction of the fog end distance where it starts, a wide band instead of a wall at the edge
+const FOG_START: f32 = 0.6;
+// Much shorter and blue-green when the camera is under the water surface
+const UNDERWATER_FOG_COLOR: [f32; 3] = [0.05, 0.3, 0.35];
+const UNDERWATER_FOG_START: f32 = 1.0;
+const UNDERWATER_FOG_END: f32 = 14.0;
+
+// Declares the block texture (bind group 0, bindings 3 and 4) and sample_block_texture, put in
+// front of every shader that draws blocks. A layer per block by default, the whole atlas with the
+// `atlas` feature
+#[cfg(not(feature = "atlas"))]
+pub const BLOCK_TEXTURE_SHADER: &str = "block_texture_array.wgsl";
+#[cfg(feature = "atlas")]
+pub const BLOCK_TEXTURE_SHADER: &str = "block_texture_atlas.wgsl";
+// The block shader, also drawn by the wireframe pipeline
+pub const BLOCK_SHADER_FILES: [&str; 3] =
+    [BLOCK_TEXTURE_SHADER, "block_vertex.wgsl", "shader.wgsl"];
+#[cfg(not(feature = "atlas"))]
+const BLOCK_TEXTURE_DIMENSION: wgpu::TextureViewDimension = wgpu::TextureViewDimension::D2Array;
+#[cfg(feature = "atlas")]
+const BLOCK_TEXTURE_DIMENSION: wgpu::TextureViewDimension = wgpu::TextureViewDimension::D2;
+
 pub struct MainPipeline {
     pub projection_buffer: wgpu::Buffer,
     pub view_buffer: wgpu::Buffer,
     pub pipeline: wgpu::RenderPipeline,
     pub bind_group_0: wgpu::BindGroup,
     pub bind_group_0_layout: wgpu::BindGroupLayout,
+    // Its sampler follows `Config::texture_filtering`
+    pub texture_atlas: Texture,
+    // Also used by the wireframe pipeline, it draws the same chunks
+    pub pipeline_layout: wgpu::PipelineLayout,
     pub depth_texture: Texture,
+    // Only with more than one sample, the scene passes resolve it into the frame
+    pub msaa_texture: Option<Texture>,
+    pub day_cycle: DayCycle,
+    pub sun_buffer: wgpu::Buffer,
+    // Distance (in blocks) from the player where the fog fully hides the world, it follows the
+    // render distance
+    pub fog_distance: f32,
+    pub underwater: bool,
+    pub fog_buffer: wgpu::Buffer,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+pub struct FogUniform {
+    pub color: [f32; 4],
+    pub start: f32,
+    pub end: f32,
+    // Uniforms are sized in multiples of 16 bytes
+    _padding: [f32; 2],
+}
+
+impl MainPipeline {
+    pub fn fog_uniform(&self) -> FogUniform {
+        Self::fog(
+            self.day_cycle.sky_color(),
+            self.fog_distance,
+            self.underwater,
+        )
+    }
+    // Same color as the sky, so the far chunks fade into the clear color
+    pub fn fog(sky_color: glam::Vec3, fog_distance: f32, underwater: bool) -> FogUniform {
+        let (color, start, end) = if underwater {
+            (
+                glam::Vec3::from(UNDERWATER_FOG_COLOR),
+                UNDERWATER_FOG_START,
+                UNDERWATER_FOG_END,
+            )
+        } else {
+            (sky_color, fog_distance * FOG_START, fog_distance)
+        };
+        FogUniform {
+            color: color.extend(1.0).to_array(),
+            start,
+            end: end.max(start + 0.001),
+            _padding: [0.0; 2],
+        }
+    }
 }
 
 impl Pipeline for MainPipeline {
+    fn shader_files() -> &'static [&'static str] {
+        &BLOCK_SHADER_FILES
+    }
+    // Only the render pipeline is built again, the day cycle and the buffers the other pipelines
+    // bind are kept
+    fn rebuild_pipeline(
+        &mut self,
+        state: &State,
+        _pipeline_manager: &PipelineManager,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.pipeline = catch_validation_error(&state.device, || {
+            Self::create_render_pipeline(
+                &state.device,
+                state.surface_format,
+                &self.pipeline_layout,
+                Self::primitive(),
+                "fs_main",
+                state.sample_count,
+            )
+        })?;
+        Ok(())
+    }
     fn render(
         &self,
         _state: &State,
@@ -25,80 +131,45 @@ impl Pipeline for MainPipeline {
         player: &std::sync::RwLockReadGuard<'_, Player>,
         chunks: &Vec<std::sync::RwLockReadGuard<'_, crate::chunk::Chunk>>,
     ) {
-        let mut main_rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: None,
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color {
-                        r: 0.03,
-                        g: 0.64,
-                        b: 0.97,
-                        a: 1.0,
-                    }),
-                    store: wgpu::StoreOp::Store,
-                },
-            })],
-            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                view: &self.depth_texture.view,
-                depth_ops: Some(wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(1.0),
-                    store: wgpu::StoreOp::Store,
-                }),
-                stencil_ops: None,
-            }),
-            timestamp_writes: None,
-            occlusion_query_set: None,
-        });
-        main_rpass.set_pipeline(&self.pipeline);
-        main_rpass.set_bind_group(0, &self.bind_group_0, &[]);
-
-        main_rpass.set_bind_group(2, &player.camera.position_bind_group, &[]);
-
-        for chunk in chunks.iter() {
-            if chunk.visible {
-                main_rpass.set_bind_group(1, &chunk.chunk_bind_group, &[]);
-                main_rpass.set_vertex_buffer(
-                    0,
-                    chunk
-                        .chunk_vertex_buffer
-                        .as_ref()
-                        .expect("Vertex buffer not initiated")
-                        .slice(..),
-                );
-                main_rpass.set_index_buffer(
-                    chunk
-                        .chunk_index_buffer
-                        .as_ref()
-                        .expect("Index buffer not initiated")
-                        .slice(..),
-                    wgpu::IndexFormat::Uint32,
-                );
-                main_rpass.draw_indexed(0..chunk.indices, 0, 0..1);
-            }
-        }
+        self.draw_chunks(&self.pipeline, encoder, view, player, chunks);
     }
 
     fn update(
         &mut self,
         _pipeline_manager: &PipelineManager,
-        _state: &State,
+        state: &State,
     ) -> Result<(), Box<dyn std::error::Error>> {
+        // The cycle is advanced by `State::advance_time`
+        self.fog_distance = state.world.fog_distance();
+        let mut player = state.player.write().unwrap();
+        self.underwater = player.head_in_water;
+        // The translucent pipeline shares the bind group, it gets the new projection too
+        if player.camera.projection_dirty {
+            player.camera.projection_dirty = false;
+            state.queue.write_buffer(
+                &self.projection_buffer,
+                0,
+                bytemuck::cast_slice(&[Uniforms::from(&player.camera).projection]),
+            );
+        }
+        std::mem::drop(player);
+        state.queue.write_buffer(
+            &self.sun_buffer,
+            0,
+            bytemuck::cast_slice(&[self.day_cycle.uniform()]),
+        );
+        state.queue.write_buffer(
+            &self.fog_buffer,
+            0,
+            bytemuck::cast_slice(&[self.fog_uniform()]),
+        );
         Ok(())
     }
-    fn init(state: &State, _pipeline_manager: &PipelineManager) -> Self {
-        let swapchain_capabilities = state.surface.get_capabilities(&state.adapter);
-        let swapchain_format = swapchain_capabilities.formats[0];
-
-        let shader_source = include_str!("../shaders/shader.wgsl");
-
-        let shader = state
-            .device
-            .create_shader_module(wgpu::ShaderModuleDescriptor {
-                label: None,
-                source: wgpu::ShaderSource::Wgsl(shader_source.into()),
-            });
+    fn init(
+        state: &State,
+        _pipeline_manager: &PipelineManager,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let swapchain_format = state.surface_format;
         let camera = &state.player.read().unwrap().camera;
         let uniforms = Uniforms::from(camera);
 
@@ -120,108 +191,46 @@ impl Pipeline for MainPipeline {
                 usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             });
 
-        // Constant bindgroup for chunks per row
-        let world_chunk_per_row_buffer =
-            state
-                .device
-                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    contents: bytemuck::cast_slice(&[crate::world::CHUNKS_PER_ROW]),
-                    label: Some("world_chunk_per_row"),
-                    usage: wgpu::BufferUsages::UNIFORM,
-                });
+        // Light direction and colors, advanced every frame
+        let day_cycle = DayCycle::new(state.config.day_length);
+        let sun_buffer = state
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("sun"),
+                contents: bytemuck::cast_slice(&[day_cycle.uniform()]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
 
-        let image_bytes = include_bytes!("../../assets/tex_atlas.png");
-        let texture_atlas = Texture::from_bytes(
-            image_bytes,
-            "tex_atlas".to_string(),
-            &state.device,
-            &state.queue,
-        )
-        .unwrap();
+        // Ends at the edge of the loaded world, the world isn't a fixed grid when streaming
+        let fog_distance = state.world.fog_distance();
+        let fog_buffer = state
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("fog"),
+                contents: bytemuck::cast_slice(&[Self::fog(
+                    day_cycle.sky_color(),
+                    fog_distance,
+                    false,
+                )]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+
+        let texture_atlas =
+            Self::load_block_textures(&state.device, &state.queue, state.config.block_sampler())?;
         // Bind 0: general purpouse group for 3d rendering
-        let bind_group_0_layout =
-            state
-                .device
-                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                    label: Some("bind_group_0"),
-                    entries: &[
-                        wgpu::BindGroupLayoutEntry {
-                            binding: 0,
-                            visibility: wgpu::ShaderStages::VERTEX,
-                            ty: wgpu::BindingType::Buffer {
-                                ty: wgpu::BufferBindingType::Uniform,
-                                has_dynamic_offset: false,
-                                min_binding_size: None,
-                            },
-                            count: None,
-                        },
-                        wgpu::BindGroupLayoutEntry {
-                            binding: 1,
-                            visibility: wgpu::ShaderStages::VERTEX,
-                            ty: wgpu::BindingType::Buffer {
-                                ty: wgpu::BufferBindingType::Uniform,
-                                has_dynamic_offset: false,
-                                min_binding_size: None,
-                            },
-                            count: None,
-                        },
-                        wgpu::BindGroupLayoutEntry {
-                            binding: 2,
-                            visibility: wgpu::ShaderStages::VERTEX,
-                            ty: wgpu::BindingType::Buffer {
-                                ty: wgpu::BufferBindingType::Uniform,
-                                has_dynamic_offset: false,
-                                min_binding_size: None,
-                            },
-                            count: None,
-                        },
-                        wgpu::BindGroupLayoutEntry {
-                            binding: 3,
-                            visibility: wgpu::ShaderStages::FRAGMENT,
-                            ty: wgpu::BindingType::Texture {
-                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                                view_dimension: wgpu::TextureViewDimension::D2,
-                                multisampled: false,
-                            },
-                            count: None,
-                        },
-                        wgpu::BindGroupLayoutEntry {
-                            binding: 4,
-                            visibility: wgpu::ShaderStages::FRAGMENT,
-                            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                            count: None,
-                        },
-                    ],
-                });
-        let bind_group_0 = state.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &bind_group_0_layout,
-            label: None,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: projection_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: view_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: world_chunk_per_row_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 3,
-                    resource: wgpu::BindingResource::TextureView(&texture_atlas.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 4,
-                    resource: wgpu::BindingResource::Sampler(&texture_atlas.sampler),
-                },
-            ],
-        });
+        let bind_group_0_layout = state
+            .device
+            .create_bind_group_layout(&Self::get_bind_group_0_layout());
+        let bind_group_0 = Self::create_bind_group_0(
+            &state.device,
+            &bind_group_0_layout,
+            [&projection_buffer, &view_buffer, &fog_buffer, &sun_buffer],
+            &texture_atlas,
+        );
 
         // Textures
         let depth_texture = Texture::create_depth_texture(state);
+        let msaa_texture = Self::create_msaa_texture(state);
 
         // Pipeline layouts
         let pipeline_layout =
@@ -242,51 +251,417 @@ impl Pipeline for MainPipeline {
                     push_constant_ranges: &[],
                 });
 
-        let render_pipeline =
-            state
-                .device
-                .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                    label: None,
-                    layout: Some(&pipeline_layout),
-                    vertex: wgpu::VertexState {
-                        module: &shader,
-                        entry_point: "vs_main",
-                        buffers: &[Block::get_vertex_data_layout()],
-                    },
-                    fragment: Some(wgpu::FragmentState {
-                        module: &shader,
-                        entry_point: "fs_main",
-                        targets: &[Some(swapchain_format.into())],
-                    }),
-
-                    primitive: wgpu::PrimitiveState {
-                        cull_mode: Some(Face::Front),
-                        ..Default::default()
-                    },
-                    depth_stencil: Some(wgpu::DepthStencilState {
-                        format: Texture::DEPTH_FORMAT,
-                        depth_write_enabled: true,
-                        depth_compare: wgpu::CompareFunction::Less,
-                        stencil: wgpu::StencilState::default(),
-                        bias: wgpu::DepthBiasState::default(),
-                    }),
-                    multisample: wgpu::MultisampleState::default(),
-                    multiview: None,
-                });
+        let render_pipeline = Self::create_render_pipeline(
+            &state.device,
+            swapchain_format,
+            &pipeline_layout,
+            Self::primitive(),
+            "fs_main",
+            state.sample_count,
+        );
 
-        Self {
+        Ok(Self {
             bind_group_0_layout,
+            pipeline_layout,
             view_buffer,
             projection_buffer,
             depth_texture,
+            msaa_texture,
             bind_group_0,
+            texture_atlas,
             pipeline: render_pipeline,
-        }
+            day_cycle,
+            sun_buffer,
+            fog_distance,
+            underwater: false,
+            fog_buffer,
+        })
+    }
+    // The surface and the depth texture follow the window, the highlight, translucent and ui
+    // passes borrow the depth texture from here when they render
+    fn resize(&mut self, state: &State, new_size: (u32, u32)) {
+        state.reconfigure_surface();
+        self.set_depth_texture(Texture::create_depth_texture(state));
+        self.msaa_texture = Self::create_msaa_texture(state);
+
+        let mut player = state.player.write().unwrap();
+        player.camera.aspect_ratio = new_size.0 as f32 / new_size.1 as f32;
+        let uniforms = Uniforms::from(&player.camera);
+        state.queue.write_buffer(
+            &self.projection_buffer,
+            0,
+            bytemuck::cast_slice(&[uniforms.projection]),
+        );
     }
 }
 
 impl MainPipeline {
+    // The uniform buffers in binding order: projection, view, fog and sun
+    fn create_bind_group_0(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        [projection, view, fog, sun]: [&wgpu::Buffer; 4],
+        atlas: &Texture,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            label: None,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: projection.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: view.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: fog.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&atlas.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::Sampler(&atlas.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: sun.as_entire_binding(),
+                },
+            ],
+        })
+    }
+    // A layer per block, each one with its own mip chain
+    #[cfg(not(feature = "atlas"))]
+    fn load_block_textures(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        sampler: SamplerConfig,
+    ) -> Result<Texture, Box<dyn std::error::Error>> {
+        Texture::from_atlas_bytes_as_array(
+            include_bytes!("../../assets/tex_atlas.png"),
+            BLOCK_OFFSET,
+            "tex_atlas".to_string(),
+            device,
+            queue,
+            true,
+            sampler,
+        )
+    }
+    #[cfg(feature = "atlas")]
+    fn load_block_textures(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        sampler: SamplerConfig,
+    ) -> Result<Texture, Box<dyn std::error::Error>> {
+        Texture::from_bytes_with_sampler(
+            include_bytes!("../../assets/tex_atlas.png"),
+            "tex_atlas".to_string(),
+            device,
+            queue,
+            true,
+            // Down to a texel per block, smaller levels would mix the tiles
+            Texture::mip_level_count(BLOCK_OFFSET),
+            sampler,
+        )
+    }
+    // Swaps the atlas sampler, every pipeline sharing bind group 0 picks it up on the next frame
+    pub fn set_sampler(&mut self, device: &wgpu::Device, sampler: SamplerConfig) {
+        self.texture_atlas.sampler = Texture::sampler(device, sampler);
+        self.bind_group_0 = Self::create_bind_group_0(
+            device,
+            &self.bind_group_0_layout,
+            [
+                &self.projection_buffer,
+                &self.view_buffer,
+                &self.fog_buffer,
+                &self.sun_buffer,
+            ],
+            &self.texture_atlas,
+        );
+    }
     pub fn set_depth_texture(&mut self, texture: Texture) {
         self.depth_texture = texture;
     }
+    fn create_msaa_texture(state: &State) -> Option<Texture> {
+        (state.sample_count > 1).then(|| Texture::create_msaa_texture(state))
+    }
+    // Color target of the passes that draw the scene, with msaa they draw into the multisampled
+    // texture and resolve it into `view`
+    pub fn color_attachment<'a>(
+        &'a self,
+        view: &'a wgpu::TextureView,
+        load: wgpu::LoadOp<wgpu::Color>,
+    ) -> wgpu::RenderPassColorAttachment<'a> {
+        let (view, resolve_target) = match &self.msaa_texture {
+            Some(msaa_texture) => (&msaa_texture.view, Some(view)),
+            None => (view, None),
+        };
+        wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target,
+            ops: wgpu::Operations {
+                load,
+                store: wgpu::StoreOp::Store,
+            },
+        }
+    }
+    pub fn get_bind_group_0_layout() -> wgpu::BindGroupLayoutDescriptor<'static> {
+        wgpu::BindGroupLayoutDescriptor {
+            label: Some("bind_group_0"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: BLOCK_TEXTURE_DIMENSION,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        }
+    }
+    fn primitive() -> wgpu::PrimitiveState {
+        wgpu::PrimitiveState {
+            cull_mode: Some(Face::Front),
+            ..Default::default()
+        }
+    }
+    // The block shader with the main pipeline's bind groups, `primitive` sets how the triangles
+    // are rasterized
+    pub fn create_render_pipeline(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        layout: &wgpu::PipelineLayout,
+        primitive: wgpu::PrimitiveState,
+        fragment_entry_point: &str,
+        sample_count: u32,
+    ) -> wgpu::RenderPipeline {
+        let shader_source = concat_shader_sources(&BLOCK_SHADER_FILES);
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(shader_source.into()),
+        });
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[Block::get_packed_vertex_data_layout()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: fragment_entry_point,
+                targets: &[Some(format.into())],
+            }),
+            primitive,
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            multiview: None,
+        })
+    }
+    // Clears the depth and draws the opaque faces of the chunks with `pipeline`
+    pub fn draw_chunks(
+        &self,
+        pipeline: &wgpu::RenderPipeline,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        player: &std::sync::RwLockReadGuard<'_, Player>,
+        chunks: &Vec<std::sync::RwLockReadGuard<'_, crate::chunk::Chunk>>,
+    ) {
+        let mut main_rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            // Over the sky, which clears the frame
+            color_attachments: &[Some(self.color_attachment(view, wgpu::LoadOp::Load))],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.depth_texture.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        main_rpass.set_pipeline(pipeline);
+        main_rpass.set_bind_group(0, &self.bind_group_0, &[]);
+
+        main_rpass.set_bind_group(2, &player.camera.position_bind_group, &[]);
+
+        for chunk in chunks.iter() {
+            // Streamed chunks are drawn once their mesh has been uploaded
+            let (Some(vertex_buffer), Some(index_buffer)) = (
+                chunk.chunk_vertex_buffer.as_ref(),
+                chunk.chunk_index_buffer.as_ref(),
+            ) else {
+                continue;
+            };
+            if chunk.visible {
+                main_rpass.set_bind_group(1, &chunk.chunk_bind_group, &[]);
+                main_rpass.set_vertex_buffer(0, vertex_buffer.buffer.slice(..));
+                main_rpass.set_index_buffer(index_buffer.buffer.slice(..), chunk.index_format);
+                main_rpass.draw_indexed(0..chunk.indices, 0, 0..1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MainPipeline, UNDERWATER_FOG_END};
+    use crate::blocks::block_type::BLOCK_OFFSET;
+    use crate::chunk::Chunk;
+    use crate::material::{SamplerConfig, Texture};
+    use crate::player::Camera;
+    use crate::utils::{test_adapter, test_device};
+    use crate::world::{CHUNKS_PER_ROW, DEFAULT_CHUNK_SIZE};
+
+    #[test]
+    fn should_hide_the_edge_of_the_world_and_shorten_the_fog_underwater() {
+        let sky = glam::vec3(0.5, 0.7, 1.0);
+        let edge = (CHUNKS_PER_ROW / 2 * DEFAULT_CHUNK_SIZE) as f32;
+
+        let fog = MainPipeline::fog(sky, edge, false);
+        assert_eq!(fog.color, [0.5, 0.7, 1.0, 1.0]);
+        assert_eq!(fog.end, edge);
+        assert!(fog.start > 0.0 && fog.start < fog.end);
+        // A larger render distance moves the fog with it
+        assert_eq!(MainPipeline::fog(sky, edge * 2.0, false).end, edge * 2.0);
+
+        let underwater = MainPipeline::fog(sky, edge, true);
+        assert_eq!(underwater.end, UNDERWATER_FOG_END);
+        assert!(underwater.end < fog.start);
+        assert_ne!(underwater.color, fog.color);
+    }
+
+    #[test]
+    fn should_load_the_atlas_with_a_mip_chain_per_tile() {
+        let Some((device, queue)) = test_device() else {
+            println!("No adapter available, skipping atlas test");
+            return;
+        };
+        let textures =
+            MainPipeline::load_block_textures(&device, &queue, SamplerConfig::default()).unwrap();
+        let texture = &textures.texture;
+        // The 256 pixel atlas has 32 pixel tiles, their chains go down to a texel per tile
+        assert_eq!(BLOCK_OFFSET, 32);
+        assert_eq!(texture.mip_level_count(), 6);
+        if cfg!(feature = "atlas") {
+            assert_eq!((texture.width(), texture.height()), (256, 256));
+            assert_eq!(texture.depth_or_array_layers(), 1);
+        } else {
+            assert_eq!((texture.width(), texture.height()), (32, 32));
+            assert_eq!(texture.depth_or_array_layers(), 64);
+        }
+    }
+
+    #[test]
+    fn should_build_the_pipeline_and_depth_texture_with_the_same_sample_count() {
+        let Some(adapter) = test_adapter() else {
+            println!("No adapter available, skipping msaa test");
+            return;
+        };
+        let format = wgpu::TextureFormat::Bgra8UnormSrgb;
+        let sample_count = 4;
+        let supported = [format, Texture::DEPTH_FORMAT].iter().all(|format| {
+            adapter
+                .get_texture_format_features(*format)
+                .flags
+                .sample_count_supported(sample_count)
+        });
+        if !supported {
+            println!("4x msaa not supported, skipping msaa test");
+            return;
+        }
+        let Some((device, _queue)) = test_device() else {
+            return;
+        };
+
+        let depth = Texture::create_sized_depth_texture(&device, (64, 48), sample_count);
+        assert_eq!(depth.texture.sample_count(), sample_count);
+
+        let bind_group_0_layout =
+            device.create_bind_group_layout(&MainPipeline::get_bind_group_0_layout());
+        let chunk_layout = device.create_bind_group_layout(&Chunk::get_bind_group_layout());
+        let camera_layout =
+            device.create_bind_group_layout(&Camera::get_position_bind_group_layout());
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_0_layout, &chunk_layout, &camera_layout],
+            push_constant_ranges: &[],
+        });
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        MainPipeline::create_render_pipeline(
+            &device,
+            format,
+            &layout,
+            wgpu::PrimitiveState::default(),
+            "fs_main",
+            sample_count,
+        );
+        let error = pollster::block_on(device.pop_error_scope());
+        assert!(error.is_none(), "{error:?}");
+    }
 }
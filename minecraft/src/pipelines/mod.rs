@@ -1,10 +1,13 @@
 use std::{error::Error, sync::RwLockReadGuard};
 
 use self::pipeline_manager::PipelineManager;
+use self::shaders::catch_validation_error;
 use crate::{chunk::Chunk, player::Player, state::State};
 
 pub trait Pipeline {
-    fn init(state: &State, pipeline_manager: &PipelineManager) -> Self;
+    fn init(state: &State, pipeline_manager: &PipelineManager) -> Result<Self, Box<dyn Error>>
+    where
+        Self: Sized;
     fn update(
         &mut self,
         pipeline_manager: &PipelineManager,
@@ -22,9 +25,33 @@ pub trait Pipeline {
         player: &RwLockReadGuard<'_, Player>,
         chunks: &Vec<RwLockReadGuard<'_, Chunk>>,
     );
+    // Called after `state.surface_config` takes the new size, never with a zero width or height
+    fn resize(&mut self, _state: &State, _new_size: (u32, u32)) {}
+    // Files in src/shaders the pipeline is built from, debug builds rebuild it when one changes
+    fn shader_files() -> &'static [&'static str] {
+        &[]
+    }
+    // Builds the pipeline again from the shader files, it's left as it was when that fails
+    fn rebuild_pipeline(
+        &mut self,
+        state: &State,
+        pipeline_manager: &PipelineManager,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        Self: Sized,
+    {
+        *self = catch_validation_error(&state.device, || Self::init(state, pipeline_manager))??;
+        Ok(())
+    }
 }
+mod clouds;
 mod highlight_selected;
+pub mod lut;
 mod main;
 pub mod pipeline_manager;
+pub mod shaders;
+mod sky;
+mod text;
 mod translucent;
 mod ui;
+pub mod wireframe;
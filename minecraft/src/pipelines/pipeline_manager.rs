@@ -5,15 +5,23 @@ use wgpu::{CommandEncoder, TextureView};
 use crate::state::State;
 
 use super::{
-    highlight_selected::HighlightSelectedPipeline, main::MainPipeline,
-    translucent::TranslucentPipeline, ui::UIPipeline, Pipeline,
+    clouds::CloudsPipeline, highlight_selected::HighlightSelectedPipeline, lut::LutPipeline,
+    main::MainPipeline, sky::SkyPipeline, text::TextPipeline, translucent::TranslucentPipeline,
+    ui::UIPipeline, wireframe::WireframePipeline, Pipeline,
 };
 
 pub struct PipelineManager {
     pub main_pipeline: Option<RefCell<MainPipeline>>,
+    pub sky_pipeline: Option<RefCell<SkyPipeline>>,
     pub translucent_pipeline: Option<RefCell<TranslucentPipeline>>,
     pub highlight_selected_pipeline: Option<RefCell<HighlightSelectedPipeline>>,
     pub ui_pipeline: Option<RefCell<UIPipeline>>,
+    // The debug overlay, over the ui
+    pub text_pipeline: Option<RefCell<TextPipeline>>,
+    pub lut_pipeline: Option<RefCell<LutPipeline>>,
+    pub wireframe_pipeline: Option<RefCell<WireframePipeline>>,
+    // None when `Config::clouds` is off
+    pub clouds_pipeline: Option<RefCell<CloudsPipeline>>,
 }
 
 impl PipelineManager {
@@ -25,29 +33,116 @@ impl PipelineManager {
     ) {
         todo!();
     }
-    pub fn init(state: &State) -> PipelineManager {
+    // Fails when an asset doesn't fit its pipeline, like an atlas that doesn't split into tiles
+    pub fn init(state: &State) -> Result<PipelineManager, Box<dyn std::error::Error>> {
         let mut pipeline = PipelineManager {
             highlight_selected_pipeline: None,
             main_pipeline: None,
+            sky_pipeline: None,
             translucent_pipeline: None,
             ui_pipeline: None,
+            text_pipeline: None,
+            lut_pipeline: None,
+            wireframe_pipeline: None,
+            clouds_pipeline: None,
         };
-        pipeline.main_pipeline = Some(RefCell::new(MainPipeline::init(state, &pipeline)));
+        pipeline.main_pipeline = Some(RefCell::new(MainPipeline::init(state, &pipeline)?));
+        pipeline.sky_pipeline = Some(RefCell::new(SkyPipeline::init(state, &pipeline)?));
         pipeline.translucent_pipeline =
-            Some(RefCell::new(TranslucentPipeline::init(state, &pipeline)));
+            Some(RefCell::new(TranslucentPipeline::init(state, &pipeline)?));
         pipeline.highlight_selected_pipeline = Some(RefCell::new(HighlightSelectedPipeline::init(
             state, &pipeline,
-        )));
-        pipeline.ui_pipeline = Some(RefCell::new(UIPipeline::init(state, &pipeline)));
-        pipeline
+        )?));
+        if state.config.clouds {
+            pipeline.clouds_pipeline = Some(RefCell::new(CloudsPipeline::init(state, &pipeline)?));
+        }
+        pipeline.ui_pipeline = Some(RefCell::new(UIPipeline::init(state, &pipeline)?));
+        pipeline.text_pipeline = Some(RefCell::new(TextPipeline::init(state, &pipeline)?));
+        pipeline.lut_pipeline = Some(RefCell::new(LutPipeline::init(state, &pipeline)?));
+        pipeline.wireframe_pipeline =
+            Some(RefCell::new(WireframePipeline::init(state, &pipeline)?));
+        Ok(pipeline)
     }
 
+    // Minimized windows report a zero size, the pipelines keep their resources until it's restored
+    pub fn resize(&self, state: &State, new_size: (u32, u32)) {
+        if new_size.0 == 0 || new_size.1 == 0 {
+            return;
+        }
+        self.main_pipeline
+            .as_ref()
+            .unwrap()
+            .borrow_mut()
+            .resize(state, new_size);
+        self.translucent_pipeline
+            .as_ref()
+            .unwrap()
+            .borrow_mut()
+            .resize(state, new_size);
+        self.highlight_selected_pipeline
+            .as_ref()
+            .unwrap()
+            .borrow_mut()
+            .resize(state, new_size);
+        self.ui_pipeline
+            .as_ref()
+            .unwrap()
+            .borrow_mut()
+            .resize(state, new_size);
+        self.lut_pipeline
+            .as_ref()
+            .unwrap()
+            .borrow_mut()
+            .resize(state, new_size);
+    }
+    // Rebuilds the pipelines built from one of the changed shader files. A shader that doesn't
+    // compile is logged and its pipelines keep the last one that did
+    #[cfg(debug_assertions)]
+    pub fn reload_shaders(&self, state: &State, changed: &[String]) {
+        self.reload(self.main_pipeline.as_ref(), state, changed);
+        self.reload(self.sky_pipeline.as_ref(), state, changed);
+        self.reload(self.translucent_pipeline.as_ref(), state, changed);
+        self.reload(self.highlight_selected_pipeline.as_ref(), state, changed);
+        self.reload(self.clouds_pipeline.as_ref(), state, changed);
+        self.reload(self.ui_pipeline.as_ref(), state, changed);
+        self.reload(self.text_pipeline.as_ref(), state, changed);
+        self.reload(self.lut_pipeline.as_ref(), state, changed);
+        self.reload(self.wireframe_pipeline.as_ref(), state, changed);
+    }
+    #[cfg(debug_assertions)]
+    fn reload<P: Pipeline>(
+        &self,
+        pipeline: Option<&RefCell<P>>,
+        state: &State,
+        changed: &[String],
+    ) {
+        let Some(pipeline) = pipeline else {
+            return;
+        };
+        if !P::shader_files()
+            .iter()
+            .any(|file| changed.iter().any(|name| name == file))
+        {
+            return;
+        }
+        let name = std::any::type_name::<P>().rsplit("::").next().unwrap();
+        match pipeline.borrow_mut().rebuild_pipeline(state, self) {
+            Ok(()) => println!("Reloaded the shaders of {name}"),
+            Err(error) => eprintln!("Keeping the last {name}, its shaders failed: {error}"),
+        }
+    }
     pub fn update(&self, state: &State) -> Result<(), Box<dyn std::error::Error>> {
         self.main_pipeline
             .as_ref()
             .unwrap()
             .borrow_mut()
             .update(self, state)?;
+        // Reads the day cycle and fog the main pipeline just updated
+        self.sky_pipeline
+            .as_ref()
+            .unwrap()
+            .borrow_mut()
+            .update(self, state)?;
         self.translucent_pipeline
             .as_ref()
             .unwrap()
@@ -58,11 +153,24 @@ impl PipelineManager {
             .unwrap()
             .borrow_mut()
             .update(self, state)?;
+        if let Some(clouds_pipeline) = self.clouds_pipeline.as_ref() {
+            clouds_pipeline.borrow_mut().update(self, state)?;
+        }
         self.ui_pipeline
             .as_ref()
             .unwrap()
             .borrow_mut()
             .update(self, state)?;
+        self.text_pipeline
+            .as_ref()
+            .unwrap()
+            .borrow_mut()
+            .update(self, state)?;
+        self.lut_pipeline
+            .as_ref()
+            .unwrap()
+            .borrow_mut()
+            .update(self, state)?;
 
         Ok(())
     }
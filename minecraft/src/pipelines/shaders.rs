@@ -0,0 +1,174 @@
+use std::borrow::Cow;
+use std::error::Error;
+
+// The wgsl files in src/shaders, embedded in the binary
+const EMBEDDED_SHADERS: [(&str, &str); 12] = [
+    (
+        "block_texture_array.wgsl",
+        include_str!("../shaders/block_texture_array.wgsl"),
+    ),
+    (
+        "block_texture_atlas.wgsl",
+        include_str!("../shaders/block_texture_atlas.wgsl"),
+    ),
+    (
+        "block_vertex.wgsl",
+        include_str!("../shaders/block_vertex.wgsl"),
+    ),
+    (
+        "chunk_border.wgsl",
+        include_str!("../shaders/chunk_border.wgsl"),
+    ),
+    ("clouds.wgsl", include_str!("../shaders/clouds.wgsl")),
+    ("highlight.wgsl", include_str!("../shaders/highlight.wgsl")),
+    ("lut.wgsl", include_str!("../shaders/lut.wgsl")),
+    ("shader.wgsl", include_str!("../shaders/shader.wgsl")),
+    ("sky.wgsl", include_str!("../shaders/sky.wgsl")),
+    ("text.wgsl", include_str!("../shaders/text.wgsl")),
+    ("ui_shader.wgsl", include_str!("../shaders/ui_shader.wgsl")),
+    (
+        "water_shader.wgsl",
+        include_str!("../shaders/water_shader.wgsl"),
+    ),
+];
+
+#[cfg(debug_assertions)]
+const SHADER_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/shaders");
+
+fn embedded_source(name: &str) -> &'static str {
+    EMBEDDED_SHADERS
+        .iter()
+        .find(|(file, _)| *file == name)
+        .map(|(_, source)| *source)
+        .unwrap_or_else(|| panic!("Unknown shader {name}"))
+}
+
+// The source of a file in src/shaders. Debug builds read it from the disk so an edited shader is
+// picked up by `ShaderWatcher`, the embedded copy is used when the file can't be read
+#[cfg(debug_assertions)]
+pub fn shader_source(name: &str) -> Cow<'static, str> {
+    let path = std::path::Path::new(SHADER_DIR).join(name);
+    match std::fs::read_to_string(path) {
+        Ok(source) => Cow::Owned(source),
+        Err(_) => Cow::Borrowed(embedded_source(name)),
+    }
+}
+#[cfg(not(debug_assertions))]
+pub fn shader_source(name: &str) -> Cow<'static, str> {
+    Cow::Borrowed(embedded_source(name))
+}
+
+// The sources of the files, one after the other in a single module
+pub fn concat_shader_sources(names: &[&str]) -> String {
+    names.iter().map(|name| shader_source(name)).collect()
+}
+
+// Runs `create` in a validation error scope, an invalid shader or pipeline becomes an error
+// instead of the uncaptured error handler's panic
+pub fn catch_validation_error<T>(
+    device: &wgpu::Device,
+    create: impl FnOnce() -> T,
+) -> Result<T, Box<dyn Error>> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let created = create();
+    match pollster::block_on(device.pop_error_scope()) {
+        Some(error) => Err(error.to_string().into()),
+        None => Ok(created),
+    }
+}
+
+// Polls the modification times of the files in src/shaders, debug builds only
+#[cfg(debug_assertions)]
+pub struct ShaderWatcher {
+    modified: std::collections::HashMap<String, std::time::SystemTime>,
+    last_poll: std::time::Instant,
+}
+
+#[cfg(debug_assertions)]
+impl ShaderWatcher {
+    const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+    pub fn new() -> Self {
+        Self {
+            modified: Self::modification_times(),
+            last_poll: std::time::Instant::now(),
+        }
+    }
+    fn modification_times() -> std::collections::HashMap<String, std::time::SystemTime> {
+        let Ok(entries) = std::fs::read_dir(SHADER_DIR) else {
+            return Default::default();
+        };
+        entries
+            .flatten()
+            .filter_map(|entry| {
+                let modified = entry.metadata().ok()?.modified().ok()?;
+                Some((entry.file_name().to_string_lossy().into_owned(), modified))
+            })
+            .collect()
+    }
+    // The files written since the last call, empty until a second has passed since then
+    pub fn poll(&mut self) -> Vec<String> {
+        if self.last_poll.elapsed() < Self::POLL_INTERVAL {
+            return Vec::new();
+        }
+        self.last_poll = std::time::Instant::now();
+        let modified = Self::modification_times();
+        let mut changed: Vec<String> = modified
+            .iter()
+            .filter(|(name, time)| self.modified.get(*name) != Some(time))
+            .map(|(name, _)| name.clone())
+            .collect();
+        changed.sort();
+        self.modified = modified;
+        changed
+    }
+}
+
+#[cfg(debug_assertions)]
+impl Default for ShaderWatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{catch_validation_error, embedded_source, shader_source, EMBEDDED_SHADERS};
+    use crate::utils::test_device;
+
+    // The folder is only read in debug builds
+    #[cfg(debug_assertions)]
+    #[test]
+    fn should_embed_every_shader_in_the_folder() {
+        let mut files: Vec<String> = std::fs::read_dir(super::SHADER_DIR)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            // Built by the lighting, outside of the pipelines
+            .filter(|name| name != "light_compute.wgsl")
+            .collect();
+        files.sort();
+        let embedded: Vec<&str> = EMBEDDED_SHADERS.iter().map(|(name, _)| *name).collect();
+        assert_eq!(files, embedded);
+        for (name, _) in EMBEDDED_SHADERS {
+            assert_eq!(shader_source(name), embedded_source(name));
+        }
+    }
+
+    #[test]
+    fn should_return_an_error_for_an_invalid_shader() {
+        let Some((device, _queue)) = test_device() else {
+            println!("No adapter available, skipping shader error test");
+            return;
+        };
+        let create = |source: &str| {
+            catch_validation_error(&device, || {
+                device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                    label: None,
+                    source: wgpu::ShaderSource::Wgsl(source.into()),
+                })
+            })
+        };
+        assert!(create("fn main() -> f32 { return true; }").is_err());
+        assert!(create(&shader_source("sky.wgsl")).is_ok());
+    }
+}
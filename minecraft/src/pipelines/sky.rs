@@ -0,0 +1,229 @@
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::day_cycle::DayCycle;
+use crate::player::Player;
+use crate::state::State;
+
+use super::main::FogUniform;
+use super::pipeline_manager::PipelineManager;
+use super::shaders::shader_source;
+use super::Pipeline;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+pub struct SkyUniform {
+    // From clip space back to a view direction, the camera position is left out so the sky
+    // never gets closer
+    pub inverse_view_projection: [f32; 16],
+    // The fog color, the far chunks fade into it
+    pub horizon: [f32; 4],
+    // w: how much the stars show, 0 during the day
+    pub zenith: [f32; 4],
+}
+
+impl SkyUniform {
+    pub fn new(
+        view: glam::Mat4,
+        projection: glam::Mat4,
+        fog: &FogUniform,
+        day_cycle: &DayCycle,
+    ) -> Self {
+        let rotation = glam::Mat4::from_mat3(glam::Mat3::from_mat4(view));
+        Self {
+            inverse_view_projection: (projection * rotation).inverse().to_cols_array(),
+            horizon: fog.color,
+            zenith: day_cycle
+                .zenith_color()
+                .extend(day_cycle.star_visibility())
+                .to_array(),
+        }
+    }
+}
+
+// Gradient from the horizon to the zenith with stars at night, drawn first over the whole frame
+pub struct SkyPipeline {
+    pub pipeline: wgpu::RenderPipeline,
+    pub sky_buffer: wgpu::Buffer,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl Pipeline for SkyPipeline {
+    fn shader_files() -> &'static [&'static str] {
+        &["sky.wgsl"]
+    }
+    fn render(
+        &self,
+        state: &State,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        _player: &std::sync::RwLockReadGuard<'_, Player>,
+        _chunks: &Vec<std::sync::RwLockReadGuard<'_, crate::chunk::Chunk>>,
+    ) {
+        let main_pipeline_ref = state
+            .pipeline_manager
+            .main_pipeline
+            .as_ref()
+            .unwrap()
+            .borrow();
+        // Clears the frame, the chunks are drawn over it without touching the depth
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(
+                main_pipeline_ref.color_attachment(view, wgpu::LoadOp::Clear(wgpu::Color::BLACK)),
+            )],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, &self.bind_group, &[]);
+        // A single triangle covering the screen, built in the vertex shader
+        rpass.draw(0..3, 0..1);
+    }
+    fn init(
+        state: &State,
+        pipeline_manager: &PipelineManager,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let swapchain_format = state.surface_format;
+        let shader = state
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: None,
+                source: wgpu::ShaderSource::Wgsl(shader_source("sky.wgsl")),
+            });
+
+        let sky_buffer = state
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("sky"),
+                contents: bytemuck::cast_slice(&[Self::uniform(state, pipeline_manager)]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+        let bind_group_layout =
+            state
+                .device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("sky-layout"),
+                    entries: &[wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    }],
+                });
+        let bind_group = state.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("sky"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: sky_buffer.as_entire_binding(),
+            }],
+        });
+
+        let pipeline_layout =
+            state
+                .device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: None,
+                    bind_group_layouts: &[&bind_group_layout],
+                    push_constant_ranges: &[],
+                });
+        let pipeline = state
+            .device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: None,
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(swapchain_format.into())],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                // No depth, nothing is hidden behind the sky
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState {
+                    count: state.sample_count,
+                    ..Default::default()
+                },
+                multiview: None,
+            });
+
+        Ok(Self {
+            pipeline,
+            sky_buffer,
+            bind_group,
+        })
+    }
+    fn update(
+        &mut self,
+        pipeline_manager: &PipelineManager,
+        state: &State,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        state.queue.write_buffer(
+            &self.sky_buffer,
+            0,
+            bytemuck::cast_slice(&[Self::uniform(state, pipeline_manager)]),
+        );
+        Ok(())
+    }
+}
+
+impl SkyPipeline {
+    // From the camera and the main pipeline's day cycle and fog, which are updated before
+    fn uniform(state: &State, pipeline_manager: &PipelineManager) -> SkyUniform {
+        let main_pipeline = pipeline_manager.main_pipeline.as_ref().unwrap().borrow();
+        let player = state.player.read().unwrap();
+        SkyUniform::new(
+            player.camera.build_view_matrix(),
+            player.camera.build_projection_matrix(),
+            &main_pipeline.fog_uniform(),
+            &main_pipeline.day_cycle,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SkyUniform;
+    use crate::day_cycle::DayCycle;
+    use crate::pipelines::main::MainPipeline;
+
+    #[test]
+    fn should_look_the_same_from_anywhere_and_meet_the_fog_at_the_horizon() {
+        let mut day_cycle = DayCycle::new(600.0);
+        let fog = MainPipeline::fog(day_cycle.sky_color(), 100.0, false);
+        let projection = glam::Mat4::perspective_lh(1.0, 1.5, 0.1, 1000.0);
+        let forward = glam::vec3(1.0, 0.2, 0.5).normalize();
+        let sky_from = |eye: glam::Vec3, day_cycle: &DayCycle| {
+            let view = glam::Mat4::look_at_lh(eye, eye + forward, glam::Vec3::Y);
+            SkyUniform::new(view, projection, &fog, day_cycle)
+        };
+
+        let here = sky_from(glam::Vec3::ZERO, &day_cycle);
+        let there = sky_from(glam::vec3(500.0, 80.0, -40.0), &day_cycle);
+        let here_matrix = glam::Mat4::from_cols_array(&here.inverse_view_projection);
+        let there_matrix = glam::Mat4::from_cols_array(&there.inverse_view_projection);
+        assert!(here_matrix.abs_diff_eq(there_matrix, 1e-4));
+        assert_eq!(here.horizon, fog.color);
+
+        // The middle of the screen goes back to the direction the camera looks at
+        let far = here_matrix * glam::vec4(0.0, 0.0, 1.0, 1.0);
+        let direction = (far.truncate() / far.w).normalize();
+        assert!(direction.distance(forward) < 1e-3, "{direction}");
+
+        // No stars by day, all of them at midnight
+        assert_eq!(here.zenith[3], 0.0);
+        day_cycle.time_of_day = 0.0;
+        assert_eq!(sky_from(glam::Vec3::ZERO, &day_cycle).zenith[3], 1.0);
+    }
+}
@@ -0,0 +1,467 @@
+use wgpu::util::DeviceExt;
+
+use crate::material::Texture;
+use crate::player::Player;
+use crate::state::State;
+
+use super::pipeline_manager::PipelineManager;
+use super::shaders::shader_source;
+use super::Pipeline;
+
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+// Screen pixels per font pixel
+const TEXT_SCALE: f32 = 2.0;
+// Space between characters and lines, and around the text, in font pixels
+const CHAR_SPACING: f32 = 1.0;
+const LINE_SPACING: f32 = 3.0;
+const MARGIN: f32 = 2.0;
+// Behind every line, so the text shows over the sky and the snow alike
+const BACKGROUND_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.5];
+const TEXT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const VERTICES_PER_QUAD: usize = 6;
+const FLOATS_PER_VERTEX: usize = 8;
+// Characters the vertex buffer has room for, the rest of a longer text is cut
+const MAX_QUADS: usize = 1024;
+
+// 5x7 uppercase font, a row per byte with the leftmost pixel in the 5th bit. Lowercase letters
+// use the uppercase glyphs and anything missing shows as '?'
+#[rustfmt::skip]
+const FONT: [(char, [u8; GLYPH_HEIGHT as usize]); 47] = [
+    (' ', [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    ('?', [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04]),
+    ('0', [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E]),
+    ('1', [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+    ('2', [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F]),
+    ('3', [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E]),
+    ('4', [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02]),
+    ('5', [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E]),
+    ('6', [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E]),
+    ('7', [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08]),
+    ('8', [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E]),
+    ('9', [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C]),
+    ('A', [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11]),
+    ('B', [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E]),
+    ('C', [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E]),
+    ('D', [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C]),
+    ('E', [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F]),
+    ('F', [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10]),
+    ('G', [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F]),
+    ('H', [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11]),
+    ('I', [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+    ('J', [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C]),
+    ('K', [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11]),
+    ('L', [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F]),
+    ('M', [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11]),
+    ('N', [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11]),
+    ('O', [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E]),
+    ('P', [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10]),
+    ('Q', [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D]),
+    ('R', [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11]),
+    ('S', [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E]),
+    ('T', [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04]),
+    ('U', [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E]),
+    ('V', [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04]),
+    ('W', [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A]),
+    ('X', [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11]),
+    ('Y', [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04]),
+    ('Z', [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F]),
+    ('.', [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C]),
+    (',', [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08]),
+    (':', [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00]),
+    ('-', [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00]),
+    ('/', [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00]),
+    ('(', [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02]),
+    (')', [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08]),
+    ('%', [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03]),
+    ('+', [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00]),
+];
+
+// Draws the debug overlay with a bitmap font, after the rest of the ui
+pub struct TextPipeline {
+    pub pipeline: wgpu::RenderPipeline,
+    pub font_bind_group: wgpu::BindGroup,
+    pub vertex_buffer: wgpu::Buffer,
+    vertices: u32,
+    // Overlay revision and screen size the vertices were laid out for
+    built_for: Option<(u64, (u32, u32))>,
+}
+
+impl Pipeline for TextPipeline {
+    fn shader_files() -> &'static [&'static str] {
+        &["text.wgsl"]
+    }
+    fn render(
+        &self,
+        state: &State,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        _player: &std::sync::RwLockReadGuard<'_, Player>,
+        _chunks: &Vec<std::sync::RwLockReadGuard<'_, crate::chunk::Chunk>>,
+    ) {
+        if !state.debug_overlay.visible || self.vertices == 0 {
+            return;
+        }
+        let main_pipeline_ref = state
+            .pipeline_manager
+            .main_pipeline
+            .as_ref()
+            .unwrap()
+            .borrow();
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(
+                main_pipeline_ref.color_attachment(view, wgpu::LoadOp::Load),
+            )],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &main_pipeline_ref.depth_texture.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Discard,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, &self.font_bind_group, &[]);
+        rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        rpass.draw(0..self.vertices, 0..1);
+    }
+    fn init(
+        state: &State,
+        _pipeline_manager: &PipelineManager,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let swapchain_format = state.surface_format;
+        let shader = state
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: None,
+                source: wgpu::ShaderSource::Wgsl(shader_source("text.wgsl")),
+            });
+
+        let (width, height, pixels) = font_pixels();
+        let font_texture = state.device.create_texture_with_data(
+            &state.queue,
+            &wgpu::TextureDescriptor {
+                label: Some("font"),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::R8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            &pixels,
+        );
+        let font_view = font_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        // Nearest, the glyphs are scaled by whole pixels
+        let font_sampler = state
+            .device
+            .create_sampler(&wgpu::SamplerDescriptor::default());
+        let bind_group_layout =
+            state
+                .device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("font-layout"),
+                    entries: &[
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Texture {
+                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                                multisampled: false,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                            count: None,
+                        },
+                    ],
+                });
+        let font_bind_group = state.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("font"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&font_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&font_sampler),
+                },
+            ],
+        });
+
+        let vertex_buffer = state.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("text"),
+            size: (MAX_QUADS * VERTICES_PER_QUAD * FLOATS_PER_VERTEX * std::mem::size_of::<f32>())
+                as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let pipeline_layout =
+            state
+                .device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: None,
+                    bind_group_layouts: &[&bind_group_layout],
+                    push_constant_ranges: &[],
+                });
+        let pipeline = state
+            .device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: None,
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[Self::get_vertex_data_layout()],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: swapchain_format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    cull_mode: None,
+                    ..Default::default()
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: Texture::DEPTH_FORMAT,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::Always,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: state.sample_count,
+                    ..Default::default()
+                },
+                multiview: None,
+            });
+
+        Ok(Self {
+            pipeline,
+            font_bind_group,
+            vertex_buffer,
+            vertices: 0,
+            built_for: None,
+        })
+    }
+    fn update(
+        &mut self,
+        _pipeline_manager: &PipelineManager,
+        state: &State,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let overlay = &state.debug_overlay;
+        let screen = (state.surface_config.width, state.surface_config.height);
+        if !overlay.visible || self.built_for == Some((overlay.revision(), screen)) {
+            return Ok(());
+        }
+        let vertices = text_vertices(overlay.lines(), screen);
+        state
+            .queue
+            .write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+        self.vertices = (vertices.len() / FLOATS_PER_VERTEX) as u32;
+        self.built_for = Some((overlay.revision(), screen));
+        Ok(())
+    }
+}
+
+impl TextPipeline {
+    fn get_vertex_data_layout() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<[f32; FLOATS_PER_VERTEX]>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                // Position
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x2,
+                    offset: 0,
+                    shader_location: 0,
+                },
+                // Uv
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x2,
+                    offset: std::mem::size_of::<[f32; 2]>() as u64,
+                    shader_location: 1,
+                },
+                // Color
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: std::mem::size_of::<[f32; 4]>() as u64,
+                    shader_location: 2,
+                },
+            ],
+        }
+    }
+}
+
+// Index of the glyph of `c` in `FONT`
+fn glyph_index(c: char) -> usize {
+    let c = c.to_ascii_uppercase();
+    FONT.iter()
+        .position(|(glyph, _)| *glyph == c)
+        .unwrap_or_else(|| glyph_index('?'))
+}
+// The glyphs side by side in a single row, one byte per pixel
+fn font_pixels() -> (u32, u32, Vec<u8>) {
+    let width = GLYPH_WIDTH * FONT.len() as u32;
+    let mut pixels = vec![0; (width * GLYPH_HEIGHT) as usize];
+    for (i, (_, rows)) in FONT.iter().enumerate() {
+        for (y, row) in rows.iter().enumerate() {
+            for x in 0..GLYPH_WIDTH {
+                if row & (1 << (GLYPH_WIDTH - 1 - x)) != 0 {
+                    pixels[y * width as usize + i * GLYPH_WIDTH as usize + x as usize] = 255;
+                }
+            }
+        }
+    }
+    (width, GLYPH_HEIGHT, pixels)
+}
+// A background quad for every line and a quad for every character that isn't a space, from the
+// top left corner of a screen of `(width, height)` pixels
+fn text_vertices<'a>(
+    lines: impl Iterator<Item = &'a str>,
+    (width, height): (u32, u32),
+) -> Vec<f32> {
+    let mut vertices = vec![];
+    // Font pixels to clip space
+    let to_clip = |x: f32, y: f32| {
+        [
+            -1.0 + x * TEXT_SCALE * 2.0 / width as f32,
+            1.0 - y * TEXT_SCALE * 2.0 / height as f32,
+        ]
+    };
+    let advance = GLYPH_WIDTH as f32 + CHAR_SPACING;
+    let glyph_u = 1.0 / FONT.len() as f32;
+    let mut quads = 0;
+    for (row, line) in lines.enumerate() {
+        let top = MARGIN + row as f32 * (GLYPH_HEIGHT as f32 + LINE_SPACING);
+        let length = line
+            .chars()
+            .count()
+            .min(MAX_QUADS.saturating_sub(quads + 1));
+        if length == 0 {
+            continue;
+        }
+        push_quad(
+            &mut vertices,
+            to_clip(MARGIN - 1.0, top - 1.0),
+            to_clip(
+                MARGIN + length as f32 * advance,
+                top + GLYPH_HEIGHT as f32 + 1.0,
+            ),
+            [[-1.0, -1.0], [-1.0, -1.0]],
+            BACKGROUND_COLOR,
+        );
+        quads += 1;
+        for (column, c) in line.chars().take(length).enumerate() {
+            if c == ' ' {
+                continue;
+            }
+            let left = MARGIN + column as f32 * advance;
+            let u = glyph_index(c) as f32 * glyph_u;
+            push_quad(
+                &mut vertices,
+                to_clip(left, top),
+                to_clip(left + GLYPH_WIDTH as f32, top + GLYPH_HEIGHT as f32),
+                [[u, 0.0], [u + glyph_u, 1.0]],
+                TEXT_COLOR,
+            );
+            quads += 1;
+        }
+    }
+    vertices
+}
+// Two triangles from the top left to the bottom right corner, the uvs go the same way
+fn push_quad(
+    vertices: &mut Vec<f32>,
+    top_left: [f32; 2],
+    bottom_right: [f32; 2],
+    [uv_min, uv_max]: [[f32; 2]; 2],
+    color: [f32; 4],
+) {
+    let corners = [
+        ([top_left[0], top_left[1]], [uv_min[0], uv_min[1]]),
+        ([top_left[0], bottom_right[1]], [uv_min[0], uv_max[1]]),
+        ([bottom_right[0], bottom_right[1]], [uv_max[0], uv_max[1]]),
+        ([top_left[0], top_left[1]], [uv_min[0], uv_min[1]]),
+        ([bottom_right[0], bottom_right[1]], [uv_max[0], uv_max[1]]),
+        ([bottom_right[0], top_left[1]], [uv_max[0], uv_min[1]]),
+    ];
+    for (position, uv) in corners {
+        vertices.extend_from_slice(&position);
+        vertices.extend_from_slice(&uv);
+        vertices.extend_from_slice(&color);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_have_a_distinct_glyph_for_every_character_of_the_font() {
+        let mut glyphs: Vec<_> = FONT.iter().map(|(_, rows)| *rows).collect();
+        assert!(glyphs.iter().flatten().all(|row| *row < 1 << GLYPH_WIDTH));
+        glyphs.sort();
+        glyphs.dedup();
+        assert_eq!(glyphs.len(), FONT.len());
+
+        let (width, height, pixels) = font_pixels();
+        assert_eq!(pixels.len(), (width * height) as usize);
+        // The top of the '1' is the middle pixel of its glyph
+        let one = glyph_index('1') * GLYPH_WIDTH as usize;
+        assert_eq!(pixels[one..one + 5], [0, 0, 255, 0, 0]);
+        assert_eq!(glyph_index('a'), glyph_index('A'));
+        assert_eq!(glyph_index('~'), glyph_index('?'));
+    }
+
+    #[test]
+    fn should_lay_out_a_quad_per_character_from_the_top_left_corner() {
+        let screen = (800, 600);
+        let lines = ["FPS: 60", "", "Chunk: 0, -1"];
+        let vertices = text_vertices(lines.into_iter(), screen);
+        // A background for each line with text, spaces have no quad
+        let quads = 2 + "FPS:60".len() + "Chunk:0,-1".len();
+        assert_eq!(
+            vertices.len(),
+            quads * VERTICES_PER_QUAD * FLOATS_PER_VERTEX
+        );
+
+        let positions: Vec<_> = vertices
+            .chunks(FLOATS_PER_VERTEX)
+            .map(|v| [v[0], v[1]])
+            .collect();
+        assert!(positions
+            .iter()
+            .all(|[x, y]| (-1.0..0.0).contains(x) && (0.0..=1.0).contains(y)));
+        // The first glyph is `TEXT_SCALE` screen pixels per font pixel
+        let glyph = &positions[VERTICES_PER_QUAD..2 * VERTICES_PER_QUAD];
+        let pixel_width = (glyph[2][0] - glyph[0][0]) * screen.0 as f32 / 2.0;
+        let pixel_height = (glyph[0][1] - glyph[2][1]) * screen.1 as f32 / 2.0;
+        assert!((pixel_width - GLYPH_WIDTH as f32 * TEXT_SCALE).abs() < 1e-3);
+        assert!((pixel_height - GLYPH_HEIGHT as f32 * TEXT_SCALE).abs() < 1e-3);
+        // The third line is below the skipped one
+        let last_top = positions.last().unwrap()[1];
+        assert!(last_top < glyph[0][1] - 2.0 * (GLYPH_HEIGHT as f32 * TEXT_SCALE) * 2.0 / 600.0);
+    }
+}
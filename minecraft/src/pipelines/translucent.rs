@@ -1,43 +1,114 @@
 use std::sync::RwLockReadGuard;
 
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use super::main::BLOCK_TEXTURE_SHADER;
 use super::pipeline_manager::PipelineManager;
+use super::shaders::concat_shader_sources;
 use super::Pipeline;
 use crate::blocks::block::Block;
-use crate::chunk::Chunk;
+use crate::chunk::{Chunk, TranslucentFace};
 use crate::material::Texture;
 use crate::player::Player;
 use crate::state::State;
 
+// Caps the sorting done each frame. Only the nearest chunks are sorted, the ones past them are
+// drawn first in any order, they're far enough to barely overlap.
+const MAX_SORTED_CHUNKS: usize = 128;
+// Faces sorted inside the chunks when underwater, nearest chunks first
+const MAX_SORTED_FACES: usize = 16384;
+
 pub struct Water;
 impl Water {
     pub fn get_vertex_data_layout() -> wgpu::VertexBufferLayout<'static> {
-        Block::get_vertex_data_layout()
+        Block::get_packed_vertex_data_layout()
     }
 }
 pub struct TranslucentPipeline {
     pub pipeline: wgpu::RenderPipeline,
+    // Per frame index data of the chunks whose faces get sorted
+    pub sorted_index_buffer: wgpu::Buffer,
+    // `State::elapsed_time`, the water waves and scrolls with it
+    pub time_buffer: wgpu::Buffer,
+    pub time_bind_group: wgpu::BindGroup,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+pub struct TimeUniform {
+    pub seconds: f32,
+    // Uniforms are sized in multiples of 16 bytes
+    _padding: [f32; 3],
+}
+
+impl TimeUniform {
+    pub fn new(seconds: f32) -> Self {
+        Self {
+            seconds,
+            _padding: [0.0; 3],
+        }
+    }
 }
+const WATER_SHADER_FILES: [&str; 3] = [
+    BLOCK_TEXTURE_SHADER,
+    "block_vertex.wgsl",
+    "water_shader.wgsl",
+];
+
+// Bound at group 3, only the water's vertices move with it
+const TIME_BIND_GROUP_LAYOUT_ENTRIES: [wgpu::BindGroupLayoutEntry; 1] =
+    [wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::VERTEX,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<TimeUniform>() as u64),
+        },
+        count: None,
+    }];
 impl Pipeline for TranslucentPipeline {
+    fn shader_files() -> &'static [&'static str] {
+        &WATER_SHADER_FILES
+    }
     fn update(
         &mut self,
         _pipeline_manager: &PipelineManager,
-        _state: &State,
+        state: &State,
     ) -> Result<(), Box<dyn std::error::Error>> {
+        state.queue.write_buffer(
+            &self.time_buffer,
+            0,
+            bytemuck::cast_slice(&[TimeUniform::new(state.elapsed_time)]),
+        );
         Ok(())
     }
     // TODO: This is very ugly and should be abstracted for all pipelines. Also doubles the resource for uniforms etc.
-    fn init(state: &State, pipeline_manager: &PipelineManager) -> Self {
-        let swapchain_capabilities = state.surface.get_capabilities(&state.adapter);
-        let swapchain_format = swapchain_capabilities.formats[0];
-
-        let shader_source = include_str!("../shaders/water_shader.wgsl");
+    fn init(
+        state: &State,
+        pipeline_manager: &PipelineManager,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let swapchain_format = state.surface_format;
 
-        let shader = state
+        let time_buffer = state
             .device
-            .create_shader_module(wgpu::ShaderModuleDescriptor {
-                label: None,
-                source: wgpu::ShaderSource::Wgsl(shader_source.into()),
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("time"),
+                contents: bytemuck::cast_slice(&[TimeUniform::new(0.0)]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             });
+        let time_bind_group_layout = state
+            .device
+            .create_bind_group_layout(&Self::get_time_bind_group_layout());
+        let time_bind_group = state.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &time_bind_group_layout,
+            label: Some("time_bind_group"),
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: time_buffer.as_entire_binding(),
+            }],
+        });
 
         // Pipeline layouts
         let pipeline_layout =
@@ -59,48 +130,32 @@ impl Pipeline for TranslucentPipeline {
                             .unwrap()
                             .camera
                             .position_bind_group_layout,
+                        &time_bind_group_layout,
                     ],
                     push_constant_ranges: &[],
                 });
 
-        let render_pipeline =
-            state
-                .device
-                .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                    label: None,
-                    layout: Some(&pipeline_layout),
-                    vertex: wgpu::VertexState {
-                        module: &shader,
-                        entry_point: "vs_main",
-                        buffers: &[Water::get_vertex_data_layout()],
-                    },
-                    fragment: Some(wgpu::FragmentState {
-                        module: &shader,
-                        entry_point: "fs_main",
-                        targets: &[Some(wgpu::ColorTargetState {
-                            format: swapchain_format,
-                            blend: Some(wgpu::BlendState::ALPHA_BLENDING),
-                            write_mask: wgpu::ColorWrites::ALL,
-                        })],
-                    }),
-                    primitive: wgpu::PrimitiveState {
-                        cull_mode: Some(wgpu::Face::Front),
-                        ..Default::default()
-                    },
-                    depth_stencil: Some(wgpu::DepthStencilState {
-                        format: Texture::DEPTH_FORMAT,
-                        depth_write_enabled: true,
-                        depth_compare: wgpu::CompareFunction::Less,
-                        stencil: wgpu::StencilState::default(),
-                        bias: wgpu::DepthBiasState::default(),
-                    }),
-                    multisample: wgpu::MultisampleState::default(),
-                    multiview: None,
-                });
+        let render_pipeline = Self::create_render_pipeline(
+            &state.device,
+            swapchain_format,
+            &pipeline_layout,
+            Self::primitive_state(state.config.double_sided_translucent),
+            state.sample_count,
+        );
 
-        Self {
+        let sorted_index_buffer = state.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("sorted-water-indices"),
+            size: (MAX_SORTED_FACES * 6 * std::mem::size_of::<u32>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Ok(Self {
             pipeline: render_pipeline,
-        }
+            sorted_index_buffer,
+            time_buffer,
+            time_bind_group,
+        })
     }
 
     fn render(
@@ -119,19 +174,15 @@ impl Pipeline for TranslucentPipeline {
             .borrow();
         let mut water_rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: None,
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Load,
-                    store: wgpu::StoreOp::Store,
-                },
-            })],
+            color_attachments: &[Some(
+                main_pipeline_ref.color_attachment(view, wgpu::LoadOp::Load),
+            )],
             depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
                 view: &main_pipeline_ref.depth_texture.view,
                 depth_ops: Some(wgpu::Operations {
                     load: wgpu::LoadOp::Load,
-                    store: wgpu::StoreOp::Discard,
+                    // Kept for the outline of the selected block, drawn after
+                    store: wgpu::StoreOp::Store,
                 }),
                 stencil_ops: None,
             }),
@@ -141,30 +192,388 @@ impl Pipeline for TranslucentPipeline {
         water_rpass.set_pipeline(&self.pipeline);
         water_rpass.set_bind_group(0, &main_pipeline_ref.bind_group_0, &[]);
         water_rpass.set_bind_group(2, &player.camera.position_bind_group, &[]);
+        water_rpass.set_bind_group(3, &self.time_bind_group, &[]);
 
-        for chunk in chunks.iter() {
-            if chunk.visible {
-                water_rpass.set_bind_group(1, &chunk.chunk_bind_group, &[]);
-                water_rpass.set_vertex_buffer(
-                    0,
-                    chunk
-                        .chunk_water_vertex_buffer
-                        .as_ref()
-                        .expect("Vertex buffer not initiated")
-                        .slice(..),
-                );
-                water_rpass.set_index_buffer(
-                    chunk
-                        .chunk_water_index_buffer
-                        .as_ref()
-                        .expect("Index buffer not initiated")
-                        .slice(..),
+        // Streamed chunks are drawn once their mesh has been uploaded
+        let drawn: Vec<_> = chunks
+            .iter()
+            .filter(|chunk| chunk.visible)
+            .filter_map(|chunk| {
+                Some((
+                    chunk,
+                    chunk.chunk_water_vertex_buffer.as_ref()?,
+                    chunk.chunk_water_index_buffer.as_ref()?,
+                ))
+            })
+            .collect();
+        let eye = player.camera.eye;
+        let distances: Vec<f32> = drawn
+            .iter()
+            .map(|(chunk, _, _)| {
+                let aabb = chunk.bounding_box();
+                let center = glam::vec3(
+                    aabb.min_x + aabb.max_x,
+                    aabb.min_y + aabb.max_y,
+                    aabb.min_z + aabb.max_z,
+                ) / 2.0;
+                center.distance_squared(eye)
+            })
+            .collect();
+        let order = Self::back_to_front(&distances, MAX_SORTED_CHUNKS);
+
+        // Looking up at the surface from underwater the faces of a chunk overlap each other, so
+        // they're sorted as well, starting from the nearest chunks
+        let mut sorted_faces_left = if player.in_water { MAX_SORTED_FACES } else { 0 };
+        let mut sorted_indices: Vec<Option<std::ops::Range<u64>>> = vec![None; drawn.len()];
+        let mut sorted_offset = 0;
+        for &i in order.iter().rev() {
+            let chunk = drawn[i].0;
+            if chunk.water_faces.is_empty() || chunk.water_faces.len() > sorted_faces_left {
+                continue;
+            }
+            sorted_faces_left -= chunk.water_faces.len();
+            let chunk_offset = glam::vec3(
+                (chunk.x * chunk.chunk_size() as i32) as f32,
+                0.0,
+                (chunk.y * chunk.chunk_size() as i32) as f32,
+            );
+            let indices = Self::sort_faces(&chunk.water_faces, eye - chunk_offset);
+            let size = (indices.len() * std::mem::size_of::<u32>()) as u64;
+            state.queue.write_buffer(
+                &self.sorted_index_buffer,
+                sorted_offset,
+                bytemuck::cast_slice(&indices),
+            );
+            sorted_indices[i] = Some(sorted_offset..sorted_offset + size);
+            sorted_offset += size;
+        }
+
+        for i in order {
+            let (chunk, vertex_buffer, index_buffer) = drawn[i];
+            water_rpass.set_bind_group(1, &chunk.chunk_bind_group, &[]);
+            water_rpass.set_vertex_buffer(0, vertex_buffer.buffer.slice(..));
+            match sorted_indices[i].clone() {
+                Some(range) => water_rpass.set_index_buffer(
+                    self.sorted_index_buffer.slice(range),
                     wgpu::IndexFormat::Uint32,
-                );
-                water_rpass.draw_indexed(0..chunk.water_indices, 0, 0..1);
+                ),
+                None => water_rpass
+                    .set_index_buffer(index_buffer.buffer.slice(..), chunk.water_index_format),
             }
+            water_rpass.draw_indexed(0..chunk.water_indices, 0, 0..1);
         }
     }
 }
 
-impl TranslucentPipeline {}
+impl TranslucentPipeline {
+    // Draw order from squared distances, farthest first. Past `max_sorted` entries only the
+    // nearest ones are sorted and go last, the rest keep an arbitrary order before them.
+    pub fn back_to_front(distances: &[f32], max_sorted: usize) -> Vec<usize> {
+        let farthest_first = |a: &usize, b: &usize| distances[*b].total_cmp(&distances[*a]);
+        let mut order: Vec<usize> = (0..distances.len()).collect();
+        let unsorted = order.len().saturating_sub(max_sorted);
+        if unsorted > 0 {
+            order.select_nth_unstable_by(unsorted, farthest_first);
+        }
+        order[unsorted..].sort_unstable_by(farthest_first);
+        order
+    }
+    // Indices of the faces from the farthest to the nearest to `eye`, relative to the chunk
+    pub fn sort_faces(faces: &[TranslucentFace], eye: glam::Vec3) -> Vec<u32> {
+        let mut faces: Vec<_> = faces
+            .iter()
+            .map(|face| (face.center.distance_squared(eye), face.indices))
+            .collect();
+        faces.sort_unstable_by(|a, b| b.0.total_cmp(&a.0));
+        faces.into_iter().flat_map(|(_, indices)| indices).collect()
+    }
+    pub fn get_time_bind_group_layout() -> wgpu::BindGroupLayoutDescriptor<'static> {
+        wgpu::BindGroupLayoutDescriptor {
+            label: Some("time_bind_group"),
+            entries: &TIME_BIND_GROUP_LAYOUT_ENTRIES,
+        }
+    }
+    pub fn create_render_pipeline(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        layout: &wgpu::PipelineLayout,
+        primitive: wgpu::PrimitiveState,
+        sample_count: u32,
+    ) -> wgpu::RenderPipeline {
+        let shader_source = concat_shader_sources(&WATER_SHADER_FILES);
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(shader_source.into()),
+        });
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[Water::get_vertex_data_layout()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive,
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                // Drawn back to front, so faces behind another translucent face still blend
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            multiview: None,
+        })
+    }
+    // Translucent faces are culled like the opaque ones unless `Config::double_sided_translucent` is
+    // set. Double sided keeps their back, so the inside of a glass box is visible through its walls.
+    pub fn primitive_state(double_sided: bool) -> wgpu::PrimitiveState {
+        wgpu::PrimitiveState {
+            cull_mode: if double_sided {
+                None
+            } else {
+                Some(wgpu::Face::Front)
+            },
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TimeUniform, TranslucentPipeline};
+    use crate::blocks::block::{Block, BlockVertexData};
+    use crate::blocks::block_type::BlockType;
+    use crate::chunk::{insert_block, BlockVec, Chunk, ChunkBlocks, ChunkData, TranslucentFace};
+    use crate::pipelines::main::MainPipeline;
+    use crate::player::Camera;
+    use crate::state::Config;
+    use crate::utils::noise::create_world_noise_data;
+    use crate::utils::test_device;
+    use crate::world::{DEFAULT_CHUNK_SIZE, FREQUENCY, NOISE_SIZE, RNG_SEED};
+    use glam::Vec3;
+    use std::collections::HashMap;
+    use std::sync::{Arc, RwLock};
+    use wgpu::util::DeviceExt;
+
+    // Whether the rasterizer drops the first triangle of a quad seen from `eye`, projected like
+    // `Camera` does while looking straight at it
+    fn is_culled(
+        quad: &[BlockVertexData],
+        indices: &[u32],
+        eye: Vec3,
+        state: wgpu::PrimitiveState,
+    ) -> bool {
+        let triangle =
+            [indices[0], indices[1], indices[2]].map(|i| Vec3::from(quad[i as usize % 4].position));
+        let forward = (triangle[0] + triangle[1] + triangle[2]) / 3.0 - eye;
+        let up = if forward.cross(Vec3::Y).length() < 1e-3 {
+            Vec3::Z
+        } else {
+            Vec3::Y
+        };
+        let view_proj = glam::Mat4::perspective_lh(1.0, 1.0, 0.1, 100.0)
+            * glam::Mat4::look_at_lh(eye, eye + forward, up);
+        let [a, b, c] = triangle.map(|p| view_proj.project_point3(p).truncate());
+        let counter_clockwise = (b - a).perp_dot(c - a) > 0.0;
+        let front = counter_clockwise == (state.front_face == wgpu::FrontFace::Ccw);
+        match state.cull_mode {
+            Some(wgpu::Face::Front) => front,
+            Some(wgpu::Face::Back) => !front,
+            None => false,
+        }
+    }
+
+    #[test]
+    fn should_only_cull_translucent_faces_when_single_sided() {
+        assert_eq!(TranslucentPipeline::primitive_state(true).cull_mode, None);
+        assert_eq!(
+            TranslucentPipeline::primitive_state(false).cull_mode,
+            Some(wgpu::Face::Front)
+        );
+        // Opt in, the default culls them like the opaque faces
+        assert!(!Config::default().double_sided_translucent);
+    }
+
+    #[test]
+    fn should_draw_the_nearest_chunks_last_and_farthest_first() {
+        let distances = [4.0, 100.0, 1.0, 25.0, 9.0];
+        assert_eq!(
+            TranslucentPipeline::back_to_front(&distances, 10),
+            vec![1, 3, 4, 0, 2]
+        );
+
+        // Only the two nearest are sorted, after the others
+        let order = TranslucentPipeline::back_to_front(&distances, 2);
+        assert_eq!(order[3..], [0, 2]);
+        let mut unsorted = order[..3].to_vec();
+        unsorted.sort();
+        assert_eq!(unsorted, vec![1, 3, 4]);
+    }
+
+    #[test]
+    fn should_sort_faces_from_the_farthest_to_the_nearest() {
+        let face = |y: f32, first: u32| TranslucentFace {
+            center: glam::vec3(0.5, y, 0.5),
+            indices: [first; 6],
+        };
+        let faces = [face(3.0, 0), face(1.0, 4), face(5.0, 8)];
+        let eye = glam::vec3(0.5, 0.0, 0.5);
+        let indices = TranslucentPipeline::sort_faces(&faces, eye);
+        assert_eq!(indices.iter().step_by(6).collect::<Vec<_>>(), [&8, &0, &4]);
+    }
+
+    #[test]
+    fn should_bind_a_time_uniform_the_water_shader_accepts() {
+        let Some((device, _queue)) = test_device() else {
+            println!("No adapter available, skipping water pipeline test");
+            return;
+        };
+        // A whole number of 16 byte rows, as uniforms are laid out
+        let size = std::mem::size_of::<TimeUniform>();
+        assert_eq!(size, 16);
+        let time_layout = TranslucentPipeline::get_time_bind_group_layout();
+        let wgpu::BindingType::Buffer {
+            min_binding_size, ..
+        } = time_layout.entries[0].ty
+        else {
+            panic!("The time is not a buffer");
+        };
+        assert_eq!(min_binding_size.map(|size| size.get()), Some(size as u64));
+
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let time_layout = device.create_bind_group_layout(&time_layout);
+        let time_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&[TimeUniform::new(1.5)]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &time_layout,
+            label: None,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: time_buffer.as_entire_binding(),
+            }],
+        });
+        let bind_group_0_layout =
+            device.create_bind_group_layout(&MainPipeline::get_bind_group_0_layout());
+        let chunk_layout = device.create_bind_group_layout(&Chunk::get_bind_group_layout());
+        let camera_layout =
+            device.create_bind_group_layout(&Camera::get_position_bind_group_layout());
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[
+                &bind_group_0_layout,
+                &chunk_layout,
+                &camera_layout,
+                &time_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+        TranslucentPipeline::create_render_pipeline(
+            &device,
+            wgpu::TextureFormat::Bgra8UnormSrgb,
+            &layout,
+            TranslucentPipeline::primitive_state(false),
+            1,
+        );
+        let error = pollster::block_on(device.pop_error_scope());
+        assert!(error.is_none(), "{error:?}");
+    }
+
+    #[test]
+    fn should_draw_the_inside_of_a_hollow_glass_cube() {
+        let Some((device, queue)) = test_device() else {
+            println!("No adapter available, skipping hollow glass test");
+            return;
+        };
+        let layout = Arc::new(device.create_bind_group_layout(&Chunk::get_bind_group_layout()));
+        let noise_data = Arc::new(create_world_noise_data(
+            NOISE_SIZE, NOISE_SIZE, FREQUENCY, RNG_SEED,
+        ));
+
+        // A 3x3x3 glass shell with a single air block in the middle, where the camera is
+        let eye = glam::vec3(6.0, 6.0, 6.0);
+        let blocks: BlockVec = Arc::new(RwLock::new(ChunkBlocks::new((0, 0), DEFAULT_CHUNK_SIZE)));
+        for x in 5..=7 {
+            for y in 5..=7 {
+                for z in 5..=7 {
+                    let position = glam::vec3(x as f32, y as f32, z as f32);
+                    if position == eye {
+                        continue;
+                    }
+                    let block = Block::new(position, (0, 0), BlockType::Glass, DEFAULT_CHUNK_SIZE);
+                    insert_block(&blocks, Arc::new(RwLock::new(block)));
+                }
+            }
+        }
+        let data = ChunkData {
+            x: 0,
+            y: 0,
+            blocks,
+            outside_blocks: vec![],
+        };
+        let chunk = Chunk::from_data(data, noise_data, device, queue, layout, None);
+        let mesh = chunk.build_mesh_data(Arc::new(RwLock::new(HashMap::new())));
+
+        // 9 faces on each outer side and one around the hole, the ones between glass blocks are culled
+        assert!(mesh.vertex.is_empty());
+        assert_eq!(mesh.water_faces().len(), 6 * 9 + 6);
+        let faces: Vec<(&[BlockVertexData], &[u32])> = mesh
+            .water_vertex
+            .chunks(4)
+            .zip(mesh.water_indices.chunks(6))
+            .collect();
+        let center = |quad: &[BlockVertexData]| {
+            quad.iter().map(|v| Vec3::from(v.position)).sum::<Vec3>() / 4.0
+        };
+        let (inner, outer): (Vec<_>, Vec<_>) = faces
+            .iter()
+            .partition(|(quad, _)| (center(quad) - eye).length() < 1.0);
+        assert_eq!(inner.len(), 6);
+        for (quad, _) in inner.iter() {
+            // Facing the camera in the hole
+            let normal = Vec3::from(quad[0].normal);
+            assert_eq!(normal, (eye - center(quad)).normalize());
+        }
+
+        // The faces around the camera are drawn either way, the outer shell only when double sided
+        for double_sided in [false, true] {
+            let state = TranslucentPipeline::primitive_state(double_sided);
+            assert!(inner
+                .iter()
+                .all(|(quad, indices)| !is_culled(quad, indices, eye, state)));
+            assert_eq!(
+                outer
+                    .iter()
+                    .filter(|(quad, indices)| !is_culled(quad, indices, eye, state))
+                    .count(),
+                if double_sided { 6 * 9 } else { 0 }
+            );
+        }
+        // From outside the single sided faces facing the camera are drawn
+        let outside = glam::vec3(6.0, 6.0, 20.0);
+        let state = TranslucentPipeline::primitive_state(false);
+        assert_eq!(
+            outer
+                .iter()
+                .filter(|(quad, indices)| !is_culled(quad, indices, outside, state))
+                .count(),
+            9
+        );
+    }
+}
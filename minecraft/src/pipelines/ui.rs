@@ -1,25 +1,74 @@
 use crate::blocks::block::{FaceDirections, TexturedBlock};
 use crate::material::Texture;
-use crate::player::Player;
+use crate::player::{hotbar_block, Player, HOTBAR_SLOTS};
 use crate::state::State;
 use wgpu::util::DeviceExt;
 use wgpu::BufferUsages;
 
+use super::main::BLOCK_TEXTURE_SHADER;
 use super::pipeline_manager::PipelineManager;
+use super::shaders::concat_shader_sources;
 use super::Pipeline;
 
+// Each slot is a frame quad with the block (or an empty square) on top
+const QUADS_PER_SLOT: usize = 2;
+const VERTICES_PER_QUAD: usize = 6;
+const FLOATS_PER_VERTEX: usize = 9;
+const HOTBAR_VERTEX_COUNT: usize = HOTBAR_SLOTS * QUADS_PER_SLOT * VERTICES_PER_QUAD;
+// A horizontal bar and the vertical one split around it, overlapping quads would invert twice
+const CROSSHAIR_VERTEX_COUNT: usize = 3 * VERTICES_PER_QUAD;
+// A single quad over the whole screen
+const UNDERWATER_VERTEX_COUNT: usize = VERTICES_PER_QUAD;
+
+// Slot size and position in clip space, the widths get scaled by the aspect ratio
+const SLOT_SIZE: f32 = 0.16;
+const SLOT_INSET: f32 = 0.02;
+const HOTBAR_BOTTOM: f32 = -0.97;
+// Half the length of each arm and half the thickness of the crosshair, in clip space
+const CROSSHAIR_ARM: f32 = 0.03;
+const CROSSHAIR_HALF_THICKNESS: f32 = 0.003;
+
+const FRAME_COLOR: [f32; 4] = [0.2, 0.2, 0.2, 0.8];
+const SELECTED_FRAME_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.95];
+const EMPTY_SLOT_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.4];
+const NO_TINT: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+// Blended over the scene when the eye is in the water
+const UNDERWATER_TINT: [f32; 4] = [0.05, 0.2, 0.6, 0.35];
+// Negative uvs tell the shader to use the vertex color instead of the block textures
+const UNTEXTURED: (u32, [[f32; 2]; 4]) = (0, [[-1.0, -1.0]; 4]);
+// Inverts whatever is behind the crosshair, so it shows on light and dark backgrounds alike
+const INVERT_BLENDING: wgpu::BlendState = wgpu::BlendState {
+    color: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::OneMinusDst,
+        dst_factor: wgpu::BlendFactor::Zero,
+        operation: wgpu::BlendOperation::Add,
+    },
+    alpha: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::Zero,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+    },
+};
+
 pub struct UIPipeline {
     pub pipeline: wgpu::RenderPipeline,
+    // Same shader, drawn last with the inverting blend
+    pub crosshair_pipeline: wgpu::RenderPipeline,
     pub screenspace_buffer: wgpu::Buffer,
+    // Selected slot and aspect ratio the buffer was last built for
+    built_for: Option<(usize, f32)>,
 }
 
 impl Pipeline for UIPipeline {
+    fn shader_files() -> &'static [&'static str] {
+        &[BLOCK_TEXTURE_SHADER, "ui_shader.wgsl"]
+    }
     fn render(
         &self,
         state: &State,
         encoder: &mut wgpu::CommandEncoder,
         view: &wgpu::TextureView,
-        _player: &std::sync::RwLockReadGuard<'_, Player>,
+        player: &std::sync::RwLockReadGuard<'_, Player>,
         _chunks: &Vec<std::sync::RwLockReadGuard<'_, crate::chunk::Chunk>>,
     ) {
         let main_pipeline_ref = state
@@ -30,14 +79,9 @@ impl Pipeline for UIPipeline {
             .borrow();
         let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: None,
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Load,
-                    store: wgpu::StoreOp::Store,
-                },
-            })],
+            color_attachments: &[Some(
+                main_pipeline_ref.color_attachment(view, wgpu::LoadOp::Load),
+            )],
             depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
                 view: &main_pipeline_ref.depth_texture.view,
                 depth_ops: Some(wgpu::Operations {
@@ -52,12 +96,26 @@ impl Pipeline for UIPipeline {
         rpass.set_pipeline(&self.pipeline);
         rpass.set_bind_group(0, &main_pipeline_ref.bind_group_0, &[]);
         rpass.set_vertex_buffer(0, self.screenspace_buffer.slice(..));
-        rpass.draw(0..6, 0..1);
+        // Under the hotbar, it only tints the scene
+        if player.head_in_water {
+            let start = HOTBAR_VERTEX_COUNT + CROSSHAIR_VERTEX_COUNT;
+            rpass.draw(start as u32..(start + UNDERWATER_VERTEX_COUNT) as u32, 0..1);
+        }
+        rpass.draw(0..HOTBAR_VERTEX_COUNT as u32, 0..1);
+        if state.show_crosshair {
+            rpass.set_pipeline(&self.crosshair_pipeline);
+            rpass.draw(
+                HOTBAR_VERTEX_COUNT as u32..(HOTBAR_VERTEX_COUNT + CROSSHAIR_VERTEX_COUNT) as u32,
+                0..1,
+            );
+        }
     }
-    fn init(state: &State, pipeline_manager: &PipelineManager) -> Self {
-        let swapchain_capabilities = state.surface.get_capabilities(&state.adapter);
-        let swapchain_format = swapchain_capabilities.formats[0];
-        let shader_source = include_str!("../shaders/ui_shader.wgsl");
+    fn init(
+        state: &State,
+        pipeline_manager: &PipelineManager,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let swapchain_format = state.surface_format;
+        let shader_source = concat_shader_sources(Self::shader_files());
 
         let shader = state
             .device
@@ -68,17 +126,15 @@ impl Pipeline for UIPipeline {
 
         let aspect_ratio = state.surface_config.height as f32 / state.surface_config.width as f32;
 
-        let player = state.player.read().unwrap();
-        let block_type = player.placing_block;
-        let tex_coords = block_type.get_texcoords(FaceDirections::Front);
-        let screen_quad = Self::create_screen_quad(aspect_ratio, tex_coords);
+        let selected_slot = state.player.read().unwrap().selected_slot;
+        let vertices = Self::create_vertices(aspect_ratio, selected_slot);
 
         let screenspace_buffer =
             state
                 .device
                 .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    contents: bytemuck::cast_slice(&screen_quad),
-                    label: Some("Screenspace rectangle"),
+                    contents: bytemuck::cast_slice(&vertices),
+                    label: Some("Hotbar"),
                     usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
                 });
 
@@ -97,7 +153,7 @@ impl Pipeline for UIPipeline {
                     push_constant_ranges: &[],
                 });
 
-        let render_pipeline =
+        let create_pipeline = |blend: wgpu::BlendState| {
             state
                 .device
                 .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
@@ -113,7 +169,7 @@ impl Pipeline for UIPipeline {
                         entry_point: "fs_main",
                         targets: &[Some(wgpu::ColorTargetState {
                             format: swapchain_format,
-                            blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                            blend: Some(blend),
                             write_mask: wgpu::ColorWrites::ALL,
                         })],
                     }),
@@ -129,66 +185,154 @@ impl Pipeline for UIPipeline {
                         stencil: wgpu::StencilState::default(),
                         bias: wgpu::DepthBiasState::default(),
                     }),
-                    multisample: wgpu::MultisampleState::default(),
+                    multisample: wgpu::MultisampleState {
+                        count: state.sample_count,
+                        ..Default::default()
+                    },
                     multiview: None,
-                });
+                })
+        };
 
-        Self {
+        Ok(Self {
             screenspace_buffer,
-            pipeline: render_pipeline,
-        }
+            pipeline: create_pipeline(wgpu::BlendState::ALPHA_BLENDING),
+            crosshair_pipeline: create_pipeline(INVERT_BLENDING),
+            built_for: Some((selected_slot, aspect_ratio)),
+        })
     }
     fn update(
         &mut self,
         _pipeline_manager: &PipelineManager,
         state: &State,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let aspect_ratio = state.surface_config.height as f32 / state.surface_config.width as f32;
-        let player = state.player.read().unwrap();
-        let block_type = player.placing_block;
-        let tex_coords = block_type.get_texcoords(FaceDirections::Front);
-        let screen_quad = Self::create_screen_quad(aspect_ratio, tex_coords);
-        state.queue.write_buffer(
-            &self.screenspace_buffer,
-            0,
-            bytemuck::cast_slice(&screen_quad),
-        );
+        self.update_hotbar(state);
         Ok(())
     }
+    // The quads depend on the aspect ratio, so they're refreshed right away on resize
+    fn resize(&mut self, state: &State, _new_size: (u32, u32)) {
+        self.built_for = None;
+        self.update_hotbar(state);
+    }
 }
 impl UIPipeline {
-    // Creates the rectangle coords for displaying the block that would be placed if something is placed.
-    fn create_screen_quad(aspect_ratio: f32, tex_coords: [[f32; 2]; 4]) -> Vec<f32> {
-        vec![
-            -0.9 * aspect_ratio,
-            -0.9,
-            tex_coords[0][0],
-            tex_coords[0][1],
-            -0.9 * aspect_ratio,
-            -0.6,
-            tex_coords[1][0],
-            tex_coords[1][1],
-            -0.6 * aspect_ratio,
-            -0.6,
-            tex_coords[2][0],
-            tex_coords[2][1],
-            -0.9 * aspect_ratio,
-            -0.9,
-            tex_coords[0][0],
-            tex_coords[0][1],
-            -0.6 * aspect_ratio,
-            -0.6,
-            tex_coords[2][0],
-            tex_coords[2][1],
-            -0.6 * aspect_ratio,
-            -0.9,
-            tex_coords[3][0],
-            tex_coords[3][1],
-        ]
+    // Only rewrites the buffer when the selection or the aspect ratio changed
+    fn update_hotbar(&mut self, state: &State) {
+        let aspect_ratio = state.surface_config.height as f32 / state.surface_config.width as f32;
+        let selected_slot = state.player.read().unwrap().selected_slot;
+        if self.built_for == Some((selected_slot, aspect_ratio)) {
+            return;
+        }
+        let vertices = Self::create_vertices(aspect_ratio, selected_slot);
+        state
+            .queue
+            .write_buffer(&self.screenspace_buffer, 0, bytemuck::cast_slice(&vertices));
+        self.built_for = Some((selected_slot, aspect_ratio));
+    }
+    // The hotbar, the crosshair and the underwater tint
+    fn create_vertices(aspect_ratio: f32, selected_slot: usize) -> Vec<f32> {
+        let mut vertices = Self::create_hotbar(aspect_ratio, selected_slot);
+        vertices.extend(Self::create_crosshair(aspect_ratio));
+        Self::push_quad(
+            &mut vertices,
+            [-1.0, -1.0],
+            [1.0, 1.0],
+            UNTEXTURED,
+            UNDERWATER_TINT,
+        );
+        vertices
+    }
+    // A cross in the middle of the screen, the widths are scaled so it stays square
+    fn create_crosshair(aspect_ratio: f32) -> Vec<f32> {
+        let mut vertices = Vec::with_capacity(CROSSHAIR_VERTEX_COUNT * FLOATS_PER_VERTEX);
+        let arm_width = CROSSHAIR_ARM * aspect_ratio;
+        let thickness_width = CROSSHAIR_HALF_THICKNESS * aspect_ratio;
+        let color = [1.0; 4];
+        Self::push_quad(
+            &mut vertices,
+            [-arm_width, -CROSSHAIR_HALF_THICKNESS],
+            [arm_width, CROSSHAIR_HALF_THICKNESS],
+            UNTEXTURED,
+            color,
+        );
+        Self::push_quad(
+            &mut vertices,
+            [-thickness_width, CROSSHAIR_HALF_THICKNESS],
+            [thickness_width, CROSSHAIR_ARM],
+            UNTEXTURED,
+            color,
+        );
+        Self::push_quad(
+            &mut vertices,
+            [-thickness_width, -CROSSHAIR_ARM],
+            [thickness_width, -CROSSHAIR_HALF_THICKNESS],
+            UNTEXTURED,
+            color,
+        );
+        vertices
+    }
+    // Creates the slot quads along the bottom of the screen, centered horizontally.
+    fn create_hotbar(aspect_ratio: f32, selected_slot: usize) -> Vec<f32> {
+        let mut vertices = Vec::with_capacity(HOTBAR_VERTEX_COUNT * FLOATS_PER_VERTEX);
+        let slot_width = SLOT_SIZE * aspect_ratio;
+        let inset_width = SLOT_INSET * aspect_ratio;
+        let left = -slot_width * HOTBAR_SLOTS as f32 / 2.0;
+
+        for slot in 0..HOTBAR_SLOTS {
+            let min = [left + slot as f32 * slot_width, HOTBAR_BOTTOM];
+            let max = [min[0] + slot_width, HOTBAR_BOTTOM + SLOT_SIZE];
+            let frame_color = if slot == selected_slot {
+                SELECTED_FRAME_COLOR
+            } else {
+                FRAME_COLOR
+            };
+            Self::push_quad(&mut vertices, min, max, UNTEXTURED, frame_color);
+
+            let inner_min = [min[0] + inset_width, min[1] + SLOT_INSET];
+            let inner_max = [max[0] - inset_width, max[1] - SLOT_INSET];
+            match hotbar_block(slot) {
+                Some(block_type) => Self::push_quad(
+                    &mut vertices,
+                    inner_min,
+                    inner_max,
+                    block_type.get_texcoords(FaceDirections::Front),
+                    NO_TINT,
+                ),
+                None => Self::push_quad(
+                    &mut vertices,
+                    inner_min,
+                    inner_max,
+                    UNTEXTURED,
+                    EMPTY_SLOT_COLOR,
+                ),
+            }
+        }
+        vertices
+    }
+    fn push_quad(
+        vertices: &mut Vec<f32>,
+        min: [f32; 2],
+        max: [f32; 2],
+        (layer, tex_coords): (u32, [[f32; 2]; 4]),
+        color: [f32; 4],
+    ) {
+        let corners = [
+            ([min[0], min[1]], tex_coords[0]),
+            ([min[0], max[1]], tex_coords[1]),
+            ([max[0], max[1]], tex_coords[2]),
+            ([min[0], min[1]], tex_coords[0]),
+            ([max[0], max[1]], tex_coords[2]),
+            ([max[0], min[1]], tex_coords[3]),
+        ];
+        for (position, uv) in corners {
+            vertices.extend_from_slice(&position);
+            vertices.extend_from_slice(&uv);
+            vertices.extend_from_slice(&color);
+            vertices.push(layer as f32);
+        }
     }
     fn get_vertex_data_layout() -> wgpu::VertexBufferLayout<'static> {
         wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+            array_stride: std::mem::size_of::<[f32; FLOATS_PER_VERTEX]>() as wgpu::BufferAddress,
             step_mode: wgpu::VertexStepMode::Vertex,
             attributes: &[
                 // Position
@@ -203,7 +347,132 @@ impl UIPipeline {
                     offset: std::mem::size_of::<[f32; 2]>() as u64,
                     shader_location: 1,
                 },
+                // Color
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: std::mem::size_of::<[f32; 4]>() as u64,
+                    shader_location: 2,
+                },
+                // Texture layer
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32,
+                    offset: std::mem::size_of::<[f32; 8]>() as u64,
+                    shader_location: 3,
+                },
             ],
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::block_type::BlockType;
+
+    fn quad_color(hotbar: &[f32], quad: usize) -> [f32; 4] {
+        let start = quad * VERTICES_PER_QUAD * FLOATS_PER_VERTEX + 4;
+        hotbar[start..start + 4].try_into().unwrap()
+    }
+
+    #[test]
+    fn should_build_every_slot_and_highlight_the_selected_one() {
+        let hotbar = UIPipeline::create_hotbar(0.5, 3);
+        assert_eq!(hotbar.len(), HOTBAR_VERTEX_COUNT * FLOATS_PER_VERTEX);
+
+        for slot in 0..HOTBAR_SLOTS {
+            let expected = if slot == 3 {
+                SELECTED_FRAME_COLOR
+            } else {
+                FRAME_COLOR
+            };
+            assert_eq!(quad_color(&hotbar, slot * QUADS_PER_SLOT), expected);
+        }
+
+        // Centered along the bottom of the screen
+        let xs = hotbar.iter().step_by(FLOATS_PER_VERTEX);
+        let (min_x, max_x) = xs.fold((f32::MAX, f32::MIN), |(a, b), x| (a.min(*x), b.max(*x)));
+        assert!((min_x + max_x).abs() < 1e-5);
+        assert!(hotbar
+            .iter()
+            .skip(1)
+            .step_by(FLOATS_PER_VERTEX)
+            .all(|y| *y < 0.0));
+
+        assert_eq!(hotbar_block(1), Some(BlockType::Dirt));
+        assert!(hotbar_block(HOTBAR_SLOTS).is_none());
+        assert!((0..HOTBAR_SLOTS).all(|slot| hotbar_block(slot) != Some(BlockType::Water)));
+    }
+
+    #[test]
+    fn should_space_the_slots_evenly_and_show_the_front_of_their_block() {
+        let aspect_ratio = 0.5;
+        let hotbar = UIPipeline::create_hotbar(aspect_ratio, 0);
+        let vertex = |quad: usize, corner: usize| {
+            let start = (quad * VERTICES_PER_QUAD + corner) * FLOATS_PER_VERTEX;
+            &hotbar[start..start + FLOATS_PER_VERTEX]
+        };
+        let slot_width = SLOT_SIZE * aspect_ratio;
+
+        for slot in 0..HOTBAR_SLOTS {
+            let frame = slot * QUADS_PER_SLOT;
+            // Slots follow each other without gaps, all the same size
+            let (min, max) = (vertex(frame, 0), vertex(frame, 2));
+            assert!((max[0] - min[0] - slot_width).abs() < 1e-5);
+            assert!((max[1] - min[1] - SLOT_SIZE).abs() < 1e-5);
+            if slot > 0 {
+                let previous_max = vertex(frame - QUADS_PER_SLOT, 2);
+                assert!((min[0] - previous_max[0]).abs() < 1e-5);
+            }
+
+            let (layer, expected_uvs) = match hotbar_block(slot) {
+                Some(block_type) => block_type.get_texcoords(FaceDirections::Front),
+                None => UNTEXTURED,
+            };
+            for (corner, uv_index) in [0, 1, 2, 0, 2, 3].into_iter().enumerate() {
+                assert_eq!(vertex(frame + 1, corner)[2..4], expected_uvs[uv_index]);
+                assert_eq!(vertex(frame + 1, corner)[8], layer as f32);
+            }
+        }
+    }
+
+    #[test]
+    fn should_center_the_crosshair_and_keep_it_square() {
+        let aspect_ratio = 0.5;
+        let vertices = UIPipeline::create_vertices(aspect_ratio, 0);
+        assert_eq!(
+            vertices.len(),
+            (HOTBAR_VERTEX_COUNT + CROSSHAIR_VERTEX_COUNT + UNDERWATER_VERTEX_COUNT)
+                * FLOATS_PER_VERTEX
+        );
+        let crosshair = &vertices[HOTBAR_VERTEX_COUNT * FLOATS_PER_VERTEX
+            ..(HOTBAR_VERTEX_COUNT + CROSSHAIR_VERTEX_COUNT) * FLOATS_PER_VERTEX];
+        let extent = |axis: usize| {
+            crosshair
+                .iter()
+                .skip(axis)
+                .step_by(FLOATS_PER_VERTEX)
+                .fold((f32::MAX, f32::MIN), |(a, b), v| (a.min(*v), b.max(*v)))
+        };
+        let (min_x, max_x) = extent(0);
+        let (min_y, max_y) = extent(1);
+        assert!((min_x + max_x).abs() < 1e-5);
+        assert!((min_y + max_y).abs() < 1e-5);
+        // Same size in pixels once the x axis is stretched back by the aspect ratio
+        assert!(((max_x - min_x) / aspect_ratio - (max_y - min_y)).abs() < 1e-5);
+
+        // The quads only touch, none of the pixels is inverted twice
+        let area = |quad: usize| {
+            let start = quad * VERTICES_PER_QUAD * FLOATS_PER_VERTEX;
+            let (min, max) = (
+                &crosshair[start..],
+                &crosshair[start + 2 * FLOATS_PER_VERTEX..],
+            );
+            (max[0] - min[0]) * (max[1] - min[1])
+        };
+        let total = (0..3).map(area).sum::<f32>();
+        let horizontal = (max_x - min_x) * CROSSHAIR_HALF_THICKNESS * 2.0;
+        let vertical = (max_y - min_y) * CROSSHAIR_HALF_THICKNESS * 2.0 * aspect_ratio;
+        let center = 4.0 * CROSSHAIR_HALF_THICKNESS * CROSSHAIR_HALF_THICKNESS * aspect_ratio;
+        assert!((total - (horizontal + vertical - center)).abs() < 1e-7);
+    }
+}
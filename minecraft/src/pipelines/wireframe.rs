@@ -0,0 +1,269 @@
+use std::sync::RwLockReadGuard;
+
+use wgpu::util::DeviceExt;
+
+use super::highlight_selected::cube_edges;
+use super::main::{MainPipeline, BLOCK_TEXTURE_SHADER};
+use super::pipeline_manager::PipelineManager;
+use super::shaders::shader_source;
+use super::Pipeline;
+use crate::chunk::Chunk;
+use crate::material::Texture;
+use crate::player::Player;
+use crate::state::State;
+
+// Debug view of the chunk meshes (F4), draws the edges of their triangles instead of the main
+// pipeline and a box around every chunk in view
+pub struct WireframePipeline {
+    pub pipeline: wgpu::RenderPipeline,
+    pub chunk_border_pipeline: wgpu::RenderPipeline,
+    // The edges of a unit cube, scaled to each chunk in the shader
+    pub chunk_border_buffer: wgpu::Buffer,
+}
+
+impl WireframePipeline {
+    // Without it the edges are found in the fragment shader instead
+    pub const FEATURES: wgpu::Features = wgpu::Features::POLYGON_MODE_LINE;
+
+    // Both sides, the faces behind the visible ones show through
+    pub fn primitive(line_mode: bool) -> wgpu::PrimitiveState {
+        wgpu::PrimitiveState {
+            cull_mode: None,
+            polygon_mode: if line_mode {
+                wgpu::PolygonMode::Line
+            } else {
+                wgpu::PolygonMode::Fill
+            },
+            ..Default::default()
+        }
+    }
+    pub fn fragment_entry_point(line_mode: bool) -> &'static str {
+        if line_mode {
+            "fs_main"
+        } else {
+            "fs_wireframe"
+        }
+    }
+    fn chunk_border_vertices() -> Vec<[f32; 3]> {
+        cube_edges(glam::Vec3::splat(0.5), 1.0)
+            .iter()
+            .map(|vertex| [vertex[0], vertex[1], vertex[2]])
+            .collect()
+    }
+}
+
+impl Pipeline for WireframePipeline {
+    fn shader_files() -> &'static [&'static str] {
+        &[
+            BLOCK_TEXTURE_SHADER,
+            "block_vertex.wgsl",
+            "shader.wgsl",
+            "chunk_border.wgsl",
+        ]
+    }
+    fn init(
+        state: &State,
+        pipeline_manager: &PipelineManager,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let swapchain_format = state.surface_format;
+        let main_pipeline = pipeline_manager.main_pipeline.as_ref().unwrap().borrow();
+        let line_mode = state.device.features().contains(Self::FEATURES);
+        if !line_mode {
+            println!("Polygon line mode not supported, the wireframe view is drawn by the shader");
+        }
+        let pipeline = MainPipeline::create_render_pipeline(
+            &state.device,
+            swapchain_format,
+            &main_pipeline.pipeline_layout,
+            Self::primitive(line_mode),
+            Self::fragment_entry_point(line_mode),
+            state.sample_count,
+        );
+
+        let chunk_border_buffer =
+            state
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("chunk-border"),
+                    contents: bytemuck::cast_slice(&Self::chunk_border_vertices()),
+                    usage: wgpu::BufferUsages::VERTEX,
+                });
+        let shader = state
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: None,
+                source: wgpu::ShaderSource::Wgsl(shader_source("chunk_border.wgsl")),
+            });
+        let chunk_border_layout =
+            state
+                .device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: None,
+                    bind_group_layouts: &[
+                        &main_pipeline.bind_group_0_layout,
+                        &state.world.chunk_data_layout,
+                    ],
+                    push_constant_ranges: &[],
+                });
+        // Line lists don't need the line mode
+        let chunk_border_pipeline =
+            state
+                .device
+                .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: None,
+                    layout: Some(&chunk_border_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader,
+                        entry_point: "vs_main",
+                        buffers: &[wgpu::VertexBufferLayout {
+                            array_stride: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                            step_mode: wgpu::VertexStepMode::Vertex,
+                            attributes: &wgpu::vertex_attr_array![0 => Float32x3],
+                        }],
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader,
+                        entry_point: "fs_main",
+                        targets: &[Some(swapchain_format.into())],
+                    }),
+                    primitive: wgpu::PrimitiveState {
+                        topology: wgpu::PrimitiveTopology::LineList,
+                        ..Default::default()
+                    },
+                    depth_stencil: Some(wgpu::DepthStencilState {
+                        format: Texture::DEPTH_FORMAT,
+                        depth_write_enabled: false,
+                        depth_compare: wgpu::CompareFunction::LessEqual,
+                        stencil: wgpu::StencilState::default(),
+                        bias: wgpu::DepthBiasState::default(),
+                    }),
+                    multisample: wgpu::MultisampleState {
+                        count: state.sample_count,
+                        ..Default::default()
+                    },
+                    multiview: None,
+                });
+
+        Ok(Self {
+            pipeline,
+            chunk_border_pipeline,
+            chunk_border_buffer,
+        })
+    }
+    fn update(
+        &mut self,
+        _pipeline_manager: &PipelineManager,
+        _state: &State,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(())
+    }
+    // Same bind groups and depth texture as the main pipeline
+    fn render(
+        &self,
+        state: &State,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        player: &RwLockReadGuard<'_, Player>,
+        chunks: &Vec<RwLockReadGuard<'_, Chunk>>,
+    ) {
+        let main_pipeline_ref = state
+            .pipeline_manager
+            .main_pipeline
+            .as_ref()
+            .unwrap()
+            .borrow();
+        main_pipeline_ref.draw_chunks(&self.pipeline, encoder, view, player, chunks);
+
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(
+                main_pipeline_ref.color_attachment(view, wgpu::LoadOp::Load),
+            )],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &main_pipeline_ref.depth_texture.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        rpass.set_pipeline(&self.chunk_border_pipeline);
+        rpass.set_bind_group(0, &main_pipeline_ref.bind_group_0, &[]);
+        rpass.set_vertex_buffer(0, self.chunk_border_buffer.slice(..));
+        let vertex_count = self.chunk_border_buffer.size() / std::mem::size_of::<[f32; 3]>() as u64;
+        for chunk in chunks.iter().filter(|chunk| chunk.visible) {
+            rpass.set_bind_group(1, &chunk.chunk_bind_group, &[]);
+            rpass.draw(0..vertex_count as u32, 0..1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WireframePipeline;
+    use crate::chunk::Chunk;
+    use crate::pipelines::main::MainPipeline;
+    use crate::player::Camera;
+    use crate::utils::test_adapter;
+    use crate::world::WORLD_MAX_Y;
+
+    #[test]
+    fn should_outline_a_unit_cube_for_the_chunk_borders() {
+        let vertices = WireframePipeline::chunk_border_vertices();
+        assert_eq!(vertices.len(), 24);
+        assert!(vertices.iter().flatten().all(|&v| v == 0.0 || v == 1.0));
+        // The shader scales it up to the chunk
+        assert!(include_str!("../shaders/chunk_border.wgsl")
+            .contains(&format!("const border_height = {}.0;", WORLD_MAX_Y)));
+    }
+
+    #[test]
+    fn should_create_the_wireframe_pipeline_with_or_without_line_mode() {
+        let Some(adapter) = test_adapter() else {
+            println!("No adapter available, skipping wireframe test");
+            return;
+        };
+        let features = adapter.features() & WireframePipeline::FEATURES;
+        let (device, _queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                features,
+                ..Default::default()
+            },
+            None,
+        ))
+        .unwrap();
+
+        let bind_group_0_layout =
+            device.create_bind_group_layout(&MainPipeline::get_bind_group_0_layout());
+        let chunk_layout = device.create_bind_group_layout(&Chunk::get_bind_group_layout());
+        let camera_layout =
+            device.create_bind_group_layout(&Camera::get_position_bind_group_layout());
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_0_layout, &chunk_layout, &camera_layout],
+            push_constant_ranges: &[],
+        });
+
+        // The line mode when there's one, and the shader fallback always
+        let mut modes = vec![false];
+        if !features.is_empty() {
+            modes.push(true);
+        }
+        for line_mode in modes {
+            device.push_error_scope(wgpu::ErrorFilter::Validation);
+            MainPipeline::create_render_pipeline(
+                &device,
+                wgpu::TextureFormat::Bgra8UnormSrgb,
+                &layout,
+                WireframePipeline::primitive(line_mode),
+                WireframePipeline::fragment_entry_point(line_mode),
+                1,
+            );
+            let error = pollster::block_on(device.pop_error_scope());
+            assert!(error.is_none(), "{line_mode} {error:?}");
+        }
+    }
+}
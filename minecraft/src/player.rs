@@ -9,19 +9,75 @@ use wgpu::util::DeviceExt;
 
 use crate::blocks::block::{Block, FaceDirections};
 use crate::blocks::block_type::BlockType;
-use crate::collision::RayResult;
-use crate::persistence::{Loadable, Saveable};
-use crate::{collision::CollisionBox, world::CHUNK_SIZE};
+use crate::chunk::WATER_SURFACE_DROP;
+use crate::persistence::{
+    read_f32, read_save_file, read_u8, save_dir, Loadable, Saveable, WorldArchive,
+};
+use crate::utils::math_utils::Frustum;
+use crate::{
+    collision::CollisionBox,
+    world::{World, SPAWN_POINT, WORLD_MIN_Y},
+};
 
 const SENSITIVITY: f32 = 0.001;
 const CAMERA_SPEED: f32 = 10.0;
-const GRAVITY: f32 = 10.0;
+// Blocks per second squared, and the fastest (blocks per second) the player can fall
+const GRAVITY: f32 = 25.0;
+const TERMINAL_VELOCITY: f32 = 40.0;
+// Water slows down both falling and jumping
+const WATER_GRAVITY_FACTOR: f32 = 0.3;
+const WATER_TERMINAL_VELOCITY: f32 = 3.0;
+// Walking in the water, and holding jump swims up this fast (blocks per second)
+const WATER_SPEED_FACTOR: f32 = 0.5;
+const SWIM_SPEED: f32 = 2.5;
+// How far past the water surface the eye goes before the view switches, so bobbing right at the
+// water line doesn't flicker between the underwater tint and the sky
+const SURFACE_HYSTERESIS: f32 = 0.05;
+const JUMP_HEIGHT: f32 = 1.25;
+const FLY_SPEED: f32 = 10.0;
+// Longer frames are simulated as this long, the collisions only check the blocks next to the player
+const MAX_PHYSICS_STEP: f32 = 0.1;
+// Collision box around the camera, the eye is this high above the feet
+const PLAYER_WIDTH: f32 = 0.8;
+const PLAYER_HEIGHT: f32 = 2.0;
+const EYE_HEIGHT: f32 = 1.8;
+// Falling this far under the world puts the player back at the spawn
+const VOID_DEPTH: f32 = 64.0;
+// Blocks can be placed this far into the player's box, so touching it isn't overlapping it even
+// when the position has rounding errors
+const PLACEMENT_EPSILON: f32 = 1e-3;
+// A block this close under the feet counts as ground
+const GROUND_PROBE_DEPTH: f32 = 0.05;
+// Two jump presses closer than this toggle flying
+const DOUBLE_TAP_TIME: Duration = Duration::from_millis(300);
+pub const DEFAULT_FLY_SPEED_FACTOR: f32 = 2.0;
+// Blocks further away than this from the eye can't be selected, placed or broken
+pub const REACH_DISTANCE: f32 = 5.0;
+// Crack textures shown over a block while it breaks, from the first crack to almost broken
+pub const CRACK_STAGES: u32 = 10;
+// Range of the vertical field of view (in radians), wider than that the edges stretch too much
+pub const MIN_FOV: f32 = consts::FRAC_PI_6;
+pub const MAX_FOV: f32 = 2.0 * consts::FRAC_PI_3;
+// Blocks shown above and below the center of the screen in the orthographic view at zoom 1
+pub const ORTHO_HALF_HEIGHT: f32 = 64.0;
 pub static PLAYER_VIEW_OFFSET: Vec3 = vec3(0.4, 1.0, 0.4); /* this is kind of a hack, we should fix the camera's eye */
+pub const HOTBAR_SLOTS: usize = 9;
 
-lazy_static! {
-    static ref JUMP_DURATION: Duration = Duration::from_secs_f32(0.1);
+// Block shown in a hotbar slot. Slots are filled in id order, skipping water since it
+// can't be placed, and the ones past the last block type are empty.
+pub fn hotbar_block(slot: usize) -> Option<BlockType> {
+    if slot >= HOTBAR_SLOTS {
+        return None;
+    }
+    (0..=BlockType::MAX_ID)
+        .map(BlockType::from_id)
+        // The ones the player can't break can't be placed either
+        .filter(|block_type| block_type.get_config().breakable)
+        .nth(slot)
+}
+pub fn hotbar_slot_of(block_type: BlockType) -> Option<usize> {
+    (0..HOTBAR_SLOTS).find(|slot| hotbar_block(*slot) == Some(block_type))
 }
-const JUMP_HEIGHT: f32 = 1.5;
 
 pub struct CameraController {
     pub movement_vector: Vec3,
@@ -34,19 +90,67 @@ impl Default for CameraController {
         }
     }
 }
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MovementMode {
+    Walk,
+    // No gravity, the vertical input moves up and down
+    Fly,
+}
+
 pub struct Player {
     pub camera: Camera,
+    pub movement_mode: MovementMode,
+    // Horizontal speed multiplier while flying
+    pub fly_speed_factor: f32,
+    last_jump_press: Option<Instant>,
     pub current_chunk: (i32, i32),
     pub on_ground: bool,
-    pub is_jumping: bool,
+    // Blocks per second, positive is up
+    pub vertical_velocity: f32,
+    // Feet or eye in the water
     pub in_water: bool,
-    pub jump_action_start: Option<Instant>,
+    // Only the eye, the view is tinted and the fog shorter
+    pub head_in_water: bool,
+    // No clip, collisions are ignored in both movement modes
     pub is_ghost: bool,
     pub placing_block: BlockType,
+    pub selected_slot: usize,
     pub facing_block: Option<Arc<RwLock<Block>>>,
     pub facing_face: Option<FaceDirections>,
+    // How far away the facing block can be
+    pub reach: f32,
+    // Block being mined and how far along it is, 0 to 1
+    pub breaking: Option<(Arc<RwLock<Block>>, f32)>,
 }
 impl Player {
+    pub fn new(camera: Camera, archive: Option<Arc<WorldArchive>>) -> Player {
+        let default_slot = hotbar_slot_of(BlockType::Dirt).unwrap_or(0);
+        let selected_slot = <Player as Loadable<usize>>::load(Box::new(archive.clone()))
+            .ok()
+            .filter(|slot| hotbar_block(*slot).is_some())
+            .unwrap_or(default_slot);
+        let movement_mode = <Player as Loadable<MovementMode>>::load(Box::new(archive))
+            .unwrap_or(MovementMode::Walk);
+        Player {
+            camera,
+            movement_mode,
+            fly_speed_factor: DEFAULT_FLY_SPEED_FACTOR,
+            last_jump_press: None,
+            placing_block: hotbar_block(selected_slot).unwrap_or(BlockType::Dirt),
+            selected_slot,
+            in_water: false,
+            head_in_water: false,
+            // Depends on the world's chunk size, set when the world loads its chunks
+            current_chunk: (0, 0),
+            on_ground: false,
+            vertical_velocity: 0.0,
+            facing_block: None,
+            facing_face: None,
+            reach: REACH_DISTANCE,
+            breaking: None,
+            is_ghost: false,
+        }
+    }
     pub fn update(&mut self) {
         self.camera.queue.write_buffer(
             &self.camera.position_buffer,
@@ -60,101 +164,229 @@ impl Player {
     }
     pub fn get_collision(&self) -> crate::collision::CollisionBox {
         crate::collision::CollisionBox::new(
-            self.camera.eye.x - 0.4,
-            self.camera.eye.y - 1.8,
-            self.camera.eye.z - 0.4,
-            0.8,
-            2.0,
-            0.8,
+            self.camera.eye.x - PLAYER_WIDTH / 2.0,
+            self.camera.eye.y - EYE_HEIGHT,
+            self.camera.eye.z - PLAYER_WIDTH / 2.0,
+            PLAYER_WIDTH,
+            PLAYER_HEIGHT,
+            PLAYER_WIDTH,
         )
     }
+    // Whether a block at this absolute position would end up inside the player
+    pub fn overlaps_block(&self, position: Vec3) -> bool {
+        let mut collision = self.get_collision();
+        collision.min_x += PLACEMENT_EPSILON;
+        collision.min_y += PLACEMENT_EPSILON;
+        collision.min_z += PLACEMENT_EPSILON;
+        collision.max_x -= PLACEMENT_EPSILON;
+        collision.max_y -= PLACEMENT_EPSILON;
+        collision.max_z -= PLACEMENT_EPSILON;
+        collision.intersects(&CollisionBox::from_block_position(
+            position.x, position.y, position.z,
+        ))
+    }
+    pub fn select_slot(&mut self, slot: usize) {
+        if let Some(block_type) = hotbar_block(slot) {
+            self.selected_slot = slot;
+            self.placing_block = block_type;
+        }
+    }
+    // Moves the hotbar selection, wrapping around the filled slots. Delta is {1, -1}
     pub fn next_placing_block(&mut self, offset: i32) {
-        // Delta is {1, -1}
-        let placing_block_id = self.placing_block.to_id();
-        let mut next_block_id = (((placing_block_id as i32 + offset)
-            + (BlockType::MAX_ID + 1) as i32)
-            % (BlockType::MAX_ID + 1) as i32);
-
-        if next_block_id == BlockType::Water.to_id() as i32 {
-            next_block_id += offset;
+        let filled_slots = (0..HOTBAR_SLOTS)
+            .filter(|slot| hotbar_block(*slot).is_some())
+            .count() as i32;
+        if filled_slots == 0 {
+            return;
         }
-
-        self.placing_block = BlockType::from_id(next_block_id as u32);
+        let next_slot = (self.selected_slot as i32 + offset).rem_euclid(filled_slots);
+        self.select_slot(next_slot as usize);
     }
-    // Gets the block that the player is facing
-    pub fn get_facing_block(
-        &mut self,
-        blocks: &Vec<Arc<RwLock<Block>>>,
-    ) -> Option<(CollisionBox, FaceDirections)> {
-        let forward = self.camera.get_forward_dir();
-        let mut ray_results: Vec<RayResult> = vec![];
-
-        let ray = crate::collision::Ray {
-            direction: forward,
-            origin: self.camera.eye + PLAYER_VIEW_OFFSET,
+    // The block the view points at and the face it hits, none past `reach`
+    pub fn update_facing(&mut self, world: &World) {
+        let hit = world.raycast(
+            self.camera.eye + PLAYER_VIEW_OFFSET,
+            self.camera.get_forward_dir(),
+            self.reach,
+        );
+        self.facing_face = hit.as_ref().map(|hit| hit.face);
+        self.facing_block = hit.map(|hit| hit.block);
+    }
+    // Mines the facing block while the left button is held, returns its position once it breaks.
+    // Looking at another block starts over
+    pub fn mine(&mut self, delta_time: f32) -> Option<Vec3> {
+        let Some(facing_block) = self.facing_block.clone() else {
+            self.breaking = None;
+            return None;
         };
-
-        for block in blocks.iter() {
-            if block.read().unwrap().block_type == BlockType::Water {
-                continue;
+        let (position, config) = {
+            let block = facing_block.read().unwrap();
+            (block.absolute_position, block.block_type.get_config())
+        };
+        let hardness = config.hardness;
+        if !config.breakable {
+            self.breaking = None;
+            return None;
+        }
+        let progress = match self.breaking.take() {
+            Some((block, progress)) if block.read().unwrap().absolute_position == position => {
+                progress
             }
+            _ => 0.0,
+        } + if hardness > 0.0 {
+            delta_time / hardness
+        } else {
+            1.0
+        };
+        if progress >= 1.0 {
+            return Some(position);
+        }
+        self.breaking = Some((facing_block, progress));
+        None
+    }
+    pub fn calc_current_chunk(&self, chunk_size: u32) -> (i32, i32) {
+        (
+            f32::floor(self.camera.eye.x / chunk_size as f32) as i32,
+            f32::floor(self.camera.eye.z / chunk_size as f32) as i32,
+        )
+    }
 
-            if let Some(intersection_points) =
-                ray.intersects_box(&block.read().unwrap().collision_box)
-            {
-                ray_results.push(RayResult {
-                    points: intersection_points,
-                    collision: block.read().unwrap().collision_box.clone(),
-                })
+    // Space: a double tap toggles flying, a single one jumps when walking
+    pub fn press_jump(&mut self, now: Instant) {
+        let double_tap = self
+            .last_jump_press
+            .is_some_and(|last| now.duration_since(last) < DOUBLE_TAP_TIME);
+        if double_tap {
+            self.last_jump_press = None;
+            self.toggle_flying();
+            return;
+        }
+        self.last_jump_press = Some(now);
+        if self.movement_mode == MovementMode::Walk {
+            self.jump();
+        }
+    }
+    // Back at the spawn point, standing still
+    pub fn respawn(&mut self) {
+        self.camera.eye = SPAWN_POINT;
+        self.vertical_velocity = 0.0;
+        self.breaking = None;
+    }
+    // Gravity starts again from zero when landing mid-air
+    pub fn toggle_flying(&mut self) {
+        self.movement_mode = match self.movement_mode {
+            MovementMode::Walk => MovementMode::Fly,
+            MovementMode::Fly => MovementMode::Walk,
+        };
+        self.vertical_velocity = 0.0;
+    }
+    // Only from the ground, or swimming up in the water
+    pub fn jump(&mut self) {
+        if self.on_ground {
+            self.vertical_velocity = f32::sqrt(2.0 * GRAVITY * JUMP_HEIGHT);
+            self.on_ground = false;
+        } else if self.in_water {
+            self.vertical_velocity = f32::sqrt(2.0 * GRAVITY * WATER_GRAVITY_FACTOR * JUMP_HEIGHT);
+        }
+    }
+    // Moves the player vertically. Walking it falls until it lands on a block or hits a ceiling,
+    // flying it moves at the speed set by `move_camera`. Blocks stop it unless it's a ghost. Out of
+    // the world (or with a broken position) it goes back to the spawn
+    pub fn apply_physics(&mut self, delta_time: f32, world: &World) {
+        let delta_time = delta_time.min(MAX_PHYSICS_STEP);
+        if !self.camera.eye.is_finite()
+            || self.get_collision().min_y < WORLD_MIN_Y as f32 - VOID_DEPTH
+        {
+            self.respawn();
+        }
+        self.update_in_water(world);
+        self.on_ground = false;
+        if self.is_ghost {
+            match self.movement_mode {
+                MovementMode::Walk => self.vertical_velocity = 0.0,
+                MovementMode::Fly => self.camera.eye.y += self.vertical_velocity * delta_time,
             }
+            return;
+        }
+        if self.movement_mode == MovementMode::Walk {
+            let (gravity, terminal_velocity) = if self.in_water {
+                (GRAVITY * WATER_GRAVITY_FACTOR, WATER_TERMINAL_VELOCITY)
+            } else {
+                (GRAVITY, TERMINAL_VELOCITY)
+            };
+            self.vertical_velocity =
+                (self.vertical_velocity - gravity * delta_time).max(-terminal_velocity);
         }
 
-        let mut block_collision: Option<&CollisionBox> = None;
-        let mut max_distance = f32::MAX;
-        let mut point: Option<Vec3> = None;
-
-        for result in ray_results.iter() {
-            let mut closest_point = result.points[0];
-            if result.points[1].distance(self.camera.eye) < closest_point.distance(self.camera.eye)
-            {
-                closest_point = result.points[1];
-            }
+        let solid_blocks: Vec<CollisionBox> = world
+            .get_blocks_around(self.camera.eye)
+            .iter()
+            .filter_map(|block| {
+                let block = block.read().unwrap();
+                block
+                    .block_type
+                    .has_collision()
+                    .then(|| block.collision_box.clone())
+            })
+            .collect();
+        let mut displacement = self.vertical_velocity * delta_time;
+        if let Some((toi, _)) = self
+            .get_collision()
+            .sweep_all(Vec3::Y * displacement, solid_blocks.iter())
+        {
+            displacement *= toi;
+            self.vertical_velocity = 0.0;
+        }
+        self.camera.eye.y += displacement;
 
-            if closest_point.distance(self.camera.eye) < max_distance {
-                max_distance = closest_point.distance(self.camera.eye);
-                block_collision = Some(&result.collision);
-                point = Some(closest_point);
-            }
+        if self.movement_mode == MovementMode::Fly {
+            return;
         }
-        let mut face_direction = None;
-
-        match (block_collision, point) {
-            (Some(block_collision), Some(point)) => {
-                // TODO: This can be precomputed
-                let point_dir = ((block_collision.center() - point).normalize()) * -1.0;
-
-                let face_directions = FaceDirections::all();
-                let mut best_dot = -1.0;
-                for face in face_directions.iter() {
-                    let dot = point_dir.dot(face.get_normal_vector());
-                    if dot > best_dot {
-                        best_dot = dot;
-                        face_direction = Some(face);
-                    }
-                }
-                Some((block_collision.clone(), *face_direction.unwrap()))
-            }
-            _ => None,
+        // Checked every step, so walking off a ledge starts a fall. Landing puts the feet right on
+        // top of the block, the eye follows at its fixed height
+        let ground = match self.vertical_velocity <= 0.0 {
+            true => self.ground_below(&solid_blocks),
+            false => None,
+        };
+        self.on_ground = ground.is_some();
+        if let Some(top) = ground {
+            self.vertical_velocity = 0.0;
+            self.camera.eye.y = top + EYE_HEIGHT;
         }
     }
-    pub fn calc_current_chunk(&self) -> (i32, i32) {
-        (
-            f32::floor(self.camera.eye.x / CHUNK_SIZE as f32) as i32,
-            f32::floor(self.camera.eye.z / CHUNK_SIZE as f32) as i32,
-        )
+    // The physics only depend on the current position, so getting out of the water restores them
+    // right away. The view keeps a small margin at the surface
+    pub fn update_in_water(&mut self, world: &World) {
+        let eye = self.camera.eye;
+        let feet = vec3(
+            eye.x,
+            self.get_collision().min_y + GROUND_PROBE_DEPTH,
+            eye.z,
+        );
+        self.head_in_water = is_submerged(world, eye, self.head_in_water);
+        self.in_water = self.head_in_water || is_in_water(world, feet);
+    }
+    // Top of the highest block under the feet, probed with a thin box below the player
+    fn ground_below(&self, solid_blocks: &[CollisionBox]) -> Option<f32> {
+        let feet = self.get_collision().min_y;
+        let probe = CollisionBox::new(
+            self.camera.eye.x - PLAYER_WIDTH / 2.0,
+            feet - GROUND_PROBE_DEPTH,
+            self.camera.eye.z - PLAYER_WIDTH / 2.0,
+            PLAYER_WIDTH,
+            GROUND_PROBE_DEPTH,
+            PLAYER_WIDTH,
+        );
+        solid_blocks
+            .iter()
+            // Walls next to the player touch the probe too, their top is above the feet
+            .filter(|block| block.max_y <= feet + GROUND_PROBE_DEPTH && probe.intersects(block))
+            .map(|block| block.max_y)
+            .reduce(f32::max)
     }
 
     /* TODO: This probably can be optimized */
+    // Horizontal movement, the vertical one is done by `apply_physics`
     pub fn move_camera(
         &mut self,
         direction: &Vec3,
@@ -183,6 +415,16 @@ impl Player {
             velocity += right * CAMERA_SPEED * delta_time;
         }
 
+        if self.movement_mode == MovementMode::Fly {
+            velocity *= self.fly_speed_factor;
+            self.vertical_velocity = input_direction.y * FLY_SPEED;
+        } else if self.in_water {
+            velocity *= WATER_SPEED_FACTOR;
+            if input_direction.y > 0.0 {
+                self.vertical_velocity = SWIM_SPEED;
+            }
+        }
+
         /* Ignore collisions if ghost */
         if self.is_ghost {
             velocity *= 4.0;
@@ -190,70 +432,60 @@ impl Player {
             return;
         }
 
-        self.in_water = false;
-        let can_move_z = player_collision.clone() + glam::vec3(0.0, 0.0, velocity.z);
-        let can_move_x = player_collision.clone() + glam::vec3(velocity.x, 0.0, 0.0);
-
-        for block in blocks.iter() {
-            let block_read = block.read().unwrap();
-            if can_move_z.intersects(&block_read.collision_box)
-                && block_read.block_type != BlockType::Water
-            {
-                velocity.z = 0.0;
-            }
-
-            if can_move_x.intersects(&block_read.collision_box)
-                && block_read.block_type != BlockType::Water
-            {
-                velocity.x = 0.0;
-            }
-
-            if player_collision.intersects(&block_read.collision_box)
-                && block_read.block_type == BlockType::Water
-            {
-                self.in_water = true;
+        let solid_blocks: Vec<CollisionBox> = blocks
+            .iter()
+            .filter_map(|block| {
+                let block_read = block.read().unwrap();
+                block_read
+                    .block_type
+                    .has_collision()
+                    .then(|| block_read.collision_box.clone())
+            })
+            .collect();
+        // Moves one axis at a time, clamped to the first block it hits, so it slides along walls and
+        // can't skip over a block in a single (long) frame
+        let mut moved_collision = player_collision.clone();
+        for axis in [Vec3::Z, Vec3::X] {
+            let axis_velocity = velocity * axis;
+            if let Some((toi, _)) = moved_collision.sweep_all(axis_velocity, solid_blocks.iter()) {
+                velocity -= axis_velocity * (1.0 - toi);
             }
+            moved_collision = moved_collision + velocity * axis;
         }
 
-        velocity.y -= GRAVITY * delta_time;
-        self.on_ground = false;
-
-        if self.in_water {
-            // Slow down gravity in water
-            velocity.y *= 0.7;
-        }
-        if self.is_jumping {
-            let now = Instant::now();
-            let delta_jump = now
-                - self
-                    .jump_action_start
-                    .expect("If it's jumping this should be set");
-            if delta_jump <= *JUMP_DURATION {
-                velocity.y = JUMP_HEIGHT * delta_time * 10.0; /* Multiply by 10 bcs animation time is 0.1  */
-            } else {
-                self.is_jumping = false;
-                self.jump_action_start = None;
-            }
-        }
-
-        let can_move_y = player_collision.clone() + glam::vec3(0.0, velocity.y, 0.0);
-        for block in blocks.iter() {
-            if can_move_y.intersects(&block.read().unwrap().collision_box)
-                && block.read().unwrap().block_type != BlockType::Water
-            {
-                velocity.y = 0.0;
-                self.on_ground = true; // This can make it infinite to jump if there is a block above
-            }
-        }
-
-        // fly up
-        if input_direction.y > 0.0 {
-            velocity.y = 2.0;
-        }
-
-        self.camera.eye += velocity;
+        self.camera.eye += velocity * (Vec3::X + Vec3::Z);
+    }
+}
+// Crack texture over a block being broken, `progress` goes from 0 to 1
+pub fn crack_stage(progress: f32) -> u32 {
+    ((progress.clamp(0.0, 1.0) * CRACK_STAGES as f32) as u32).min(CRACK_STAGES - 1)
+}
+// Under the surface of a water block, which is lowered under the sky like its mesh
+fn is_in_water(world: &World, position: Vec3) -> bool {
+    if world.get_block(position) != Some(BlockType::Water) {
+        return false;
     }
+    let surface = position.y.floor() + 1.0 - WATER_SURFACE_DROP;
+    position.y < surface || world.get_block(position + Vec3::Y).is_some()
+}
+// Whether the eye is under the water, it has to cross the surface by `SURFACE_HYSTERESIS` to
+// change from `was_submerged`
+fn is_submerged(world: &World, eye: Vec3, was_submerged: bool) -> bool {
+    let margin = if was_submerged {
+        -SURFACE_HYSTERESIS
+    } else {
+        SURFACE_HYSTERESIS
+    };
+    is_in_water(world, eye + Vec3::Y * margin)
+}
+// How the camera projects the world, the orthographic one is for a top-down map view
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProjectionKind {
+    Perspective,
+    // Bigger zooms show less of the world
+    Orthographic { zoom: f32 },
 }
+
 pub struct Camera {
     pub eye: Vec3,
     pub yaw: f32,
@@ -262,7 +494,12 @@ pub struct Camera {
     pub fovy: f32,
     pub znear: f32,
     pub zfar: f32,
+    pub projection_kind: ProjectionKind,
     pub needs_update: bool,
+    // The fov, far plane, aspect ratio or projection kind changed, the projection has to be uploaded again
+    pub projection_dirty: bool,
+    // Placed where the save left it, the world doesn't move it to the spawn
+    pub from_save: bool,
     pub device: Arc<wgpu::Device>,
     pub queue: Arc<wgpu::Queue>,
     pub position_buffer: wgpu::Buffer,
@@ -271,17 +508,33 @@ pub struct Camera {
 }
 
 impl Camera {
+    pub fn get_position_bind_group_layout() -> wgpu::BindGroupLayoutDescriptor<'static> {
+        wgpu::BindGroupLayoutDescriptor {
+            label: Some("camera-position-layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                // The fragment shader uses it for the fog
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        }
+    }
     pub fn new(
         surface_width: f32,
         surface_height: f32,
         device: Arc<wgpu::Device>,
         queue: Arc<wgpu::Queue>,
+        archive: Option<Arc<WorldArchive>>,
     ) -> Camera {
-        let (eye, yaw, pitch) = if let Ok((eye, yaw, pitch)) = Camera::load(Box::new(())) {
-            (eye, yaw, pitch)
-        } else {
-            (glam::vec3(-4.0, 50.0, 4.0), consts::FRAC_PI_2, 0.0)
-        };
+        let saved = Camera::load(Box::new(archive));
+        let from_save = saved.is_ok();
+        let (eye, yaw, pitch) =
+            saved.unwrap_or((glam::vec3(-4.0, 50.0, 4.0), consts::FRAC_PI_2, 0.0));
 
         let position_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             contents: bytemuck::cast_slice(&[eye]),
@@ -289,19 +542,7 @@ impl Camera {
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
         let position_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: Some("camera-position-layout"),
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
-            });
+            device.create_bind_group_layout(&Camera::get_position_bind_group_layout());
         let position_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &position_bind_group_layout,
             label: Some("camera-position-layout"),
@@ -324,14 +565,73 @@ impl Camera {
             fovy: consts::FRAC_PI_4,
             znear: 0.1,
             zfar: 1000.,
+            projection_kind: ProjectionKind::Perspective,
             needs_update: false,
+            projection_dirty: false,
+            from_save,
         }
     }
     pub fn build_view_matrix(&self) -> glam::Mat4 {
         glam::Mat4::look_at_lh(self.eye, self.eye + self.get_forward_dir(), glam::Vec3::Y)
     }
     pub fn build_projection_matrix(&self) -> glam::Mat4 {
-        glam::Mat4::perspective_lh(self.fovy, self.aspect_ratio, self.znear, self.zfar)
+        match self.projection_kind {
+            ProjectionKind::Perspective => {
+                glam::Mat4::perspective_lh(self.fovy, self.aspect_ratio, self.znear, self.zfar)
+            }
+            ProjectionKind::Orthographic { zoom } => {
+                let (half_width, half_height) = self.ortho_half_size(zoom);
+                glam::Mat4::orthographic_lh(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    self.znear,
+                    self.zfar,
+                )
+            }
+        }
+    }
+    fn ortho_half_size(&self, zoom: f32) -> (f32, f32) {
+        let half_height = ORTHO_HALF_HEIGHT / zoom.max(f32::EPSILON);
+        (half_height * self.aspect_ratio, half_height)
+    }
+    pub fn set_projection_kind(&mut self, projection_kind: ProjectionKind) {
+        self.projection_kind = projection_kind;
+        self.projection_dirty = true;
+    }
+    // Vertical field of view in radians, for zooming or sprinting
+    pub fn set_fov(&mut self, fovy: f32) {
+        self.fovy = fovy.clamp(MIN_FOV, MAX_FOV);
+        self.projection_dirty = true;
+    }
+    // The far plane always stays beyond the near one
+    pub fn set_far(&mut self, zfar: f32) {
+        self.zfar = zfar.max(self.znear * 2.0);
+        self.projection_dirty = true;
+    }
+    pub(crate) fn frustum(&self) -> Frustum {
+        match self.projection_kind {
+            ProjectionKind::Perspective => Frustum::new(
+                self.eye,
+                self.get_forward_dir(),
+                self.fovy,
+                self.aspect_ratio,
+                self.znear,
+                self.zfar,
+            ),
+            ProjectionKind::Orthographic { zoom } => {
+                let (half_width, half_height) = self.ortho_half_size(zoom);
+                Frustum::orthographic(
+                    self.eye,
+                    self.get_forward_dir(),
+                    half_width,
+                    half_height,
+                    self.znear,
+                    self.zfar,
+                )
+            }
+        }
     }
     pub fn get_right_dir(&self) -> glam::Vec3 {
         glam::vec3(0.0, 1.0, 0.0).cross(self.get_forward_dir())
@@ -356,26 +656,149 @@ impl Camera {
     }
 }
 
-impl Saveable<glam::Vec3> for Camera {
-    fn save(&self) -> Result<(), Box<dyn Error>> {
-        if std::fs::create_dir("data").is_ok() {
-            println!("Created dir");
+/*
+Player file layout (player.dat in the save directory, little endian):
+  u8 format version
+  f32 x, y, z of the eye, f32 yaw, f32 pitch
+  u8 selected hotbar slot, u8 movement mode (0 walk, 1 fly)
+  f32 time of day */
+const PLAYER_FILE: &str = "player.dat";
+const PLAYER_FORMAT_VERSION: u8 = 1;
+
+// Where the player was, what it looked at and the time of the day, written by the autosave and
+// on exit
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlayerSave {
+    pub eye: Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub selected_slot: usize,
+    pub movement_mode: MovementMode,
+    pub time_of_day: f32,
+}
+
+impl PlayerSave {
+    pub fn new(player: &Player, time_of_day: f32) -> Self {
+        Self {
+            eye: player.camera.eye,
+            yaw: player.camera.yaw,
+            pitch: player.camera.pitch,
+            selected_slot: player.selected_slot,
+            movement_mode: player.movement_mode,
+            time_of_day,
+        }
+    }
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = vec![PLAYER_FORMAT_VERSION];
+        for value in [self.eye.x, self.eye.y, self.eye.z, self.yaw, self.pitch] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.push(self.selected_slot as u8);
+        data.push(match self.movement_mode {
+            MovementMode::Walk => 0,
+            MovementMode::Fly => 1,
+        });
+        data.extend_from_slice(&self.time_of_day.to_le_bytes());
+        data
+    }
+    pub fn decode(data: &[u8]) -> Result<PlayerSave, Box<dyn Error>> {
+        let mut reader = data;
+        let version = read_u8(&mut reader)?;
+        if version != PLAYER_FORMAT_VERSION {
+            return Err(format!("Unknown player format version {version}").into());
         }
-        let data = format!(
-            "{},{},{},{},{}",
-            self.eye.x, self.eye.y, self.eye.z, self.yaw, self.pitch
+        let eye = vec3(
+            read_f32(&mut reader)?,
+            read_f32(&mut reader)?,
+            read_f32(&mut reader)?,
         );
+        let yaw = read_f32(&mut reader)?;
+        let pitch = read_f32(&mut reader)?;
+        let selected_slot = read_u8(&mut reader)? as usize;
+        let movement_mode = match read_u8(&mut reader)? {
+            0 => MovementMode::Walk,
+            1 => MovementMode::Fly,
+            other => return Err(format!("Unknown movement mode {other}").into()),
+        };
+        Ok(PlayerSave {
+            eye,
+            yaw,
+            pitch,
+            selected_slot,
+            movement_mode,
+            time_of_day: read_f32(&mut reader)?,
+        })
+    }
+}
 
-        let player_file_name = "data/player";
-        std::fs::write(player_file_name, data.as_bytes())?;
-
+impl Saveable<PlayerSave> for PlayerSave {
+    fn save(&self) -> Result<(), Box<dyn Error>> {
+        let dir = save_dir();
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(format!("{dir}/{PLAYER_FILE}"), self.encode())?;
         Ok(())
     }
 }
 
+impl Loadable<PlayerSave> for PlayerSave {
+    fn load(args: Box<dyn Any>) -> Result<PlayerSave, Box<dyn Error>> {
+        let archive = args
+            .downcast::<Option<Arc<WorldArchive>>>()
+            .map(|a| *a)
+            .unwrap_or(None);
+        PlayerSave::decode(&read_save_file(
+            &save_dir(),
+            PLAYER_FILE,
+            archive.as_deref(),
+        )?)
+    }
+}
+
+// The loose files below are from the saves before player.dat
+impl Loadable<usize> for Player {
+    fn load(args: Box<dyn Any>) -> Result<usize, Box<dyn Error>> {
+        let archive = args
+            .downcast::<Option<Arc<WorldArchive>>>()
+            .map(|a| *a)
+            .unwrap_or(None);
+        if let Ok(save) = PlayerSave::load(Box::new(archive.clone())) {
+            return Ok(save.selected_slot);
+        }
+        let data = String::from_utf8(read_save_file(&save_dir(), "hotbar", archive.as_deref())?)?;
+
+        Ok(data.trim().parse::<usize>()?)
+    }
+}
+
+impl Loadable<MovementMode> for Player {
+    fn load(args: Box<dyn Any>) -> Result<MovementMode, Box<dyn Error>> {
+        let archive = args
+            .downcast::<Option<Arc<WorldArchive>>>()
+            .map(|a| *a)
+            .unwrap_or(None);
+        if let Ok(save) = PlayerSave::load(Box::new(archive.clone())) {
+            return Ok(save.movement_mode);
+        }
+        let data = String::from_utf8(read_save_file(&save_dir(), "movement", archive.as_deref())?)?;
+
+        match data.trim() {
+            "walk" => Ok(MovementMode::Walk),
+            "fly" => Ok(MovementMode::Fly),
+            other => Err(format!("Unknown movement mode {other}").into()),
+        }
+    }
+}
+
 impl Loadable<(glam::Vec3, f32, f32)> for Camera {
-    fn load(_: Box<dyn Any>) -> Result<(Vec3, f32, f32), Box<dyn Error>> {
-        let data = String::from_utf8(std::fs::read("data/player")?)?;
+    fn load(args: Box<dyn Any>) -> Result<(Vec3, f32, f32), Box<dyn Error>> {
+        let archive = args
+            .downcast::<Option<Arc<WorldArchive>>>()
+            .map(|a| *a)
+            .unwrap_or(None);
+        if let Ok(save) = PlayerSave::load(Box::new(archive.clone())) {
+            return Ok((save.eye, save.yaw, save.pitch));
+        }
+        let data = String::from_utf8(read_save_file(&save_dir(), "player", archive.as_deref())?)?;
         let mut data = data.split(',');
         let x = data.next().unwrap().parse::<f32>().unwrap();
         let y = data.next().unwrap().parse::<f32>().unwrap();
@@ -386,3 +809,562 @@ impl Loadable<(glam::Vec3, f32, f32)> for Camera {
         Ok((glam::vec3(x, y, z), yaw, pitch))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Camera, MovementMode, Player, PlayerSave, ProjectionKind, MAX_FOV, MIN_FOV};
+    use crate::blocks::block::Block;
+    use crate::blocks::block_type::BlockType;
+    use crate::chunk::{insert_block, BlockVec, Chunk, ChunkBlocks, ChunkData};
+    use crate::collision::CollisionBox;
+    use crate::utils::test_device;
+    use crate::world::{
+        World, DEFAULT_CHUNK_SIZE, RNG_SEED, SPAWN_POINT, WORLD_MAX_Y, WORLD_MIN_Y,
+    };
+    use std::sync::{Arc, RwLock};
+    use std::time::{Duration, Instant};
+
+    const FRAME: f32 = 1.0 / 60.0;
+
+    #[test]
+    fn should_round_trip_the_player_file() {
+        let save = PlayerSave {
+            eye: glam::vec3(-12.5, 71.25, 300.0),
+            yaw: 1.2,
+            pitch: -0.4,
+            selected_slot: 3,
+            movement_mode: MovementMode::Fly,
+            time_of_day: 0.75,
+        };
+        let data = save.encode();
+        assert_eq!(data.len(), 1 + 5 * 4 + 2 + 4);
+        assert_eq!(PlayerSave::decode(&data).unwrap(), save);
+        assert!(PlayerSave::decode(&data[..data.len() - 1]).is_err());
+        let mut unknown_mode = data.clone();
+        unknown_mode[22] = 7;
+        assert!(PlayerSave::decode(&unknown_mode).is_err());
+    }
+
+    #[test]
+    fn should_break_blocks_by_hardness_and_start_over_on_another_block() {
+        let Some((device, queue)) = test_device() else {
+            println!("No adapter available, skipping mining test");
+            return;
+        };
+        let camera = Camera::new(1.0, 1.0, device, queue, None);
+        let mut player = Player::new(camera, None);
+        let block = |x: f32, block_type| {
+            Arc::new(RwLock::new(Block::new(
+                glam::vec3(x, 1.0, 0.0),
+                (0, 0),
+                block_type,
+                DEFAULT_CHUNK_SIZE,
+            )))
+        };
+        // Frames holding the button until it breaks, None if it never does
+        let frames_to_break = |player: &mut Player, target| {
+            player.facing_block = Some(target);
+            (1..1000).find(|_| player.mine(FRAME).is_some())
+        };
+
+        let stone = frames_to_break(&mut player, block(0.0, BlockType::Stone)).unwrap();
+        let dirt = frames_to_break(&mut player, block(1.0, BlockType::Dirt)).unwrap();
+        assert!(dirt < stone);
+        assert_eq!(
+            frames_to_break(&mut player, block(2.0, BlockType::Leaf)),
+            Some(1)
+        );
+        assert_eq!(
+            frames_to_break(&mut player, block(3.0, BlockType::Water)),
+            None
+        );
+        assert!(player.breaking.is_none());
+        assert_eq!(
+            frames_to_break(&mut player, block(3.0, BlockType::Bedrock)),
+            None
+        );
+
+        // Half way into a stone, then another one from the start
+        player.facing_block = Some(block(4.0, BlockType::Stone));
+        for _ in 0..stone / 2 {
+            assert!(player.mine(FRAME).is_none());
+        }
+        assert!(player.breaking.as_ref().unwrap().1 > 0.4);
+        player.facing_block = Some(block(5.0, BlockType::Stone));
+        player.mine(FRAME);
+        assert!(player.breaking.as_ref().unwrap().1 < 0.1);
+    }
+
+    #[test]
+    fn should_narrow_the_frustum_with_the_fov_and_flag_the_projection() {
+        let Some((device, queue)) = test_device() else {
+            println!("No adapter available, skipping fov test");
+            return;
+        };
+        let mut camera = Camera::new(1.0, 1.0, device, queue, None);
+        (camera.eye, camera.yaw, camera.pitch) = (glam::Vec3::ZERO, 0.0, 0.0);
+        // 20 blocks ahead and 15 to the side, looking along x
+        let side_box = CollisionBox {
+            min_x: 20.0,
+            max_x: 21.0,
+            min_y: -0.5,
+            max_y: 0.5,
+            min_z: 15.0,
+            max_z: 16.0,
+        };
+
+        camera.set_fov(std::f32::consts::FRAC_PI_2);
+        assert!(camera.projection_dirty);
+        assert!(camera.frustum().intersects_aabb(&side_box));
+        // The half width 20 blocks away goes from 20 down to 20 * tan(15°) ~= 5.4
+        camera.projection_dirty = false;
+        camera.set_fov(std::f32::consts::FRAC_PI_6);
+        assert!(camera.projection_dirty);
+        assert!(!camera.frustum().intersects_aabb(&side_box));
+
+        camera.set_fov(0.0);
+        assert_eq!(camera.fovy, MIN_FOV);
+        camera.set_fov(std::f32::consts::PI);
+        assert_eq!(camera.fovy, MAX_FOV);
+        // A far plane closer than the box hides it
+        camera.set_fov(std::f32::consts::FRAC_PI_2);
+        camera.set_far(10.0);
+        assert!(!camera.frustum().intersects_aabb(&side_box));
+        camera.set_far(-1.0);
+        assert!(camera.zfar > camera.znear);
+    }
+
+    #[test]
+    fn should_switch_to_an_orthographic_projection_and_cull_with_a_box() {
+        let Some((device, queue)) = test_device() else {
+            println!("No adapter available, skipping projection test");
+            return;
+        };
+        let mut camera = Camera::new(1.0, 1.0, device, queue, None);
+        // Looking down on the map from above
+        let eye = glam::vec3(0.0, 100.0, 0.0);
+        (camera.eye, camera.yaw, camera.pitch) = (eye, 0.0, -std::f32::consts::FRAC_PI_2);
+        let view_projection =
+            |camera: &Camera| camera.build_projection_matrix() * camera.build_view_matrix();
+        let visible = |camera: &Camera, min: glam::Vec3| {
+            camera.frustum().intersects_aabb(&CollisionBox {
+                min_x: min.x,
+                max_x: min.x + 1.0,
+                min_y: min.y,
+                max_y: min.y + 1.0,
+                min_z: min.z,
+                max_z: min.z + 1.0,
+            })
+        };
+        // 50 blocks to the side on the ground, out of a 45° fov but within the ortho half height
+        let side = glam::vec3(50.0, 0.0, 0.0);
+        // Over the eye, behind the near plane
+        let behind = eye + glam::vec3(0.0, 10.0, 0.0);
+
+        assert_eq!(camera.build_projection_matrix().row(3), glam::Vec4::Z);
+        assert!(!visible(&camera, side));
+        assert!(!visible(&camera, behind));
+        assert!((view_projection(&camera) * behind.extend(1.0)).w < 0.0);
+
+        camera.set_projection_kind(ProjectionKind::Orthographic { zoom: 1.0 });
+        assert!(camera.projection_dirty);
+        // No perspective divide, w stays 1
+        assert_eq!(camera.build_projection_matrix().row(3), glam::Vec4::W);
+        assert!(visible(&camera, side));
+        assert!(!visible(&camera, behind));
+        let clip = view_projection(&camera) * behind.extend(1.0);
+        assert_eq!(clip.w, 1.0);
+        assert!(clip.z < 0.0);
+        // Zooming in shows less
+        camera.set_projection_kind(ProjectionKind::Orthographic { zoom: 2.0 });
+        assert!(!visible(&camera, side));
+        assert!(visible(&camera, side / 2.0));
+    }
+
+    #[test]
+    fn should_only_select_blocks_within_reach() {
+        let Some((device, queue)) = test_device() else {
+            println!("No adapter available, skipping reach test");
+            return;
+        };
+        // Half a block inside and outside of the reach, looking along x
+        let world = world_with(
+            device.clone(),
+            queue.clone(),
+            &[
+                (glam::vec3(5.0, 1.0, 3.0), BlockType::Stone),
+                (glam::vec3(6.0, 1.0, 5.0), BlockType::Stone),
+            ],
+        );
+        let mut camera = Camera::new(1.0, 1.0, device, queue, None);
+        (camera.yaw, camera.pitch) = (0.0, 0.0);
+        let mut player = Player::new(camera, None);
+        assert_eq!(player.reach, super::REACH_DISTANCE);
+        let look_from = |player: &mut Player, eye: glam::Vec3| {
+            player.camera.eye = eye - super::PLAYER_VIEW_OFFSET;
+            player.update_facing(&world);
+        };
+
+        look_from(&mut player, glam::vec3(0.5, 1.5, 3.5));
+        let block = player.facing_block.as_ref().unwrap();
+        assert_eq!(block.read().unwrap().absolute_position.x, 5.0);
+        assert_eq!(player.facing_face, Some(super::FaceDirections::Left));
+
+        look_from(&mut player, glam::vec3(0.5, 1.5, 5.5));
+        assert!(player.facing_block.is_none());
+        assert!(player.facing_face.is_none());
+        // The ray would hit it further on
+        let origin = glam::vec3(0.5, 1.5, 5.5);
+        assert!(world.raycast(origin, glam::Vec3::X, 8.0).is_some());
+        // Nothing to break either
+        assert!(player.mine(1.0).is_none());
+        assert!(player.breaking.is_none());
+
+        // Exactly at the reach is still in it, a bit further isn't
+        look_from(&mut player, glam::vec3(0.0, 1.5, 3.5));
+        assert!(player.facing_block.is_some());
+        look_from(&mut player, glam::vec3(-0.01, 1.5, 3.5));
+        assert!(player.facing_block.is_none());
+
+        // A longer reach gets to the far block
+        player.reach = 8.0;
+        look_from(&mut player, glam::vec3(0.5, 1.5, 5.5));
+        assert!(player.facing_block.is_some());
+    }
+
+    #[test]
+    fn should_step_through_the_crack_stages_as_the_block_breaks() {
+        use super::{crack_stage, CRACK_STAGES};
+        assert_eq!(crack_stage(0.0), 0);
+        assert_eq!(crack_stage(0.09), 0);
+        assert_eq!(crack_stage(0.1), 1);
+        assert_eq!(crack_stage(0.55), 5);
+        assert_eq!(crack_stage(0.9), 9);
+        // Broken, or any rounding past it, stays on the last stage
+        assert_eq!(crack_stage(1.0), CRACK_STAGES - 1);
+        assert_eq!(crack_stage(1.5), CRACK_STAGES - 1);
+        assert_eq!(crack_stage(-0.1), 0);
+        // Every stage shows up, in order
+        let stages: Vec<_> = (0..100).map(|i| crack_stage(i as f32 / 100.0)).collect();
+        assert!(stages.windows(2).all(|w| w[1] == w[0] || w[1] == w[0] + 1));
+        assert_eq!(*stages.last().unwrap(), CRACK_STAGES - 1);
+    }
+
+    // A world with a single stone block at (3, 1, 3), its top is at y = 2
+    fn world_with_a_block(device: Arc<wgpu::Device>, queue: Arc<wgpu::Queue>) -> World {
+        world_with(
+            device,
+            queue,
+            &[(glam::vec3(3.0, 1.0, 3.0), BlockType::Stone)],
+        )
+    }
+    // Only these blocks, all in the chunk at (0, 0)
+    fn world_with(
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        placed: &[(glam::Vec3, BlockType)],
+    ) -> World {
+        let world = World::with_seed(device.clone(), queue.clone(), None, false, RNG_SEED);
+        let blocks: BlockVec = Arc::new(RwLock::new(ChunkBlocks::new((0, 0), DEFAULT_CHUNK_SIZE)));
+        for (position, block_type) in placed {
+            let block = Block::new(*position, (0, 0), *block_type, DEFAULT_CHUNK_SIZE);
+            insert_block(&blocks, Arc::new(RwLock::new(block)));
+        }
+        let data = ChunkData {
+            x: 0,
+            y: 0,
+            blocks,
+            outside_blocks: vec![],
+        };
+        let chunk = Chunk::from_data(
+            data,
+            world.noise_data.clone(),
+            device.clone(),
+            queue.clone(),
+            world.chunk_data_layout.clone(),
+            None,
+        );
+        world
+            .chunks
+            .write()
+            .unwrap()
+            .insert((0, 0), Arc::new(RwLock::new(chunk)));
+        world
+    }
+
+    #[test]
+    fn should_land_on_top_of_a_floor_and_only_jump_from_the_ground() {
+        let Some((device, queue)) = test_device() else {
+            println!("No adapter available, skipping physics test");
+            return;
+        };
+        let world = world_with_a_block(device.clone(), queue.clone());
+
+        let mut camera = Camera::new(1.0, 1.0, device, queue, None);
+        camera.eye = glam::vec3(3.5, 6.0, 3.5);
+        let mut player = Player::new(camera, None);
+        let feet = |player: &Player| player.get_collision().min_y;
+
+        // Falling, it can't jump
+        player.apply_physics(FRAME, &world);
+        let falling_velocity = player.vertical_velocity;
+        assert!(falling_velocity < 0.0 && !player.on_ground);
+        player.jump();
+        assert_eq!(player.vertical_velocity, falling_velocity);
+
+        for _ in 0..120 {
+            player.apply_physics(FRAME, &world);
+        }
+        assert!(player.on_ground);
+        assert!((feet(&player) - 2.0).abs() < 1e-4);
+        assert_eq!(player.vertical_velocity, 0.0);
+
+        // Jumps and lands back on the same block
+        player.jump();
+        player.apply_physics(FRAME, &world);
+        assert!(!player.on_ground && feet(&player) > 2.0);
+        for _ in 0..120 {
+            player.apply_physics(FRAME, &world);
+        }
+        assert!(player.on_ground);
+        assert!((feet(&player) - 2.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn should_start_falling_when_walking_off_a_ledge() {
+        let Some((device, queue)) = test_device() else {
+            println!("No adapter available, skipping physics test");
+            return;
+        };
+        let world = world_with_a_block(device.clone(), queue.clone());
+
+        // Slightly above the block, a long frame lands right on its top
+        let mut camera = Camera::new(1.0, 1.0, device, queue, None);
+        camera.eye = glam::vec3(3.5, 2.03 + super::EYE_HEIGHT, 3.5);
+        let mut player = Player::new(camera, None);
+        player.apply_physics(0.1, &world);
+        assert!(player.on_ground);
+        assert_eq!(player.get_collision().min_y, 2.0);
+        assert_eq!(player.camera.eye.y, 2.0 + super::EYE_HEIGHT);
+
+        // Next to the block, without jumping
+        player.camera.eye.x += 1.0;
+        player.apply_physics(FRAME, &world);
+        assert!(!player.on_ground);
+        assert!(player.vertical_velocity < 0.0);
+        assert!(player.get_collision().min_y < 2.0);
+    }
+
+    #[test]
+    fn should_fly_on_a_double_tap_without_going_through_blocks() {
+        let Some((device, queue)) = test_device() else {
+            println!("No adapter available, skipping physics test");
+            return;
+        };
+        let world = world_with_a_block(device.clone(), queue.clone());
+        let mut camera = Camera::new(1.0, 1.0, device, queue, None);
+        camera.eye = glam::vec3(3.5, 10.0, 3.5);
+        let mut player = Player::new(camera, None);
+        let start = Instant::now();
+
+        // Too slow for a double tap
+        player.press_jump(start);
+        player.press_jump(start + Duration::from_secs(1));
+        assert_eq!(player.movement_mode, MovementMode::Walk);
+        player.press_jump(start + Duration::from_millis(1100));
+        assert_eq!(player.movement_mode, MovementMode::Fly);
+
+        // Hovers without input, flies faster and goes up and down
+        let height = player.camera.eye.y;
+        player.apply_physics(FRAME, &world);
+        assert_eq!(player.camera.eye.y, height);
+        let walked = {
+            let before = player.camera.eye;
+            player.movement_mode = MovementMode::Walk;
+            player.move_camera(&glam::vec3(0.0, 0.0, 1.0), FRAME, &vec![]);
+            player.movement_mode = MovementMode::Fly;
+            player.camera.eye - before
+        };
+        let before = player.camera.eye;
+        player.move_camera(&glam::vec3(0.0, 1.0, 1.0), FRAME, &vec![]);
+        let flown = player.camera.eye - before;
+        assert!((flown.length() - walked.length() * player.fly_speed_factor).abs() < 1e-4);
+        player.apply_physics(FRAME, &world);
+        assert!(player.camera.eye.y > height);
+
+        // Lands on the block instead of flying through it
+        player.move_camera(&glam::vec3(0.0, -1.0, 0.0), FRAME, &vec![]);
+        for _ in 0..120 {
+            player.apply_physics(FRAME, &world);
+        }
+        assert!((player.get_collision().min_y - 2.0).abs() < 1e-4);
+
+        // Back to walking mid-air, gravity starts from zero
+        player.camera.eye.y = 10.0;
+        player.vertical_velocity = -super::FLY_SPEED;
+        player.toggle_flying();
+        assert_eq!(player.movement_mode, MovementMode::Walk);
+        player.apply_physics(FRAME, &world);
+        assert!((player.vertical_velocity + super::GRAVITY * FRAME).abs() < 1e-5);
+    }
+
+    #[test]
+    fn should_only_refuse_placing_blocks_inside_the_player() {
+        let Some((device, queue)) = test_device() else {
+            println!("No adapter available, skipping placement test");
+            return;
+        };
+        let mut camera = Camera::new(1.0, 1.0, device, queue, None);
+        // Standing on a block boundary with rounding errors, the box spans a hair under
+        // x = 3..3.8 and y = 2..4, flooring its corners would take cells x = 2 and y = 1 too
+        camera.eye = glam::vec3(3.4 - 1e-5, 2.0 + super::EYE_HEIGHT - 1e-5, 3.4);
+        let player = Player::new(camera, None);
+        assert!(player.get_collision().min_x < 3.0 && player.get_collision().min_y < 2.0);
+
+        for (position, overlaps) in [
+            // Feet and head
+            ((3.0, 2.0, 3.0), true),
+            ((3.0, 3.0, 3.0), true),
+            // Under the feet, over the head and next to the player
+            ((3.0, 1.0, 3.0), false),
+            ((3.0, 4.0, 3.0), false),
+            ((2.0, 2.0, 3.0), false),
+            ((4.0, 2.0, 3.0), false),
+            ((3.0, 2.0, 2.0), false),
+        ] {
+            let position = glam::Vec3::from(position);
+            assert_eq!(player.overlaps_block(position), overlaps, "{position}");
+        }
+    }
+
+    #[test]
+    fn should_cap_the_falling_speed() {
+        let Some((device, queue)) = test_device() else {
+            println!("No adapter available, skipping physics test");
+            return;
+        };
+        let world = World::with_seed(device.clone(), queue.clone(), None, false, RNG_SEED);
+        let mut player = Player::new(Camera::new(1.0, 1.0, device, queue, None), None);
+        // High enough not to fall out of the world
+        player.camera.eye.y = WORLD_MAX_Y as f32;
+
+        for _ in 0..600 {
+            player.apply_physics(FRAME, &world);
+        }
+        assert_eq!(player.vertical_velocity, -super::TERMINAL_VELOCITY);
+    }
+
+    #[test]
+    fn should_respawn_after_falling_out_of_the_world() {
+        let Some((device, queue)) = test_device() else {
+            println!("No adapter available, skipping physics test");
+            return;
+        };
+        let world = World::with_seed(device.clone(), queue.clone(), None, false, RNG_SEED);
+        let mut player = Player::new(Camera::new(1.0, 1.0, device, queue, None), None);
+        let back_at_the_spawn = |player: &Player| {
+            player.camera.eye.x == SPAWN_POINT.x
+                && player.camera.eye.z == SPAWN_POINT.z
+                && (player.camera.eye.y - SPAWN_POINT.y).abs() < 0.1
+                && player.vertical_velocity > -1.0
+        };
+
+        // Still falling right under the bottom
+        player.camera.eye = glam::vec3(5.0, WORLD_MIN_Y as f32 - 10.0, 5.0);
+        player.apply_physics(FRAME, &world);
+        assert!(!back_at_the_spawn(&player));
+
+        player.camera.eye.y = WORLD_MIN_Y as f32 - super::VOID_DEPTH - 1.0;
+        player.vertical_velocity = -super::TERMINAL_VELOCITY;
+        player.apply_physics(FRAME, &world);
+        assert!(back_at_the_spawn(&player));
+
+        player.camera.eye = glam::Vec3::NAN;
+        player.apply_physics(FRAME, &world);
+        assert!(back_at_the_spawn(&player));
+    }
+
+    #[test]
+    fn should_swim_up_and_get_normal_gravity_back_out_of_the_water() {
+        let Some((device, queue)) = test_device() else {
+            println!("No adapter available, skipping physics test");
+            return;
+        };
+        // A pool 4 blocks deep under the sky, and a block of water under glass
+        let mut placed: Vec<_> = (0..4)
+            .map(|y| (glam::vec3(3.0, y as f32, 3.0), BlockType::Water))
+            .collect();
+        placed.push((glam::vec3(6.0, 1.0, 6.0), BlockType::Water));
+        placed.push((glam::vec3(6.0, 2.0, 6.0), BlockType::Glass));
+        let world = world_with(device.clone(), queue.clone(), &placed);
+
+        // The surface is lowered under the sky only
+        let surface = 4.0 - crate::chunk::WATER_SURFACE_DROP;
+        assert!(super::is_in_water(
+            &world,
+            glam::vec3(3.5, surface - 0.01, 3.5)
+        ));
+        assert!(!super::is_in_water(
+            &world,
+            glam::vec3(3.5, surface + 0.01, 3.5)
+        ));
+        assert!(super::is_in_water(&world, glam::vec3(6.5, 1.99, 6.5)));
+
+        let mut camera = Camera::new(1.0, 1.0, device, queue, None);
+        camera.eye = glam::vec3(3.5, 2.5, 3.5);
+        let mut player = Player::new(camera, None);
+        player.apply_physics(FRAME, &world);
+        assert!(player.in_water && player.head_in_water);
+
+        // Holding jump, the eye gets out first and the feet keep swimming
+        let swim_up = glam::vec3(0.0, 1.0, 0.0);
+        while player.head_in_water {
+            let blocks = world.get_blocks_around(player.camera.eye);
+            player.move_camera(&swim_up, FRAME, &blocks);
+            player.apply_physics(FRAME, &world);
+            assert!(player.vertical_velocity > 0.0);
+        }
+        assert!(player.in_water);
+        assert!(player.camera.eye.y >= surface);
+
+        // Out of the water the same step
+        player.camera.eye.y = 10.0;
+        player.vertical_velocity = 0.0;
+        player.apply_physics(FRAME, &world);
+        assert!(!player.in_water && !player.head_in_water);
+        assert_eq!(player.vertical_velocity, -super::GRAVITY * FRAME);
+    }
+
+    #[test]
+    fn should_keep_the_view_until_the_eye_clears_the_water_surface() {
+        let Some((device, queue)) = test_device() else {
+            println!("No adapter available, skipping submerged test");
+            return;
+        };
+        let placed: Vec<_> = (0..4)
+            .map(|y| (glam::vec3(3.0, y as f32, 3.0), BlockType::Water))
+            .collect();
+        let world = world_with(device, queue, &placed);
+        let surface = 4.0 - crate::chunk::WATER_SURFACE_DROP;
+        let eye = |offset: f32| glam::vec3(3.5, surface + offset, 3.5);
+        let inside = super::SURFACE_HYSTERESIS / 2.0;
+        let past = super::SURFACE_HYSTERESIS * 2.0;
+
+        // Well under and well above the surface it doesn't matter where the eye was
+        for was_submerged in [false, true] {
+            assert!(super::is_submerged(&world, eye(-past), was_submerged));
+            assert!(!super::is_submerged(&world, eye(past), was_submerged));
+        }
+        // Right at the surface, it stays as it was
+        for offset in [-inside, inside] {
+            assert!(super::is_submerged(&world, eye(offset), true));
+            assert!(!super::is_submerged(&world, eye(offset), false));
+        }
+        // Deep in the pool, the block above is water too
+        assert!(super::is_submerged(
+            &world,
+            glam::vec3(3.5, 1.01, 3.5),
+            false
+        ));
+    }
+}
@@ -0,0 +1,190 @@
+use std::error::Error;
+use std::path::Path;
+
+// A copy of a texture into a buffer the cpu can read, recorded with the frame that draws it
+pub struct TextureReadback {
+    buffer: wgpu::Buffer,
+    width: u32,
+    height: u32,
+    // Rows of the buffer are padded to COPY_BYTES_PER_ROW_ALIGNMENT
+    padded_bytes_per_row: u32,
+    // The red and blue channels are swapped in bgra formats, the usual ones for a swapchain
+    bgra: bool,
+}
+
+impl TextureReadback {
+    // The texture needs the COPY_SRC usage and an 8 bit rgba or bgra format
+    pub fn new(
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+    ) -> Result<Self, Box<dyn Error>> {
+        let bgra = match texture.format() {
+            wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+            format => return Err(format!("Can't read back a {format:?} texture").into()),
+        };
+        if !texture.usage().contains(wgpu::TextureUsages::COPY_SRC) {
+            return Err("The texture can't be copied from".into());
+        }
+        let (width, height) = (texture.width(), texture.height());
+        let padded_bytes_per_row = padded_bytes_per_row(width);
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback"),
+            size: (padded_bytes_per_row * height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            texture.size(),
+        );
+        Ok(Self {
+            buffer,
+            width,
+            height,
+            padded_bytes_per_row,
+            bgra,
+        })
+    }
+    // Waits for the copy, call it after submitting the encoder given to `new`
+    pub fn read(self, device: &wgpu::Device) -> Result<image::RgbaImage, Box<dyn Error>> {
+        let slice = self.buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device.poll(wgpu::Maintain::Wait);
+        receiver.recv()??;
+
+        let pixels = unpad_rows(
+            &slice.get_mapped_range(),
+            self.width,
+            self.height,
+            self.padded_bytes_per_row,
+            self.bgra,
+        );
+        self.buffer.unmap();
+        image::RgbaImage::from_raw(self.width, self.height, pixels)
+            .ok_or_else(|| "The readback doesn't fit the image".into())
+    }
+}
+
+// Copies the texture on its own submission and reads it back
+pub fn read_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+) -> Result<image::RgbaImage, Box<dyn Error>> {
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("readback"),
+    });
+    let readback = TextureReadback::new(device, &mut encoder, texture)?;
+    queue.submit(Some(encoder.finish()));
+    readback.read(device)
+}
+
+pub fn save_png(image: &image::RgbaImage, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+    image.save_with_format(path, image::ImageFormat::Png)?;
+    Ok(())
+}
+
+fn padded_bytes_per_row(width: u32) -> u32 {
+    let alignment = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    (width * 4).div_ceil(alignment) * alignment
+}
+
+// Tightly packed rgba rows, without the padding at their end
+fn unpad_rows(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    padded_bytes_per_row: u32,
+    bgra: bool,
+) -> Vec<u8> {
+    let row_bytes = (width * 4) as usize;
+    let mut pixels = Vec::with_capacity(row_bytes * height as usize);
+    for row in data
+        .chunks(padded_bytes_per_row as usize)
+        .take(height as usize)
+    {
+        pixels.extend_from_slice(&row[..row_bytes]);
+    }
+    if bgra {
+        for pixel in pixels.chunks_exact_mut(4) {
+            pixel.swap(0, 2);
+        }
+    }
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{padded_bytes_per_row, read_texture, save_png};
+    use crate::utils::test_device;
+
+    #[test]
+    fn should_save_a_cleared_frame_with_its_color() {
+        let Some((device, queue)) = test_device() else {
+            println!("No adapter available, skipping screenshot test");
+            return;
+        };
+        // 30 pixels are 120 bytes, the rows get padded to 256
+        let (width, height) = (30, 20);
+        assert_eq!(padded_bytes_per_row(width), 256);
+        // Like a swapchain texture
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Bgra8UnormSrgb,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color {
+                        r: 1.0,
+                        g: 0.0,
+                        b: 0.0,
+                        a: 1.0,
+                    }),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        queue.submit(Some(encoder.finish()));
+
+        let path =
+            std::env::temp_dir().join(format!("diorama-screenshot-{}.png", std::process::id()));
+        save_png(&read_texture(&device, &queue, &texture).unwrap(), &path).unwrap();
+        let saved = image::open(&path).unwrap().to_rgba8();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(saved.dimensions(), (width, height));
+        assert!(saved.pixels().all(|p| p.0 == [255, 0, 0, 255]));
+    }
+}
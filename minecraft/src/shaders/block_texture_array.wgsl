@@ -0,0 +1,14 @@
+// Block textures as a texture array, a layer per tile (Texture::from_atlas_bytes_as_array). Put in
+// front of the shaders that draw blocks
+
+@group(0) @binding(3)
+var diffuse: texture_2d_array<f32>;
+@group(0) @binding(4)
+var t_sampler: sampler;
+
+// `uv` goes from 0 to 1 across the texture and repeats past 1 when `repeat`. The gradients come
+// from the unwrapped coordinates so the repeat doesn't jump to the smallest mip
+fn sample_block_texture(layer: u32, uv: vec2<f32>, repeat: bool) -> vec4<f32> {
+    let local = select(clamp(uv, vec2<f32>(0.0), vec2<f32>(1.0)), fract(uv), repeat);
+    return textureSampleGrad(diffuse, t_sampler, local, layer, dpdx(uv), dpdy(uv));
+}
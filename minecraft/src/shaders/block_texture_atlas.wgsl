@@ -0,0 +1,28 @@
+// Block textures as a single atlas, with the `atlas` feature. Put in front of the shaders that
+// draw blocks
+
+@group(0) @binding(3)
+var diffuse: texture_2d<f32>;
+@group(0) @binding(4)
+var t_sampler: sampler;
+
+// Size of a block's texture in the atlas (BLOCK_OFFSET_NORMALIZED)
+const tile_size = 0.125;
+const tiles_per_row = 8u;
+
+// `uv` goes from 0 to 1 across the layer's tile and repeats past 1 when `repeat`. The gradients
+// come from the unwrapped coordinates so the repeat doesn't jump to the smallest mip, and the
+// position stays half a texel of the sampled level away from the next tiles
+fn sample_block_texture(layer: u32, uv: vec2<f32>, repeat: bool) -> vec4<f32> {
+    let tile_origin = vec2<f32>(f32(layer % tiles_per_row), f32(layer / tiles_per_row)) * tile_size;
+    let ddx = dpdx(uv) * tile_size;
+    let ddy = dpdy(uv) * tile_size;
+    let atlas_size = f32(textureDimensions(diffuse).x);
+    let footprint = max(length(ddx), length(ddy)) * atlas_size;
+    // The atlas stops at a texel per tile, Texture::mip_level_count(BLOCK_OFFSET)
+    let lod = clamp(log2(max(footprint, 1.0)), 0.0, log2(tile_size * atlas_size));
+    let half_texel = exp2(ceil(lod)) * 0.5 / atlas_size;
+    let local = select(clamp(uv, vec2<f32>(0.0), vec2<f32>(1.0)), fract(uv), repeat);
+    let inside = clamp(local * tile_size, vec2<f32>(half_texel), vec2<f32>(tile_size - half_texel));
+    return textureSampleGrad(diffuse, t_sampler, tile_origin + inside, ddx, ddy);
+}
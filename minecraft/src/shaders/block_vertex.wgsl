@@ -0,0 +1,52 @@
+// Chunk mesh vertices, packed by BlockVertexPacked (blocks/block.rs). Put in front of the shaders
+// that draw the chunks
+
+struct BlockVertex {
+    position: vec3<f32>,
+    normal: vec3<f32>,
+    tex_coords: vec2<f32>,
+    ao: f32,
+    light: f32,
+    // Texture of the face, sampled with sample_block_texture
+    layer: u32,
+    tiling: f32,
+    sky_light: f32,
+    water: vec2<f32>,
+}
+
+fn unpack_vertex(data: vec4<u32>) -> BlockVertex {
+    var out: BlockVertex;
+    out.position = vec3<f32>(
+        f32(data.x & 0xffffu) / 256.0,
+        f32(data.y & 0xffffu) / 128.0,
+        f32(data.x >> 16u) / 256.0,
+    ) - 0.5;
+    out.light = f32((data.y >> 16u) & 0xffu) / 255.0;
+    out.sky_light = f32(data.y >> 24u) / 255.0;
+
+    let attributes = data.w;
+    out.layer = attributes & 0x3fu;
+    // Same order as FaceDirections::all
+    var normals = array<vec3<f32>, 6>(
+        vec3<f32>(0.0, 0.0, 1.0),
+        vec3<f32>(0.0, -1.0, 0.0),
+        vec3<f32>(0.0, 1.0, 0.0),
+        vec3<f32>(0.0, 0.0, -1.0),
+        vec3<f32>(-1.0, 0.0, 0.0),
+        vec3<f32>(1.0, 0.0, 0.0),
+    );
+    var normal = normals[(attributes >> 6u) & 0x7u];
+    // Plants, folded onto a diagonal
+    if ((attributes >> 9u) & 1u) == 1u {
+        normal = normalize(normal + vec3<f32>(-normal.z, 0.0, normal.x));
+    }
+    out.normal = normal;
+    out.ao = f32((attributes >> 10u) & 0x3u) / 3.0;
+    out.tiling = f32((attributes >> 12u) & 1u);
+    out.water = vec2<f32>(f32((attributes >> 13u) & 1u), f32((attributes >> 14u) & 1u));
+
+    // From 0 to 1 across the texture, merged faces go up to their size in blocks
+    out.tex_coords = vec2<f32>(f32(data.z & 0xffffu), f32(data.z >> 16u)) / 128.0;
+    return out;
+}
+
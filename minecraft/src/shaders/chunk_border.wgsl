@@ -0,0 +1,41 @@
+struct VertexInput {
+    // A corner of a unit cube
+    @location(0) position: vec3<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+}
+
+// Chunk coordinates and the width of the chunks in blocks
+struct ChunkUniform {
+    position: vec2<i32>,
+    size: i32,
+}
+
+
+@group(0) @binding(0)
+var<uniform> projection: mat4x4<f32>;
+@group(0) @binding(1)
+var<uniform> view: mat4x4<f32>;
+@group(1) @binding(0)
+var<uniform> current_chunk: ChunkUniform;
+
+// From the bottom of the world to WORLD_MAX_Y (world.rs)
+const border_height = 383.0;
+const border_color = vec4<f32>(1.0, 0.9, 0.0, 1.0);
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    let size = f32(current_chunk.size);
+    let chunk_offset = vec3<f32>(f32(current_chunk.position.x), 0.0, f32(current_chunk.position.y)) * size;
+    var out: VertexOutput;
+    let position = chunk_offset + in.position * vec3<f32>(size, border_height, size);
+    out.clip_position = projection * view * vec4<f32>(position, 1.0);
+    return out;
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return border_color;
+}
@@ -0,0 +1,76 @@
+struct Clouds {
+    eye: vec3<f32>,
+    altitude: f32,
+    center: vec2<f32>,
+    scroll: vec2<f32>,
+}
+
+struct Sun {
+    direction: vec4<f32>,
+    color: vec4<f32>,
+}
+
+// Blends towards the sky color between the start and end distances (in blocks) from the player
+struct Fog {
+    color: vec4<f32>,
+    start: f32,
+    end: f32,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+}
+
+
+@group(0) @binding(0)
+var<uniform> projection: mat4x4<f32>;
+@group(0) @binding(1)
+var<uniform> view: mat4x4<f32>;
+@group(0) @binding(2)
+var<uniform> fog: Fog;
+@group(0) @binding(5)
+var<uniform> sun: Sun;
+@group(1) @binding(0)
+var<uniform> clouds: Clouds;
+@group(1) @binding(1)
+var clouds_texture: texture_2d<f32>;
+@group(1) @binding(2)
+var clouds_sampler: sampler;
+
+// Half the width of the plane around the player, the fog hides its edges
+const extent = 1024.0;
+// Blocks covered by the texture, CLOUD_TILE_SIZE (clouds.rs)
+const tile_size = 512.0;
+// Clouds are a bit brighter than the terrain in the same light
+const brightness = 1.2;
+const max_opacity = 0.8;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, 1.0),
+    );
+    let corner = clouds.center + corners[index] * extent;
+    var out: VertexOutput;
+    out.world_position = vec3<f32>(corner.x, clouds.altitude, corner.y);
+    out.clip_position = projection * view * vec4<f32>(out.world_position, 1.0);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // From the world position, the texture stays put when the plane follows the player
+    let uv = (in.world_position.xz - clouds.scroll) / tile_size;
+    let coverage = textureSample(clouds_texture, clouds_sampler, uv).r;
+    let color = min(sun.color.rgb * brightness, vec3<f32>(1.0));
+
+    let distance = length(in.world_position - clouds.eye);
+    let fog_amount = smoothstep(fog.start, fog.end, distance);
+    return vec4<f32>(mix(color, fog.color.rgb, fog_amount), coverage * max_opacity * (1.0 - fog_amount));
+}
@@ -1,10 +1,14 @@
 struct VertexInput {
     @builtin(vertex_index) vertex_index: u32,
     @location(0) position: vec3<f32>,
+    // uv and layer of the crack texture
+    @location(1) tex_coords: vec3<f32>,
 }
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) @interpolate(flat) layer: u32,
 }
 
 
@@ -18,6 +22,8 @@ var<uniform> view: mat4x4<f32>;
 fn vs_main(in: VertexInput) -> VertexOutput {
     var out: VertexOutput;
     out.clip_position = projection * view * vec4<f32>(in.position, 1.0);
+    out.tex_coords = in.tex_coords.xy;
+    out.layer = u32(in.tex_coords.z);
     return out;
 }
 
@@ -27,9 +33,11 @@ const ambient_light = 0.005;
 
 @fragment
 fn fs_main() -> @location(0) vec4<f32> {
-    var color: vec4<f32>;
-
-    color = vec4<f32>(1.0, 0.0, 0.0, 0.2);
+    return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+}
 
-    return color;
+// The crack tile of the block being broken, transparent around the cracks
+@fragment
+fn fs_crack(in: VertexOutput) -> @location(0) vec4<f32> {
+    return sample_block_texture(in.layer, in.tex_coords, false);
 }
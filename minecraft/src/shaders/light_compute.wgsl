@@ -0,0 +1,49 @@
+struct Params {
+    size: vec3<u32>,
+}
+
+@group(0) @binding(0)
+var<uniform> params: Params;
+// bits 0..3: emitted light, bit 8: opaque
+@group(0) @binding(1)
+var<storage, read> cells: array<u32>;
+@group(0) @binding(2)
+var<storage, read> light_in: array<u32>;
+@group(0) @binding(3)
+var<storage, read_write> light_out: array<u32>;
+
+fn cell_index(p: vec3<u32>) -> u32 {
+    return p.x + params.size.x * (p.y + params.size.y * p.z);
+}
+
+// One relaxation step of the flood fill, every cell takes the brightest neighbour minus one
+@compute @workgroup_size(4, 4, 4)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (any(id >= params.size)) {
+        return;
+    }
+    let i = cell_index(id);
+    let cell = cells[i];
+    var light = cell & 15u;
+
+    if ((cell & 256u) == 0u) {
+        var offsets = array<vec3<i32>, 6>(
+            vec3<i32>(1, 0, 0),
+            vec3<i32>(-1, 0, 0),
+            vec3<i32>(0, 1, 0),
+            vec3<i32>(0, -1, 0),
+            vec3<i32>(0, 0, 1),
+            vec3<i32>(0, 0, -1),
+        );
+        for (var k = 0; k < 6; k++) {
+            let n = vec3<i32>(id) + offsets[k];
+            if (all(n >= vec3<i32>(0)) && all(n < vec3<i32>(params.size))) {
+                let neighbour = light_in[cell_index(vec3<u32>(n))];
+                if (neighbour > light + 1u) {
+                    light = neighbour - 1u;
+                }
+            }
+        }
+    }
+    light_out[i] = light;
+}
@@ -0,0 +1,60 @@
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+struct LutParams {
+    intensity: f32,
+    size: f32,
+    // 1.0 if the scene texture is srgb, since luts are authored for gamma encoded colors
+    srgb: f32,
+}
+
+@group(0) @binding(0)
+var scene: texture_2d<f32>;
+@group(0) @binding(1)
+var scene_sampler: sampler;
+@group(0) @binding(2)
+var lut: texture_3d<f32>;
+@group(0) @binding(3)
+var lut_sampler: sampler;
+@group(0) @binding(4)
+var<uniform> params: LutParams;
+
+
+// Fullscreen triangle, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    var out: VertexOutput;
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    out.clip_position = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+fn to_srgb(c: vec3<f32>) -> vec3<f32> {
+    return select(1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055, c * 12.92, c <= vec3<f32>(0.0031308));
+}
+
+fn to_linear(c: vec3<f32>) -> vec3<f32> {
+    return select(pow((c + 0.055) / 1.055, vec3<f32>(2.4)), c / 12.92, c <= vec3<f32>(0.04045));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(scene, scene_sampler, in.uv);
+
+    var encoded = clamp(color.rgb, vec3<f32>(0.0), vec3<f32>(1.0));
+    if (params.srgb > 0.5) {
+        encoded = to_srgb(encoded);
+    }
+    // Sample the center of the texels so 0 and 1 map to the first and last entries
+    let coords = encoded * ((params.size - 1.0) / params.size) + 0.5 / params.size;
+    let graded = textureSample(lut, lut_sampler, coords).rgb;
+
+    var result = mix(encoded, graded, params.intensity);
+    if (params.srgb > 0.5) {
+        result = to_linear(result);
+    }
+    return vec4<f32>(result, color.a);
+}
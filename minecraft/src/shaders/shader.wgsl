@@ -2,11 +2,8 @@
 
 struct VertexInput {
     @builtin(vertex_index) vertex_index: u32,
-    @location(0) position: vec3<f32>,
-    @location(1) normal: vec3<f32>,
-    @location(2) tex_coords: vec2<f32>,
-    @location(3) ao: f32,
-
+    // Unpacked with unpack_vertex (block_vertex.wgsl)
+    @location(0) packed_vertex: vec4<u32>,
 }
 struct InstanceInput {
     // @location(2) instance_transform: vec3<f32>,
@@ -21,7 +18,11 @@ struct VertexOutput {
     @location(2) chunk_position: vec2<i32>,
     @location(3) block_type: u32,
     @location(4) ao: f32,
-    @location(5) fog: f32
+    @location(5) world_position: vec3<f32>,
+    @location(6) light: f32,
+    @location(7) @interpolate(flat) layer: u32,
+    @location(8) tiling: f32,
+    @location(9) sky_light: f32,
 }
 
 
@@ -30,13 +31,11 @@ var<uniform> projection: mat4x4<f32>;
 @group(0) @binding(1) 
 var<uniform> view: mat4x4<f32>;
 @group(0) @binding(2)
-var <uniform> chunks_per_row: u32;
-@group(0) @binding(3)
-var diffuse: texture_2d<f32>;
-@group(0) @binding(4)
-var t_sampler: sampler;
+var<uniform> fog: Fog;
+@group(0) @binding(5)
+var<uniform> sun: Sun;
 @group(1) @binding(0)
-var <uniform> current_chunk: vec2<i32>;
+var <uniform> current_chunk: ChunkUniform;
 @group(2) @binding(0)
 var <uniform> player_position: vec3<f32>;
 
@@ -44,21 +43,23 @@ var <uniform> player_position: vec3<f32>;
 @vertex
 fn vs_main(in: VertexInput, instance_data: InstanceInput) -> VertexOutput {
     var out: VertexOutput;
+    let vertex = unpack_vertex(in.packed_vertex);
 
 
-    let chunk_offset = vec3<f32>(f32(current_chunk.x) * 16.0, 0.0, f32(current_chunk.y) * 16.0);
-    let block_position = in.position + chunk_offset;
+    let chunk_offset = vec3<f32>(vec3<i32>(current_chunk.position.x, 0, current_chunk.position.y) * current_chunk.size);
+    let block_position = vertex.position + chunk_offset;
 
-    let player_dist = distance(player_position, block_position);
 
-    let r = (f32(chunks_per_row) - 1.0) * 8.0;
-    out.fog = 1.0 - clamp((r - player_dist) / 8.0, 0.0, 1.0);
+    out.world_position = block_position;
 
-    // out.fog = min(pow(player_dist / 80.0, 6.0), 1.0);
     out.clip_position = projection * view * (vec4<f32>(block_position, 1.0));
-    out.normals = in.normal;
-    out.tex_coords = in.tex_coords;
-    out.ao = in.ao;
+    out.normals = vertex.normal;
+    out.tex_coords = vertex.tex_coords;
+    out.ao = vertex.ao;
+    out.light = vertex.light;
+    out.layer = vertex.layer;
+    out.tiling = vertex.tiling;
+    out.sky_light = vertex.sky_light;
 
     return out;
 }
@@ -71,21 +72,80 @@ struct FragmentInput {
         @location(2) current_chunk: vec2<i32>,
         @location(3) block_type: u32,
         @location(4) ao: f32,
-        @location(5) fog: f32
+        @location(5) world_position: vec3<f32>,
+        @location(6) light: f32,
+        @location(7) @interpolate(flat) layer: u32,
+        @location(8) tiling: f32,
+        @location(9) sky_light: f32,
+}
+
+// direction.w: daylight (0-1), the colors are in rgb
+struct Sun {
+    direction: vec4<f32>,
+    color: vec4<f32>,
+}
+
+// Chunk coordinates and the width of the chunks in blocks
+struct ChunkUniform {
+    position: vec2<i32>,
+    size: i32,
+}
+
+// Blends towards the sky color between the start and end distances (in blocks) from the player
+struct Fog {
+    color: vec4<f32>,
+    start: f32,
+    end: f32,
 }
 
-const light_direction = vec3<f32>(0.25, 1.0, -0.5);
 const ambient_light = 0.005;
+// Share of the sun that still reaches the cells the sky can't see
+const sky_shade = 0.2;
+const torch_color = vec3<f32>(1.0, 0.85, 0.6);
 
 @fragment
 fn fs_main(in: FragmentInput) -> @location(0) vec4<f32> {
+    return shade(in);
+}
+
+// Width of the edges in pixels
+const wireframe_width = 1.0;
+
+// The wireframe view on devices without line mode: only the borders of the faces are kept. Merged
+// faces show the borders of each of their blocks
+@fragment
+fn fs_wireframe(in: FragmentInput) -> @location(0) vec4<f32> {
+    // Position on the face, in blocks
+    let face = in.tex_coords;
+    let border = min(fract(face), 1.0 - fract(face)) / fwidth(face);
+    if min(border.x, border.y) > wireframe_width {
+        discard;
+    }
+    return shade(in);
+}
+
+fn shade(in: FragmentInput) -> vec4<f32> {
     var color: vec4<f32>;
 
-    color = textureSample(diffuse, t_sampler, in.tex_coords);
-    color *= max(dot(in.normals, normalize(light_direction)), 0.2);
+    // Merged faces repeat the block's texture once per block
+    color = sample_block_texture(in.layer, in.tex_coords, in.tiling > 0.5);
+    // Plants and leaves are cut out of their tile
+    if color.a < 0.5 {
+        discard;
+    }
+    let diffuse = max(dot(in.normals, sun.direction.xyz), 0.2);
+    // Block light only shows where it's brighter than the sun
+    let sun_light = sun.color.rgb * diffuse * mix(sky_shade, 1.0, in.sky_light);
+    let light = max(sun_light, torch_color * in.light);
+    color = vec4<f32>(color.rgb * light, color.a);
     color += vec4<f32>(vec3<f32>(ambient_light), 0.0);
     color *= 1.0 - (in.ao * 0.9);
-    color = mix(color, vec4<f32>(0.03, 0.64, 0.97, 1.0), in.fog);
+    let fog_amount = clamp(
+        (distance(player_position, in.world_position) - fog.start) / (fog.end - fog.start),
+        0.0,
+        1.0
+    );
+    color = mix(color, vec4<f32>(fog.color.rgb, 1.0), fog_amount);
 
     return color;
 }
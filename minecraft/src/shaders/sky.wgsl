@@ -0,0 +1,51 @@
+struct Sky {
+    inverse_view_projection: mat4x4<f32>,
+    horizon: vec4<f32>,
+    // w: how much the stars show
+    zenith: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+}
+
+
+@group(0) @binding(0)
+var<uniform> sky: Sky;
+
+
+// A triangle twice the size of the screen, so it covers all of it without a vertex buffer
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let ndc = vec2<f32>(f32(index / 2u) * 4.0 - 1.0, f32(index % 2u) * 4.0 - 1.0);
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(ndc, 0.0, 1.0);
+    out.ndc = ndc;
+    return out;
+}
+
+
+// Cells of the sky per unit of direction, the smaller the stars
+const star_density = 300.0;
+// Share of the cells that hold a star
+const star_chance = 0.002;
+
+fn hash(cell: vec3<f32>) -> f32 {
+    let p = fract(cell * vec3<f32>(0.1031, 0.1030, 0.0973));
+    let q = p + dot(p, p.yzx + 33.33);
+    return fract((q.x + q.y) * q.z);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let far = sky.inverse_view_projection * vec4<f32>(in.ndc, 1.0, 1.0);
+    let direction = normalize(far.xyz / far.w);
+    // Exactly the fog color at and below the horizon, so the far chunks fade into it
+    let height = max(direction.y, 0.0);
+    var color = mix(sky.horizon.rgb, sky.zenith.rgb, sqrt(height));
+
+    let star = step(1.0 - star_chance, hash(floor(direction * star_density)));
+    color += vec3<f32>(star * sky.zenith.w * height);
+    return vec4<f32>(color, 1.0);
+}
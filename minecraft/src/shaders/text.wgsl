@@ -0,0 +1,38 @@
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) color: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+
+@group(0) @binding(0)
+var font: texture_2d<f32>;
+@group(0) @binding(1)
+var font_sampler: sampler;
+
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(in.position, 0.0, 1.0);
+    out.uv = in.uv;
+    out.color = in.color;
+    return out;
+}
+
+
+// The font only has coverage in its red channel. Negative uvs are the line backgrounds
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let coverage = textureSample(font, font_sampler, in.uv).r;
+    if in.uv.x < 0.0 {
+        return in.color;
+    }
+    return vec4<f32>(in.color.rgb, in.color.a * coverage);
+}
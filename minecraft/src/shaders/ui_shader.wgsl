@@ -1,8 +1,9 @@
 struct VertexInput {
     @builtin(vertex_index) vertex_index: u32,
     @location(0) position: vec2<f32>,
-    @location(1) uv: vec2<f32>
-
+    @location(1) uv: vec2<f32>,
+    @location(2) color: vec4<f32>,
+    @location(3) layer: f32,
 }
 
 
@@ -10,26 +11,32 @@ struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) pos: vec2<f32>,
     @location(1) uv: vec2<f32>,
-
+    @location(2) color: vec4<f32>,
+    @location(3) @interpolate(flat) layer: u32,
 }
 
 
-@group(0) @binding(3)
-var diffuse: texture_2d<f32>;
-@group(0) @binding(4)
-var t_sampler: sampler;
 @group(1) @binding(0)
 var<uniform> resolution: vec2<f32>;
 @group(1) @binding(1)
 var<uniform> blockid: u32;
 
 
+// The vertex colors are picked in sRGB like the atlas, the surface expects linear values
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, color <= vec3<f32>(0.04045));
+}
+
 @vertex
 fn vs_main(in: VertexInput) -> VertexOutput {
     var out: VertexOutput;
     out.clip_position = vec4<f32>(in.position, 0.0, 1.0);
     out.pos = vec2<f32>(in.position);
     out.uv = in.uv;
+    out.layer = u32(in.layer);
+    out.color = vec4<f32>(srgb_to_linear(in.color.rgb), in.color.a);
 
     return out;
 }
@@ -39,6 +46,8 @@ struct FragmentInput {
         @builtin(position) clip_position: vec4<f32>,
         @location(0) pos: vec2<f32>,
         @location(1) uv: vec2<f32>,
+        @location(2) color: vec4<f32>,
+        @location(3) @interpolate(flat) layer: u32,
 }
 
 
@@ -47,7 +56,11 @@ fn fs_main(in: FragmentInput) -> @location(0) vec4<f32> {
     var color: vec4<f32>;
     // Normalize in range 0->1
 
-    color = textureSample(diffuse, t_sampler, in.uv);
+    color = sample_block_texture(in.layer, in.uv, false) * in.color;
+    // Untextured quads (slot frames and empty slots) only use the vertex color
+    if in.uv.x < 0.0 {
+        color = in.color;
+    }
     // let norm = (in.pos + 1.0) * 0.5;
     // let coords = norm * vec3<f32>(resolution, 1.0);
 
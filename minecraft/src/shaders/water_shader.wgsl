@@ -2,10 +2,8 @@
 
 struct VertexInput {
     @builtin(vertex_index) vertex_index: u32,
-    @location(0) position: vec3<f32>,
-    @location(1) normal: vec3<f32>,
-    @location(2) tex_coords: vec2<f32>,
-
+    // Unpacked with unpack_vertex (block_vertex.wgsl)
+    @location(0) packed_vertex: vec4<u32>,
 }
 struct InstanceInput {
     // @location(2) instance_transform: vec3<f32>,
@@ -19,7 +17,10 @@ struct VertexOutput {
     @location(1) normals: vec3<f32>,
     @location(2) chunk_position: vec2<i32>,
     @location(3) block_type: u32,
-    @location(4) fog: f32
+    @location(4) world_position: vec3<f32>,
+    @location(5) @interpolate(flat) layer: u32,
+    // Water's tex_coords scroll and repeat
+    @location(6) water: f32,
 }
 
 
@@ -28,33 +29,61 @@ var<uniform> projection: mat4x4<f32>;
 @group(0) @binding(1)
 var<uniform> view: mat4x4<f32>;
 @group(0) @binding(2)
-var <uniform> chunks_per_row: u32;
-@group(0) @binding(3)
-var diffuse: texture_2d<f32>;
-@group(0) @binding(4)
-var t_sampler: sampler;
+var<uniform> fog: Fog;
+@group(0) @binding(5)
+var<uniform> sun: Sun;
 @group(1) @binding(0)
-var <uniform> current_chunk: vec2<i32>;
+var <uniform> current_chunk: ChunkUniform;
 @group(2) @binding(0)
 var <uniform> player_position: vec3<f32>;
+@group(3) @binding(0)
+var <uniform> time: Time;
+
+// Tiles per second
+const scroll_speed = vec2<f32>(0.03, 0.05);
+// Sum of sines, each one is (direction x, direction z, height, speed) with the direction scaled
+// by its frequency
+const wave_a = vec4<f32>(1.3, 0.4, 0.025, 1.7);
+const wave_b = vec4<f32>(-0.5, 1.1, 0.015, 1.3);
+const wave_c = vec4<f32>(2.1, -1.7, 0.008, 2.9);
+
+// Height of a sine at a point (x) and its derivatives along x and z (yz)
+fn sine_wave(w: vec4<f32>, position: vec2<f32>, seconds: f32) -> vec3<f32> {
+    let phase = dot(w.xy, position) + w.w * seconds;
+    return w.z * vec3<f32>(sin(phase), cos(phase) * w.xy);
+}
+
+// Height and slope of the waves at a point of the surface, from the world position so the faces of
+// neighbouring blocks (and chunks) stay joined
+fn wave(position: vec2<f32>, seconds: f32) -> vec3<f32> {
+    return sine_wave(wave_a, position, seconds) + sine_wave(wave_b, position, seconds) + sine_wave(wave_c, position, seconds);
+}
 
 @vertex
 fn vs_main(in: VertexInput, instance_data: InstanceInput) -> VertexOutput {
     var out: VertexOutput;
+    let vertex = unpack_vertex(in.packed_vertex);
 
-    let chunk_offset = vec3<f32>(f32(current_chunk.x) * 16.0, 0.0, f32(current_chunk.y) * 16.0);
+    let chunk_offset = vec3<f32>(vec3<i32>(current_chunk.position.x, 0, current_chunk.position.y) * current_chunk.size);
 
-    let block_position = in.position + chunk_offset;
+    var block_position = vertex.position + chunk_offset;
+    let surface = wave(block_position.xz, time.seconds);
+    block_position.y += vertex.water.y * surface.x;
 
-
-    let player_dist = distance(player_position, block_position);
-
-    let r = (f32(chunks_per_row) - 1.0) * 8.0;
-    out.fog = 1.0 - clamp((r - player_dist) / 8.0, 0.0, 1.0);
+    out.world_position = block_position;
 
     out.clip_position = projection * view * (vec4<f32>(block_position, 1.0));
-    out.normals = in.normal;
-    out.tex_coords = in.tex_coords;
+    out.normals = vertex.normal;
+    // Only the top face waves, the sides just follow it with their upper edge
+    if vertex.water.y > 0.5 && vertex.normal.y > 0.5 {
+        out.normals = normalize(vec3<f32>(-surface.y, 1.0, -surface.z));
+    }
+    out.tex_coords = vertex.tex_coords;
+    out.layer = vertex.layer;
+    out.water = vertex.water.x;
+    if vertex.water.x > 0.5 {
+        out.tex_coords = vertex.tex_coords + scroll_speed * time.seconds;
+    }
 
     return out;
 }
@@ -66,16 +95,50 @@ struct FragmentInput {
         @location(1) normals: vec3<f32>,
         @location(2) current_chunk: vec2<i32>,
         @location(3) block_type: u32,
-        @location(4) fog: f32
+        @location(4) world_position: vec3<f32>,
+        @location(5) @interpolate(flat) layer: u32,
+        @location(6) water: f32,
+}
+
+
+struct Time {
+    seconds: f32,
+}
+
+struct Sun {
+    direction: vec4<f32>,
+    color: vec4<f32>,
 }
 
+// Chunk coordinates and the width of the chunks in blocks
+struct ChunkUniform {
+    position: vec2<i32>,
+    size: i32,
+}
+
+// Blends towards the sky color between the start and end distances (in blocks) from the player
+struct Fog {
+    color: vec4<f32>,
+    start: f32,
+    end: f32,
+}
 
 @fragment
 fn fs_main(in: FragmentInput) -> @location(0) vec4<f32> {
     var color: vec4<f32>;
-    color = textureSample(diffuse, t_sampler, in.tex_coords);
-    color.a = 0.6;
-    color = mix(color, vec4<f32>(0.03, 0.64, 0.97, 1.0), in.fog);
+    color = sample_block_texture(in.layer, in.tex_coords, in.water > 0.5);
+    // Water is opaque in the atlas, glass keeps its own (lower) alpha
+    color.a = min(color.a, 0.6);
+    // Same tint as the opaque blocks, never fully black so the surface stays visible at night. The
+    // slope of the waves shades them a bit
+    let diffuse = mix(0.8, 1.0, max(dot(normalize(in.normals), sun.direction.xyz), 0.0));
+    color = vec4<f32>(color.rgb * diffuse * mix(vec3<f32>(0.2), vec3<f32>(1.0), sun.color.rgb), color.a);
+    let fog_amount = clamp(
+        (distance(player_position, in.world_position) - fog.start) / (fog.end - fog.start),
+        0.0,
+        1.0
+    );
+    color = mix(color, vec4<f32>(fog.color.rgb, 1.0), fog_amount);
 
     return color;
 }
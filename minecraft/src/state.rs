@@ -1,5 +1,8 @@
+use std::error::Error;
+use std::path::Path;
 use std::sync::Arc;
 use std::sync::{Mutex, RwLock};
+use std::time::Instant;
 use winit::event::MouseButton;
 use winit::{
     dpi::PhysicalSize,
@@ -10,15 +13,26 @@ use winit::{
 
 use crate::blocks::block::Block;
 use crate::blocks::block_type::BlockType;
-use crate::persistence::Saveable;
+use crate::debug_overlay::{DebugOverlay, DebugStats};
+use crate::frame_pacing::{FrameLimiter, FramePacing};
+use crate::lighting::GpuLighting;
+use crate::material::{SamplerConfig, Texture, TextureFiltering};
+use crate::mesh_cache::MeshCache;
+use crate::persistence::{pack, save_dir, set_world_name, Loadable, Saveable, WorldArchive};
 use crate::pipelines::pipeline_manager::PipelineManager;
+#[cfg(debug_assertions)]
+use crate::pipelines::shaders::ShaderWatcher;
+use crate::pipelines::wireframe::WireframePipeline;
 use crate::pipelines::Pipeline;
-use crate::utils::{ChunkFromPosition, RelativeFromAbsolute};
+use crate::screenshot::{save_png, TextureReadback};
+use crate::utils::ChunkFromPosition;
 use crate::{
-    material::Texture,
     pipeline::Uniforms,
-    player::{Camera, CameraController, Player},
-    world::World,
+    player::{
+        hotbar_slot_of, Camera, CameraController, Player, PlayerSave, DEFAULT_FLY_SPEED_FACTOR,
+        REACH_DISTANCE,
+    },
+    world::{World, WorldConfig, WorldMeta, RNG_SEED},
 };
 
 pub struct State {
@@ -29,14 +43,39 @@ pub struct State {
     pub queue: Arc<wgpu::Queue>,
     pub window: Arc<Mutex<Window>>,
     pub surface_config: wgpu::SurfaceConfiguration,
+    // Chosen once for the surface, the pipelines target it instead of querying the capabilities
+    pub surface_format: wgpu::TextureFormat,
     pub pipeline_manager: PipelineManager,
     pub player: Arc<RwLock<Player>>,
     pub world: World,
     pub camera_controller: CameraController,
+    pub config: Config,
+    // Smooths and clamps the frame time, the raw one is still available in `frame_pacing.raw_delta`
+    pub frame_pacing: Option<FramePacing>,
+    // Waits after presenting a frame when `Config::max_fps` is set
+    pub frame_limiter: Option<FrameLimiter>,
+    // Hidden with F1, for screenshots
+    pub show_crosshair: bool,
+    // Draws the chunk meshes as lines and the chunk borders (F4)
+    pub debug_wireframe: bool,
+    // Frame rate, position and facing block (F3)
+    pub debug_overlay: DebugOverlay,
+    // Samples per pixel of the scene passes, 1 without msaa
+    pub sample_count: u32,
+    // Seconds of game time since the start, from the same frame times as the day cycle
+    pub elapsed_time: f32,
+    // Left button held, the facing block cracks until it breaks
+    pub mining: bool,
+    // Real seconds since the last autosave
+    pub autosave_timer: f32,
+    // Edited shaders are picked up while the game runs, release builds only use the embedded ones
+    #[cfg(debug_assertions)]
+    pub shader_watcher: ShaderWatcher,
 }
 
 impl State {
-    pub async fn new(window: Arc<Mutex<Window>>) -> Self {
+    pub async fn new(window: Arc<Mutex<Window>>, mut config: Config) -> Self {
+        set_world_name(config.world_name.clone());
         let windowbrw = window.lock().unwrap();
         let size = windowbrw.inner_size();
         let instance = wgpu::Instance::default();
@@ -55,7 +94,8 @@ impl State {
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: None,
-                    features: wgpu::Features::empty(),
+                    // Lines for the wireframe view, the shader finds the edges without it
+                    features: adapter.features() & WireframePipeline::FEATURES,
                     limits: wgpu::Limits::default(),
                 },
                 None,
@@ -66,54 +106,105 @@ impl State {
         let device = Arc::new(device);
         let queue = Arc::new(queue);
         let swapchain_capabilities = surface.get_capabilities(&adapter);
-        let swapchain_format = swapchain_capabilities.formats[0];
+        let swapchain_format = preferred_surface_format(&swapchain_capabilities.formats);
 
+        let sample_count = supported_sample_count(&adapter, swapchain_format, config.msaa_samples);
+
+        // Copied from for screenshots, when the surface allows it
+        let copy_usage = swapchain_capabilities.usages & wgpu::TextureUsages::COPY_SRC;
         let surface_config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | copy_usage,
             format: swapchain_format,
             width: size.width,
             height: size.height,
-            present_mode: wgpu::PresentMode::Fifo,
+            present_mode: supported_present_mode(
+                config.present_mode,
+                &swapchain_capabilities.present_modes,
+            ),
             alpha_mode: swapchain_capabilities.alpha_modes[0],
             view_formats: vec![],
         };
 
+        // Loose files in the save directory take priority over the archive ones
+        let archive = config
+            .world_archive
+            .as_ref()
+            .and_then(|path| WorldArchive::open(path).ok())
+            .map(Arc::new);
+        // A saved world keeps the seed it was created with
+        if let Ok(meta) = <WorldMeta as Loadable<WorldMeta>>::load(Box::new(archive.clone())) {
+            if meta.seed != config.seed {
+                println!("Using the seed {} the world was created with", meta.seed);
+            }
+            config.seed = meta.seed;
+            // Its chunks can't be read back with another chunk size
+            if let Err(err) = meta.check_config(&config.world) {
+                panic!("{err}");
+            }
+        }
+
         let camera = Camera::new(
             surface_config.width as f32,
             surface_config.height as f32,
             device.clone(),
             queue.clone(),
+            archive.clone(),
         );
-        let current_chunk = camera.eye.get_chunk_from_position_absolute();
-        let player = Arc::new(RwLock::new(Player {
-            camera,
-            placing_block: BlockType::Dirt,
-            in_water: false,
-            current_chunk,
-            is_jumping: false,
-            on_ground: false,
-            facing_block: None,
-            facing_face: None,
-            jump_action_start: None,
-            is_ghost: false,
-        }));
+        let mut player = Player::new(camera, archive.clone());
+        player.fly_speed_factor = config.fly_speed_factor;
+        player.reach = config.reach_distance;
+        let player = Arc::new(RwLock::new(player));
 
         surface.configure(&device, &surface_config);
 
-        let mut world = World::init_world(device.clone(), queue.clone());
+        let mut world = World::with_config(
+            device.clone(),
+            queue.clone(),
+            archive,
+            config.background_worldgen,
+            config.seed,
+            config.world,
+        );
+        if config.dedup_chunk_meshes {
+            world.mesh_cache = Some(Arc::new(MeshCache::default()));
+        }
+        world.greedy_meshing = config.greedy_meshing;
+        if config.gpu_lighting {
+            world.gpu_lighting =
+                GpuLighting::new(&adapter, device.clone(), queue.clone()).map(Arc::new);
+            if world.gpu_lighting.is_none() {
+                println!("Compute shaders not supported, using cpu lighting");
+            }
+        }
+        world.lod_distance = config.lod_distance.map(|distance| distance as i32);
+        if let Some(radius) = config.chunk_streaming_radius {
+            world.enable_streaming(radius);
+        }
         world.init_chunks(Arc::clone(&player));
 
+        let frame_pacing = config
+            .frame_smoothing
+            .map(|smoothing| FramePacing::new(smoothing, config.max_frame_time));
+        let frame_limiter = config.max_fps.map(FrameLimiter::new);
+
+        let debug_wireframe = config.polygon_mode == wgpu::PolygonMode::Line;
         let mut state = Self {
             player,
             surface_config,
+            surface_format: swapchain_format,
             instance,
             window: window.clone(),
             // just an empty object so we can initialize it later (without using options everywhere..)
             pipeline_manager: PipelineManager {
                 main_pipeline: None,
+                sky_pipeline: None,
                 highlight_selected_pipeline: None,
                 translucent_pipeline: None,
                 ui_pipeline: None,
+                text_pipeline: None,
+                lut_pipeline: None,
+                wireframe_pipeline: None,
+                clouds_pipeline: None,
             },
             device,
             world,
@@ -121,19 +212,58 @@ impl State {
             surface,
             adapter,
             camera_controller: CameraController::default(),
+            config,
+            frame_pacing,
+            frame_limiter,
+            show_crosshair: true,
+            debug_wireframe,
+            debug_overlay: DebugOverlay::default(),
+            sample_count,
+            elapsed_time: 0.0,
+            mining: false,
+            autosave_timer: 0.0,
+            #[cfg(debug_assertions)]
+            shader_watcher: ShaderWatcher::new(),
+        };
+        state.pipeline_manager = match PipelineManager::init(&state) {
+            Ok(pipeline_manager) => pipeline_manager,
+            Err(err) => panic!("Failed to create the pipelines: {err}"),
         };
-        state.pipeline_manager = PipelineManager::init(&state);
+        // The camera, hotbar and movement mode were loaded with the player
+        if let Ok(save) = PlayerSave::load(Box::new(state.world.archive.clone())) {
+            state.set_time_of_day(save.time_of_day);
+        }
 
         state
     }
+    fn save_player(&self) {
+        let save = PlayerSave::new(&self.player.read().unwrap(), self.time_of_day());
+        if let Err(err) = save.save() {
+            eprintln!("Failed to save the player: {err}");
+        }
+    }
+    // Every `Config::autosave_interval`, the chunks are written on the thread pool
+    pub fn autosave(&mut self) {
+        self.save_player();
+        self.world.autosave();
+    }
+    // On exit, blocks until everything is written. Failed writes are logged, the rest is still saved
     pub fn save_state(&mut self) {
-        self.player
-            .read()
-            .unwrap()
-            .camera
-            .save()
-            .expect("Failed to save camera state");
+        self.save_player();
         self.world.save_state();
+
+        if let Some(archive_path) = self.config.world_archive.as_ref() {
+            // Chunks that were only read from the archive need to be on disk before repacking
+            if let Some(archive) = self.world.archive.as_ref() {
+                if let Err(err) = archive.unpack_to(&save_dir(), false) {
+                    eprintln!("Failed to extract the world archive: {err}");
+                    return;
+                }
+            }
+            if let Err(err) = pack(&save_dir(), archive_path) {
+                eprintln!("Failed to pack the world archive: {err}");
+            }
+        }
     }
     pub fn dispose(&mut self) {
         self.world.dispose();
@@ -144,6 +274,13 @@ impl State {
         let is_pressed: f32 = if event.state.is_pressed() { 1. } else { 0. };
         let mut player = self.player.write().unwrap();
 
+        if let (PhysicalKey::Code(code), true) = (event.physical_key, event.state.is_pressed()) {
+            if let Some(slot) = hotbar_slot_for_key(code) {
+                player.select_slot(slot);
+                return;
+            }
+        }
+
         match event {
             KeyEvent {
                 physical_key: PhysicalKey::Code(KeyCode::KeyW),
@@ -158,10 +295,13 @@ impl State {
                 state: winit::event::ElementState::Released,
                 ..
             } => {
-                if player.placing_block == BlockType::Dirt {
-                    player.placing_block = BlockType::Grass
+                let next_block = if player.placing_block == BlockType::Dirt {
+                    BlockType::Grass
                 } else {
-                    player.placing_block = BlockType::Dirt
+                    BlockType::Dirt
+                };
+                if let Some(slot) = hotbar_slot_of(next_block) {
+                    player.select_slot(slot);
                 }
             }
             KeyEvent {
@@ -190,16 +330,39 @@ impl State {
                 state: winit::event::ElementState::Pressed,
                 ..
             } => player.next_placing_block(1),
+            // Up when flying, a double tap toggles flying
             KeyEvent {
                 physical_key: PhysicalKey::Code(KeyCode::Space),
-                state: winit::event::ElementState::Pressed,
+                state,
+                repeat,
                 ..
             } => {
-                if player.on_ground || player.in_water {
-                    player.is_jumping = true;
-                    player.jump_action_start = Some(std::time::Instant::now());
+                self.camera_controller.movement_vector.y = 1.0 * is_pressed;
+                if state.is_pressed() && !repeat {
+                    player.press_jump(Instant::now());
                 }
             }
+            KeyEvent {
+                physical_key: PhysicalKey::Code(KeyCode::ShiftLeft),
+                ..
+            } => self.camera_controller.movement_vector.y = -1.0 * is_pressed,
+            // Jumps between noon and midnight
+            KeyEvent {
+                physical_key: PhysicalKey::Code(KeyCode::KeyT),
+                state: winit::event::ElementState::Pressed,
+                ..
+            } => {
+                let is_day = self
+                    .pipeline_manager
+                    .main_pipeline
+                    .as_ref()
+                    .unwrap()
+                    .borrow()
+                    .day_cycle
+                    .daylight()
+                    > 0.5;
+                self.set_time_of_day(if is_day { 0.0 } else { 0.5 });
+            }
             KeyEvent {
                 physical_key: PhysicalKey::Code(KeyCode::KeyG),
                 state: winit::event::ElementState::Pressed,
@@ -207,56 +370,150 @@ impl State {
             } => {
                 player.is_ghost = !player.is_ghost;
             }
+            KeyEvent {
+                physical_key: PhysicalKey::Code(KeyCode::F1),
+                state: winit::event::ElementState::Pressed,
+                ..
+            } => self.show_crosshair = !self.show_crosshair,
+            KeyEvent {
+                physical_key: PhysicalKey::Code(KeyCode::F3),
+                state: winit::event::ElementState::Pressed,
+                ..
+            } => self.debug_overlay.toggle(),
+            KeyEvent {
+                physical_key: PhysicalKey::Code(KeyCode::F4),
+                state: winit::event::ElementState::Pressed,
+                ..
+            } => self.debug_wireframe = !self.debug_wireframe,
             _ => {}
         }
     }
     pub fn on_click(&mut self, button: MouseButton) {
+        // Holding it mines whatever block the player looks at
+        if button == MouseButton::Left {
+            self.mining = true;
+            return;
+        }
         let player = self.player.read().unwrap();
         if let Some(facing_block) = player.facing_block.as_ref() {
             let facing_face = player
                 .facing_face
                 .expect("Cannot be not facing a face if it's facing a block");
-            match button {
-                MouseButton::Left => {
-                
-                }
-                MouseButton::Right => {
-                    let block_borrow = facing_block.read().unwrap();
-                    let new_block_abs_position =
-                        block_borrow.absolute_position + facing_face.get_normal_vector();
-    
-                    let chunk = new_block_abs_position.get_chunk_from_position_absolute();
-                    let position = new_block_abs_position.relative_from_absolute();
-    
-                    println!("Placing block {:?}", player.placing_block);
-    
-                   
+            let facing_position = facing_block.read().unwrap().absolute_position;
+            if button == MouseButton::Right {
+                let new_block_abs_position = facing_position + facing_face.get_normal_vector();
+                // Can't place a block where the player stands
+                if !player.overlaps_block(new_block_abs_position) {
+                    self.world
+                        .place_block(new_block_abs_position, player.placing_block);
                 }
-                _ => {}
             }
         }
     }
-    
-   
+
+    pub fn on_release(&mut self, button: MouseButton) {
+        if button == MouseButton::Left {
+            self.mining = false;
+        }
+    }
+
+    // Scrolling up moves the hotbar selection to the left
+    pub fn handle_scroll(&mut self, delta: f32) {
+        if delta != 0.0 {
+            self.player
+                .write()
+                .unwrap()
+                .next_placing_block(-delta.signum() as i32);
+        }
+    }
     pub fn handle_mouse(&mut self, delta: &glam::Vec2) {
         self.player.write().unwrap().camera.move_target(delta)
     }
 
     pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
+        // Minimizing reports a zero size, there's nothing to render into until it's restored
         if new_size.width > 0 && new_size.height > 0 {
-            self.surface_config.width = new_size.width.max(1);
-            self.surface_config.height = new_size.height.max(1);
-            self.surface.configure(&self.device, &self.surface_config);
-            let new_depth = Texture::create_depth_texture(self);
+            self.surface_config.width = new_size.width;
+            self.surface_config.height = new_size.height;
             self.pipeline_manager
-                .main_pipeline
-                .as_mut()
-                .unwrap()
-                .borrow_mut()
-                .set_depth_texture(new_depth);
+                .resize(self, (new_size.width, new_size.height));
         }
     }
+    // Applies `surface_config` (size, present mode) to the surface
+    pub fn reconfigure_surface(&self) {
+        self.surface.configure(&self.device, &self.surface_config);
+    }
+    // Falls back to Fifo when the surface doesn't support the mode
+    pub fn set_present_mode(&mut self, present_mode: wgpu::PresentMode) {
+        let present_modes = self.surface.get_capabilities(&self.adapter).present_modes;
+        self.surface_config.present_mode = supported_present_mode(present_mode, &present_modes);
+        self.config.present_mode = present_mode;
+        self.reconfigure_surface();
+    }
+    // None draws as fast as the present mode allows
+    pub fn set_max_fps(&mut self, max_fps: Option<u32>) {
+        self.frame_limiter = max_fps.map(FrameLimiter::new);
+        self.config.max_fps = max_fps;
+    }
+    // Loads a color grading lut (strip or hald image), None disables it
+    pub fn set_color_lut(&mut self, path: Option<String>) {
+        self.pipeline_manager
+            .lut_pipeline
+            .as_ref()
+            .unwrap()
+            .borrow_mut()
+            .set_lut(self, path.as_deref());
+        self.config.lut_path = path;
+    }
+    pub fn set_texture_filtering(&mut self, filtering: TextureFiltering) {
+        self.pipeline_manager
+            .main_pipeline
+            .as_ref()
+            .unwrap()
+            .borrow_mut()
+            .set_sampler(
+                &self.device,
+                SamplerConfig::new(filtering, self.config.max_anisotropy),
+            );
+        self.config.texture_filtering = filtering;
+    }
+    // 0 is midnight, 0.5 noon
+    pub fn time_of_day(&self) -> f32 {
+        self.pipeline_manager
+            .main_pipeline
+            .as_ref()
+            .unwrap()
+            .borrow()
+            .day_cycle
+            .time_of_day
+    }
+    pub fn set_time_of_day(&self, time_of_day: f32) {
+        self.pipeline_manager
+            .main_pipeline
+            .as_ref()
+            .unwrap()
+            .borrow_mut()
+            .day_cycle
+            .time_of_day = time_of_day.rem_euclid(1.0);
+    }
+    // Moves the day/night cycle forward, a whole day takes `Config::day_length` seconds
+    pub fn advance_time(&self, delta_time: f32) {
+        self.pipeline_manager
+            .main_pipeline
+            .as_ref()
+            .unwrap()
+            .borrow_mut()
+            .day_cycle
+            .advance(delta_time);
+    }
     pub fn update(&mut self, delta_time: f32) {
+        let raw_delta_time = delta_time;
+        let delta_time = match self.frame_pacing.as_mut() {
+            Some(frame_pacing) => frame_pacing.update(delta_time),
+            None => delta_time,
+        };
+        self.advance_time(delta_time);
+        self.elapsed_time += delta_time;
         let nearby_blocks = self.world.get_blocks_nearby(Arc::clone(&self.player));
 
         let mut player = self.player.write().unwrap();
@@ -265,15 +522,17 @@ impl State {
             delta_time,
             &nearby_blocks,
         );
+        player.apply_physics(delta_time, &self.world);
+        // Again after the vertical move, the view follows where the eye ended up
+        player.update_in_water(&self.world);
         player.update();
-        if let Some((block, face_dir)) = player.get_facing_block(&nearby_blocks) {
-            let block = self.world.get_blocks_absolute(&block.to_block_position());
-
-          
+        player.update_facing(&self.world);
+        let broken_block = if self.mining {
+            player.mine(delta_time)
         } else {
-            player.facing_block = None;
-            player.facing_face = None;
-        }
+            player.breaking = None;
+            None
+        };
 
         let uniforms = Uniforms::from(&player.camera);
 
@@ -290,22 +549,97 @@ impl State {
         );
         // Drop write lock
         std::mem::drop(player);
+        if let Some(position) = broken_block {
+            self.world.remove_block(position);
+        }
 
         self.world.update(
             Arc::clone(&self.player),
             Arc::clone(&self.queue),
             Arc::clone(&self.device),
         );
+        if let Some(interval) = self.config.autosave_interval {
+            self.autosave_timer += raw_delta_time;
+            if self.autosave_timer >= interval {
+                self.autosave_timer = 0.0;
+                self.autosave();
+            }
+        }
+        if self.debug_overlay.visible {
+            let stats = self.debug_stats();
+            self.debug_overlay.update(raw_delta_time, &stats);
+        }
+        #[cfg(debug_assertions)]
+        {
+            let changed = self.shader_watcher.poll();
+            if !changed.is_empty() {
+                self.pipeline_manager.reload_shaders(self, &changed);
+            }
+        }
         self.pipeline_manager.update(self).expect("Update failed");
     }
+    // What the debug overlay shows, the vertices of the chunks that are drawn
+    fn debug_stats(&self) -> DebugStats {
+        let player = self.player.read().unwrap();
+        let facing = player.facing_block.as_ref().zip(player.facing_face);
+        let chunks = self.world.chunks.read().unwrap();
+        let vertices = chunks
+            .values()
+            .filter_map(|chunk| {
+                let chunk = chunk.read().unwrap();
+                let mesh = chunk.mesh.as_ref().filter(|_| chunk.visible)?;
+                Some(mesh.vertex.len() + mesh.water_vertex.len())
+            })
+            .sum();
+        DebugStats {
+            position: player.camera.eye,
+            chunk: player
+                .camera
+                .eye
+                .get_chunk_from_position_absolute(self.world.chunk_size),
+            facing: facing.map(|(block, face)| (block.read().unwrap().block_type, face)),
+            loaded_chunks: chunks.len(),
+            vertices,
+            present_mode: self.surface_config.present_mode,
+            max_fps: self.frame_limiter.as_ref().map(FrameLimiter::max_fps),
+        }
+    }
     pub fn draw(&mut self) {
+        let Some(frame) = self.acquire_frame() else {
+            return;
+        };
+        let encoder = self.encode_frame(&frame.texture);
+        self.queue.submit(Some(encoder.finish()));
+        frame.present();
+        if let Some(frame_limiter) = self.frame_limiter.as_mut() {
+            frame_limiter.wait();
+        }
+    }
+    // Draws a frame and saves it as a png once the gpu is done with it
+    pub fn capture_screenshot(&mut self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
         let frame = self
-            .surface
-            .get_current_texture()
-            .expect("Failed to acquire next swapchain texture");
-        let view = frame
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+            .acquire_frame()
+            .ok_or("The surface is being reconfigured, try again on the next frame")?;
+        let mut encoder = self.encode_frame(&frame.texture);
+        let readback = TextureReadback::new(&self.device, &mut encoder, &frame.texture)?;
+        self.queue.submit(Some(encoder.finish()));
+        frame.present();
+        save_png(&readback.read(&self.device)?, path)
+    }
+    // None when the surface doesn't match the window anymore (resized or minimized), the frame
+    // is skipped
+    fn acquire_frame(&self) -> Option<wgpu::SurfaceTexture> {
+        match self.surface.get_current_texture() {
+            Ok(frame) => Some(frame),
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                self.reconfigure_surface();
+                None
+            }
+            Err(err) => panic!("Failed to acquire next swapchain texture: {err}"),
+        }
+    }
+    fn encode_frame(&self, frame: &wgpu::Texture) -> wgpu::CommandEncoder {
+        let frame_view = frame.create_view(&wgpu::TextureViewDescriptor::default());
 
         let mut encoder = self
             .device
@@ -319,14 +653,50 @@ impl State {
             .collect::<Vec<_>>();
 
         let player = self.player.read().unwrap();
-        // Draw main pipeline
-        let _ = &self
+        let lut_pipeline = self
             .pipeline_manager
-            .main_pipeline
+            .lut_pipeline
+            .as_ref()
+            .unwrap()
+            .borrow();
+        // With a color lut the scene is drawn offscreen and graded into the frame at the end
+        let view = if lut_pipeline.is_enabled() {
+            &lut_pipeline.scene_texture.view
+        } else {
+            &frame_view
+        };
+
+        // Draw the sky behind everything
+        self.pipeline_manager
+            .sky_pipeline
             .as_ref()
             .unwrap()
             .borrow()
-            .render(self, &mut encoder, &view, &player, &chunks);
+            .render(self, &mut encoder, view, &player, &chunks);
+
+        // Draw main pipeline, or the wireframe one in its place
+        if self.debug_wireframe {
+            self.pipeline_manager
+                .wireframe_pipeline
+                .as_ref()
+                .unwrap()
+                .borrow()
+                .render(self, &mut encoder, view, &player, &chunks)
+        } else {
+            self.pipeline_manager
+                .main_pipeline
+                .as_ref()
+                .unwrap()
+                .borrow()
+                .render(self, &mut encoder, view, &player, &chunks)
+        }
+
+        // Clouds before the water, they're behind it unless the player flies above them
+        if let Some(clouds_pipeline) = self.pipeline_manager.clouds_pipeline.as_ref() {
+            clouds_pipeline
+                .borrow()
+                .render(self, &mut encoder, view, &player, &chunks);
+        }
 
         // Draw translucent pipeline
         let _ = &self
@@ -335,7 +705,7 @@ impl State {
             .as_ref()
             .unwrap()
             .borrow()
-            .render(self, &mut encoder, &view, &player, &chunks);
+            .render(self, &mut encoder, view, &player, &chunks);
 
         let _ = &self
             .pipeline_manager
@@ -343,7 +713,7 @@ impl State {
             .as_ref()
             .unwrap()
             .borrow()
-            .render(self, &mut encoder, &view, &player, &chunks);
+            .render(self, &mut encoder, view, &player, &chunks);
 
         let _ = &self
             .pipeline_manager
@@ -351,13 +721,242 @@ impl State {
             .as_ref()
             .unwrap()
             .borrow()
-            .render(self, &mut encoder, &view, &player, &chunks);
+            .render(self, &mut encoder, view, &player, &chunks);
 
-        self.queue.submit(Some(encoder.finish()));
-        frame.present();
+        let _ = &self
+            .pipeline_manager
+            .text_pipeline
+            .as_ref()
+            .unwrap()
+            .borrow()
+            .render(self, &mut encoder, view, &player, &chunks);
+
+        if lut_pipeline.is_enabled() {
+            lut_pipeline.render(self, &mut encoder, &frame_view, &player, &chunks);
+        }
+        encoder
+    }
+}
+
+// An sRGB format when the surface has one, so the shaders output linear colors and the
+// hardware encodes them, else the first (preferred) one
+fn preferred_surface_format(formats: &[wgpu::TextureFormat]) -> wgpu::TextureFormat {
+    formats
+        .iter()
+        .copied()
+        .find(|format| format.is_srgb())
+        .unwrap_or(formats[0])
+}
+
+// Fifo (vsync) is the only mode every surface has
+fn supported_present_mode(
+    requested: wgpu::PresentMode,
+    available: &[wgpu::PresentMode],
+) -> wgpu::PresentMode {
+    if available.contains(&requested) {
+        return requested;
+    }
+    println!("{requested:?} present mode not supported, using Fifo");
+    wgpu::PresentMode::Fifo
+}
+
+// The configured msaa sample count if the frame and depth formats support it, else 1
+fn supported_sample_count(adapter: &wgpu::Adapter, format: wgpu::TextureFormat, count: u32) -> u32 {
+    let supported = [format, Texture::DEPTH_FORMAT].iter().all(|format| {
+        adapter
+            .get_texture_format_features(*format)
+            .flags
+            .sample_count_supported(count)
+    });
+    if !supported {
+        println!("{count}x msaa not supported, drawing without it");
+        return 1;
+    }
+    count
+}
+
+// Number keys 1-9 pick the hotbar slot directly
+fn hotbar_slot_for_key(code: KeyCode) -> Option<usize> {
+    match code {
+        KeyCode::Digit1 => Some(0),
+        KeyCode::Digit2 => Some(1),
+        KeyCode::Digit3 => Some(2),
+        KeyCode::Digit4 => Some(3),
+        KeyCode::Digit5 => Some(4),
+        KeyCode::Digit6 => Some(5),
+        KeyCode::Digit7 => Some(6),
+        KeyCode::Digit8 => Some(7),
+        KeyCode::Digit9 => Some(8),
+        _ => None,
     }
 }
 
 pub struct Config {
+    // Line starts in the wireframe view
     pub polygon_mode: wgpu::PolygonMode,
+    // If set, the save directory is packed into this file on save, and the world is loaded
+    // from it when the save directory doesn't have the files.
+    pub world_archive: Option<String>,
+    // Run light propagation in a compute shader, falls back to the cpu if unsupported
+    pub gpu_lighting: bool,
+    // Stream new chunks from a world gen thread instead of blocking the frame until they're generated
+    pub background_worldgen: bool,
+    // Render both sides of translucent faces (water, glass) instead of culling them like opaque ones.
+    // Off by default, the back faces cost extra fragment work
+    pub double_sided_translucent: bool,
+    // Color grading lut applied as the last pass, a strip (size^2 x size) or hald image
+    pub lut_path: Option<String>,
+    // 0 shows the original colors, 1 the fully graded ones
+    pub lut_intensity: f32,
+    // Chunks with identical geometry share a single vertex/index buffer, saves memory in flat worlds
+    pub dedup_chunk_meshes: bool,
+    // Merge neighbouring faces of the same block into bigger quads, less vertices to upload and draw
+    pub greedy_meshing: bool,
+    // Weight of the newest frame time in the moving average (1 = no smoothing), None uses the raw frame time
+    pub frame_smoothing: Option<f32>,
+    // Frame times are clamped to this many seconds when smoothing, so a stall doesn't launch the player
+    pub max_frame_time: f32,
+    // Chunks this many chunks away from the player get coarser meshes (2x2x2 blocks merged, 4x4x4
+    // from twice as far), None keeps every chunk at full detail
+    pub lod_distance: Option<u32>,
+    // Load chunks in this radius (in chunks) around the player and unload the rest,
+    // None keeps the fixed CHUNKS_PER_ROW grid
+    pub chunk_streaming_radius: Option<u32>,
+    // Worlds with the same seed generate the same terrain, a saved world keeps its own
+    pub seed: u64,
+    // Saves in WORLDS_DIR/<name> instead of SAVE_DIR
+    pub world_name: Option<String>,
+    // Seconds between autosaves, None only saves on exit
+    pub autosave_interval: Option<f32>,
+    // Real seconds for a whole day/night cycle
+    pub day_length: f32,
+    // How far away blocks can be selected
+    pub reach_distance: f32,
+    // Horizontal speed multiplier while flying (double tap space)
+    pub fly_speed_factor: f32,
+    // Chunk dimensions, saves are only readable with the chunk size they were made with
+    pub world: WorldConfig,
+    // Samples per pixel for antialiasing: 1 (off), 2, 4 or 8
+    pub msaa_samples: u32,
+    // Draw the cloud layer, off saves a pass and the fill rate of a plane over the screen
+    pub clouds: bool,
+    // Crisp or smooth atlas texels up close, changed with `State::set_texture_filtering`
+    pub texture_filtering: TextureFiltering,
+    // Sharper blocks at glancing angles (1 is off), only with the Smooth filtering
+    pub max_anisotropy: u16,
+    // Fifo waits for the display (vsync), Mailbox replaces the queued frame without tearing and
+    // Immediate tears. Unsupported modes fall back to Fifo, changed with `State::set_present_mode`
+    pub present_mode: wgpu::PresentMode,
+    // Frames per second cap on the cpu, mostly for Immediate so it doesn't spin in menus
+    pub max_fps: Option<u32>,
+}
+
+impl Config {
+    // Sampler of the block textures
+    pub fn block_sampler(&self) -> SamplerConfig {
+        SamplerConfig::new(self.texture_filtering, self.max_anisotropy)
+    }
+    // The defaults with the command line options: `--seed <u64>` and `--world <name>`
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Config, Box<dyn Error>> {
+        let mut config = Config::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("Missing the value of {arg}"));
+            match arg.as_str() {
+                "--seed" => {
+                    let seed = value()?;
+                    config.seed = seed.parse().map_err(|_| format!("Invalid seed {seed}"))?;
+                }
+                "--world" => config.world_name = Some(value()?),
+                _ => return Err(format!("Unknown option {arg}").into()),
+            }
+        }
+        Ok(config)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            polygon_mode: wgpu::PolygonMode::Fill,
+            world_archive: None,
+            gpu_lighting: false,
+            background_worldgen: false,
+            double_sided_translucent: false,
+            lut_path: None,
+            lut_intensity: 1.0,
+            dedup_chunk_meshes: false,
+            greedy_meshing: false,
+            frame_smoothing: None,
+            max_frame_time: 0.1,
+            lod_distance: None,
+            chunk_streaming_radius: None,
+            seed: RNG_SEED,
+            world_name: None,
+            autosave_interval: Some(60.0),
+            day_length: 600.0,
+            reach_distance: REACH_DISTANCE,
+            fly_speed_factor: DEFAULT_FLY_SPEED_FACTOR,
+            world: WorldConfig::default(),
+            msaa_samples: 4,
+            clouds: true,
+            texture_filtering: TextureFiltering::default(),
+            max_anisotropy: 16,
+            present_mode: wgpu::PresentMode::Fifo,
+            max_fps: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{preferred_surface_format, supported_present_mode, Config};
+    use wgpu::{PresentMode, TextureFormat};
+
+    #[test]
+    fn should_prefer_an_srgb_surface_format() {
+        let formats = [
+            TextureFormat::Bgra8Unorm,
+            TextureFormat::Rgba8Unorm,
+            TextureFormat::Bgra8UnormSrgb,
+        ];
+        assert_eq!(
+            preferred_surface_format(&formats),
+            TextureFormat::Bgra8UnormSrgb
+        );
+        // Without one the surface's own preference is kept
+        assert_eq!(
+            preferred_surface_format(&[TextureFormat::Rgba16Float, TextureFormat::Bgra8Unorm]),
+            TextureFormat::Rgba16Float
+        );
+    }
+
+    #[test]
+    fn should_fall_back_to_fifo_for_unsupported_present_modes() {
+        let available = [PresentMode::Fifo, PresentMode::Immediate];
+        assert_eq!(
+            supported_present_mode(PresentMode::Immediate, &available),
+            PresentMode::Immediate
+        );
+        assert_eq!(
+            supported_present_mode(PresentMode::Mailbox, &available),
+            PresentMode::Fifo
+        );
+    }
+
+    #[test]
+    fn should_read_the_seed_and_the_world_from_the_arguments() {
+        let args = |args: &[&str]| Config::from_args(args.iter().map(|arg| arg.to_string()));
+        let config = args(&["--world", "island", "--seed", "1234"]).unwrap();
+        assert_eq!(config.seed, 1234);
+        assert_eq!(config.world_name.as_deref(), Some("island"));
+
+        let config = args(&[]).unwrap();
+        assert_eq!(config.seed, Config::default().seed);
+        assert_eq!(config.world_name, None);
+
+        assert!(args(&["--seed", "-3"]).is_err());
+        assert!(args(&["--seed"]).is_err());
+        assert!(args(&["--fast"]).is_err());
+    }
 }
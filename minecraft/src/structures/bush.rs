@@ -0,0 +1,29 @@
+use std::sync::{Arc, RwLock};
+
+use crate::blocks::{block::Block, block_type::BlockType};
+
+use super::{block_at, Structure};
+
+// A small cluster of leaves on the ground, without a trunk
+pub struct Bush;
+
+impl Structure for Bush {
+    fn kind(&self) -> &'static str {
+        "bush"
+    }
+    fn get_blocks(&self, position: glam::Vec3, chunk_size: u32) -> Vec<Arc<RwLock<Block>>> {
+        let mut blocks = vec![];
+        for x in -1..=1 {
+            for z in -1..=1 {
+                let offset = glam::vec3(x as f32, 1.0, z as f32);
+                blocks.push(block_at(position + offset, BlockType::Leaf, chunk_size));
+            }
+        }
+        blocks.push(block_at(
+            position + glam::vec3(0.0, 2.0, 0.0),
+            BlockType::Leaf,
+            chunk_size,
+        ));
+        blocks
+    }
+}
@@ -0,0 +1,48 @@
+use std::sync::{Arc, RwLock};
+
+use glam::Vec3;
+
+use crate::blocks::{block::Block, block_type::BlockType};
+use crate::chunk::{Chunk, ChunkBlocks};
+
+use super::{block_at, Structure};
+
+const MAX_HEIGHT: i32 = 3;
+
+// A column of cactus, 1 to 3 blocks tall
+pub struct Cactus;
+
+impl Cactus {
+    // From the position, so a chunk gets the same cacti every time it's generated
+    fn height(position: Vec3) -> i32 {
+        let hash = (position.x as i32).wrapping_mul(73_856_093)
+            ^ (position.z as i32).wrapping_mul(19_349_663);
+        1 + hash.rem_euclid(MAX_HEIGHT)
+    }
+}
+
+impl Structure for Cactus {
+    fn kind(&self) -> &'static str {
+        "cactus"
+    }
+    fn get_blocks(&self, position: Vec3, chunk_size: u32) -> Vec<Arc<RwLock<Block>>> {
+        (1..=Self::height(position))
+            .map(|y| block_at(position + Vec3::Y * y as f32, BlockType::Cactus, chunk_size))
+            .collect()
+    }
+    // Only on sand, not next to water and with nothing next to it as high as it can grow. The
+    // neighbour chunks aren't there while generating, so it also keeps off the chunk's border
+    fn can_grow(&self, position: Vec3, blocks: &ChunkBlocks) -> bool {
+        let is_clear = |ground: Vec3| {
+            (1..=MAX_HEIGHT).all(|y| blocks.type_at(&(ground + Vec3::Y * y as f32)).is_none())
+        };
+        blocks.type_at(&position) == Some(BlockType::Sand)
+            && is_clear(position)
+            && [Vec3::X, -Vec3::X, Vec3::Z, -Vec3::Z].iter().all(|side| {
+                let next = position + *side;
+                !Chunk::is_outside_chunk(&next, blocks.chunk_size())
+                    && blocks.type_at(&next) != Some(BlockType::Water)
+                    && is_clear(next)
+            })
+    }
+}
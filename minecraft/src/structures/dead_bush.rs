@@ -0,0 +1,21 @@
+use std::sync::{Arc, RwLock};
+
+use crate::blocks::{block::Block, block_type::BlockType};
+
+use super::{block_at, Structure};
+
+// A single dry plant, drawn as a cross and walked through
+pub struct DeadBush;
+
+impl Structure for DeadBush {
+    fn kind(&self) -> &'static str {
+        "dead bush"
+    }
+    fn get_blocks(&self, position: glam::Vec3, chunk_size: u32) -> Vec<Arc<RwLock<Block>>> {
+        vec![block_at(
+            position + glam::Vec3::Y,
+            BlockType::DeadBush,
+            chunk_size,
+        )]
+    }
+}
@@ -1,11 +1,224 @@
+pub mod bush;
+pub mod cactus;
+pub mod dead_bush;
+pub mod pine;
 pub mod tree;
 
+use std::collections::BTreeMap;
 use std::sync::{Arc, RwLock};
 
+use rand::rngs::StdRng;
+
+use crate::blocks::block_type::BlockType;
+use crate::chunk::ChunkBlocks;
+use crate::utils::{ChunkFromPosition, RelativeFromAbsolute};
+
+// Object safe, so the species of a biome can be held in a slice
 pub trait Structure {
-    // position: Initial absolute position
-    fn get_blocks(position: glam::Vec3) -> Vec<Arc<RwLock<Block>>>;
+    // Short name, for debugging
+    fn kind(&self) -> &'static str;
+    // position: Initial absolute position, the blocks are split into chunks of `chunk_size`
+    fn get_blocks(&self, position: glam::Vec3, chunk_size: u32) -> Vec<Arc<RwLock<Block>>>;
+    // A variant drawn from `rng`, the same seed builds the same structure. Species with a single
+    // shape ignore it
+    fn get_blocks_seeded(
+        &self,
+        position: glam::Vec3,
+        chunk_size: u32,
+        _rng: &mut StdRng,
+    ) -> Vec<Arc<RwLock<Block>>> {
+        self.get_blocks(position, chunk_size)
+    }
+    // Whether it can grow on top of the block at `position`, relative to the chunk being generated
+    fn can_grow(&self, _position: glam::Vec3, _blocks: &ChunkBlocks) -> bool {
+        true
+    }
 }
+pub use bush::Bush;
+pub use cactus::Cactus;
+pub use dead_bush::DeadBush;
+pub use pine::Pine;
 pub use tree::Tree;
 
 use crate::blocks::block::Block; // Reexport into structures module
+
+// Block of a structure at an absolute position, it can end up in a neighbour chunk
+fn block_at(position: glam::Vec3, block_type: BlockType, chunk_size: u32) -> Arc<RwLock<Block>> {
+    Arc::new(RwLock::new(Block::new(
+        position.relative_from_absolute(chunk_size),
+        position.get_chunk_from_position_absolute(chunk_size),
+        block_type,
+        chunk_size,
+    )))
+}
+// Adds a snow block on top of every column of the structure, for the trees of snowy places
+pub fn covered_with_snow(mut blocks: Vec<Arc<RwLock<Block>>>) -> Vec<Arc<RwLock<Block>>> {
+    let mut tops: BTreeMap<(i32, i32), (glam::Vec3, u32)> = BTreeMap::new();
+    for block in blocks.iter() {
+        let block = block.read().unwrap();
+        let position = block.absolute_position;
+        let top = tops
+            .entry((position.x as i32, position.z as i32))
+            .or_insert((position, block.chunk_size));
+        if position.y > top.0.y {
+            top.0 = position;
+        }
+    }
+    blocks.extend(
+        tops.into_values()
+            .map(|(top, chunk_size)| block_at(top + glam::Vec3::Y, BlockType::Snow, chunk_size)),
+    );
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::{covered_with_snow, Bush, Cactus, DeadBush, Pine, Structure, Tree};
+    use crate::blocks::block_type::BlockType;
+    use crate::world::DEFAULT_CHUNK_SIZE;
+
+    #[test]
+    fn should_build_each_species_with_its_shape() {
+        let position = glam::vec3(5.0, 10.0, 7.0);
+        // Wood, leaves, and the bounding box of the blocks relative to the position
+        let species: [(&dyn Structure, usize, usize, glam::Vec3, glam::Vec3); 3] = [
+            (
+                &Tree,
+                3,
+                17,
+                glam::vec3(-1.0, 1.0, -1.0),
+                glam::vec3(1.0, 5.0, 1.0),
+            ),
+            (
+                &Pine,
+                6,
+                57,
+                glam::vec3(-2.0, 1.0, -2.0),
+                glam::vec3(2.0, 7.0, 2.0),
+            ),
+            (
+                &Bush,
+                0,
+                10,
+                glam::vec3(-1.0, 1.0, -1.0),
+                glam::vec3(1.0, 2.0, 1.0),
+            ),
+        ];
+        for (structure, wood, leaves, min, max) in species {
+            let blocks = structure.get_blocks(position, DEFAULT_CHUNK_SIZE);
+            let count = |block_type: BlockType| {
+                blocks
+                    .iter()
+                    .filter(|b| b.read().unwrap().block_type == block_type)
+                    .count()
+            };
+            assert_eq!(count(BlockType::Wood), wood, "{}", structure.kind());
+            assert_eq!(count(BlockType::Leaf), leaves, "{}", structure.kind());
+            assert_eq!(blocks.len(), wood + leaves, "{}", structure.kind());
+
+            let offsets: Vec<_> = blocks
+                .iter()
+                .map(|b| b.read().unwrap().absolute_position - position)
+                .collect();
+            let bounds_min = offsets.iter().fold(glam::Vec3::MAX, |a, b| a.min(*b));
+            let bounds_max = offsets.iter().fold(glam::Vec3::MIN, |a, b| a.max(*b));
+            assert_eq!(bounds_min, min, "{}", structure.kind());
+            assert_eq!(bounds_max, max, "{}", structure.kind());
+
+            // No two blocks in the same place
+            let mut unique = offsets
+                .iter()
+                .map(|o| o.to_array().map(f32::to_bits))
+                .collect::<Vec<_>>();
+            unique.sort();
+            unique.dedup();
+            assert_eq!(unique.len(), blocks.len(), "{}", structure.kind());
+        }
+    }
+
+    #[test]
+    fn should_put_snow_on_top_of_every_column_of_the_canopy() {
+        let position = glam::vec3(5.0, 10.0, 7.0);
+        let covered = covered_with_snow(Pine.get_blocks(position, DEFAULT_CHUNK_SIZE));
+        let positions: Vec<_> = covered
+            .iter()
+            .map(|b| b.read().unwrap().absolute_position)
+            .collect();
+        let (tree, snow) = positions.split_at(Pine.get_blocks(position, DEFAULT_CHUNK_SIZE).len());
+        assert!(covered[..tree.len()]
+            .iter()
+            .all(|b| b.read().unwrap().block_type != BlockType::Snow));
+
+        // The widest ring is 5x5 with rounded corners, each snow block is right above the highest
+        // block of its column
+        assert_eq!(snow.len(), 21);
+        for (top, block) in snow.iter().zip(&covered[tree.len()..]) {
+            assert_eq!(block.read().unwrap().block_type, BlockType::Snow);
+            let below = tree.iter().filter(|p| p.x == top.x && p.z == top.z);
+            assert_eq!(below.fold(f32::MIN, |a, p| a.max(p.y)), top.y - 1.0);
+        }
+    }
+
+    #[test]
+    fn should_grow_cacti_up_to_three_blocks_and_a_single_dead_bush() {
+        let mut heights = vec![];
+        for x in 0..32 {
+            let position = glam::vec3(x as f32, 4.0, 9.0);
+            let cactus = Cactus.get_blocks(position, DEFAULT_CHUNK_SIZE);
+            assert!(cactus.iter().enumerate().all(|(i, b)| {
+                let b = b.read().unwrap();
+                b.block_type == BlockType::Cactus
+                    && b.absolute_position == position + glam::Vec3::Y * (i + 1) as f32
+            }));
+            // The same every time
+            assert_eq!(
+                Cactus.get_blocks(position, DEFAULT_CHUNK_SIZE).len(),
+                cactus.len()
+            );
+            heights.push(cactus.len());
+        }
+        heights.sort();
+        heights.dedup();
+        assert_eq!(heights, [1, 2, 3]);
+
+        let bush = DeadBush.get_blocks(glam::vec3(1.0, 4.0, 2.0), DEFAULT_CHUNK_SIZE);
+        assert_eq!(bush.len(), 1);
+        let bush = bush[0].read().unwrap();
+        assert_eq!(bush.block_type, BlockType::DeadBush);
+        assert_eq!(bush.absolute_position, glam::vec3(1.0, 5.0, 2.0));
+    }
+
+    #[test]
+    fn should_grow_the_same_tree_from_the_same_seed() {
+        let position = glam::vec3(5.0, 10.0, 7.0);
+        let tree = |seed: u64| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            Tree.get_blocks_seeded(position, DEFAULT_CHUNK_SIZE, &mut rng)
+                .iter()
+                .map(|b| {
+                    let b = b.read().unwrap();
+                    (b.absolute_position - position, b.block_type)
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(tree(7), tree(7));
+
+        let trees: Vec<_> = (0..16).map(tree).collect();
+        assert!(trees.iter().any(|t| *t != trees[0]));
+        // Small, tall and birch trees all show up
+        let trunk = |t: &[(glam::Vec3, BlockType)], wood: BlockType| {
+            t.iter()
+                .filter(|(_, block_type)| *block_type == wood)
+                .count()
+        };
+        assert!(trees.iter().any(|t| trunk(t, BlockType::Wood) == 3));
+        assert!(trees.iter().any(|t| trunk(t, BlockType::Wood) >= 5));
+        assert!(trees
+            .iter()
+            .any(|t| trunk(t, BlockType::BirchWood) >= 5
+                && t.iter().all(|(_, b)| *b != BlockType::Leaf)));
+    }
+}
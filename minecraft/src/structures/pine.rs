@@ -0,0 +1,51 @@
+use std::sync::{Arc, RwLock};
+
+use crate::blocks::{block::Block, block_type::BlockType};
+
+use super::{block_at, Structure};
+
+const TRUNK_HEIGHT: i32 = 6;
+// Height above the ground and radius of each ring of leaves around the trunk, wide and narrow
+// rings alternate
+const LEAF_RINGS: [(i32, i32); 4] = [(3, 2), (4, 1), (5, 2), (6, 1)];
+
+// Tall tree with layered rings of leaves and a single leaf on top
+pub struct Pine;
+
+impl Structure for Pine {
+    fn kind(&self) -> &'static str {
+        "pine"
+    }
+    fn get_blocks(&self, position: glam::Vec3, chunk_size: u32) -> Vec<Arc<RwLock<Block>>> {
+        let mut blocks: Vec<_> = (1..=TRUNK_HEIGHT)
+            .map(|y| {
+                block_at(
+                    position + glam::vec3(0.0, y as f32, 0.0),
+                    BlockType::Wood,
+                    chunk_size,
+                )
+            })
+            .collect();
+
+        for (y, radius) in LEAF_RINGS {
+            for x in -radius..=radius {
+                for z in -radius..=radius {
+                    let is_trunk = x == 0 && z == 0;
+                    // Rounded corners on the wide rings
+                    let is_corner = radius > 1 && x.abs() == radius && z.abs() == radius;
+                    if is_trunk || is_corner {
+                        continue;
+                    }
+                    let offset = glam::vec3(x as f32, y as f32, z as f32);
+                    blocks.push(block_at(position + offset, BlockType::Leaf, chunk_size));
+                }
+            }
+        }
+        blocks.push(block_at(
+            position + glam::vec3(0.0, (TRUNK_HEIGHT + 1) as f32, 0.0),
+            BlockType::Leaf,
+            chunk_size,
+        ));
+        blocks
+    }
+}
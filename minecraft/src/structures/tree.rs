@@ -1,16 +1,94 @@
 use std::sync::{Arc, RwLock};
 
-use crate::{
-    blocks::{block::Block, block_type::BlockType},
-    utils::{ChunkFromPosition, RelativeFromAbsolute},
-};
+use rand::rngs::StdRng;
+use rand::Rng;
 
-use super::Structure;
+use crate::blocks::{block::Block, block_type::BlockType};
 
+use super::{block_at, Structure};
+
+// Chance of keeping each corner of a ring of leaves, rounded or square canopies
+const CORNER_CHANCE: f64 = 0.5;
+
+// Small by default, the seeded variants are also tall or birch trees
 pub struct Tree;
 
+impl Tree {
+    // Trunk of 5 to 6 blocks under two wide and two narrow layers of leaves
+    fn tall(position: glam::Vec3, chunk_size: u32, rng: &mut StdRng) -> Vec<Arc<RwLock<Block>>> {
+        let trunk_height = rng.gen_range(5..=6);
+        let mut blocks = trunk(position, trunk_height, BlockType::Wood, chunk_size);
+        for (y, radius) in [
+            (trunk_height - 2, 2),
+            (trunk_height - 1, 2),
+            (trunk_height, 1),
+            (trunk_height + 1, 1),
+        ] {
+            // The top layer is a plus, the others lose some corners
+            let corner_chance = if y > trunk_height { 0.0 } else { CORNER_CHANCE };
+            let ring = leaf_ring(position, y, radius, corner_chance, trunk_height, rng);
+            blocks.extend(
+                ring.into_iter()
+                    .map(|p| block_at(p, BlockType::Leaf, chunk_size)),
+            );
+        }
+        blocks
+    }
+    // Thin and tall, a narrow canopy along the top half of the trunk
+    fn birch(position: glam::Vec3, chunk_size: u32, rng: &mut StdRng) -> Vec<Arc<RwLock<Block>>> {
+        let trunk_height = rng.gen_range(5..=7);
+        let mut blocks = trunk(position, trunk_height, BlockType::BirchWood, chunk_size);
+        for y in trunk_height - 2..=trunk_height + 1 {
+            let corner_chance = if y > trunk_height { 0.0 } else { CORNER_CHANCE };
+            let ring = leaf_ring(position, y, 1, corner_chance, trunk_height, rng);
+            blocks.extend(
+                ring.into_iter()
+                    .map(|p| block_at(p, BlockType::BirchLeaf, chunk_size)),
+            );
+        }
+        blocks
+    }
+}
+// Column of wood from the block above the position
+fn trunk(
+    position: glam::Vec3,
+    height: i32,
+    wood: BlockType,
+    chunk_size: u32,
+) -> Vec<Arc<RwLock<Block>>> {
+    (1..=height)
+        .map(|y| block_at(position + glam::vec3(0.0, y as f32, 0.0), wood, chunk_size))
+        .collect()
+}
+// Positions of a square of leaves around the trunk at `y` above the position, every corner is
+// kept with `corner_chance`
+fn leaf_ring(
+    position: glam::Vec3,
+    y: i32,
+    radius: i32,
+    corner_chance: f64,
+    trunk_height: i32,
+    rng: &mut StdRng,
+) -> Vec<glam::Vec3> {
+    let mut positions = vec![];
+    for x in -radius..=radius {
+        for z in -radius..=radius {
+            let is_trunk = x == 0 && z == 0 && y <= trunk_height;
+            let is_corner = x.abs() == radius && z.abs() == radius;
+            if is_trunk || (is_corner && !rng.gen_bool(corner_chance)) {
+                continue;
+            }
+            positions.push(position + glam::vec3(x as f32, y as f32, z as f32));
+        }
+    }
+    positions
+}
+
 impl Structure for Tree {
-    fn get_blocks(position: glam::Vec3) -> Vec<Arc<RwLock<Block>>> {
+    fn kind(&self) -> &'static str {
+        "tree"
+    }
+    fn get_blocks(&self, position: glam::Vec3, chunk_size: u32) -> Vec<Arc<RwLock<Block>>> {
         let trunk_pos = [
             position + glam::vec3(0.0, 1.0, 0.0),
             position + glam::vec3(0.0, 2.0, 0.0),
@@ -41,21 +119,25 @@ impl Structure for Tree {
             position + glam::vec3(0.0, 5.0, 0.0),
         ];
 
-        let blocks = trunk_pos.iter().map(|p| {
-            Arc::new(RwLock::new(Block::new(
-                p.relative_from_absolute(),
-                p.get_chunk_from_position_absolute(),
-                BlockType::Wood,
-            )))
-        });
-        let leafs_iter = leafs_pos.iter().map(|p| {
-            Arc::new(RwLock::new(Block::new(
-                p.relative_from_absolute(),
-                p.get_chunk_from_position_absolute(),
-                BlockType::Leaf,
-            )))
-        });
-        
+        let blocks = trunk_pos
+            .iter()
+            .map(|p| block_at(*p, BlockType::Wood, chunk_size));
+        let leafs_iter = leafs_pos
+            .iter()
+            .map(|p| block_at(*p, BlockType::Leaf, chunk_size));
+
         blocks.chain(leafs_iter).collect::<Vec<_>>()
     }
+    fn get_blocks_seeded(
+        &self,
+        position: glam::Vec3,
+        chunk_size: u32,
+        rng: &mut StdRng,
+    ) -> Vec<Arc<RwLock<Block>>> {
+        match rng.gen_range(0..3) {
+            0 => self.get_blocks(position, chunk_size),
+            1 => Tree::tall(position, chunk_size, rng),
+            _ => Tree::birch(position, chunk_size, rng),
+        }
+    }
 }
@@ -1,7 +1,8 @@
-use crate::world::CHUNK_SIZE;
 use glam::{vec3, Vec3};
 
 pub(crate) mod math_utils {
+    use crate::collision::CollisionBox;
+
     #[derive(Debug)]
     pub struct Plane {
         pub point: glam::Vec3,
@@ -12,30 +13,137 @@ pub(crate) mod math_utils {
             (point - self.point).dot(self.normal)
         }
     }
+
+    // https://www.lighthouse3d.com/tutorials/view-frustum-culling/
+    // Every normal points inside the frustum
+    #[derive(Debug)]
+    pub struct Frustum {
+        pub planes: [Plane; 6],
+    }
+    impl Frustum {
+        // fovy in radians, forward is expected to be normalized
+        pub fn new(
+            eye: glam::Vec3,
+            forward: glam::Vec3,
+            fovy: f32,
+            aspect_ratio: f32,
+            znear: f32,
+            zfar: f32,
+        ) -> Self {
+            let (right, up) = Self::basis(forward);
+            let half_v = f32::tan(fovy / 2.0);
+            let half_h = half_v * aspect_ratio;
+
+            let side = |normal: glam::Vec3| Plane {
+                point: eye,
+                normal: normal.normalize(),
+            };
+            Self {
+                planes: [
+                    Plane {
+                        point: eye + znear * forward,
+                        normal: forward,
+                    },
+                    Plane {
+                        point: eye + zfar * forward,
+                        normal: -forward,
+                    },
+                    side(up.cross(forward - right * half_h)),
+                    side((forward + right * half_h).cross(up)),
+                    side(right.cross(forward + up * half_v)),
+                    side((forward - up * half_v).cross(right)),
+                ],
+            }
+        }
+        // A box instead of a pyramid, the sides are `half_width` and `half_height` away from the
+        // line of sight
+        pub fn orthographic(
+            eye: glam::Vec3,
+            forward: glam::Vec3,
+            half_width: f32,
+            half_height: f32,
+            znear: f32,
+            zfar: f32,
+        ) -> Self {
+            let (right, up) = Self::basis(forward);
+            let side = |offset: glam::Vec3, normal: glam::Vec3| Plane {
+                point: eye + offset,
+                normal,
+            };
+            Self {
+                planes: [
+                    side(znear * forward, forward),
+                    side(zfar * forward, -forward),
+                    side(-right * half_width, right),
+                    side(right * half_width, -right),
+                    side(-up * half_height, up),
+                    side(up * half_height, -up),
+                ],
+            }
+        }
+        fn basis(forward: glam::Vec3) -> (glam::Vec3, glam::Vec3) {
+            let mut right = glam::Vec3::Y.cross(forward);
+            // Looking straight up or down
+            if right.length_squared() < 1e-6 {
+                right = glam::Vec3::X;
+            }
+            let right = right.normalize();
+            (right, forward.cross(right))
+        }
+        // Conservative: a box that is close to a corner of the frustum may pass while being outside
+        pub fn intersects_aabb(&self, aabb: &CollisionBox) -> bool {
+            self.planes.iter().all(|plane| {
+                // Corner of the box the furthest along the normal
+                let corner = glam::vec3(
+                    if plane.normal.x >= 0.0 {
+                        aabb.max_x
+                    } else {
+                        aabb.min_x
+                    },
+                    if plane.normal.y >= 0.0 {
+                        aabb.max_y
+                    } else {
+                        aabb.min_y
+                    },
+                    if plane.normal.z >= 0.0 {
+                        aabb.max_z
+                    } else {
+                        aabb.min_z
+                    },
+                );
+                plane.signed_plane_dist(corner) >= 0.0
+            })
+        }
+    }
 }
-pub(crate) mod noise {
+pub mod noise {
     use std::fmt::Debug;
 
-    use crate::world::RNG_SEED;
+    use crate::world::{NoiseData, DEFAULT_CHUNK_SIZE};
 
-    use glam::Vec2;
+    use glam::{Vec2, Vec3};
 
     const WRAP: u32 = 256;
-    lazy_static! {
-        pub static ref PERM_TABLE: Vec<u32> = {
+
+    // Shuffled 0..WRAP twice in a row, so perm[perm[x] + y] never goes out of bounds
+    #[derive(Debug, Clone)]
+    pub struct PermutationTable(Vec<u32>);
+
+    impl PermutationTable {
+        pub fn new(seed: u64) -> Self {
             let mut table: Vec<u32> = (0..WRAP).collect();
-            shuffle(&mut table);
+            shuffle(&mut table, seed);
             for i in 0..WRAP {
                 table.push(table[i as usize]);
             }
-            table
-        };
+            Self(table)
+        }
     }
 
-    pub fn shuffle<T: Copy + Debug>(vec: &mut Vec<T>) -> &mut Vec<T> {
+    pub fn shuffle<T: Copy + Debug>(vec: &mut Vec<T>, seed: u64) -> &mut Vec<T> {
         use rand::prelude::*;
 
-        let mut rng = StdRng::seed_from_u64(RNG_SEED);
+        let mut rng = StdRng::seed_from_u64(seed);
 
         for i in (0..vec.len()).rev() {
             let a: usize = if i > 0 {
@@ -60,21 +168,43 @@ pub(crate) mod noise {
         }
     }
 
+    // Edge directions of a cube, the first 4 repeated to fill the 16 values
+    fn get_corner_consts_3d(v: u32) -> Vec3 {
+        match v & 15 {
+            0 | 12 => glam::vec3(1.0, 1.0, 0.0),
+            1 | 13 => glam::vec3(-1.0, 1.0, 0.0),
+            2 | 14 => glam::vec3(1.0, -1.0, 0.0),
+            3 | 15 => glam::vec3(-1.0, -1.0, 0.0),
+            4 => glam::vec3(1.0, 0.0, 1.0),
+            5 => glam::vec3(-1.0, 0.0, 1.0),
+            6 => glam::vec3(1.0, 0.0, -1.0),
+            7 => glam::vec3(-1.0, 0.0, -1.0),
+            8 => glam::vec3(0.0, 1.0, 1.0),
+            9 => glam::vec3(0.0, -1.0, 1.0),
+            10 => glam::vec3(0.0, 1.0, -1.0),
+            _ => glam::vec3(0.0, -1.0, -1.0),
+        }
+    }
+
     // https://rtouti.github.io/graphics/perlin-noise-algorithm
     // https://gamedev.stackexchange.com/questions/23625/how-do-you-generate-tileable-perlin-noise
-    pub fn perlin_noise(x: f32, y: f32, per: u32) -> f32 {
-        let int_x = f32::floor(x) as u32;
-        let int_y = f32::floor(y) as u32;
+    pub fn perlin_noise(x: f32, y: f32, per: u32, perm: &PermutationTable) -> f32 {
+        // Signed grid coords, casting a negative floor to u32 would clamp it to 0
+        let int_x = f32::floor(x) as i32;
+        let int_y = f32::floor(y) as i32;
+        let per = per.max(1) as i32;
 
-        let surflet = |grid_x: u32, grid_y: u32| {
+        let surflet = |grid_x: i32, grid_y: i32| {
             let dist_x = f32::abs(x - grid_x as f32) % WRAP as f32;
             let dist_y = f32::abs(y - grid_y as f32) % WRAP as f32;
             let poly_x = 1.0 - 6.0 * f32::powi(dist_x, 5) + 15.0 * f32::powi(dist_x, 4)
                 - 10.0 * f32::powi(dist_x, 3);
             let poly_y = 1.0 - 6.0 * f32::powi(dist_y, 5) + 15.0 * f32::powi(dist_y, 4)
                 - 10.0 * f32::powi(dist_y, 3);
-            let hashed =
-                PERM_TABLE[(PERM_TABLE[(grid_x % per) as usize] + (grid_y % per)) as usize];
+            // rem_euclid keeps negative coords in 0..per, so the noise tiles the same way on both sides of 0
+            let wrapped_x = grid_x.rem_euclid(per) as u32 % WRAP;
+            let wrapped_y = grid_y.rem_euclid(per) as u32 % WRAP;
+            let hashed = perm.0[(perm.0[wrapped_x as usize] + wrapped_y) as usize];
             let grad = (x - grid_x as f32) * get_corner_consts(hashed).x
                 + (y - grid_y as f32) * get_corner_consts(hashed).y;
             poly_x * poly_y * grad
@@ -88,7 +218,43 @@ pub(crate) mod noise {
             1.0,
         )
     }
-    pub fn fbm(x: f32, y: f32, per: u32, octs: u32) -> f32 {
+    // Same surflets as perlin_noise with a third axis, the corner hash is perm[perm[perm[x] + y] + z]
+    pub fn perlin_noise_3d(x: f32, y: f32, z: f32, per: u32, perm: &PermutationTable) -> f32 {
+        let int_x = f32::floor(x) as i32;
+        let int_y = f32::floor(y) as i32;
+        let int_z = f32::floor(z) as i32;
+        let per = per.max(1) as i32;
+
+        let poly = |dist: f32| {
+            let dist = f32::abs(dist);
+            1.0 - 6.0 * f32::powi(dist, 5) + 15.0 * f32::powi(dist, 4) - 10.0 * f32::powi(dist, 3)
+        };
+        let surflet = |grid_x: i32, grid_y: i32, grid_z: i32| {
+            let dist = glam::vec3(x - grid_x as f32, y - grid_y as f32, z - grid_z as f32);
+            let wrapped_x = grid_x.rem_euclid(per) as u32 % WRAP;
+            let wrapped_y = grid_y.rem_euclid(per) as u32 % WRAP;
+            let wrapped_z = grid_z.rem_euclid(per) as u32 % WRAP;
+            let hashed = perm.0
+                [(perm.0[(perm.0[wrapped_x as usize] + wrapped_y) as usize] + wrapped_z) as usize];
+            poly(dist.x) * poly(dist.y) * poly(dist.z) * dist.dot(get_corner_consts_3d(hashed))
+        };
+
+        let mut value = 0.0;
+        for (dx, dy, dz) in [
+            (0, 0, 0),
+            (1, 0, 0),
+            (0, 1, 0),
+            (1, 1, 0),
+            (0, 0, 1),
+            (1, 0, 1),
+            (0, 1, 1),
+            (1, 1, 1),
+        ] {
+            value += surflet(int_x + dx, int_y + dy, int_z + dz);
+        }
+        f32::clamp(value, -1.0, 1.0)
+    }
+    pub fn fbm(x: f32, y: f32, per: u32, octs: u32, perm: &PermutationTable) -> f32 {
         let mut val: f32 = 0.0;
 
         for o in 0..octs {
@@ -97,26 +263,39 @@ pub(crate) mod noise {
                     x * f32::powi(2.0, o as i32),
                     y * f32::powi(2.0, o as i32),
                     (per as f32 * f32::powi(2.0, o as i32)) as u32,
+                    perm,
                 );
         }
         val
     }
     // pub fn surflet(gridX: u32, gridY: u32) {}
     // pub fn noise(x: f32, y: f32, per: f32) {}
-    pub fn create_world_noise_data(width: u32, height: u32, frequency: f32) -> Vec<f32> {
-        let mut data: Vec<f32> = Vec::with_capacity((width * height) as usize);
+    pub fn create_world_noise_data(
+        width: u32,
+        height: u32,
+        frequency: f32,
+        seed: u64,
+    ) -> NoiseData {
+        let perm = PermutationTable::new(seed);
+        let mut heights: Vec<f32> = Vec::with_capacity((width * height) as usize);
 
         for y in 0..height {
             for x in 0..width {
-                data.push(fbm(
+                heights.push(fbm(
                     (x as f32) * frequency,
                     (y as f32) * frequency,
                     (width as f32 * frequency) as u32,
                     4,
+                    &perm,
                 ));
             }
         }
-        data
+        NoiseData {
+            seed,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            heights,
+            perm,
+        }
     }
 }
 
@@ -128,22 +307,33 @@ pub(crate) mod threadpool {
 
     pub struct Worker {
         id: usize,
-        thread: thread::JoinHandle<()>,
+        // None once the worker has been joined
+        thread: Option<thread::JoinHandle<()>>,
     }
     impl Worker {
-        pub fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Job>>>) -> Worker {
+        pub fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Message>>>) -> Worker {
             let thread = thread::spawn(move || loop {
-                let receiver = receiver.lock().unwrap();
-                if let Ok(job) = receiver.recv() {
-                    job();
+                // The lock is released before running the job so the other workers can take the next one
+                let message = receiver.lock().unwrap().recv();
+                match message {
+                    Ok(Message::NewJob(job)) => job(),
+                    // A closed channel means the pool is gone too
+                    Ok(Message::Terminate) | Err(_) => break,
                 }
             });
-            Worker { id, thread }
+            Worker {
+                id,
+                thread: Some(thread),
+            }
         }
     }
+    pub enum Message {
+        NewJob(Job),
+        Terminate,
+    }
     pub struct ThreadPool {
         workers: Vec<Worker>,
-        sender: mpsc::Sender<Job>,
+        sender: Option<mpsc::Sender<Message>>,
     }
     type Job = Box<dyn FnOnce() + Send + 'static>;
     impl ThreadPool {
@@ -152,7 +342,11 @@ pub(crate) mod threadpool {
             F: FnOnce() + Send + 'static,
         {
             let job = Box::new(f);
-            self.sender.send(job).unwrap();
+            self.sender
+                .as_ref()
+                .unwrap()
+                .send(Message::NewJob(job))
+                .unwrap();
         }
         pub fn new(size: usize) -> ThreadPool {
             assert!(size > 0);
@@ -165,65 +359,293 @@ pub(crate) mod threadpool {
             for id in 0..size {
                 workers.push(Worker::new(id, Arc::clone(&receiver)))
             }
-            ThreadPool { workers, sender }
+            ThreadPool {
+                workers,
+                sender: Some(sender),
+            }
+        }
+    }
+    // Jobs already queued still run, the terminate messages are received after them
+    impl Drop for ThreadPool {
+        fn drop(&mut self) {
+            if let Some(sender) = self.sender.take() {
+                for _ in self.workers.iter() {
+                    let _ = sender.send(Message::Terminate);
+                }
+            }
+            for worker in self.workers.iter_mut() {
+                if let Some(thread) = worker.thread.take() {
+                    if thread.join().is_err() {
+                        println!("Worker {} panicked", worker.id);
+                    }
+                }
+            }
         }
     }
 }
 
 /* Utility traits */
+// Both take the width of the world's chunks
 pub trait ChunkFromPosition {
-    fn get_chunk_from_position_absolute(&self) -> (i32, i32);
+    fn get_chunk_from_position_absolute(&self, chunk_size: u32) -> (i32, i32);
 }
 
 pub trait RelativeFromAbsolute {
-    fn relative_from_absolute(&self) -> glam::Vec3;
+    fn relative_from_absolute(&self, chunk_size: u32) -> glam::Vec3;
 }
 
 impl RelativeFromAbsolute for glam::Vec3 {
-    fn relative_from_absolute(&self) -> Vec3 {
+    fn relative_from_absolute(&self, chunk_size: u32) -> Vec3 {
+        let size = chunk_size as f32;
         vec3(
-            ((f32::floor(self.x) % CHUNK_SIZE as f32) + CHUNK_SIZE as f32) % CHUNK_SIZE as f32,
+            ((f32::floor(self.x) % size) + size) % size,
             f32::max(f32::floor(self.y), 0.0),
-            ((f32::floor(self.z) % CHUNK_SIZE as f32) + CHUNK_SIZE as f32) % CHUNK_SIZE as f32,
+            ((f32::floor(self.z) % size) + size) % size,
         )
     }
 }
 
 impl ChunkFromPosition for glam::Vec3 {
-    fn get_chunk_from_position_absolute(&self) -> (i32, i32) {
+    fn get_chunk_from_position_absolute(&self, chunk_size: u32) -> (i32, i32) {
         (
-            (f32::floor(self.x / CHUNK_SIZE as f32)) as i32,
-            (f32::floor(self.z / CHUNK_SIZE as f32)) as i32,
+            (f32::floor(self.x / chunk_size as f32)) as i32,
+            (f32::floor(self.z / chunk_size as f32)) as i32,
         )
     }
 }
 
+// For the tests that need a gpu, None when the machine has no adapter and they should be skipped
+#[cfg(test)]
+pub(crate) fn test_adapter() -> Option<wgpu::Adapter> {
+    let instance = wgpu::Instance::default();
+    pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))
+}
+
+#[cfg(test)]
+pub(crate) fn test_device() -> Option<(std::sync::Arc<wgpu::Device>, std::sync::Arc<wgpu::Queue>)> {
+    let adapter = test_adapter()?;
+    let (device, queue) =
+        pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default(), None))
+            .unwrap();
+    Some((std::sync::Arc::new(device), std::sync::Arc::new(queue)))
+}
+
+// Empty folder under the system temp dir for the tests that write files, unique to the process
+#[cfg(test)]
+pub(crate) fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("diorama-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[cfg(test)]
 mod tests {
+    use crate::collision::CollisionBox;
+    use crate::utils::math_utils::Frustum;
+    use crate::utils::noise::{perlin_noise, PermutationTable};
+    use crate::utils::threadpool::ThreadPool;
     use crate::utils::{ChunkFromPosition, RelativeFromAbsolute};
+    use crate::world::{DEFAULT_CHUNK_SIZE, RNG_SEED};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
     #[test]
     fn should_get_the_correct_chunk_from_position_absolute() {
         let absolute_position = glam::vec3(17.0, 0.0, 20.0);
-        assert_eq!(absolute_position.get_chunk_from_position_absolute(), (1, 1));
+        assert_eq!(
+            absolute_position.get_chunk_from_position_absolute(DEFAULT_CHUNK_SIZE),
+            (1, 1)
+        );
         let absolute_position = glam::vec3(32.0, 0.0, 20.0);
-        assert_eq!(absolute_position.get_chunk_from_position_absolute(), (2, 1));
+        assert_eq!(
+            absolute_position.get_chunk_from_position_absolute(DEFAULT_CHUNK_SIZE),
+            (2, 1)
+        );
         let absolute_position = glam::vec3(-5.0, 0.0, -20.0);
         assert_eq!(
-            absolute_position.get_chunk_from_position_absolute(),
+            absolute_position.get_chunk_from_position_absolute(DEFAULT_CHUNK_SIZE),
             (-1, -2)
         ); //
+           // Bigger chunks hold more blocks
+        let absolute_position = glam::vec3(17.0, 0.0, 40.0);
+        assert_eq!(
+            absolute_position.get_chunk_from_position_absolute(32),
+            (0, 1)
+        );
+        assert_eq!(
+            absolute_position.relative_from_absolute(32),
+            glam::vec3(17.0, 0.0, 8.0)
+        );
     }
 
     #[test]
     fn should_get_the_correct_relative_position() {
         let absolute_position = glam::vec3(17.0, 0.0, 20.0); // Since there are 16 blocks 0->15, the next chunk will start from 16->31
         assert_eq!(
-            absolute_position.relative_from_absolute(),
+            absolute_position.relative_from_absolute(DEFAULT_CHUNK_SIZE),
             glam::vec3(1.0, 0.0, 4.0)
         );
         let absolute_position = glam::vec3(-1.0, 0.0, -1.0);
         assert_eq!(
-            absolute_position.relative_from_absolute(),
+            absolute_position.relative_from_absolute(DEFAULT_CHUNK_SIZE),
             glam::vec3(15.0, 0.0, 15.0)
         );
     }
+
+    fn aabb(min: glam::Vec3, max: glam::Vec3) -> CollisionBox {
+        CollisionBox {
+            min_x: min.x,
+            max_x: max.x,
+            min_y: min.y,
+            max_y: max.y,
+            min_z: min.z,
+            max_z: max.z,
+        }
+    }
+
+    #[test]
+    fn should_cull_boxes_outside_every_frustum_plane() {
+        let eye = glam::vec3(0.0, 40.0, 0.0);
+        let fovy = std::f32::consts::FRAC_PI_4;
+        let frustum = Frustum::new(eye, glam::Vec3::Z, fovy, 1.0, 0.1, 100.0);
+        let inside = |min: glam::Vec3, max: glam::Vec3| frustum.intersects_aabb(&aabb(min, max));
+
+        // The half height of the frustum 20 blocks away is 20 * tan(fovy / 2) ~= 8.3
+        assert!(inside(
+            glam::vec3(-1.0, 39.0, 20.0),
+            glam::vec3(1.0, 41.0, 21.0)
+        ));
+        // Behind, past the far plane, left and right
+        assert!(!inside(
+            glam::vec3(-1.0, 39.0, -21.0),
+            glam::vec3(1.0, 41.0, -20.0)
+        ));
+        assert!(!inside(
+            glam::vec3(-1.0, 39.0, 150.0),
+            glam::vec3(1.0, 41.0, 151.0)
+        ));
+        assert!(!inside(
+            glam::vec3(-31.0, 39.0, 20.0),
+            glam::vec3(-30.0, 41.0, 21.0)
+        ));
+        assert!(!inside(
+            glam::vec3(30.0, 39.0, 20.0),
+            glam::vec3(31.0, 41.0, 21.0)
+        ));
+        // Above and below
+        assert!(!inside(
+            glam::vec3(-1.0, 60.0, 20.0),
+            glam::vec3(1.0, 61.0, 21.0)
+        ));
+        assert!(!inside(
+            glam::vec3(-1.0, 0.0, 20.0),
+            glam::vec3(1.0, 30.0, 21.0)
+        ));
+        // Only the top of a tall box reaches the view
+        assert!(inside(
+            glam::vec3(-1.0, 0.0, 20.0),
+            glam::vec3(1.0, 35.0, 21.0)
+        ));
+
+        // Looking down, the distance is out of view
+        let down = glam::vec3(0.0, -1.0, 0.1).normalize();
+        let frustum = Frustum::new(eye, down, fovy, 1.0, 0.1, 100.0);
+        assert!(frustum.intersects_aabb(&aabb(
+            glam::vec3(-8.0, 0.0, -8.0),
+            glam::vec3(8.0, 10.0, 8.0)
+        )));
+        assert!(!frustum.intersects_aabb(&aabb(
+            glam::vec3(-8.0, 0.0, 64.0),
+            glam::vec3(8.0, 10.0, 80.0)
+        )));
+    }
+
+    #[test]
+    fn should_keep_tall_boxes_above_visible_when_looking_up() {
+        let eye = glam::vec3(0.0, 5.0, 0.0);
+        let pitch = std::f32::consts::FRAC_PI_3;
+        let up = glam::vec3(0.0, pitch.sin(), pitch.cos());
+        let frustum = Frustum::new(eye, up, std::f32::consts::FRAC_PI_4, 1.0, 0.1, 100.0);
+
+        // A chunk with a tall column ahead, only its upper part is in view
+        assert!(frustum.intersects_aabb(&aabb(
+            glam::vec3(-8.0, 0.0, 8.0),
+            glam::vec3(8.0, 80.0, 24.0)
+        )));
+        // The same chunk with flat terrain is below the view
+        assert!(!frustum.intersects_aabb(&aabb(
+            glam::vec3(-8.0, 0.0, 8.0),
+            glam::vec3(8.0, 5.0, 24.0)
+        )));
+    }
+
+    #[test]
+    fn should_run_every_job_and_join_the_workers_on_drop() {
+        let jobs = 64;
+        let counter = Arc::new(AtomicUsize::new(0));
+
+        let pool = ThreadPool::new(4);
+        for _ in 0..jobs {
+            let counter = Arc::clone(&counter);
+            pool.execute(move || {
+                std::thread::sleep(std::time::Duration::from_millis(1));
+                counter.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        std::mem::drop(pool);
+
+        assert_eq!(counter.load(Ordering::SeqCst), jobs);
+        // Every job closure (and its clone of the counter) is gone, so no worker is still holding one
+        assert_eq!(Arc::strong_count(&counter), 1);
+    }
+
+    #[test]
+    fn should_run_jobs_concurrently_and_wait_for_them_on_drop() {
+        let sleep = std::time::Duration::from_millis(100);
+        let finished = Arc::new(AtomicUsize::new(0));
+
+        let pool = ThreadPool::new(4);
+        let start = std::time::Instant::now();
+        for _ in 0..4 {
+            let finished = Arc::clone(&finished);
+            pool.execute(move || {
+                std::thread::sleep(sleep);
+                finished.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        // The jobs are still sleeping here, dropping has to wait for them
+        std::mem::drop(pool);
+
+        assert_eq!(finished.load(Ordering::SeqCst), 4);
+        assert!(start.elapsed() < sleep * 2);
+    }
+
+    #[test]
+    fn should_be_continuous_across_the_origin() {
+        let step = 0.01;
+        let max_jump = 0.05;
+        let perm = PermutationTable::new(RNG_SEED);
+
+        for y in [-0.5, 0.37, 3.8] {
+            let samples = (-300..=300)
+                .map(|i| perlin_noise(i as f32 * step, y, 256, &perm))
+                .collect::<Vec<_>>();
+            for pair in samples.windows(2) {
+                assert!(
+                    (pair[1] - pair[0]).abs() < max_jump,
+                    "jump of {} at y = {}",
+                    pair[1] - pair[0],
+                    y
+                );
+            }
+        }
+
+        // Diagonal from (-0.5, -0.5) to (0.5, 0.5)
+        let diagonal = (-50..=50)
+            .map(|i| perlin_noise(i as f32 * step, i as f32 * step, 256, &perm))
+            .collect::<Vec<_>>();
+        for pair in diagonal.windows(2) {
+            assert!((pair[1] - pair[0]).abs() < max_jump);
+        }
+    }
 }
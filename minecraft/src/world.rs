@@ -1,26 +1,50 @@
 use crate::blocks::block_type::BlockType;
-use crate::persistence::Saveable;
+use crate::lighting::GpuLighting;
+use crate::mesh_cache::MeshCache;
+use crate::persistence::{
+    read_save_file, read_u32, read_u64, read_u8, save_dir, Loadable, Saveable, WorldArchive,
+};
+use crate::utils::noise::PermutationTable;
 use crate::utils::{ChunkFromPosition, RelativeFromAbsolute};
-use crate::{blocks::block::Block, chunk::Chunk, player::Player, utils::threadpool::ThreadPool};
+use crate::worldgen::WorldGen;
+use crate::{
+    blocks::block::{Block, FaceDirections},
+    chunk::{Chunk, ChunkData, ChunkMesh, ChunkSnapshot, MAX_LOD},
+    collision::Ray,
+    player::Player,
+    utils::threadpool::ThreadPool,
+};
 use glam::Vec3;
+use std::any::Any;
 use std::borrow::Borrow;
-use std::collections::HashMap;
-use std::sync::RwLock;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, RwLock};
 use std::{
     sync::{mpsc, Arc},
     thread,
 };
 
+// Seed of the worlds created with `init_world`
 pub const RNG_SEED: u64 = 0;
-pub const CHUNK_SIZE: u32 = 16;
-pub const CHUNK_HEIGHT: u8 = u8::MAX;
-pub const NOISE_SIZE: u32 = 200;
+// Chunks are this wide and deep unless the world is created with another `WorldConfig`
+pub const DEFAULT_CHUNK_SIZE: u32 = 16;
+// Blocks go from WORLD_MIN_Y to WORLD_MAX_Y included. The packed vertices can't go past 511
+pub const WORLD_MIN_Y: i32 = 0;
+pub const WORLD_MAX_Y: i32 = 383;
+// New worlds start here, high over the water, and the player comes back here after falling out
+pub const SPAWN_POINT: Vec3 = Vec3::new(0.0, WATER_HEIGHT_LEVEL as f32 + 100.0, 0.0);
+// Side of the height map, the terrain repeats every NOISE_SIZE blocks. Chunk sizes have to divide it
+pub const NOISE_SIZE: u32 = 256;
 pub const FREQUENCY: f32 = 1. / 128.;
-pub const NOISE_CHUNK_PER_ROW: u32 = NOISE_SIZE / CHUNK_SIZE;
 pub const MAX_TREES_PER_CHUNK: u32 = 2;
 pub const CHUNKS_PER_ROW: u32 = 5;
 pub const CHUNKS_REGION: u32 = CHUNKS_PER_ROW * CHUNKS_PER_ROW;
 pub const WATER_HEIGHT_LEVEL: u8 = 3;
+// Blocks where the 3d cave noise is above this are carved out
+pub const CAVE_THRESHOLD: f32 = 0.25;
+pub const CAVE_FREQUENCY: f32 = 1. / 8.;
 // Lower bound of chunk
 pub const LB: i32 = -((CHUNKS_PER_ROW / 2) as i32);
 // Upper bound of chunk
@@ -30,45 +54,265 @@ pub const UB: i32 = if CHUNKS_PER_ROW % 2 == 0 {
     (CHUNKS_PER_ROW / 2) as i32
 };
 
-pub type NoiseData = Vec<f32>;
+#[derive(Debug, Clone, Copy)]
+pub struct WorldConfig {
+    // Bigger chunks mean less draw calls and meshing jobs but more work on every edit
+    pub chunk_size: u32,
+}
+
+impl Default for WorldConfig {
+    fn default() -> Self {
+        WorldConfig {
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        }
+    }
+}
+
+impl WorldConfig {
+    // The height map has to hold a whole number of chunks, or it wouldn't tile
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.chunk_size == 0 || !NOISE_SIZE.is_multiple_of(self.chunk_size) {
+            return Err(format!(
+                "The chunk size {} doesn't divide the height map size {NOISE_SIZE}",
+                self.chunk_size
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+// Saved with the chunks, a world keeps generating with the seed it was created with. The chunks
+// are stored column by column, they can only be read back with the same chunk size
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WorldMeta {
+    pub seed: u64,
+    pub chunk_size: u32,
+}
+
+/*
+World file layout (in the save directory, integers little endian):
+  u8 format version
+  u64 seed
+  u32 chunk size
+Version 1 has no chunk size, those worlds were saved with DEFAULT_CHUNK_SIZE */
+const WORLD_META_FILE: &str = "world";
+const WORLD_META_VERSION: u8 = 2;
+
+impl WorldMeta {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = vec![WORLD_META_VERSION];
+        data.extend_from_slice(&self.seed.to_le_bytes());
+        data.extend_from_slice(&self.chunk_size.to_le_bytes());
+        data
+    }
+    pub fn decode(data: &[u8]) -> Result<WorldMeta, Box<dyn Error>> {
+        let mut reader = data;
+        let version = read_u8(&mut reader)?;
+        if version != WORLD_META_VERSION && version != 1 {
+            return Err(format!("Unknown world format version {version}").into());
+        }
+        let seed = read_u64(&mut reader)?;
+        let chunk_size = match version {
+            1 => DEFAULT_CHUNK_SIZE,
+            _ => read_u32(&mut reader)?,
+        };
+        Ok(WorldMeta { seed, chunk_size })
+    }
+    // Errors when the world's chunks were saved with another chunk size than `config`'s
+    pub fn check_config(&self, config: &WorldConfig) -> Result<(), Box<dyn Error>> {
+        if self.chunk_size != config.chunk_size {
+            return Err(format!(
+                "The world was saved with {} block chunks, not {}",
+                self.chunk_size, config.chunk_size
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+impl Saveable<WorldMeta> for WorldMeta {
+    fn save(&self) -> Result<(), Box<dyn Error>> {
+        let dir = save_dir();
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(
+            std::path::Path::new(&dir).join(WORLD_META_FILE),
+            self.encode(),
+        )?;
+        Ok(())
+    }
+}
+
+impl Loadable<WorldMeta> for WorldMeta {
+    fn load(args: Box<dyn Any>) -> Result<WorldMeta, Box<dyn Error>> {
+        let archive = args
+            .downcast::<Option<Arc<WorldArchive>>>()
+            .map(|a| *a)
+            .unwrap_or(None);
+        WorldMeta::decode(&read_save_file(
+            &save_dir(),
+            WORLD_META_FILE,
+            archive.as_deref(),
+        )?)
+    }
+}
+
+// Height map of the world, along with the seed everything else in the terrain is derived from
+#[derive(Debug, Clone)]
+pub struct NoiseData {
+    pub seed: u64,
+    // The terrain is generated a chunk of this size at a time
+    pub chunk_size: u32,
+    pub heights: Vec<f32>,
+    // Also used for the 3d cave noise
+    pub perm: PermutationTable,
+}
 pub type WorldChunk = Arc<RwLock<Chunk>>;
 pub type ChunkMap = Arc<RwLock<HashMap<(i32, i32), WorldChunk>>>;
 
+// First block hit by `World::raycast`, `position` is where the ray enters it
+#[derive(Debug)]
+pub struct RayHit {
+    pub block: Arc<RwLock<Block>>,
+    pub face: FaceDirections,
+    pub distance: f32,
+    pub position: Vec3,
+}
+
+// Sent back by the streaming jobs, the render thread only inserts chunks and uploads meshes
+enum StreamResult {
+    Generated(ChunkData),
+    Meshed((i32, i32), u64, ChunkMesh),
+}
+
 // TODO: It should be better to unsafely pass the hashmap between threads, since we never modify it except when we're done
 // and it will be save since every chunk has its own lock.
 pub struct World {
     pub chunks: ChunkMap,
     pub thread_pool: Option<ThreadPool>,
-    pub seed: u32,
+    pub seed: u64,
+    // Width and depth of the chunks, from the `WorldConfig`
+    pub chunk_size: u32,
     pub noise_data: Arc<NoiseData>,
     pub chunk_data_layout: Arc<wgpu::BindGroupLayout>,
     pub device: Arc<wgpu::Device>,
     pub queue: Arc<wgpu::Queue>,
+    // Fallback for chunks that aren't in the save directory
+    pub archive: Option<Arc<WorldArchive>>,
+    // If set, chunks are streamed from the world gen thread instead of generated in the thread pool
+    pub worldgen: Option<WorldGen>,
+    // Chunks requested to the world gen thread that haven't been received yet
+    pub pending_chunks: HashSet<(i32, i32)>,
+    // If set, chunks with identical geometry share their mesh buffers
+    pub mesh_cache: Option<Arc<MeshCache>>,
+    // Merge coplanar faces when meshing, see Chunk::build_greedy_mesh_data
+    pub greedy_meshing: bool,
+    // If set, the chunks spread their block light with the compute shader, given to every chunk
+    // as it's loaded
+    pub gpu_lighting: Option<Arc<GpuLighting>>,
+    // If set, chunks within this many chunks of the player are streamed in and the rest unloaded
+    pub streaming_radius: Option<i32>,
+    // If set, chunks this many chunks away from the player get coarser meshes, with a level less
+    // detail each time the distance doubles (see `World::lod_level`)
+    pub lod_distance: Option<i32>,
+    // Chunk the player was in on the last update, the mesh detail depends on the distance to it
+    player_chunk: (i32, i32),
+    // Chunks of the last autosave still being written
+    pending_saves: Arc<AtomicUsize>,
+    stream_pool: Option<ThreadPool>,
+    stream_sender: mpsc::Sender<StreamResult>,
+    stream_receiver: mpsc::Receiver<StreamResult>,
+    // Latest mesh requested for each chunk, meshes that finish after a newer one are dropped.
+    // Versions are never reused, so a chunk unloaded and loaded again ignores the old jobs
+    mesh_versions: HashMap<(i32, i32), u64>,
+    last_mesh_version: u64,
+    // Unloaded chunks that are still being saved, they can't be loaded back until it's done
+    saving_chunks: Arc<Mutex<HashSet<(i32, i32)>>>,
+    // Blocks (tree leaves) for a chunk that is still being generated or is busy
+    deferred_blocks: Vec<Arc<RwLock<Block>>>,
 }
 
 impl World {
-    
+    pub fn raycast(&self, origin: Vec3, direction: Vec3, max_distance: f32) -> Option<RayHit> {
+        Self::raycast_chunks(
+            &self.chunks,
+            self.chunk_size,
+            origin,
+            direction,
+            max_distance,
+        )
+    }
+    // Steps through the cells along the ray front to back and returns the first block in reach.
+    // Water is skipped, so blocks can be selected through the water surface
+    pub fn raycast_chunks(
+        chunks: &ChunkMap,
+        chunk_size: u32,
+        origin: Vec3,
+        direction: Vec3,
+        max_distance: f32,
+    ) -> Option<RayHit> {
+        let ray = Ray {
+            origin,
+            direction: direction.normalize_or_zero(),
+        };
+        let chunk_map = chunks.read().unwrap();
+
+        ray.voxel_traverse(max_distance).find_map(|(cell, _)| {
+            if cell.y < WORLD_MIN_Y {
+                return None;
+            }
+            let cell_position = cell.as_vec3();
+            let chunk = chunk_map
+                .get(&cell_position.get_chunk_from_position_absolute(chunk_size))?
+                .read()
+                .unwrap();
+            let relative_position = cell_position.relative_from_absolute(chunk_size);
+            if !chunk.exists_block_at(&relative_position) {
+                return None;
+            }
+            let block = chunk.get_block_at_relative(&relative_position)?;
+            let (position, face) = {
+                let block = block.read().unwrap();
+                if block.block_type == BlockType::Water {
+                    return None;
+                }
+                ray.entered_face(&block.collision_box)?
+            };
+            let distance = position.distance(origin);
+            (distance <= max_distance).then_some(RayHit {
+                block,
+                face,
+                distance,
+                position,
+            })
+        })
+    }
     pub fn get_blocks_absolute(&self, position: &Vec3) -> Option<Arc<RwLock<Block>>> {
-        let (chunk_x, chunk_y) = position.get_chunk_from_position_absolute();
+        let (chunk_x, chunk_y) = position.get_chunk_from_position_absolute(self.chunk_size);
 
         let chunk_map = self.chunks.read().unwrap();
         let chunk = chunk_map.get(&(chunk_x, chunk_y))?;
         let chunk = chunk.read().unwrap();
 
-        let relative_position = position.relative_from_absolute();
+        let relative_position = position.relative_from_absolute(self.chunk_size);
         let block = chunk.get_block_at_relative(&relative_position)?;
 
         Some(block)
     }
     pub fn get_blocks_nearby(&self, player: Arc<RwLock<Player>>) -> Vec<Arc<RwLock<Block>>> {
-        let player = player.read().unwrap();
+        let eye = player.read().unwrap().camera.eye;
+        self.get_blocks_around(eye)
+    }
+    // Blocks up to 5 blocks away on each axis
+    pub fn get_blocks_around(&self, center: Vec3) -> Vec<Arc<RwLock<Block>>> {
         let mut positions = vec![];
         let mut nearby_blocks = vec![];
 
         for i in -5..=5 {
             for j in -5..=5 {
                 for h in -5..=5 {
-                    positions.push(player.camera.eye + glam::vec3(i as f32, h as f32, j as f32));
+                    positions.push(center + glam::vec3(i as f32, h as f32, j as f32));
                 }
             }
         }
@@ -88,10 +332,10 @@ impl World {
         device: Arc<wgpu::Device>,
     ) {
         let mut player_write = player.write().unwrap();
-        let current_chunk = player_write.calc_current_chunk();
+        let current_chunk = player_write.calc_current_chunk(self.chunk_size);
 
         // Update loaded chunks based on player position
-        if current_chunk != player_write.current_chunk {
+        if self.streaming_radius.is_none() && current_chunk != player_write.current_chunk {
             let delta = (
                 current_chunk.0 - player_write.current_chunk.0,
                 current_chunk.1 - player_write.current_chunk.1,
@@ -155,10 +399,11 @@ impl World {
                     .unwrap()
                     .remove(key)
                     .expect("Something went wrong");
+                self.mesh_versions.remove(key);
                 let sender = sender.clone();
                 self.thread_pool.as_ref().unwrap().execute(move || {
                     let chunk = chunk.write().unwrap();
-                    if chunk.modified {
+                    if chunk.is_modified() {
                         chunk.save().unwrap();
                     }
                     sender.send(()).unwrap();
@@ -169,44 +414,73 @@ impl World {
                 receiver.recv().unwrap();
             }
 
-            let chunks_added = new_chunks_positions.len();
-            let (sender, receiver) = mpsc::channel();
+            if let Some(worldgen) = self.worldgen.as_ref() {
+                // Queued chunks that went out of range before being generated
+                self.pending_chunks.retain(|c| {
+                    let in_range = (LB + current_chunk.0..=UB + current_chunk.0).contains(&c.0)
+                        && (LB + current_chunk.1..=UB + current_chunk.1).contains(&c.1);
+                    if !in_range {
+                        worldgen.cancel(c.0, c.1);
+                    }
+                    in_range
+                });
+                for position in new_chunks_positions.iter() {
+                    if self.pending_chunks.insert(*position) {
+                        worldgen.request(position.0, position.1);
+                    }
+                }
+            } else {
+                let chunks_added = new_chunks_positions.len();
+                let (sender, receiver) = mpsc::channel();
 
-            for i in 0..new_chunks_positions.len() {
-                let new_chunk_pos = new_chunks_positions[i];
-                let sender = sender.clone();
-                let noise_data = Arc::clone(&self.noise_data);
-                let chunk_data_layout = Arc::clone(&self.chunk_data_layout);
-                let device = Arc::clone(&device);
-                let queue = Arc::clone(&queue);
+                for i in 0..new_chunks_positions.len() {
+                    let new_chunk_pos = new_chunks_positions[i];
+                    let sender = sender.clone();
+                    let noise_data = Arc::clone(&self.noise_data);
+                    let chunk_data_layout = Arc::clone(&self.chunk_data_layout);
+                    let device = Arc::clone(&device);
+                    let queue = Arc::clone(&queue);
+                    let archive = self.archive.clone();
 
-                self.thread_pool.as_ref().unwrap().execute(move || {
-                    let chunk = Chunk::new(
-                        new_chunk_pos.0,
-                        new_chunk_pos.1,
-                        noise_data,
-                        device,
-                        queue,
-                        chunk_data_layout,
-                    );
-                    sender.send(chunk).unwrap()
-                })
-            }
+                    self.thread_pool.as_ref().unwrap().execute(move || {
+                        let chunk = Chunk::new(
+                            new_chunk_pos.0,
+                            new_chunk_pos.1,
+                            noise_data,
+                            device,
+                            queue,
+                            chunk_data_layout,
+                            archive,
+                        );
+                        sender.send(chunk).unwrap()
+                    })
+                }
 
-            for _ in 0..chunks_added {
-                let chunk = receiver.recv().unwrap();
-                self.chunks
-                    .write()
-                    .unwrap()
-                    .insert((chunk.x, chunk.y), Arc::new(RwLock::new(chunk)));
+                for _ in 0..chunks_added {
+                    let mut chunk = receiver.recv().unwrap();
+                    chunk.gpu_lighting = self.gpu_lighting.clone();
+                    self.chunks
+                        .write()
+                        .unwrap()
+                        .insert((chunk.x, chunk.y), Arc::new(RwLock::new(chunk)));
+                }
+                self.handle_outside_blocks();
+                // Re-render only the last inserted chunks and the ones next to them
+                self.render_chunks(self.chunks_loaded(new_chunks_positions));
             }
-            self.handle_outside_blocks();
-            // Re-render only the last inserted chunks
-            self.render_chunks(new_chunks_positions);
         }
 
         player_write.current_chunk = current_chunk;
         std::mem::drop(player_write);
+        self.player_chunk = current_chunk;
+        if let Some(radius) = self.streaming_radius {
+            self.stream_chunks(current_chunk, radius);
+        } else {
+            self.receive_generated_chunks();
+            self.receive_stream_results();
+        }
+        self.remesh_dirty_chunks();
+        self.remesh_lod_changes();
         // Update visible chunks based on player position and direction
         {
             let (sender, receiver) = mpsc::channel();
@@ -215,8 +489,10 @@ impl World {
                 let sender = sender.clone();
                 let player = Arc::clone(&player);
                 self.thread_pool.as_ref().unwrap().execute(move || {
-                    let mut chunk = chunk.write().unwrap();
-                    chunk.visible = chunk.is_visible(player);
+                    // A chunk that is being meshed keeps last frame's visibility
+                    if let Ok(mut chunk) = chunk.try_write() {
+                        chunk.visible = chunk.is_visible(player);
+                    }
                     sender.send(()).unwrap();
                 });
             }
@@ -226,35 +502,520 @@ impl World {
         }
     }
     pub fn dispose(&mut self) {
+        self.worldgen = None;
+        self.stream_pool = None;
         self.thread_pool = None;
     }
+    pub fn enable_streaming(&mut self, radius: u32) {
+        let threads = thread::available_parallelism().map_or(4, usize::from);
+        self.streaming_radius = Some(radius.max(1) as i32);
+        // Separate from the per frame pool, so the visibility pass never waits behind world gen
+        self.stream_pool = Some(ThreadPool::new(threads));
+    }
+    // Distance in blocks where the fog hides the edge of the loaded world
+    pub fn fog_distance(&self) -> f32 {
+        (self.streaming_radius.unwrap_or(UB) * self.chunk_size as i32) as f32
+    }
+    // Chunks that should be loaded around `center`, closest first
+    pub fn chunks_in_radius(center: (i32, i32), radius: i32) -> Vec<(i32, i32)> {
+        let mut positions: Vec<(i32, i32)> = (-radius..=radius)
+            .flat_map(|x| (-radius..=radius).map(move |y| (center.0 + x, center.1 + y)))
+            .collect();
+        positions.sort_by_key(|p| (p.0 - center.0).pow(2) + (p.1 - center.1).pow(2));
+        positions
+    }
+    // Mesh detail of a chunk `distance` chunks away (along the furthest axis), 0 is full detail
+    pub fn lod_level(distance: i32, lod_distance: Option<i32>) -> u8 {
+        let Some(mut start) = lod_distance.filter(|d| *d > 0) else {
+            return 0;
+        };
+        let mut level = 0;
+        while distance >= start && level < MAX_LOD {
+            level += 1;
+            start *= 2;
+        }
+        level
+    }
+    fn lod_of(&self, key: (i32, i32)) -> u8 {
+        let distance = (key.0 - self.player_chunk.0)
+            .abs()
+            .max((key.1 - self.player_chunk.1).abs());
+        Self::lod_level(distance, self.lod_distance)
+    }
+    // Meshes again the chunks whose distance to the player crossed a detail level
+    fn remesh_lod_changes(&mut self) {
+        if self.lod_distance.is_none() {
+            return;
+        }
+        let changed: Vec<(i32, i32)> = self
+            .chunks
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(key, chunk)| {
+                // Chunks being meshed (or edited) are checked again on the next frame
+                !self.mesh_versions.contains_key(key)
+                    && chunk
+                        .try_read()
+                        .is_ok_and(|chunk| chunk.lod().is_some_and(|lod| lod != self.lod_of(**key)))
+            })
+            .map(|(key, _)| *key)
+            .collect();
+        for key in changed {
+            self.queue_mesh(key);
+        }
+    }
+    // Chunks are unloaded one chunk further than they're loaded, so walking back and forth
+    // over a chunk border doesn't keep loading and unloading the same row
+    pub fn should_unload(center: (i32, i32), position: (i32, i32), radius: i32) -> bool {
+        (position.0 - center.0).abs() > radius + 1 || (position.1 - center.1).abs() > radius + 1
+    }
+    fn stream_chunks(&mut self, center: (i32, i32), radius: i32) {
+        let pool = self.stream_pool.as_ref().unwrap();
+
+        // Unload the chunks out of range, the edited ones are saved off the render thread
+        let out_of_range: Vec<(i32, i32)> = self
+            .chunks
+            .read()
+            .unwrap()
+            .keys()
+            .filter(|p| Self::should_unload(center, **p, radius))
+            .copied()
+            .collect();
+        for key in out_of_range {
+            let Some(chunk) = self.chunks.write().unwrap().remove(&key) else {
+                continue;
+            };
+            self.mesh_versions.remove(&key);
+            if !chunk.read().unwrap().is_modified() {
+                continue;
+            }
+            let saving_chunks = Arc::clone(&self.saving_chunks);
+            saving_chunks.lock().unwrap().insert(key);
+            pool.execute(move || {
+                if let Err(err) = chunk.read().unwrap().save() {
+                    eprintln!("Failed to save chunk {:?}: {}", key, err);
+                }
+                saving_chunks.lock().unwrap().remove(&key);
+            });
+        }
+        self.pending_chunks.retain(|p| {
+            let in_range = !Self::should_unload(center, *p, radius);
+            if let (false, Some(worldgen)) = (in_range, self.worldgen.as_ref()) {
+                worldgen.cancel(p.0, p.1);
+            }
+            in_range
+        });
+
+        // Queue the missing chunks
+        {
+            let chunks = self.chunks.read().unwrap();
+            let saving_chunks = self.saving_chunks.lock().unwrap();
+            for position in Self::chunks_in_radius(center, radius) {
+                if chunks.contains_key(&position)
+                    || self.pending_chunks.contains(&position)
+                    || saving_chunks.contains(&position)
+                {
+                    continue;
+                }
+                self.pending_chunks.insert(position);
+                if let Some(worldgen) = self.worldgen.as_ref() {
+                    worldgen.request(position.0, position.1);
+                    continue;
+                }
+                let sender = self.stream_sender.clone();
+                let noise_data = Arc::clone(&self.noise_data);
+                let archive = self.archive.clone();
+                pool.execute(move || {
+                    let data = ChunkData::generate(
+                        position.0,
+                        position.1,
+                        noise_data,
+                        &save_dir(),
+                        archive,
+                    );
+                    // The world is gone if the receiver was dropped
+                    let _ = sender.send(StreamResult::Generated(data));
+                });
+            }
+        }
+
+        // Insert the generated chunks and upload the finished meshes
+        let mut generated = vec![];
+        if let Some(worldgen) = self.worldgen.as_ref() {
+            while let Some(data) = worldgen.try_recv() {
+                generated.push(data);
+            }
+        }
+        generated.append(&mut self.receive_stream_results());
+
+        let mut loaded = vec![];
+        for data in generated {
+            let key = (data.x, data.y);
+            // Went out of range while it was being generated
+            if !self.pending_chunks.remove(&key) {
+                continue;
+            }
+            let mut chunk = Chunk::from_data(
+                data,
+                Arc::clone(&self.noise_data),
+                Arc::clone(&self.device),
+                Arc::clone(&self.queue),
+                Arc::clone(&self.chunk_data_layout),
+                self.archive.clone(),
+            );
+            chunk.gpu_lighting = self.gpu_lighting.clone();
+            self.deferred_blocks.append(&mut chunk.outside_blocks);
+            self.chunks
+                .write()
+                .unwrap()
+                .insert(key, Arc::new(RwLock::new(chunk)));
+            loaded.push(key);
+        }
+        let mut to_mesh: HashSet<_> = self.chunks_loaded(loaded).into_iter().collect();
+
+        for block in std::mem::take(&mut self.deferred_blocks) {
+            let key = block.read().unwrap().get_chunk_coords();
+            let chunk = self.chunks.read().unwrap().get(&key).cloned();
+            match chunk {
+                Some(chunk) => match chunk.try_write() {
+                    Ok(mut chunk) => {
+                        if let Err(err) = chunk.add_block(block.clone(), false) {
+                            eprintln!("Skipped a structure block: {err}");
+                        }
+                        to_mesh.insert(key);
+                    }
+                    Err(_) => self.deferred_blocks.push(block),
+                },
+                None if self.pending_chunks.contains(&key) => self.deferred_blocks.push(block),
+                None => {}
+            }
+        }
+
+        for key in to_mesh {
+            self.queue_mesh(key);
+        }
+    }
+    // Uploads the meshes finished since the last frame and returns the generated chunks
+    fn receive_stream_results(&mut self) -> Vec<ChunkData> {
+        let mut generated = vec![];
+        let mut busy = vec![];
+        while let Ok(result) = self.stream_receiver.try_recv() {
+            match result {
+                StreamResult::Generated(data) => generated.push(data),
+                StreamResult::Meshed(key, version, mesh) => {
+                    // A newer mesh is on the way, or the chunk was unloaded
+                    if self.mesh_versions.get(&key) != Some(&version) {
+                        continue;
+                    }
+                    let Some(chunk) = self.chunks.read().unwrap().get(&key).cloned() else {
+                        continue;
+                    };
+                    // Another job is reading the chunk, try again next frame
+                    let Ok(mut chunk) = chunk.try_write() else {
+                        busy.push(StreamResult::Meshed(key, version, mesh));
+                        continue;
+                    };
+                    chunk.set_mesh(mesh, self.mesh_cache.as_deref());
+                    self.mesh_versions.remove(&key);
+                }
+            }
+        }
+        for result in busy {
+            self.stream_sender.send(result).unwrap();
+        }
+        generated
+    }
+    // Called with the chunks that were just inserted. Their neighbours were meshed with the faces
+    // towards them hidden, returns the chunks to mesh: the new ones and their loaded neighbours
+    fn chunks_loaded(&self, keys: Vec<(i32, i32)>) -> Vec<(i32, i32)> {
+        let chunks = self.chunks.read().unwrap();
+        let mut to_mesh = keys.clone();
+        for key in keys {
+            for neighbour in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+                let neighbour = (key.0 + neighbour.0, key.1 + neighbour.1);
+                if chunks.contains_key(&neighbour) && !to_mesh.contains(&neighbour) {
+                    to_mesh.push(neighbour);
+                }
+            }
+        }
+        to_mesh
+    }
+    // Edited chunks are meshed once per frame, no matter how many edits they got
+    fn remesh_dirty_chunks(&mut self) {
+        let dirty: Vec<(i32, i32)> = self
+            .chunks
+            .read()
+            .unwrap()
+            .iter()
+            .filter_map(|(key, chunk)| {
+                // A chunk that is being meshed stays dirty until the next frame
+                let mut chunk = chunk.try_write().ok()?;
+                std::mem::take(&mut chunk.dirty).then_some(*key)
+            })
+            .collect();
+        for key in dirty {
+            self.queue_mesh(key);
+        }
+    }
+    // Block type at an absolute position, None for air and chunks that aren't loaded
+    pub fn get_block(&self, position: Vec3) -> Option<BlockType> {
+        let key = position.get_chunk_from_position_absolute(self.chunk_size);
+        let chunk = self.chunks.read().unwrap().get(&key).cloned()?;
+        let block_type = chunk
+            .read()
+            .unwrap()
+            .block_type_at(&position.relative_from_absolute(self.chunk_size));
+        block_type
+    }
+    // Places (Some) or removes (None) the block at an absolute position. The faces around it are
+    // replaced in its chunk's mesh right away, the neighbour chunks that can see it (border blocks
+    // and light emitters) are marked to be meshed again in the background.
+    // Returns false if nothing changed: outside the world's height, not loaded or already that block
+    pub fn set_block(&mut self, position: Vec3, block_type: Option<BlockType>) -> bool {
+        if Chunk::is_outside_bounds(&position) {
+            if block_type.is_some() {
+                println!(
+                    "Can't place a block at y {}, the world goes from {WORLD_MIN_Y} to {WORLD_MAX_Y}",
+                    position.y
+                );
+            }
+            return false;
+        }
+        let key = position.get_chunk_from_position_absolute(self.chunk_size);
+        let Some(chunk) = self.chunks.read().unwrap().get(&key).cloned() else {
+            return false;
+        };
+        let relative_position = position.relative_from_absolute(self.chunk_size);
+        let previous = {
+            let mut chunk = chunk.write().unwrap();
+            let previous = chunk.block_type_at(&relative_position);
+            if previous == block_type {
+                return false;
+            }
+            let was_dirty = chunk.dirty;
+            match block_type {
+                Some(block_type) => chunk
+                    .set_block(&relative_position, block_type, true)
+                    .expect("Inside the world's height"),
+                None => chunk
+                    .remove_block(&relative_position)
+                    .expect("Inside the world's height"),
+            }
+            self.remesh_edit(key, &mut chunk, &relative_position, was_dirty);
+            previous
+        };
+        // The neighbours could see (or be lit by) the old block too
+        let neighbours = [previous, block_type]
+            .into_iter()
+            .flatten()
+            .flat_map(|block_type| {
+                Block::new(relative_position, key, block_type, self.chunk_size)
+                    .get_neighbour_chunks_coords()
+            })
+            .collect();
+        self.mark_dirty(neighbours);
+        true
+    }
+    // Player edits, the blocks that aren't breakable stay
+    pub fn remove_block(&mut self, position: Vec3) {
+        if self
+            .get_block(position)
+            .is_some_and(|block_type| !block_type.get_config().breakable)
+        {
+            return;
+        }
+        self.set_block(position, None);
+    }
+    // Only into air or water, returns false if the block wasn't placed
+    pub fn place_block(&mut self, position: Vec3, block_type: BlockType) -> bool {
+        if matches!(self.get_block(position), Some(current) if current != BlockType::Water) {
+            return false;
+        }
+        self.set_block(position, Some(block_type))
+    }
+    // The uploaded mesh is only up to date when there were no other edits since and no mesh is
+    // on the way, otherwise the chunk stays dirty and gets a whole new mesh
+    fn remesh_edit(&self, key: (i32, i32), chunk: &mut Chunk, position: &Vec3, was_dirty: bool) {
+        if !was_dirty
+            && !self.mesh_versions.contains_key(&key)
+            && chunk.remesh_around(position, self.chunks.clone(), self.mesh_cache.as_deref())
+        {
+            chunk.dirty = false;
+        }
+    }
+    fn mark_dirty(&self, keys: Vec<(i32, i32)>) {
+        let chunks = self.chunks.read().unwrap();
+        for key in keys {
+            if let Some(chunk) = chunks.get(&key) {
+                chunk.write().unwrap().dirty = true;
+            }
+        }
+    }
+    // Builds the mesh of a chunk off the render thread, it's uploaded when received
+    fn queue_mesh(&mut self, key: (i32, i32)) {
+        let Some(chunk) = self.chunks.read().unwrap().get(&key).cloned() else {
+            return;
+        };
+        self.last_mesh_version += 1;
+        let version = self.last_mesh_version;
+        self.mesh_versions.insert(key, version);
+        let sender = self.stream_sender.clone();
+        let chunk_map = Arc::clone(&self.chunks);
+        let greedy_meshing = self.greedy_meshing;
+        let lod = self.lod_of(key);
+        let pool = self
+            .stream_pool
+            .as_ref()
+            .or(self.thread_pool.as_ref())
+            .unwrap();
 
+        pool.execute(move || {
+            let mesh = chunk
+                .read()
+                .unwrap()
+                .mesh_data(chunk_map, greedy_meshing, lod);
+            let _ = sender.send(StreamResult::Meshed(key, version, mesh));
+        });
+    }
+    // Uploads the chunks that the world gen thread finished since the last frame
+    fn receive_generated_chunks(&mut self) {
+        let Some(worldgen) = self.worldgen.as_ref() else {
+            return;
+        };
+        let mut new_chunks_positions = vec![];
+        while let Some(data) = worldgen.try_recv() {
+            // Went out of range while it was being generated
+            if !self.pending_chunks.remove(&(data.x, data.y)) {
+                continue;
+            }
+            let mut chunk = Chunk::from_data(
+                data,
+                Arc::clone(&self.noise_data),
+                Arc::clone(&self.device),
+                Arc::clone(&self.queue),
+                Arc::clone(&self.chunk_data_layout),
+                self.archive.clone(),
+            );
+            chunk.gpu_lighting = self.gpu_lighting.clone();
+            new_chunks_positions.push((chunk.x, chunk.y));
+            self.chunks
+                .write()
+                .unwrap()
+                .insert((chunk.x, chunk.y), Arc::new(RwLock::new(chunk)));
+        }
+        if !new_chunks_positions.is_empty() {
+            self.handle_outside_blocks();
+            self.render_chunks(self.chunks_loaded(new_chunks_positions));
+        }
+    }
+
+    // Snapshots of the modified chunks, along with the chunk to mark again if the write fails
+    fn modified_chunks(&self) -> Vec<(WorldChunk, ChunkSnapshot)> {
+        self.chunks
+            .read()
+            .unwrap()
+            .values()
+            .filter_map(|chunk| Some((chunk.clone(), chunk.read().unwrap().take_snapshot()?)))
+            .collect()
+    }
+    fn save_meta(&self) {
+        let meta = WorldMeta {
+            seed: self.seed,
+            chunk_size: self.chunk_size,
+        };
+        if let Err(err) = meta.save() {
+            eprintln!("Failed to save the world: {err}");
+        }
+    }
+    // Writes the modified chunks on the thread pool, the frame doesn't wait for the disk. Skipped
+    // while the last one is still writing, so an older copy of a chunk never lands after a newer one
+    pub fn autosave(&self) {
+        let Some(thread_pool) = self.thread_pool.as_ref() else {
+            return;
+        };
+        if self.pending_saves.load(Ordering::Acquire) > 0 {
+            println!("The last autosave is still writing, skipped");
+            return;
+        }
+        self.save_meta();
+        for (chunk, snapshot) in self.modified_chunks() {
+            let pending_saves = self.pending_saves.clone();
+            pending_saves.fetch_add(1, Ordering::AcqRel);
+            thread_pool.execute(move || {
+                if let Err(err) = snapshot.write() {
+                    eprintln!("Failed to save chunk {} {}: {err}", snapshot.x, snapshot.y);
+                    chunk.read().unwrap().mark_modified();
+                }
+                pending_saves.fetch_sub(1, Ordering::AcqRel);
+            });
+        }
+    }
+    // Blocks until every modified chunk is written, the autosave in progress included
     pub fn save_state(&self) {
-        for chunk in self.chunks.read().unwrap().values() {
-            let chunkbrw = chunk.read().unwrap();
-            if chunkbrw.modified {
-                chunkbrw.save().expect("failed to save");
+        while self.pending_saves.load(Ordering::Acquire) > 0 {
+            thread::sleep(std::time::Duration::from_millis(10));
+        }
+        self.save_meta();
+        let chunks = self.modified_chunks();
+        println!("Saving {} chunks", chunks.len());
+        let mut saved = 0;
+        for (chunk, snapshot) in chunks.iter() {
+            match snapshot.write() {
+                Ok(()) => saved += 1,
+                Err(err) => {
+                    eprintln!("Failed to save chunk {} {}: {err}", snapshot.x, snapshot.y);
+                    chunk.read().unwrap().mark_modified();
+                }
             }
         }
+        println!("Saved {saved} of {} chunks", chunks.len());
     }
     pub fn init_chunks(&mut self, player: Arc<RwLock<Player>>) {
         let (sender, receiver) = mpsc::channel();
         let mut player_write = player.write().unwrap();
-    
-        // Establecer posición inicial segura para el jugador (en el centro del mapa, por ejemplo)
-        let initial_x = 0;
-        let initial_z = 0;
-        let initial_y = WATER_HEIGHT_LEVEL as f32 + 100.0; // Altura segura por encima del agua
-    
-        player_write.camera.eye = glam::Vec3::new(initial_x as f32, initial_y, initial_z as f32);
-    
-        for chunk_x in LB + player_write.current_chunk.0..=UB + player_write.current_chunk.0 {
-            for chunk_y in LB + player_write.current_chunk.1..=UB + player_write.current_chunk.1 {
+
+        if !player_write.camera.from_save {
+            player_write.camera.eye = SPAWN_POINT;
+        }
+
+        let current_chunk = player_write.calc_current_chunk(self.chunk_size);
+        player_write.current_chunk = current_chunk;
+        let positions: Vec<(i32, i32)> = match self.streaming_radius {
+            Some(radius) => Self::chunks_in_radius(current_chunk, radius),
+            None => (LB + current_chunk.0..=UB + current_chunk.0)
+                .flat_map(|x| (LB + current_chunk.1..=UB + current_chunk.1).map(move |y| (x, y)))
+                .collect(),
+        };
+
+        if let Some(worldgen) = self.worldgen.as_ref() {
+            for (chunk_x, chunk_y) in positions.iter() {
+                worldgen.request(*chunk_x, *chunk_y);
+            }
+            // Wait for the whole starting region before the first frame
+            for _ in 0..positions.len() {
+                let mut chunk = Chunk::from_data(
+                    worldgen.recv(),
+                    Arc::clone(&self.noise_data),
+                    Arc::clone(&self.device),
+                    Arc::clone(&self.queue),
+                    Arc::clone(&self.chunk_data_layout),
+                    self.archive.clone(),
+                );
+                chunk.gpu_lighting = self.gpu_lighting.clone();
+                self.chunks
+                    .write()
+                    .unwrap()
+                    .insert((chunk.x, chunk.y), Arc::new(RwLock::new(chunk)));
+            }
+        } else {
+            for (chunk_x, chunk_y) in positions.iter().copied() {
                 let sender = sender.clone();
                 let noise_data = Arc::clone(&self.noise_data);
                 let chunk_data_layout = Arc::clone(&self.chunk_data_layout);
                 let device = Arc::clone(&self.device);
                 let queue = Arc::clone(&self.queue);
+                let archive = self.archive.clone();
                 self.thread_pool.as_ref().unwrap().execute(move || {
                     let chunk = Chunk::new(
                         chunk_x,
@@ -263,24 +1024,26 @@ impl World {
                         device,
                         queue,
                         chunk_data_layout,
+                        archive,
                     );
                     sender.send(chunk).unwrap();
                 });
             }
+
+            for _ in 0..positions.len() {
+                let mut chunk = receiver.recv().expect("Some chunks are missing");
+                chunk.gpu_lighting = self.gpu_lighting.clone();
+                self.chunks
+                    .write()
+                    .unwrap()
+                    .insert((chunk.x, chunk.y), Arc::new(RwLock::new(chunk)));
+            }
         }
-    
-        for _ in 0..CHUNKS_PER_ROW * CHUNKS_PER_ROW {
-            let chunk = receiver.recv().expect("Some chunks are missing");
-            self.chunks
-                .write()
-                .unwrap()
-                .insert((chunk.x, chunk.y), Arc::new(RwLock::new(chunk)));
-        }
-    
+
         self.handle_outside_blocks();
         self.render_chunks(self.chunks.read().unwrap().keys().collect::<Vec<_>>());
     }
-    
+
     // chunks: slice containing the chunk to re-render
     fn render_chunks<I>(&self, chunk_keys: Vec<I>)
     where
@@ -295,34 +1058,24 @@ impl World {
                 // let other = self.get_other_chunks(chunk.clone());
                 let chunk = chunk.clone();
                 let chunk_map = self.chunks.clone();
+                let greedy_meshing = self.greedy_meshing;
+                let lod = self.lod_of(*key.borrow());
 
                 self.thread_pool.as_ref().unwrap().execute(move || {
-                    let chunk_ptr = chunk.clone();
-                    let chunk = chunk.read().unwrap();
-                    let res = chunk.build_mesh(chunk_map);
-                    sender.send((res, chunk_ptr)).unwrap();
+                    let mesh = chunk
+                        .read()
+                        .unwrap()
+                        .mesh_data(chunk_map, greedy_meshing, lod);
+                    sender.send((mesh, chunk)).unwrap();
                 });
             }
         }
         for _ in chunk_keys.iter() {
-            let (
-                (
-                    indices,
-                    water_indices,
-                    vertex_buffer,
-                    index_buffer,
-                    water_vertex_buffer,
-                    water_index_buffer,
-                ),
-                chunk_ptr,
-            ) = receiver.recv().expect("Some chunks didn't render");
-            let mut chunk_mut = chunk_ptr.write().unwrap();
-            chunk_mut.indices = indices;
-            chunk_mut.chunk_vertex_buffer = Some(vertex_buffer);
-            chunk_mut.chunk_index_buffer = Some(index_buffer);
-            chunk_mut.water_indices = water_indices;
-            chunk_mut.chunk_water_vertex_buffer = Some(water_vertex_buffer);
-            chunk_mut.chunk_water_index_buffer = Some(water_index_buffer);
+            let (mesh, chunk) = receiver.recv().expect("Some chunks didn't render");
+            chunk
+                .write()
+                .unwrap()
+                .set_mesh(mesh, self.mesh_cache.as_deref());
         }
     }
     fn handle_outside_blocks(&mut self) {
@@ -338,23 +1091,65 @@ impl World {
             let chunk_coords = block.read().unwrap().get_chunk_coords();
             if let Some(chunkptr) = self.chunks.read().unwrap().get(&chunk_coords) {
                 let mut chunkbrw = chunkptr.write().unwrap();
-                chunkbrw.add_block(block.clone(), false);
+                if let Err(err) = chunkbrw.add_block(block.clone(), false) {
+                    eprintln!("Skipped a structure block: {err}");
+                }
                 if !chunks_to_rerender.iter().any(|c| Arc::ptr_eq(&c, chunkptr)) {
                     chunks_to_rerender.push(chunkptr.clone());
                 };
             }
         }
     }
-    pub fn init_world(device: Arc<wgpu::Device>, queue: Arc<wgpu::Queue>) -> Self {
-        let noise_data = Arc::new(crate::utils::noise::create_world_noise_data(
-            NOISE_SIZE, NOISE_SIZE, FREQUENCY,
-        ));
+    pub fn init_world(
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        archive: Option<Arc<WorldArchive>>,
+        background_worldgen: bool,
+    ) -> Self {
+        Self::with_seed(device, queue, archive, background_worldgen, RNG_SEED)
+    }
+    // Terrain, trees and block types all derive from the seed, the same seed gives the same world
+    pub fn with_seed(
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        archive: Option<Arc<WorldArchive>>,
+        background_worldgen: bool,
+        seed: u64,
+    ) -> Self {
+        Self::with_config(
+            device,
+            queue,
+            archive,
+            background_worldgen,
+            seed,
+            WorldConfig::default(),
+        )
+    }
+    pub fn with_config(
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        archive: Option<Arc<WorldArchive>>,
+        background_worldgen: bool,
+        seed: u64,
+        config: WorldConfig,
+    ) -> Self {
+        if let Err(err) = config.validate() {
+            panic!("{err}");
+        }
+        let mut noise_data =
+            crate::utils::noise::create_world_noise_data(NOISE_SIZE, NOISE_SIZE, FREQUENCY, seed);
+        noise_data.chunk_size = config.chunk_size;
+        let noise_data = Arc::new(noise_data);
         let chunk_data_layout =
             Arc::new(device.create_bind_group_layout(&Chunk::get_bind_group_layout()));
 
         let threads = thread::available_parallelism().unwrap();
         // let threads = usize::max(usize::from(max_threads), 8);
         let thread_pool = ThreadPool::new(usize::from(threads));
+        let worldgen = background_worldgen
+            .then(|| WorldGen::new(usize::from(threads), noise_data.clone(), archive.clone()));
+
+        let (stream_sender, stream_receiver) = mpsc::channel();
 
         World {
             chunk_data_layout,
@@ -362,8 +1157,610 @@ impl World {
             noise_data,
             device,
             queue,
-            seed: 0,
+            seed,
+            chunk_size: config.chunk_size,
             thread_pool: Some(thread_pool),
+            archive,
+            worldgen,
+            pending_chunks: HashSet::new(),
+            mesh_cache: None,
+            greedy_meshing: false,
+            gpu_lighting: None,
+            streaming_radius: None,
+            lod_distance: None,
+            player_chunk: (0, 0),
+            pending_saves: Arc::new(AtomicUsize::new(0)),
+            stream_pool: None,
+            stream_sender,
+            stream_receiver,
+            mesh_versions: HashMap::new(),
+            last_mesh_version: 0,
+            saving_chunks: Arc::new(Mutex::new(HashSet::new())),
+            deferred_blocks: vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        World, WorldConfig, WorldMeta, DEFAULT_CHUNK_SIZE, FREQUENCY, NOISE_SIZE, RNG_SEED,
+        WORLD_MAX_Y, WORLD_MIN_Y,
+    };
+    use crate::blocks::block::{Block, FaceDirections};
+    use crate::blocks::block_type::BlockType;
+    use crate::chunk::{insert_block, BlockVec, Chunk, ChunkBlocks, ChunkData};
+    use crate::utils::noise::create_world_noise_data;
+    use crate::utils::test_device;
+    use crate::utils::threadpool::ThreadPool;
+    use std::collections::HashMap;
+    use std::sync::{Arc, RwLock};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    // Height of every column in a few chunks, the ones around the origin are flat for any seed
+    fn height_map(seed: u64) -> Vec<u32> {
+        let noise_data = Arc::new(create_world_noise_data(
+            NOISE_SIZE, NOISE_SIZE, FREQUENCY, seed,
+        ));
+        [(2, 3), (5, -4), (-6, 1)]
+            .iter()
+            .flat_map(|(chunk_x, chunk_y)| {
+                let noise_data = noise_data.clone();
+                (0..DEFAULT_CHUNK_SIZE * DEFAULT_CHUNK_SIZE).map(move |i| {
+                    Chunk::get_height_value(
+                        *chunk_x,
+                        *chunk_y,
+                        i / DEFAULT_CHUNK_SIZE,
+                        i % DEFAULT_CHUNK_SIZE,
+                        noise_data.clone(),
+                    )
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn should_round_trip_the_world_meta() {
+        let meta = WorldMeta {
+            seed: 0xdead_beef_1234,
+            chunk_size: 32,
+        };
+        assert_eq!(WorldMeta::decode(&meta.encode()).unwrap(), meta);
+        // Truncated or from a newer version
+        assert!(WorldMeta::decode(&meta.encode()[..5]).is_err());
+        assert!(WorldMeta::decode(&meta.encode()[..11]).is_err());
+        let mut newer = meta.encode();
+        newer[0] += 1;
+        assert!(WorldMeta::decode(&newer).is_err());
+
+        // Version 1 worlds were all saved with the default chunk size
+        let mut v1 = vec![1];
+        v1.extend_from_slice(&meta.seed.to_le_bytes());
+        assert_eq!(
+            WorldMeta::decode(&v1).unwrap(),
+            WorldMeta {
+                seed: meta.seed,
+                chunk_size: DEFAULT_CHUNK_SIZE
+            }
+        );
+    }
+
+    #[test]
+    fn should_reject_a_chunk_size_the_world_wasnt_saved_with() {
+        let meta = WorldMeta {
+            seed: RNG_SEED,
+            chunk_size: 32,
+        };
+        assert!(meta.check_config(&WorldConfig { chunk_size: 32 }).is_ok());
+        assert!(meta.check_config(&WorldConfig::default()).is_err());
+    }
+
+    #[test]
+    fn should_only_accept_chunk_sizes_that_tile_the_height_map() {
+        for chunk_size in [8, DEFAULT_CHUNK_SIZE, 32, 64] {
+            assert!(WorldConfig { chunk_size }.validate().is_ok());
+        }
+        for chunk_size in [0, 48, 200, NOISE_SIZE * 2] {
+            assert!(WorldConfig { chunk_size }.validate().is_err());
+        }
+    }
+
+    #[test]
+    fn should_generate_and_edit_a_world_with_32_block_chunks() {
+        let Some((device, queue)) = test_device() else {
+            println!("No adapter available, skipping chunk size test");
+            return;
+        };
+        let mut world = World::with_config(
+            device,
+            queue,
+            None,
+            false,
+            RNG_SEED,
+            WorldConfig { chunk_size: 32 },
+        );
+        assert_eq!(world.chunk_size, 32);
+        for x in [0, 1] {
+            let data = ChunkData {
+                x,
+                y: 0,
+                blocks: Chunk::create_blocks_data(x, 0, world.noise_data.clone()),
+                outside_blocks: vec![],
+            };
+            let chunk = Chunk::from_data(
+                data,
+                world.noise_data.clone(),
+                world.device.clone(),
+                world.queue.clone(),
+                world.chunk_data_layout.clone(),
+                None,
+            );
+            world
+                .chunks
+                .write()
+                .unwrap()
+                .insert((x, 0), Arc::new(RwLock::new(chunk)));
+        }
+        let chunk_map = world.chunks.clone();
+        let chunk = |key: (i32, i32)| chunk_map.read().unwrap()[&key].clone();
+        {
+            let chunk = chunk((0, 0));
+            let chunk = chunk.read().unwrap();
+            assert_eq!(chunk.chunk_size(), 32);
+            let blocks = chunk.blocks.read().unwrap();
+            assert_eq!(blocks.chunk_size(), 32);
+            // Generated all the way to the 32nd column
+            assert!(blocks.column_height(31, 31) > 0);
+            assert_eq!(blocks.get(32, 0, 0), None);
+        }
+
+        // x = 31 is still the first chunk, x = 32 the second one
+        let (last, first) = (glam::vec3(31.0, 300.0, 4.0), glam::vec3(32.0, 300.0, 4.0));
+        assert!(world.set_block(last, Some(BlockType::Stone)));
+        assert!(world.set_block(first, Some(BlockType::Glass)));
+        assert_eq!(world.get_block(last), Some(BlockType::Stone));
+        assert_eq!(world.get_block(first), Some(BlockType::Glass));
+        assert_eq!(
+            chunk((0, 0))
+                .read()
+                .unwrap()
+                .block_type_at(&glam::vec3(31.0, 300.0, 4.0)),
+            Some(BlockType::Stone)
+        );
+        assert_eq!(
+            chunk((1, 0))
+                .read()
+                .unwrap()
+                .block_type_at(&glam::vec3(0.0, 300.0, 4.0)),
+            Some(BlockType::Glass)
+        );
+        // Past the second chunk, not loaded
+        assert!(!world.set_block(glam::vec3(64.0, 300.0, 4.0), Some(BlockType::Stone)));
+    }
+
+    #[test]
+    fn should_generate_the_same_world_only_with_the_same_seed() {
+        assert_eq!(height_map(42), height_map(42));
+        assert_ne!(height_map(42), height_map(1337));
+    }
+
+    #[test]
+    fn should_load_closest_chunks_first_and_unload_past_the_radius() {
+        let positions = World::chunks_in_radius((10, -3), 2);
+        assert_eq!(positions.len(), 25);
+        assert_eq!(positions[0], (10, -3));
+        assert!(positions.contains(&(12, -5)));
+
+        // One chunk of slack before unloading
+        assert!(!World::should_unload((10, -3), (13, -3), 2));
+        assert!(World::should_unload((10, -3), (14, -3), 2));
+        assert!(World::should_unload((10, -3), (10, 1), 2));
+    }
+
+    #[test]
+    fn should_lower_the_mesh_detail_each_time_the_distance_doubles() {
+        let levels: Vec<u8> = (0..12)
+            .map(|distance| World::lod_level(distance, Some(3)))
+            .collect();
+        assert_eq!(levels, [0, 0, 0, 1, 1, 1, 2, 2, 2, 2, 2, 2]);
+        assert_eq!(World::lod_level(100, None), 0);
+    }
+
+    #[test]
+    fn should_unload_the_chunks_left_behind_while_walking_across_borders() {
+        let Some((device, queue)) = test_device() else {
+            println!("No adapter available, skipping unload test");
+            return;
+        };
+        let mut world = World::with_seed(device, queue, None, false, RNG_SEED);
+        world.enable_streaming(1);
+        let radius = world.streaming_radius.unwrap();
+
+        for center in [(0, 0), (1, 0), (2, 0), (3, 0), (3, 1), (3, 2)] {
+            // Until the chunks generated on the stream pool are all in
+            let start = Instant::now();
+            world.stream_chunks(center, radius);
+            while !world.pending_chunks.is_empty() {
+                assert!(start.elapsed() < Duration::from_secs(30), "{center:?}");
+                thread::sleep(Duration::from_millis(5));
+                world.stream_chunks(center, radius);
+            }
+            let chunks = world.chunks.read().unwrap();
+            assert!(World::chunks_in_radius(center, radius)
+                .iter()
+                .all(|p| chunks.contains_key(p)));
+            assert!(chunks
+                .keys()
+                .all(|p| !World::should_unload(center, *p, radius)));
+        }
+        let chunks = world.chunks.read().unwrap();
+        // Where the walk started is gone, the chunks just behind are kept for the way back
+        assert!(!chunks.contains_key(&(0, 0)) && !chunks.contains_key(&(-1, -1)));
+        assert!(chunks.contains_key(&(1, 1)));
+        assert!(chunks.len() <= 5 * 5);
+    }
+
+    #[test]
+    fn should_hit_the_closest_block_of_a_corridor_on_the_entered_face() {
+        let Some((device, queue)) = test_device() else {
+            println!("No adapter available, skipping raycast test");
+            return;
+        };
+        let layout = Arc::new(device.create_bind_group_layout(&Chunk::get_bind_group_layout()));
+        let noise_data = Arc::new(create_world_noise_data(
+            NOISE_SIZE, NOISE_SIZE, FREQUENCY, RNG_SEED,
+        ));
+
+        // A row of stone blocks along z, with water in front of it
+        let blocks: BlockVec = Arc::new(RwLock::new(ChunkBlocks::new((0, 0), DEFAULT_CHUNK_SIZE)));
+        let corridor = (5..=10).map(|z| (glam::vec3(3.0, 6.0, z as f32), BlockType::Stone));
+        for (position, block_type) in
+            corridor.chain([(glam::vec3(3.0, 6.0, 3.0), BlockType::Water)])
+        {
+            let block = Block::new(position, (0, 0), block_type, DEFAULT_CHUNK_SIZE);
+            insert_block(&blocks, Arc::new(RwLock::new(block)));
+        }
+        let data = ChunkData {
+            x: 0,
+            y: 0,
+            blocks,
+            outside_blocks: vec![],
+        };
+        let chunk = Chunk::from_data(data, noise_data, device, queue, layout, None);
+        let chunks = Arc::new(RwLock::new(HashMap::from([(
+            (0, 0),
+            Arc::new(RwLock::new(chunk)),
+        )])));
+        let raycast = |origin: glam::Vec3, direction: glam::Vec3, max_distance: f32| {
+            World::raycast_chunks(&chunks, DEFAULT_CHUNK_SIZE, origin, direction, max_distance)
+        };
+
+        // Down the corridor from both ends
+        let hit = raycast(glam::vec3(3.5, 6.5, 0.5), glam::Vec3::Z, 8.0).unwrap();
+        assert_eq!(hit.block.read().unwrap().absolute_position.z, 5.0);
+        assert_eq!(hit.face, FaceDirections::Front);
+        assert!((hit.distance - 4.5).abs() < 1e-4);
+        assert!((hit.position.z - 5.0).abs() < 1e-4);
+
+        let hit = raycast(glam::vec3(3.5, 6.5, 14.5), glam::Vec3::NEG_Z, 8.0).unwrap();
+        assert_eq!(hit.block.read().unwrap().absolute_position.z, 10.0);
+        assert_eq!(hit.face, FaceDirections::Back);
+
+        // From above, at an angle
+        let hit = raycast(glam::vec3(3.5, 9.5, 7.5), glam::vec3(0.0, -1.0, 0.2), 8.0).unwrap();
+        assert_eq!(hit.face, FaceDirections::Top);
+        assert!((hit.position.y - 7.0).abs() < 1e-4);
+
+        // Out of reach
+        assert!(raycast(glam::vec3(3.5, 6.5, 0.5), glam::Vec3::Z, 4.0).is_none());
+    }
+
+    #[test]
+    fn should_set_and_get_blocks_across_a_chunk_border() {
+        let Some((device, queue)) = test_device() else {
+            println!("No adapter available, skipping absolute edit test");
+            return;
+        };
+        let mut world = World::with_seed(device, queue, None, false, RNG_SEED);
+        for x in [-1, 0] {
+            let data = ChunkData {
+                x,
+                y: 0,
+                blocks: Arc::new(RwLock::new(ChunkBlocks::new((x, 0), DEFAULT_CHUNK_SIZE))),
+                outside_blocks: vec![],
+            };
+            let chunk = Chunk::from_data(
+                data,
+                world.noise_data.clone(),
+                world.device.clone(),
+                world.queue.clone(),
+                world.chunk_data_layout.clone(),
+                None,
+            );
+            world
+                .chunks
+                .write()
+                .unwrap()
+                .insert((x, 0), Arc::new(RwLock::new(chunk)));
+        }
+        let chunk_map = world.chunks.clone();
+        let chunk = |key: (i32, i32)| chunk_map.read().unwrap()[&key].clone();
+
+        // On both sides of the border between the chunks
+        assert!(world.set_block(glam::vec3(-1.0, 3.0, 4.0), Some(BlockType::Stone)));
+        assert!(world.set_block(glam::vec3(0.0, 3.0, 4.0), Some(BlockType::Glass)));
+        assert_eq!(
+            world.get_block(glam::vec3(-1.0, 3.0, 4.0)),
+            Some(BlockType::Stone)
+        );
+        assert_eq!(
+            world.get_block(glam::vec3(0.0, 3.0, 4.0)),
+            Some(BlockType::Glass)
+        );
+        assert_eq!(world.get_block(glam::vec3(1.0, 3.0, 4.0)), None);
+        assert_eq!(
+            chunk((-1, 0))
+                .read()
+                .unwrap()
+                .block_type_at(&glam::vec3(15.0, 3.0, 4.0)),
+            Some(BlockType::Stone)
+        );
+        for key in [(-1, 0), (0, 0)] {
+            let chunk = chunk(key);
+            let chunk = chunk.read().unwrap();
+            assert!(chunk.is_modified() && chunk.dirty);
+        }
+
+        // Nothing to change
+        assert!(!world.set_block(glam::vec3(-1.0, 3.0, 4.0), Some(BlockType::Stone)));
+        assert!(!world.set_block(
+            glam::vec3(5.0, WORLD_MIN_Y as f32 - 1.0, 4.0),
+            Some(BlockType::Stone)
+        ));
+        assert!(!world.set_block(glam::vec3(40.0, 3.0, 4.0), Some(BlockType::Stone)));
+
+        assert!(world.set_block(glam::vec3(0.0, 3.0, 4.0), None));
+        assert_eq!(world.get_block(glam::vec3(0.0, 3.0, 4.0)), None);
+
+        // Blocks are only placed into air or water and inside the world's height
+        assert!(!world.place_block(glam::vec3(-1.0, 3.0, 4.0), BlockType::Glass));
+        assert!(world.set_block(glam::vec3(2.0, 3.0, 4.0), Some(BlockType::Water)));
+        assert!(world.place_block(glam::vec3(2.0, 3.0, 4.0), BlockType::Glass));
+        assert!(world.place_block(glam::vec3(2.0, WORLD_MAX_Y as f32, 4.0), BlockType::Glass));
+        assert!(!world.place_block(
+            glam::vec3(2.0, WORLD_MAX_Y as f32 + 1.0, 4.0),
+            BlockType::Glass
+        ));
+        assert_eq!(
+            world.get_block(glam::vec3(2.0, 3.0, 4.0)),
+            Some(BlockType::Glass)
+        );
+    }
+
+    #[test]
+    fn should_only_replace_the_faces_around_an_interior_edit() {
+        let Some((device, queue)) = test_device() else {
+            println!("No adapter available, skipping incremental remesh test");
+            return;
+        };
+        let mut world = World::with_seed(device, queue, None, false, RNG_SEED);
+        // Two chunks with a floor two blocks thick
+        for x in 0..2 {
+            let mut blocks = ChunkBlocks::new((x, 0), DEFAULT_CHUNK_SIZE);
+            for (bx, y, z) in (0..DEFAULT_CHUNK_SIZE).flat_map(|bx| {
+                (0..2).flat_map(move |y| (0..DEFAULT_CHUNK_SIZE).map(move |z| (bx, y, z)))
+            }) {
+                blocks.set(bx, y, z, Some(BlockType::Stone));
+            }
+            let data = ChunkData {
+                x,
+                y: 0,
+                blocks: Arc::new(RwLock::new(blocks)),
+                outside_blocks: vec![],
+            };
+            let chunk = Chunk::from_data(
+                data,
+                world.noise_data.clone(),
+                world.device.clone(),
+                world.queue.clone(),
+                world.chunk_data_layout.clone(),
+                None,
+            );
+            world
+                .chunks
+                .write()
+                .unwrap()
+                .insert((x, 0), Arc::new(RwLock::new(chunk)));
+        }
+        world.render_chunks(vec![(0, 0), (1, 0)]);
+        let first = world.chunks.read().unwrap()[&(0, 0)].clone();
+        let second = world.chunks.read().unwrap()[&(1, 0)].clone();
+        // Small meshes, their indices are uploaded as 16 bit
+        assert_eq!(
+            first.read().unwrap().index_format,
+            wgpu::IndexFormat::Uint16
+        );
+        // Every vertex of the mesh, in any order
+        let vertices = |vertex: &[crate::blocks::block::BlockVertexData]| {
+            let mut vertices: Vec<_> = vertex
+                .iter()
+                .map(|v| {
+                    (
+                        v.position.map(f32::to_bits),
+                        v.normal.map(f32::to_bits),
+                        v.ao.to_bits(),
+                    )
+                })
+                .collect();
+            vertices.sort();
+            vertices
+        };
+        let chunk_map = world.chunks.clone();
+        let assert_matches_a_whole_remesh = |chunk: &Arc<RwLock<Chunk>>| {
+            let chunk = chunk.read().unwrap();
+            let mesh = chunk.mesh.as_ref().unwrap();
+            let expected = chunk.build_mesh_data(chunk_map.clone());
+            assert_eq!(vertices(&mesh.vertex), vertices(&expected.vertex));
+            assert_eq!(
+                vertices(&mesh.water_vertex),
+                vertices(&expected.water_vertex)
+            );
+            assert_eq!(chunk.indices as usize, expected.indices.len());
+            assert_eq!(chunk.water_indices as usize, expected.water_indices.len());
+        };
+
+        world.remove_block(glam::vec3(5.0, 1.0, 5.0));
+        world.place_block(glam::vec3(6.0, 2.0, 5.0), BlockType::Glass);
+        assert!(!first.read().unwrap().dirty && !second.read().unwrap().dirty);
+        assert_eq!(world.last_mesh_version, 0);
+        assert_matches_a_whole_remesh(&first);
+
+        // The neighbour can see the faces of a border block
+        world.remove_block(glam::vec3(15.0, 1.0, 7.0));
+        assert!(!first.read().unwrap().dirty);
+        assert!(second.read().unwrap().dirty);
+        assert_matches_a_whole_remesh(&first);
+    }
+
+    #[test]
+    fn should_remesh_edited_chunks_once_and_drop_meshes_of_unloaded_chunks() {
+        let Some((device, queue)) = test_device() else {
+            println!("No adapter available, skipping remesh test");
+            return;
+        };
+        let mut world = World::with_seed(device, queue, None, false, RNG_SEED);
+        let add_chunk = |world: &World, x: i32| {
+            let blocks: BlockVec =
+                Arc::new(RwLock::new(ChunkBlocks::new((x, 0), DEFAULT_CHUNK_SIZE)));
+            for z in 0..DEFAULT_CHUNK_SIZE {
+                let block = Block::new(
+                    glam::vec3(15.0, 1.0, z as f32),
+                    (x, 0),
+                    BlockType::Stone,
+                    DEFAULT_CHUNK_SIZE,
+                );
+                insert_block(&blocks, Arc::new(RwLock::new(block)));
+            }
+            let data = ChunkData {
+                x,
+                y: 0,
+                blocks,
+                outside_blocks: vec![],
+            };
+            let chunk = Chunk::from_data(
+                data,
+                world.noise_data.clone(),
+                world.device.clone(),
+                world.queue.clone(),
+                world.chunk_data_layout.clone(),
+                None,
+            );
+            let chunk = Arc::new(RwLock::new(chunk));
+            world.chunks.write().unwrap().insert((x, 0), chunk.clone());
+            chunk
+        };
+        let first = add_chunk(&world, 0);
+        let second = add_chunk(&world, 1);
+        // Waits for every queued job, so their meshes are in the channel
+        let finish_jobs = |world: &mut World| {
+            world.thread_pool = Some(ThreadPool::new(2));
+        };
+
+        // Two edits on the border only queue one mesh for each chunk
+        world.remove_block(glam::vec3(15.0, 1.0, 5.0));
+        world.place_block(glam::vec3(14.0, 1.0, 5.0), BlockType::Glass);
+        assert!(first.read().unwrap().dirty && second.read().unwrap().dirty);
+        world.remesh_dirty_chunks();
+        assert!(!first.read().unwrap().dirty && !second.read().unwrap().dirty);
+        assert_eq!(world.last_mesh_version, 2);
+
+        finish_jobs(&mut world);
+        world.receive_stream_results();
+        assert!(first.read().unwrap().chunk_vertex_buffer.is_some());
+        assert_eq!(first.read().unwrap().water_indices, 6 * 6);
+
+        // Unloaded while its mesh is being built, the chunk loaded in its place doesn't get it
+        second.write().unwrap().dirty = true;
+        world.remesh_dirty_chunks();
+        world.chunks.write().unwrap().remove(&(1, 0));
+        world.mesh_versions.remove(&(1, 0));
+        let reloaded = add_chunk(&world, 1);
+        finish_jobs(&mut world);
+        world.receive_stream_results();
+        assert!(reloaded.read().unwrap().chunk_vertex_buffer.is_none());
+    }
+
+    #[test]
+    fn should_only_mesh_the_seam_faces_that_can_be_seen() {
+        let Some((device, queue)) = test_device() else {
+            println!("No adapter available, skipping seam test");
+            return;
+        };
+        let world = World::with_seed(device, queue, None, false, RNG_SEED);
+        let add_chunk = |x: i32, carve: &dyn Fn(&mut ChunkBlocks)| {
+            let blocks = Chunk::create_blocks_data(x, 0, world.noise_data.clone());
+            carve(&mut blocks.write().unwrap());
+            let data = ChunkData {
+                x,
+                y: 0,
+                blocks,
+                outside_blocks: vec![],
+            };
+            let chunk = Chunk::from_data(
+                data,
+                world.noise_data.clone(),
+                world.device.clone(),
+                world.queue.clone(),
+                world.chunk_data_layout.clone(),
+                None,
+            );
+            let chunk = Arc::new(RwLock::new(chunk));
+            world.chunks.write().unwrap().insert((x, 0), chunk.clone());
+            chunk
+        };
+        // Blocks at the border `x` with a face towards the other chunk
+        let seam_faces = |chunk: &Arc<RwLock<Chunk>>, x: f32, normal: f32| {
+            let chunk = chunk.read().unwrap();
+            let mesh = chunk.mesh.as_ref().unwrap();
+            mesh.face_blocks
+                .iter()
+                .enumerate()
+                .filter(|(i, block)| block.x == x && mesh.vertex[i * 4].normal[0] == normal)
+                .map(|(_, block)| *block)
+                .collect::<Vec<_>>()
+        };
+        let edge = (DEFAULT_CHUNK_SIZE - 1) as f32;
+
+        // Nothing is drawn towards a chunk that isn't loaded yet
+        let first = add_chunk(0, &|_| {});
+        world.render_chunks(world.chunks_loaded(vec![(0, 0)]));
+        assert!(seam_faces(&first, edge, 1.0).is_empty());
+
+        // A pit in the neighbour, right next to the border
+        let second = add_chunk(1, &|blocks| {
+            for y in 1..blocks.column_height(0, 5) {
+                blocks.set(0, y, 5, None);
+            }
+        });
+        let to_mesh = world.chunks_loaded(vec![(1, 0)]);
+        assert_eq!(to_mesh, vec![(1, 0), (0, 0)]);
+        world.render_chunks(to_mesh);
+
+        let type_at = |chunk: &Arc<RwLock<Chunk>>, position: glam::Vec3| {
+            chunk.read().unwrap().block_type_at(&position)
+        };
+        let visible = seam_faces(&first, edge, 1.0);
+        assert!(visible.iter().any(|block| block.z == 5.0));
+        for block in visible {
+            let owner = type_at(&first, block).unwrap();
+            let across = type_at(&second, glam::vec3(0.0, block.y, block.z));
+            assert!(!across.is_some_and(|t| t.hides_face_of(owner)), "{block}");
+        }
+        for block in seam_faces(&second, 0.0, -1.0) {
+            let owner = type_at(&second, block).unwrap();
+            let across = type_at(&first, glam::vec3(edge, block.y, block.z));
+            assert!(!across.is_some_and(|t| t.hides_face_of(owner)), "{block}");
         }
     }
 }
@@ -0,0 +1,158 @@
+use std::collections::VecDeque;
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+use crate::chunk::ChunkData;
+use crate::persistence::{save_dir, WorldArchive};
+use crate::utils::threadpool::ThreadPool;
+use crate::world::NoiseData;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WorldGenRequest {
+    GenerateChunk(i32, i32),
+    // Drops the request if it hasn't started yet, chunks already being generated are still returned
+    Cancel(i32, i32),
+    Shutdown,
+}
+
+enum Message {
+    Request(WorldGenRequest),
+    // A worker finished a chunk, so another one can be started
+    Finished,
+}
+
+// Owns the generation inputs and a group of workers. Requests are queued in order and at most
+// `max_in_flight` chunks are generated at the same time, finished chunks are sent to the result channel.
+pub struct WorldGen {
+    sender: mpsc::Sender<Message>,
+    results: mpsc::Receiver<ChunkData>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl WorldGen {
+    pub fn new(
+        threads: usize,
+        noise_data: Arc<NoiseData>,
+        archive: Option<Arc<WorldArchive>>,
+    ) -> WorldGen {
+        let (sender, receiver) = mpsc::channel::<Message>();
+        let (result_sender, results) = mpsc::channel::<ChunkData>();
+        let worker_sender = sender.clone();
+
+        let thread = thread::Builder::new()
+            .name("world-gen".to_string())
+            .spawn(move || {
+                let pool = ThreadPool::new(threads);
+                let mut queue: VecDeque<(i32, i32)> = VecDeque::new();
+                let mut in_flight = 0;
+
+                loop {
+                    while in_flight < threads {
+                        let Some((x, y)) = queue.pop_front() else {
+                            break;
+                        };
+                        in_flight += 1;
+
+                        let noise_data = Arc::clone(&noise_data);
+                        let archive = archive.clone();
+                        let result_sender = result_sender.clone();
+                        let worker_sender = worker_sender.clone();
+                        pool.execute(move || {
+                            let data = ChunkData::generate(x, y, noise_data, &save_dir(), archive);
+                            // The world might be gone already, nothing to do then
+                            let _ = result_sender.send(data);
+                            let _ = worker_sender.send(Message::Finished);
+                        });
+                    }
+
+                    match receiver.recv() {
+                        Ok(Message::Request(WorldGenRequest::GenerateChunk(x, y))) => {
+                            if !queue.contains(&(x, y)) {
+                                queue.push_back((x, y));
+                            }
+                        }
+                        Ok(Message::Request(WorldGenRequest::Cancel(x, y))) => {
+                            queue.retain(|c| *c != (x, y))
+                        }
+                        Ok(Message::Finished) => in_flight -= 1,
+                        Ok(Message::Request(WorldGenRequest::Shutdown)) | Err(_) => break,
+                    }
+                }
+            })
+            .expect("Failed to spawn the world gen thread");
+
+        WorldGen {
+            sender,
+            results,
+            thread: Some(thread),
+        }
+    }
+    pub fn send(&self, request: WorldGenRequest) {
+        self.sender
+            .send(Message::Request(request))
+            .expect("World gen thread is gone");
+    }
+    pub fn request(&self, x: i32, y: i32) {
+        self.send(WorldGenRequest::GenerateChunk(x, y));
+    }
+    pub fn cancel(&self, x: i32, y: i32) {
+        self.send(WorldGenRequest::Cancel(x, y));
+    }
+    // Non blocking, returns None when there are no finished chunks
+    pub fn try_recv(&self) -> Option<ChunkData> {
+        self.results.try_recv().ok()
+    }
+    pub fn recv(&self) -> ChunkData {
+        self.results.recv().expect("World gen thread is gone")
+    }
+}
+
+impl Drop for WorldGen {
+    fn drop(&mut self) {
+        let _ = self
+            .sender
+            .send(Message::Request(WorldGenRequest::Shutdown));
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WorldGen;
+    use crate::utils::noise::create_world_noise_data;
+    use crate::world::{FREQUENCY, NOISE_SIZE, RNG_SEED};
+    use std::sync::Arc;
+
+    #[test]
+    fn should_return_one_chunk_per_request() {
+        let noise_data = Arc::new(create_world_noise_data(
+            NOISE_SIZE, NOISE_SIZE, FREQUENCY, RNG_SEED,
+        ));
+        // Far away so there's no saved chunk for them
+        let mut requested = vec![];
+        for x in 1000..1003 {
+            for y in -1003..-1000 {
+                requested.push((x, y));
+            }
+        }
+
+        let worldgen = WorldGen::new(2, noise_data, None);
+        for (x, y) in requested.iter() {
+            worldgen.request(*x, *y);
+        }
+
+        let mut received = (0..requested.len())
+            .map(|_| {
+                let data = worldgen.recv();
+                assert_eq!(data.blocks.read().unwrap().chunk(), (data.x, data.y));
+                (data.x, data.y)
+            })
+            .collect::<Vec<_>>();
+        received.sort();
+        requested.sort();
+        assert_eq!(received, requested);
+        assert!(worldgen.try_recv().is_none());
+    }
+}